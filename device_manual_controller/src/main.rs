@@ -2,11 +2,15 @@ mod logger;
 mod ui;
 
 use egui_glfw::AppWindow;
+use headlight_if::ServiceProperties;
 use log::{error, info};
 use service::AudioHeadlightService;
 use std::sync::{Arc, Mutex};
 
-use crate::{service::ServiceRegister, ui::ui_controller::UiController};
+use crate::{
+    service::{ServiceRegister, ServoId},
+    ui::{device_states::DeviceCommand, ui_controller::UiController},
+};
 
 mod service;
 
@@ -24,7 +28,7 @@ fn main() {
     }
 
     let mut service_register = ServiceRegister::new();
-    let service = service_register.add_service("RtAudioEffect");
+    let service = service_register.add_service("RtAudioEffect", ServiceProperties::from_env());
 
     let mut context = AppContext::new(service);
 
@@ -79,15 +83,7 @@ impl AppContext {
             });
 
             self.app_window.end_frame();
-            self.service.lock().unwrap().set_servo(
-                service::ServoId::Servo1,
-                self.ui_controller.states.lock().unwrap().joystick1.x,
-            );
-
-            self.service.lock().unwrap().set_servo(
-                service::ServoId::Servo2,
-                self.ui_controller.states.lock().unwrap().joystick1.y,
-            );
+            self.handle_commands();
 
             // add sleep to reduce cpu usage
             std::thread::sleep(std::time::Duration::from_secs_f32(0.001));
@@ -95,4 +91,39 @@ impl AppContext {
 
         true
     }
+
+    fn handle_commands(&mut self) {
+        let mut states = self.ui_controller.states.lock().unwrap();
+        let mut service = self.service.lock().unwrap();
+
+        for command in std::mem::take(&mut states.commands) {
+            match command {
+                DeviceCommand::SetServoPulse(servo_id, pulse_us) => {
+                    service.set_servo_pulse(servo_id, pulse_us)
+                }
+                DeviceCommand::SetServoCalibration(servo_id, calibration) => {
+                    service.set_servo_calibration(servo_id, calibration)
+                }
+                DeviceCommand::ReadServoCalibration(servo_id) => {
+                    if let Err(err) = service.request_servo_calibration(servo_id) {
+                        error!("Failed to read calibration of {:?}: {}", servo_id, err);
+                    }
+                }
+            }
+        }
+
+        match service.poll_servo_calibration() {
+            Some((servo_id, Some(calibration))) => {
+                states.calibrations[servo_id.index()] = calibration
+            }
+            Some((servo_id, None)) => error!("Failed to read calibration of {:?}", servo_id),
+            None => {}
+        }
+
+        // joystick would override raw pulses sent by the wizard
+        if states.wizard.is_none() {
+            service.set_servo(ServoId::Servo1, states.joystick1.x);
+            service.set_servo(ServoId::Servo2, states.joystick1.y);
+        }
+    }
 }
@@ -5,12 +5,15 @@ use std::{
     time::{Duration, Instant},
 };
 
-use log::{error, info};
+use log::{debug, error, info, warn};
 use mdns_sd::ServiceInfo;
 
 use super::ServiceClient;
 
-use headlight_if::{Message, SetColorMessage, SetServo};
+use headlight_if::{
+    KeepaliveMessage, Message, ServoCalibration, ServoCalibrationMessage,
+    ServoCalibrationRequestMessage, SetColorMessage, SetServo, SetServoPulse, KEEPALIVE_PERIOD,
+};
 
 struct ServiceSharedCtx {
     is_alive: bool,
@@ -25,14 +28,30 @@ pub struct AudioHeadlightService {
     listner_thread: Option<JoinHandle<()>>,
     rainbow_thread: Option<JoinHandle<()>>,
     servo_thread: Option<JoinHandle<()>>,
+    /// Thread waiting for the reply of `request_servo_calibration`.
+    calibration_request: Option<(ServoId, JoinHandle<Option<ServoCalibration>>)>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ServoId {
     Servo1,
     Servo2,
 }
 
+impl ServoId {
+    pub const ALL: [ServoId; 2] = [ServoId::Servo1, ServoId::Servo2];
+
+    pub fn index(&self) -> usize {
+        match self {
+            ServoId::Servo1 => 0,
+            ServoId::Servo2 => 1,
+        }
+    }
+}
+
 impl AudioHeadlightService {
+    const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
     pub fn new(name: &str, listner: TcpListener, service_info: ServiceInfo) -> Self {
         info!("Service {} created", name);
         listner
@@ -50,6 +69,7 @@ impl AudioHeadlightService {
             listner_thread: None,
             rainbow_thread: None,
             servo_thread: None,
+            calibration_request: None,
         }
     }
 
@@ -68,12 +88,25 @@ impl AudioHeadlightService {
     }
 
     fn service_thread(shared_ctx: Arc<Mutex<ServiceSharedCtx>>) {
+        let mut last_keepalive = Instant::now();
         loop {
             if let Ok(context_lock) = &mut shared_ctx.lock() {
                 if !context_lock.is_alive {
                     break;
                 }
 
+                // devices time out a silent host, the wizard may not send anything for long
+                if last_keepalive.elapsed() >= KEEPALIVE_PERIOD {
+                    last_keepalive = Instant::now();
+                    for client in context_lock.clients.iter_mut() {
+                        if let Err(err) =
+                            client.send_message(Message::Keepalive(KeepaliveMessage {}))
+                        {
+                            error!("Failed to send keepalive to {}: {}", client.addr, err);
+                        }
+                    }
+                }
+
                 if let Ok((stream, address)) = context_lock.listner.accept() {
                     println!("New connection from: {}", address);
                     context_lock
@@ -88,10 +121,7 @@ impl AudioHeadlightService {
 
     pub fn set_servo(&mut self, servo_id: ServoId, position: f32) {
         // generate sin wave
-        let id = match servo_id {
-            ServoId::Servo1 => 0,
-            ServoId::Servo2 => 1,
-        };
+        let id = servo_id.index() as u8;
 
         if let Ok(context_lock) = &mut self.shared_ctx.try_lock() {
             for client in context_lock.clients.iter_mut() {
@@ -102,6 +132,82 @@ impl AudioHeadlightService {
         }
     }
 
+    pub fn set_servo_pulse(&mut self, servo_id: ServoId, pulse_us: u32) {
+        let id = servo_id.index() as u8;
+        self.broadcast(|| Message::SetServoPulse(SetServoPulse { id, pulse_us }));
+    }
+
+    pub fn set_servo_calibration(&mut self, servo_id: ServoId, calibration: ServoCalibration) {
+        let id = servo_id.index() as u8;
+        self.broadcast(|| {
+            Message::SetServoCalibration(ServoCalibrationMessage {
+                id,
+                calibration: calibration.clone(),
+            })
+        });
+    }
+
+    /// Requests calibration from the first connected device. The reply is awaited on
+    /// another thread, without holding the service, and taken with `poll_servo_calibration`.
+    pub fn request_servo_calibration(&mut self, servo_id: ServoId) -> Result<(), String> {
+        if self.calibration_request.is_some() {
+            return Err(String::from("Calibration request already pending"));
+        }
+
+        let id = servo_id.index() as u8;
+        let mut port = {
+            let mut context_lock = self.shared_ctx.lock().unwrap();
+            let client = context_lock
+                .clients
+                .first_mut()
+                .ok_or("No device connected")?;
+            client.send_message(Message::ServoCalibrationRequest(
+                ServoCalibrationRequestMessage { id },
+            ))?;
+            client.port.try_clone()?
+        };
+        port.set_read_timeout(Some(Self::REPLY_TIMEOUT))?;
+
+        let thread = std::thread::spawn(move || {
+            let calibration = loop {
+                match port.recv() {
+                    Ok(Message::ServoCalibration(msg)) if msg.id == id => {
+                        break Some(msg.calibration)
+                    }
+                    Ok(msg) => debug!("Ignoring message while waiting for calibration: {:?}", msg),
+                    Err(err) => {
+                        warn!("No servo calibration received: {}", err);
+                        break None;
+                    }
+                }
+            };
+            if let Err(err) = port.set_read_timeout(None) {
+                error!("{}", err);
+            }
+            calibration
+        });
+        self.calibration_request = Some((servo_id, thread));
+        Ok(())
+    }
+
+    /// Result of `request_servo_calibration` once it has been received or timed out.
+    pub fn poll_servo_calibration(&mut self) -> Option<(ServoId, Option<ServoCalibration>)> {
+        if !self.calibration_request.as_ref()?.1.is_finished() {
+            return None;
+        }
+        let (servo_id, thread) = self.calibration_request.take()?;
+        Some((servo_id, thread.join().unwrap_or(None)))
+    }
+
+    fn broadcast(&mut self, message: impl Fn() -> Message) {
+        let mut context_lock = self.shared_ctx.lock().unwrap();
+        for client in context_lock.clients.iter_mut() {
+            if let Err(err) = client.send_message(message()) {
+                error!("Failed to send message to {}: {}", client.addr, err);
+            }
+        }
+    }
+
     pub fn start(&mut self) {
         if let None = self.listner_thread {
             let shared_ctx = self.shared_ctx.clone();
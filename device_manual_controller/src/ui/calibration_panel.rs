@@ -0,0 +1,254 @@
+use std::sync::{Arc, Mutex};
+
+use egui::{DragValue, Grid, Response, Ui, Widget};
+use headlight_if::ServoCalibration;
+
+use crate::service::ServoId;
+
+use super::{
+    calibration_wizard::{CalibrationWizard, WizardStep},
+    device_states::{DeviceCommand, DeviceStates},
+};
+
+pub struct CalibrationPanel {
+    states: Arc<Mutex<DeviceStates>>,
+}
+
+impl CalibrationPanel {
+    pub fn build(states: Arc<Mutex<DeviceStates>>) -> Self {
+        Self { states }
+    }
+
+    fn calibration_ui(ui: &mut Ui, servo_id: ServoId, states: &mut DeviceStates) {
+        let calibration = &mut states.calibrations[servo_id.index()];
+
+        ui.label(format!("{:?}", servo_id));
+        Grid::new(format!("{:?} calibration", servo_id))
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                Self::fields_ui(ui, calibration);
+            });
+
+        ui.horizontal(|ui| {
+            if ui.button("read").clicked() {
+                states
+                    .commands
+                    .push(DeviceCommand::ReadServoCalibration(servo_id));
+            }
+            if ui.button("send").clicked() {
+                states.commands.push(DeviceCommand::SetServoCalibration(
+                    servo_id,
+                    calibration.clone(),
+                ));
+            }
+            if ui.button("wizard").clicked() {
+                states.wizard = Some(CalibrationWizard::new(servo_id, calibration.clone()));
+            }
+        });
+    }
+
+    fn fields_ui(ui: &mut Ui, calibration: &mut ServoCalibration) {
+        ui.label("min pulse");
+        ui.add(
+            DragValue::new(&mut calibration.min_pulse_us)
+                .clamp_range(300..=2700)
+                .suffix(" us"),
+        );
+        ui.end_row();
+
+        ui.label("max pulse");
+        ui.add(
+            DragValue::new(&mut calibration.max_pulse_us)
+                .clamp_range(300..=2700)
+                .suffix(" us"),
+        );
+        ui.end_row();
+
+        ui.label("center trim");
+        ui.add(
+            DragValue::new(&mut calibration.center_trim)
+                .clamp_range(-1.0..=1.0)
+                .speed(0.005),
+        );
+        ui.end_row();
+
+        ui.label("soft min");
+        ui.add(
+            DragValue::new(&mut calibration.soft_min)
+                .clamp_range(-1.0..=1.0)
+                .speed(0.005),
+        );
+        ui.end_row();
+
+        ui.label("soft max");
+        ui.add(
+            DragValue::new(&mut calibration.soft_max)
+                .clamp_range(-1.0..=1.0)
+                .speed(0.005),
+        );
+        ui.end_row();
+
+        ui.label("max speed");
+        ui.add(
+            DragValue::new(&mut calibration.max_speed)
+                .clamp_range(0.1..=20.0)
+                .speed(0.05)
+                .suffix(" /s"),
+        );
+        ui.end_row();
+
+        ui.label("inverted");
+        ui.checkbox(&mut calibration.inverted, "");
+        ui.end_row();
+    }
+
+    fn wizard_ui(ui: &mut Ui, states: &mut DeviceStates) {
+        let Some(wizard) = &mut states.wizard else {
+            return;
+        };
+
+        ui.label(format!("{:?} wizard: {:?}", wizard.servo_id, wizard.step));
+        ui.label(wizard.step.description());
+
+        let mut close = false;
+        let mut apply = false;
+        if wizard.step == WizardStep::Done {
+            Grid::new("wizard result")
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    Self::fields_ui(ui, &mut wizard.calibration);
+                });
+
+            if ui.button("apply").clicked() {
+                apply = true;
+            }
+        } else {
+            ui.label(format!("pulse: {} us", wizard.get_pulse_us()));
+            ui.horizontal(|ui| {
+                if ui.button("-").clicked() {
+                    wizard.nudge(-1.0);
+                }
+                ui.toggle_value(&mut wizard.sweeping, "sweep");
+                if ui.button("+").clicked() {
+                    wizard.nudge(1.0);
+                }
+                if ui.button("mark").clicked() {
+                    wizard.mark();
+                }
+            });
+        }
+
+        if ui.button("cancel").clicked() {
+            close = true;
+        }
+        if apply {
+            Self::apply_wizard(states);
+        } else if close {
+            states.wizard = None;
+        }
+    }
+
+    /// Closes the wizard, its result replaces the edited calibration and is sent to the device.
+    fn apply_wizard(states: &mut DeviceStates) {
+        if let Some(wizard) = states.wizard.take() {
+            states.calibrations[wizard.servo_id.index()] = wizard.calibration.clone();
+            states.commands.push(DeviceCommand::SetServoCalibration(
+                wizard.servo_id,
+                wizard.calibration,
+            ));
+        }
+    }
+}
+
+impl Widget for CalibrationPanel {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let mut states = self.states.lock().unwrap();
+
+        ui.group(|ui| {
+            ui.label("servo calibration");
+            if states.wizard.is_some() {
+                Self::wizard_ui(ui, &mut states);
+            } else {
+                for servo_id in ServoId::ALL {
+                    Self::calibration_ui(ui, servo_id, &mut states);
+                }
+            }
+        })
+        .response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::{Context, RawInput, Vec2};
+
+    use super::*;
+
+    fn new_states() -> Arc<Mutex<DeviceStates>> {
+        Arc::new(Mutex::new(DeviceStates {
+            joystick1: Vec2::ZERO,
+            calibrations: Default::default(),
+            wizard: None,
+            commands: Vec::new(),
+        }))
+    }
+
+    fn show(states: &Arc<Mutex<DeviceStates>>) {
+        let context = Context::default();
+        let _ = context.run(RawInput::default(), |context| {
+            egui::CentralPanel::default().show(context, |ui| {
+                ui.add(CalibrationPanel::build(states.clone()));
+            });
+        });
+    }
+
+    #[test]
+    fn test_show_without_input() {
+        let states = new_states();
+        let calibration = ServoCalibration {
+            min_pulse_us: 800,
+            center_trim: 0.1,
+            soft_min: -0.5,
+            inverted: true,
+            ..Default::default()
+        };
+        states.lock().unwrap().calibrations[1] = calibration.clone();
+
+        show(&states);
+        {
+            let mut states = states.lock().unwrap();
+            assert_eq!(states.calibrations[1], calibration);
+            assert!(states.commands.is_empty());
+            states.wizard = Some(CalibrationWizard::new(ServoId::Servo2, calibration.clone()));
+        }
+
+        show(&states);
+        let states = states.lock().unwrap();
+        assert!(states.wizard.is_some());
+        assert!(states.commands.is_empty());
+    }
+
+    #[test]
+    fn test_apply_wizard() {
+        let states = new_states();
+        let mut states = states.lock().unwrap();
+        CalibrationPanel::apply_wizard(&mut states);
+        assert!(states.commands.is_empty());
+
+        let mut wizard = CalibrationWizard::new(ServoId::Servo2, ServoCalibration::default());
+        wizard.calibration.min_pulse_us = 900;
+        states.wizard = Some(wizard);
+        CalibrationPanel::apply_wizard(&mut states);
+
+        assert!(states.wizard.is_none());
+        assert_eq!(states.calibrations[0], ServoCalibration::default());
+        assert_eq!(states.calibrations[1].min_pulse_us, 900);
+        assert!(matches!(
+            states.commands.as_slice(),
+            [DeviceCommand::SetServoCalibration(ServoId::Servo2, calibration)]
+                if calibration.min_pulse_us == 900
+        ));
+    }
+}
@@ -0,0 +1,283 @@
+use std::time::Duration;
+
+use headlight_if::ServoCalibration;
+
+use crate::service::ServoId;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WizardStep {
+    MinPulse,
+    MaxPulse,
+    Center,
+    SoftMin,
+    SoftMax,
+    Done,
+}
+
+impl WizardStep {
+    pub fn description(&self) -> &'static str {
+        match self {
+            WizardStep::MinPulse => "Sweep down and mark where servo stops moving",
+            WizardStep::MaxPulse => "Sweep up and mark where servo stops moving",
+            WizardStep::Center => "Adjust and mark mechanical center",
+            WizardStep::SoftMin => "Sweep down and mark the lowest safe position",
+            WizardStep::SoftMax => "Sweep up and mark the highest safe position",
+            WizardStep::Done => "Calibration finished, send it to the device",
+        }
+    }
+}
+
+/// Drives servo with raw pulses and collects its endpoints, step by step.
+#[derive(Clone, Debug)]
+pub struct CalibrationWizard {
+    pub servo_id: ServoId,
+    pub step: WizardStep,
+    pub sweeping: bool,
+    pub calibration: ServoCalibration,
+    pulse_us: f32,
+    sent_pulse_us: Option<u32>,
+}
+
+impl CalibrationWizard {
+    pub const SWEEP_SPEED_US_PER_S: f32 = 200.0;
+    pub const NUDGE_US: f32 = 10.0;
+    const MIN_PULSE_US: f32 = 300.0;
+    const MAX_PULSE_US: f32 = 2700.0;
+    const CENTER_PULSE_US: f32 = 1500.0;
+
+    pub fn new(servo_id: ServoId, calibration: ServoCalibration) -> Self {
+        Self {
+            servo_id,
+            step: WizardStep::MinPulse,
+            sweeping: false,
+            calibration,
+            pulse_us: Self::CENTER_PULSE_US,
+            sent_pulse_us: None,
+        }
+    }
+
+    pub fn get_pulse_us(&self) -> u32 {
+        self.pulse_us.round() as u32
+    }
+
+    /// Advances the sweep. Returns pulse width when it has to be sent to the device.
+    pub fn update(&mut self, time_step: Duration) -> Option<u32> {
+        if self.sweeping {
+            let delta = Self::SWEEP_SPEED_US_PER_S * time_step.as_secs_f32();
+            self.pulse_us = match self.sweep_direction() {
+                Some(true) => self.pulse_us + delta,
+                Some(false) => self.pulse_us - delta,
+                None => self.pulse_us,
+            };
+        }
+        self.pulse_us = self.pulse_us.clamp(Self::MIN_PULSE_US, Self::MAX_PULSE_US);
+
+        let pulse = self.get_pulse_us();
+        if self.step != WizardStep::Done && self.sent_pulse_us != Some(pulse) {
+            self.sent_pulse_us = Some(pulse);
+            Some(pulse)
+        } else {
+            None
+        }
+    }
+
+    pub fn nudge(&mut self, direction: f32) {
+        self.sweeping = false;
+        self.pulse_us += direction * Self::NUDGE_US;
+    }
+
+    /// Stores current pulse width as a result of the current step and moves to the next one.
+    pub fn mark(&mut self) {
+        let pulse = self.get_pulse_us();
+        self.sweeping = false;
+
+        self.step = match self.step {
+            WizardStep::MinPulse => {
+                self.calibration.min_pulse_us = pulse;
+                self.pulse_us = Self::CENTER_PULSE_US;
+                WizardStep::MaxPulse
+            }
+            WizardStep::MaxPulse => {
+                self.calibration.max_pulse_us = pulse;
+                self.pulse_us = self.middle_pulse();
+                WizardStep::Center
+            }
+            WizardStep::Center => {
+                self.calibration.center_trim = self.to_physical_set_point(pulse);
+                self.pulse_us = pulse as f32;
+                WizardStep::SoftMin
+            }
+            WizardStep::SoftMin => {
+                self.calibration.soft_min = self.to_set_point(pulse);
+                self.pulse_us = self.center_pulse();
+                WizardStep::SoftMax
+            }
+            WizardStep::SoftMax => {
+                let set_point = self.to_set_point(pulse);
+                // servo may be inverted, so limits are sorted at the end
+                let soft_min = self.calibration.soft_min.min(set_point);
+                let soft_max = self.calibration.soft_min.max(set_point);
+                self.calibration.soft_min = soft_min;
+                self.calibration.soft_max = soft_max;
+                WizardStep::Done
+            }
+            WizardStep::Done => WizardStep::Done,
+        };
+    }
+
+    /// true - increasing pulse, false - decreasing pulse
+    fn sweep_direction(&self) -> Option<bool> {
+        match self.step {
+            WizardStep::MinPulse | WizardStep::SoftMin => Some(false),
+            WizardStep::MaxPulse | WizardStep::SoftMax => Some(true),
+            WizardStep::Center | WizardStep::Done => None,
+        }
+    }
+
+    fn middle_pulse(&self) -> f32 {
+        (self.calibration.min_pulse_us as f32 + self.calibration.max_pulse_us as f32) / 2.0
+    }
+
+    fn center_pulse(&self) -> f32 {
+        let half_range =
+            (self.calibration.max_pulse_us as f32 - self.calibration.min_pulse_us as f32) / 2.0;
+        self.middle_pulse() + self.calibration.center_trim * half_range
+    }
+
+    // Set point before trim and direction are applied
+    fn to_physical_set_point(&self, pulse: u32) -> f32 {
+        let min_pulse = self.calibration.min_pulse_us as f32;
+        let max_pulse = self.calibration.max_pulse_us as f32;
+        let set_point = 2.0 * (pulse as f32 - min_pulse) / (max_pulse - min_pulse) - 1.0;
+        set_point.clamp(-1.0, 1.0)
+    }
+
+    fn to_set_point(&self, pulse: u32) -> f32 {
+        let set_point = self.to_physical_set_point(pulse) - self.calibration.center_trim;
+        let set_point = if self.calibration.inverted {
+            -set_point
+        } else {
+            set_point
+        };
+        set_point.clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Moves the wizard to `pulse_us` and marks it.
+    fn mark_at(wizard: &mut CalibrationWizard, pulse_us: u32) {
+        let delta = pulse_us as f32 - wizard.get_pulse_us() as f32;
+        wizard.nudge(delta / CalibrationWizard::NUDGE_US);
+        assert_eq!(wizard.get_pulse_us(), pulse_us);
+        wizard.mark();
+    }
+
+    fn assert_near(value: f32, expected: f32) {
+        assert!((value - expected).abs() < 1e-5, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn test_sweep() {
+        let mut wizard = CalibrationWizard::new(ServoId::Servo1, ServoCalibration::default());
+        assert_eq!(wizard.update(Duration::ZERO), Some(1500));
+        assert_eq!(wizard.update(Duration::ZERO), None);
+
+        // min pulse is searched downwards, and never beyond the safe range
+        wizard.sweeping = true;
+        assert_eq!(wizard.update(Duration::from_secs(1)), Some(1300));
+        assert_eq!(wizard.update(Duration::from_secs(10)), Some(300));
+        assert_eq!(wizard.update(Duration::from_secs(1)), None);
+
+        wizard.nudge(1.0);
+        assert!(!wizard.sweeping);
+        assert_eq!(wizard.update(Duration::from_secs(1)), Some(310));
+
+        // max pulse is searched upwards, from the center
+        wizard.mark();
+        assert_eq!(wizard.step, WizardStep::MaxPulse);
+        wizard.sweeping = true;
+        assert_eq!(wizard.update(Duration::from_secs(1)), Some(1700));
+        assert_eq!(wizard.update(Duration::from_secs(10)), Some(2700));
+
+        // center doesn't sweep
+        wizard.mark();
+        assert_eq!(wizard.step, WizardStep::Center);
+        assert_eq!(wizard.update(Duration::ZERO), Some(1505));
+        wizard.sweeping = true;
+        assert_eq!(wizard.update(Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn test_steps() {
+        let mut wizard = CalibrationWizard::new(ServoId::Servo1, ServoCalibration::default());
+
+        mark_at(&mut wizard, 1000);
+        assert_eq!(wizard.step, WizardStep::MaxPulse);
+        assert_eq!(wizard.calibration.min_pulse_us, 1000);
+
+        mark_at(&mut wizard, 2000);
+        assert_eq!(wizard.step, WizardStep::Center);
+        assert_eq!(wizard.calibration.max_pulse_us, 2000);
+        assert_eq!(wizard.get_pulse_us(), 1500);
+
+        mark_at(&mut wizard, 1600);
+        assert_eq!(wizard.step, WizardStep::SoftMin);
+        assert_near(wizard.calibration.center_trim, 0.2);
+
+        // soft limits are relative to the trimmed center
+        mark_at(&mut wizard, 1350);
+        assert_eq!(wizard.step, WizardStep::SoftMax);
+        assert_eq!(wizard.get_pulse_us(), 1600);
+
+        mark_at(&mut wizard, 1850);
+        assert_eq!(wizard.step, WizardStep::Done);
+        assert_near(wizard.calibration.soft_min, -0.5);
+        assert_near(wizard.calibration.soft_max, 0.5);
+
+        // pulses aren't sent once finished
+        wizard.mark();
+        assert_eq!(wizard.step, WizardStep::Done);
+        assert_eq!(wizard.update(Duration::ZERO), None);
+    }
+
+    #[test]
+    fn test_inverted_soft_limits() {
+        let calibration = ServoCalibration {
+            min_pulse_us: 1000,
+            max_pulse_us: 2000,
+            center_trim: 0.0,
+            inverted: true,
+            ..Default::default()
+        };
+        let mut wizard = CalibrationWizard::new(ServoId::Servo2, calibration);
+        wizard.step = WizardStep::SoftMin;
+
+        mark_at(&mut wizard, 1250);
+        assert_near(wizard.calibration.soft_min, 0.5);
+
+        mark_at(&mut wizard, 1900);
+        assert_near(wizard.calibration.soft_min, -0.8);
+        assert_near(wizard.calibration.soft_max, 0.5);
+    }
+
+    #[test]
+    fn test_set_points_are_clamped() {
+        let calibration = ServoCalibration {
+            min_pulse_us: 1000,
+            max_pulse_us: 2000,
+            center_trim: 0.5,
+            ..Default::default()
+        };
+        let mut wizard = CalibrationWizard::new(ServoId::Servo1, calibration);
+        wizard.step = WizardStep::SoftMin;
+
+        mark_at(&mut wizard, 700);
+        assert_near(wizard.calibration.soft_min, -1.0);
+
+        mark_at(&mut wizard, 2600);
+        assert_near(wizard.calibration.soft_max, 0.5);
+    }
+}
@@ -3,7 +3,7 @@ use std::sync::{Arc, Mutex};
 use egui::{Response, Ui, Vec2, Widget};
 use egui_addons::joystick::Joystick;
 
-use super::device_states::DeviceStates;
+use super::{calibration_panel::CalibrationPanel, device_states::DeviceStates};
 
 pub struct CentralPanel {
     fps: f32,
@@ -21,7 +21,7 @@ impl Widget for CentralPanel {
         // let mut states = (*self.states.lock().unwrap()).clone();
         // println!("Available rect: {:?}", ui.available_size());
         ui.vertical_centered(|ui| {
-            ui.group(|ui| {
+            let joystick = ui.group(|ui| {
                 let response = ui.label("joystick");
                 response.union(ui.add(Joystick::new(
                     &mut self.states.lock().unwrap().joystick1,
@@ -31,8 +31,10 @@ impl Widget for CentralPanel {
                     self.states.lock().unwrap().joystick1 = Vec2::ZERO;
                 }
                 response
-            })
-            .inner
+            });
+            joystick
+                .inner
+                .union(ui.add(CalibrationPanel::build(self.states.clone())))
         })
         .inner
     }
@@ -1,6 +1,22 @@
 use egui::Vec2;
+use headlight_if::ServoCalibration;
+
+use crate::service::ServoId;
+
+use super::calibration_wizard::CalibrationWizard;
+
+/// Requests from the UI, executed by the main loop on the service.
+#[derive(Clone, Debug)]
+pub enum DeviceCommand {
+    SetServoPulse(ServoId, u32),
+    SetServoCalibration(ServoId, ServoCalibration),
+    ReadServoCalibration(ServoId),
+}
 
 #[derive(Clone, Debug)]
 pub struct DeviceStates {
     pub joystick1: Vec2,
+    pub calibrations: [ServoCalibration; 2],
+    pub wizard: Option<CalibrationWizard>,
+    pub commands: Vec<DeviceCommand>,
 }
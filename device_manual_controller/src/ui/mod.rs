@@ -1,3 +1,5 @@
+pub mod calibration_panel;
+pub mod calibration_wizard;
 pub mod central_panel;
 pub mod device_states;
 pub mod ui_controller;
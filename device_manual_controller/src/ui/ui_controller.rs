@@ -5,7 +5,10 @@ use std::{
 
 use egui::{FontData, FontDefinitions, FontFamily, FontId, TextStyle, Vec2};
 
-use super::{central_panel::CentralPanel, device_states::DeviceStates};
+use super::{
+    central_panel::CentralPanel,
+    device_states::{DeviceCommand, DeviceStates},
+};
 
 pub struct UiController {
     pub states: Arc<Mutex<DeviceStates>>,
@@ -16,11 +19,25 @@ impl UiController {
         Self {
             states: Arc::new(Mutex::new(DeviceStates {
                 joystick1: Vec2::new(0.0, 0.0),
+                calibrations: Default::default(),
+                wizard: None,
+                commands: Vec::new(),
             })),
         }
     }
 
-    pub fn update_data(&self, time_step: Duration) {}
+    pub fn update_data(&self, time_step: Duration) {
+        let mut states = self.states.lock().unwrap();
+        let states = &mut *states;
+
+        if let Some(wizard) = &mut states.wizard {
+            if let Some(pulse_us) = wizard.update(time_step) {
+                states
+                    .commands
+                    .push(DeviceCommand::SetServoPulse(wizard.servo_id, pulse_us));
+            }
+        }
+    }
 
     pub fn get_central_panel(&self, fps: f32) -> CentralPanel {
         CentralPanel::build(fps, self.states.clone())
@@ -1,3 +1,5 @@
+use std::{cmp::Ordering, ops::RangeInclusive};
+
 use egui::WidgetText;
 
 pub fn number_input<T>(
@@ -27,3 +29,27 @@ where
 
     None
 }
+
+/// Like `number_input`, values outside of the range are clamped to it and shown clamped.
+pub fn clamped_number_input<T>(
+    ui: &mut egui::Ui,
+    label: impl Into<WidgetText>,
+    value_text: &mut String,
+    range: RangeInclusive<T>,
+) -> Option<T>
+where
+    T: std::str::FromStr + PartialOrd + Copy + ToString,
+{
+    let value = number_input::<T>(ui, label, value_text)?;
+    // NaN is not comparable and replaced by the start
+    let clamped = match (
+        value.partial_cmp(range.start()),
+        value.partial_cmp(range.end()),
+    ) {
+        (Some(Ordering::Less) | None, _) => *range.start(),
+        (_, Some(Ordering::Greater)) => *range.end(),
+        _ => return Some(value),
+    };
+    *value_text = clamped.to_string();
+    Some(clamped)
+}
@@ -0,0 +1,45 @@
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_sys::EspError;
+use headlight_core::config::ConfigStorage;
+
+/// Stores device config as a single blob in the NVS.
+pub struct NvsConfigStorage {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl NvsConfigStorage {
+    const NAMESPACE: &'static str = "headlight";
+    const CONFIG_KEY: &'static str = "config";
+
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        Ok(Self {
+            nvs: EspNvs::new(partition, Self::NAMESPACE, true)?,
+        })
+    }
+}
+
+impl ConfigStorage for NvsConfigStorage {
+    fn read(&mut self) -> Result<Option<Vec<u8>>, String> {
+        let Some(length) = self
+            .nvs
+            .blob_len(Self::CONFIG_KEY)
+            .map_err(|err| format!("Failed to read config length: {:?}", err))?
+        else {
+            return Ok(None);
+        };
+
+        let mut buffer = vec![0; length];
+        let bytes = self
+            .nvs
+            .get_blob(Self::CONFIG_KEY, &mut buffer)
+            .map_err(|err| format!("Failed to read config: {:?}", err))?;
+
+        Ok(bytes.map(|bytes| bytes.to_vec()))
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.nvs
+            .set_blob(Self::CONFIG_KEY, bytes)
+            .map_err(|err| format!("Failed to write config: {:?}", err))
+    }
+}
@@ -1,20 +1,36 @@
-use std::{net::TcpStream, thread::sleep, time::Duration};
+use std::{
+    net::TcpStream,
+    sync::{
+        mpsc::{self, Sender},
+        Mutex,
+    },
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+mod config_storage;
+mod network;
 mod servo;
+use config_storage::NvsConfigStorage;
 use esp_idf_hal::{
-    delay::FreeRtos,
     ledc::{self, config::TimerConfig},
     peripherals::Peripherals,
     rmt::{FixedLengthSignal, PinState, Pulse, TxRmtConfig, TxRmtDriver},
     units::Hertz,
 };
-use esp_idf_svc::{
-    eventloop::EspSystemEventLoop,
-    mdns::{EspMdns, Interface, Protocol, QueryResult},
-    nvs::EspDefaultNvsPartition,
-    wifi::{ClientConfiguration, Configuration, EspWifi},
+use esp_idf_svc::{eventloop::EspSystemEventLoop, nvs::EspDefaultNvsPartition, wifi::EspWifi};
+use headlight_core::{
+    calibration::{self, CalibratedServo},
+    config,
+    connection::{ConnectionAction, ConnectionEvent, ConnectionManager},
+    motion::ServoMotion,
+    provisioning,
+};
+use headlight_if::{
+    EchoMessage, FlashAckMessage, IdentityMessage, IdentityRequestMessage, Message,
+    ServoCalibration, ServoCalibrationMessage,
 };
-use headlight_if::{EchoMessage, IdentityMessage, IdentityRequestMessage, Message};
 use log::{debug, error, info, warn};
+use network::Network;
 use serializer::ByteMessagePort;
 use servo::*;
 
@@ -34,78 +50,14 @@ fn main() {
     let sys_loop = EspSystemEventLoop::take().unwrap();
     let nvs = EspDefaultNvsPartition::take().unwrap();
 
-    let mut wifi = EspWifi::new(peripherals.modem, sys_loop, Some(nvs)).unwrap();
-
-    let ssid = "D7D433";
-    let password = "8RaycXmTcHcG";
-
-    wifi.set_configuration(&Configuration::Client(ClientConfiguration {
-        ssid: ssid.try_into().unwrap(),
-        password: password.try_into().unwrap(),
-        ..Default::default()
-    }))
-    .unwrap();
-
-    wifi.start().unwrap();
-    wifi.connect().unwrap();
-
-    while !wifi.is_connected().unwrap() {
-        let config = wifi.get_configuration().unwrap();
-        info!("Waiting for station {:?}", config);
-        sleep(Duration::from_secs_f32(0.1));
-    }
-    info!("Should be connected now");
-    info!("IP info: {:?}", wifi.sta_netif().get_ip_info().unwrap());
-
-    let mdns = EspMdns::take().unwrap();
-    let mut results = [QueryResult {
-        instance_name: None,
-        hostname: None,
-        port: 0,
-        txt: Vec::new(),
-        addr: Vec::new(),
-        interface: Interface::STA,
-        ip_protocol: Protocol::V4,
-    }];
-
-    loop {
-        sleep(Duration::from_secs_f32(0.1));
-        let result = mdns.query_srv(
-            "RtAudioEffect",
-            "_RtAudioEffect",
-            "_tcp",
-            Duration::from_secs(5),
-            &mut results,
-        );
-        match result {
-            Ok(count) => {
-                if count == 0 {
-                    warn!("No results found");
-                } else {
-                    info!("Found {:} results: {:?}", count, results[0]);
-                }
-                break;
-            }
-            Err(e) => {
-                error!("Error: {:}", e);
-            }
-        }
-    }
+    let mut config_storage = NvsConfigStorage::new(nvs.clone()).unwrap();
+    let mut device_config = config::load(&mut config_storage);
+    info!("Device config: {:?}", device_config);
 
     let led_pin = peripherals.pins.gpio2;
     let channel = peripherals.rmt.channel0;
     let config = TxRmtConfig::new().clock_divider(1);
     let mut tx = TxRmtDriver::new(channel, led_pin, &config).unwrap();
-    // 3 seconds white at 10% brightness
-    neopixel(Rgb::new(25, 25, 25), &mut tx).unwrap();
-    FreeRtos::delay_ms(3000);
-
-    let ip = results[0].addr.last().unwrap();
-    let port = results[0].port;
-
-    let stream = TcpStream::connect((*ip, port)).unwrap();
-
-    let mut handler = CommandHandler::new(stream);
 
     let timer_driver = ledc::LedcTimerDriver::new(
         peripherals.ledc.timer0,
@@ -115,46 +67,318 @@ fn main() {
     )
     .unwrap();
 
-    let mut servo1 = Servo::new(
+    let servo1 = Servo::new(
         &timer_driver,
         peripherals.ledc.channel0,
         peripherals.pins.gpio3,
     )
     .unwrap();
-    let mut servo2 = Servo::new(
+    let servo2 = Servo::new(
         &timer_driver,
         peripherals.ledc.channel1,
         peripherals.pins.gpio4,
     )
     .unwrap();
 
-    servo1.set_duty(0.5).unwrap();
-    servo2.set_duty(0.5).unwrap();
+    let servos = Mutex::new([
+        new_servo_motion(servo1, device_config.get_servo_calibration(0)),
+        new_servo_motion(servo2, device_config.get_servo_calibration(1)),
+    ]);
+    for servo in servos.lock().unwrap().iter_mut() {
+        servo.set_position(0.5).unwrap();
+    }
 
-    loop {
-        handler.handle(|message| {
-            debug!("Command callback: {:?}", message);
+    let wifi = EspWifi::new(peripherals.modem, sys_loop, Some(nvs)).unwrap();
+    let mut network = Network::new(wifi).unwrap();
+
+    // provisioning events from the setup page and the serial console
+    let (event_sender, event_receiver) = mpsc::channel();
+    let serial_sender = event_sender.clone();
+    thread::Builder::new()
+        .stack_size(SERIAL_THREAD_STACK_SIZE)
+        .spawn(move || serial_console(serial_sender))
+        .unwrap();
+
+    let mut connection = ConnectionManager::new(device_config.get_wifi_credentials().cloned())
+        .with_preferred_host(device_config.get_preferred_host().map(String::from));
+    let mut stream: Option<TcpStream> = None;
+    let mut replies: Vec<Message> = Vec::new();
+    // origin of timestamps reported in flash acks
+    let boot = Instant::now();
+
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(MOTION_THREAD_STACK_SIZE)
+            .spawn_scoped(scope, || motion_loop(&servos))
+            .unwrap();
+
+        let mut action = connection.start();
+        loop {
+            info!("Connection action: {:?}", action);
+
+            let event = match action {
+                ConnectionAction::StartProvisioning(timeout) => {
+                    neopixel(PROVISIONING_COLOR, &mut tx).unwrap();
+                    if let Err(err) = network.start_provisioning(event_sender.clone()) {
+                        error!("Failed to start provisioning: {}", err);
+                    }
+                    info!("Waiting for credentials, send `wifi <ssid> <password>` over serial");
 
-            match message {
-                Message::SetColor(set_color) => {
-                    let rgb = Rgb::new(set_color.r, set_color.g, set_color.b);
-                    neopixel(rgb, &mut tx).unwrap();
+                    let event = match timeout {
+                        Some(timeout) => event_receiver.recv_timeout(timeout).ok(),
+                        None => event_receiver.recv().ok(),
+                    };
+                    event.unwrap_or(ConnectionEvent::TimedOut)
+                }
+                ConnectionAction::ConnectWifi(credentials) => {
+                    match network.connect_wifi(&credentials) {
+                        Ok(_) => ConnectionEvent::WifiConnected,
+                        Err(err) => {
+                            error!("{}", err);
+                            ConnectionEvent::WifiFailed
+                        }
+                    }
+                }
+                ConnectionAction::Wait(delay) => event_receiver
+                    .recv_timeout(delay)
+                    .unwrap_or(ConnectionEvent::TimedOut),
+                ConnectionAction::DiscoverService => {
+                    if !network.is_wifi_connected() {
+                        ConnectionEvent::WifiLost
+                    } else {
+                        match network.discover_services() {
+                            Ok(hosts) => ConnectionEvent::ServicesFound(hosts),
+                            Err(err) => {
+                                error!("{}", err);
+                                ConnectionEvent::ServicesFound(Vec::new())
+                            }
+                        }
+                    }
                 }
-                Message::SetServo(set_servo) => {
-                    let duty = set_servo.position;
-                    if set_servo.id == 0 {
-                        servo1.set_duty(duty).unwrap();
-                    } else if set_servo.id == 1 {
-                        servo2.set_duty(duty).unwrap();
+                ConnectionAction::ConnectService(address) => {
+                    match TcpStream::connect_timeout(&address, SERVICE_CONNECT_TIMEOUT) {
+                        Ok(connected) => {
+                            stream = Some(connected);
+                            ConnectionEvent::ServiceConnected
+                        }
+                        Err(err) => {
+                            error!("Failed to connect to {}: {:?}", address, err);
+                            ConnectionEvent::ServiceFailed
+                        }
                     }
                 }
-                _ => {
-                    debug!("Unhandled message: {:?}", message);
+                ConnectionAction::Serve(timeout) => {
+                    neopixel(CONNECTED_COLOR, &mut tx).unwrap();
+                    let stream = stream.take().unwrap();
+                    // a hung host would block the receive forever
+                    if let Err(err) = stream.set_read_timeout(Some(timeout)) {
+                        error!("Failed to set read timeout: {:?}", err);
+                    }
+                    let mut handler = CommandHandler::new(stream);
+                    let mut last_received = Instant::now();
+
+                    loop {
+                        let result = handler.handle(|message| {
+                            debug!("Command callback: {:?}", message);
+
+                            match message {
+                                Message::SetColor(set_color) => {
+                                    let rgb = Rgb::new(set_color.r, set_color.g, set_color.b);
+                                    neopixel(rgb, &mut tx).unwrap();
+                                }
+                                Message::Flash(flash) => {
+                                    let received_us = boot.elapsed().as_micros() as u64;
+                                    let rgb = Rgb::new(flash.r, flash.g, flash.b);
+                                    neopixel(rgb, &mut tx).unwrap();
+                                    replies.push(Message::FlashAck(FlashAckMessage {
+                                        id: flash.id,
+                                        received_us,
+                                        lit_us: boot.elapsed().as_micros() as u64,
+                                    }));
+                                }
+                                Message::SetServo(set_servo) => {
+                                    with_servo(&servos, set_servo.id, |servo| {
+                                        servo.set_position(set_servo.position).unwrap();
+                                    })
+                                }
+                                Message::ServoMove(servo_move) => {
+                                    with_servo(&servos, servo_move.id, |servo| {
+                                        if let Err(err) = servo.move_to(
+                                            servo_move.target,
+                                            Duration::from_millis(servo_move.duration_ms as u64),
+                                            servo_move.easing,
+                                        ) {
+                                            warn!("Dropping ServoMove: {}", err);
+                                        }
+                                    })
+                                }
+                                Message::ServoPath(servo_path) => {
+                                    with_servo(&servos, servo_path.id, |servo| {
+                                        if let Err(err) = servo
+                                            .follow_path(&servo_path.keyframes, servo_path.looping)
+                                        {
+                                            warn!("Dropping ServoPath: {}", err);
+                                        }
+                                    })
+                                }
+                                Message::SetServoPulse(set_pulse) => {
+                                    with_servo(&servos, set_pulse.id, |servo| {
+                                        servo
+                                            .set_raw_pulse_width(set_pulse.pulse_us as f32)
+                                            .unwrap();
+                                    })
+                                }
+                                Message::ServoCalibrationRequest(request) => {
+                                    with_servo(&servos, request.id, |servo| {
+                                        replies.push(Message::ServoCalibration(
+                                            ServoCalibrationMessage {
+                                                id: request.id,
+                                                calibration: servo.get_calibration().clone(),
+                                            },
+                                        ));
+                                    })
+                                }
+                                Message::SetServoCalibration(calibration_message) => {
+                                    let id = calibration_message.id;
+                                    let calibration = &calibration_message.calibration;
+                                    if let Err(err) = calibration::validate(calibration) {
+                                        error!("Invalid calibration of servo {}: {}", id, err);
+                                        return None;
+                                    }
+
+                                    with_servo(&servos, id, |servo| {
+                                        servo.set_calibration(calibration.clone()).unwrap();
+                                        device_config
+                                            .set_servo_calibration(id, calibration.clone());
+                                        if let Err(err) =
+                                            config::store(&mut config_storage, &device_config)
+                                        {
+                                            error!("Failed to store config: {}", err);
+                                        }
+                                    });
+                                }
+                                // only keeps the read timeout from expiring
+                                Message::Keepalive(_) => {}
+                                _ => {
+                                    debug!("Unhandled message: {:?}", message);
+                                }
+                            }
+
+                            Some(())
+                        });
+
+                        if let Err(err) = result {
+                            warn!("Connection lost: {}", err);
+                            break;
+                        }
+                        last_received = Instant::now();
+
+                        for reply in replies.drain(..) {
+                            if let Err(err) = handler.send_message(reply) {
+                                error!("Failed to send reply: {}", err);
+                            }
+                        }
+                    }
+
+                    if !network.is_wifi_connected() {
+                        ConnectionEvent::WifiLost
+                    } else if last_received.elapsed() >= timeout {
+                        ConnectionEvent::ServiceTimedOut
+                    } else {
+                        ConnectionEvent::ServiceLost
+                    }
+                }
+            };
+
+            let config_changed = match &event {
+                ConnectionEvent::CredentialsReceived(credentials) => {
+                    device_config.wifi = credentials.clone();
+                    true
+                }
+                ConnectionEvent::PreferredHostChanged(preferred) => {
+                    device_config.preferred_host = preferred.clone().unwrap_or_default();
+                    true
+                }
+                _ => false,
+            };
+            if config_changed {
+                if let Err(err) = config::store(&mut config_storage, &device_config) {
+                    error!("Failed to store config: {}", err);
                 }
             }
+            action = connection.handle(event);
+        }
+    });
+}
 
-            Some(())
-        });
+/// Reads provisioning commands from the serial console.
+fn serial_console(sender: Sender<ConnectionEvent>) {
+    let mut line = String::new();
+    loop {
+        // stdin doesn't block on esp-idf, so partial lines are accumulated
+        match std::io::stdin().read_line(&mut line) {
+            Ok(_) if line.ends_with('\n') => {}
+            _ => {
+                sleep(SERIAL_POLL_PERIOD);
+                continue;
+            }
+        }
+
+        match provisioning::parse_serial_command(&line) {
+            Ok(event) => {
+                info!("Serial command accepted");
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+            Err(err) => warn!("{}", err),
+        }
+        line.clear();
+    }
+}
+
+type HeadlightServo<'d> = ServoMotion<CalibratedServo<Servo<'d>>>;
+
+const MOTION_UPDATE_PERIOD: Duration = Duration::from_millis(10);
+const MOTION_THREAD_STACK_SIZE: usize = 4096;
+const SERIAL_THREAD_STACK_SIZE: usize = 4096;
+const SERIAL_POLL_PERIOD: Duration = Duration::from_millis(100);
+const SERVICE_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// 10% brightness
+const PROVISIONING_COLOR: Rgb = Rgb { r: 0, g: 0, b: 25 };
+const CONNECTED_COLOR: Rgb = Rgb {
+    r: 25,
+    g: 25,
+    b: 25,
+};
+
+fn new_servo_motion(servo: Servo, calibration: ServoCalibration) -> HeadlightServo {
+    let limits = calibration::motion_limits(&calibration);
+    ServoMotion::new(CalibratedServo::new(servo, calibration), limits)
+}
+
+fn with_servo<F>(servos: &Mutex<[HeadlightServo; 2]>, id: u8, action: F)
+where
+    F: FnOnce(&mut HeadlightServo),
+{
+    match servos.lock().unwrap().get_mut(id as usize) {
+        Some(servo) => action(servo),
+        None => warn!("Unknown servo id: {}", id),
+    }
+}
+
+fn motion_loop(servos: &Mutex<[HeadlightServo; 2]>) {
+    let mut last_update = Instant::now();
+    loop {
+        sleep(MOTION_UPDATE_PERIOD);
+        let time_step = last_update.elapsed();
+        last_update = Instant::now();
+
+        for servo in servos.lock().unwrap().iter_mut() {
+            if let Err(err) = servo.update(time_step) {
+                error!("Failed to update servo: {:?}", err);
+            }
+        }
     }
 }
 
@@ -169,17 +393,10 @@ impl CommandHandler {
         }
     }
 
-    fn recv_message(&mut self) -> Message {
-        match self.message_port.recv() {
-            Ok(message) => {
-                debug!("Received message: {:?}", message);
-                return message;
-            }
-            Err(err) => {
-                error!("Failed to receive message: {:?}", err);
-                return Message::Invalid;
-            }
-        }
+    fn recv_message(&mut self) -> Result<Message, String> {
+        let message = self.message_port.recv()?;
+        debug!("Received message: {:?}", message);
+        Ok(message)
     }
 
     pub fn send_message(&mut self, message: Message) -> Result<(), String> {
@@ -192,26 +409,34 @@ impl CommandHandler {
         Ok(())
     }
 
-    pub fn handle<F>(&mut self, mut callback: F) -> Option<()>
+    /// Receives and handles single message. Fails when the connection is broken.
+    pub fn handle<F>(&mut self, callback: F) -> Result<Option<()>, String>
+    where
+        F: FnMut(&Message) -> Option<()>,
+    {
+        let message = self.recv_message()?;
+        Ok(self.dispatch(&message, callback))
+    }
+
+    fn dispatch<F>(&mut self, message: &Message, mut callback: F) -> Option<()>
     where
         F: FnMut(&Message) -> Option<()>,
     {
-        let message = self.recv_message();
-        match &message {
+        match message {
             Message::Invalid => {
                 error!("Invalid message");
-                return callback(&message);
+                return callback(message);
             }
 
             Message::Ack => {
                 warn!("Ack not implemented");
-                return callback(&message);
+                return callback(message);
             }
 
-            Message::Echo(echo_message) => match self.handle_echo(&echo_message) {
+            Message::Echo(echo_message) => match self.handle_echo(echo_message) {
                 Ok(_) => {
                     debug!("Echo handled");
-                    return callback(&message);
+                    return callback(message);
                 }
                 Err(err) => {
                     error!("Failed to handle echo: {:?}", err);
@@ -219,10 +444,10 @@ impl CommandHandler {
                 }
             },
 
-            Message::IdentityRequest(request) => match self.handle_identity_request(&request) {
+            Message::IdentityRequest(request) => match self.handle_identity_request(request) {
                 Ok(_) => {
                     debug!("Identity handled");
-                    return callback(&message);
+                    return callback(message);
                 }
                 Err(err) => {
                     error!("Failed to handle identity: {:?}", err);
@@ -235,7 +460,7 @@ impl CommandHandler {
                     "Unhandled message by handler: {:?}. Calling callback",
                     message
                 );
-                return callback(&message);
+                return callback(message);
             }
         };
     }
@@ -0,0 +1,201 @@
+use std::{
+    net::SocketAddr,
+    sync::mpsc::Sender,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use esp_idf_svc::{
+    http::{
+        server::{Configuration as HttpServerConfiguration, EspHttpServer},
+        Method,
+    },
+    io::{Read, Write},
+    mdns::{EspMdns, Interface, Protocol, QueryResult},
+    wifi::{AccessPointConfiguration, AuthMethod, ClientConfiguration, Configuration, EspWifi},
+};
+use esp_idf_sys::EspError;
+use headlight_core::{
+    config::WifiCredentials,
+    connection::ConnectionEvent,
+    discovery::ServiceHost,
+    provisioning::{self, ACCESS_POINT_SSID, SETUP_DONE_PAGE, SETUP_PAGE},
+};
+use log::{info, warn};
+
+/// Wi-Fi station, soft-AP with the setup page and mDNS discovery of the PC app.
+pub struct Network<'d> {
+    wifi: EspWifi<'d>,
+    mdns: EspMdns,
+    setup_server: Option<EspHttpServer<'static>>,
+}
+
+impl<'d> Network<'d> {
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+    const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+    const POLL_PERIOD: Duration = Duration::from_millis(100);
+    const MAX_FORM_SIZE: usize = 512;
+    const MAX_HOSTS: usize = 8;
+
+    pub fn new(wifi: EspWifi<'d>) -> Result<Self, EspError> {
+        Ok(Self {
+            wifi,
+            mdns: EspMdns::take()?,
+            setup_server: None,
+        })
+    }
+
+    pub fn connect_wifi(&mut self, credentials: &WifiCredentials) -> Result<(), String> {
+        self.setup_server = None;
+        self.restart(Configuration::Client(ClientConfiguration {
+            ssid: credentials
+                .ssid
+                .as_str()
+                .try_into()
+                .map_err(|_| String::from("SSID too long"))?,
+            password: credentials
+                .password
+                .as_str()
+                .try_into()
+                .map_err(|_| String::from("Password too long"))?,
+            auth_method: if credentials.password.is_empty() {
+                AuthMethod::None
+            } else {
+                AuthMethod::WPA2Personal
+            },
+            ..Default::default()
+        }))?;
+
+        self.wifi
+            .connect()
+            .map_err(|err| format!("Failed to connect: {:?}", err))?;
+
+        let start = Instant::now();
+        while !self.wifi.is_up().unwrap_or(false) {
+            if start.elapsed() > Self::CONNECT_TIMEOUT {
+                let _ = self.wifi.disconnect();
+                return Err(format!("Failed to connect to {}", credentials.ssid));
+            }
+            sleep(Self::POLL_PERIOD);
+        }
+        info!("IP info: {:?}", self.wifi.sta_netif().get_ip_info());
+
+        Ok(())
+    }
+
+    pub fn is_wifi_connected(&self) -> bool {
+        self.wifi.is_connected().unwrap_or(false)
+    }
+
+    /// Starts open soft-AP with the setup page. Submitted credentials are sent to the `sender`.
+    pub fn start_provisioning(&mut self, sender: Sender<ConnectionEvent>) -> Result<(), String> {
+        if self.setup_server.is_some() {
+            return Ok(());
+        }
+
+        self.restart(Configuration::AccessPoint(AccessPointConfiguration {
+            ssid: ACCESS_POINT_SSID.try_into().unwrap(),
+            auth_method: AuthMethod::None,
+            ..Default::default()
+        }))?;
+        info!("Access point {} started", ACCESS_POINT_SSID);
+
+        let mut server = EspHttpServer::new(&HttpServerConfiguration::default())
+            .map_err(|err| format!("Failed to start setup server: {:?}", err))?;
+
+        server
+            .fn_handler("/", Method::Get, |request| {
+                request.into_ok_response()?.write_all(SETUP_PAGE.as_bytes())
+            })
+            .map_err(|err| format!("Failed to register setup page: {:?}", err))?;
+
+        server
+            .fn_handler("/", Method::Post, move |mut request| {
+                let mut body = vec![0; Self::MAX_FORM_SIZE];
+                let mut length = 0;
+                while length < body.len() {
+                    let read = request
+                        .read(&mut body[length..])
+                        .map_err(|err| format!("{:?}", err))?;
+                    if read == 0 {
+                        break;
+                    }
+                    length += read;
+                }
+
+                let body = String::from_utf8_lossy(&body[..length]);
+                let credentials = provisioning::parse_setup_form(&body)?;
+                info!("Received credentials for {}", credentials.ssid);
+                sender
+                    .send(ConnectionEvent::CredentialsReceived(credentials))
+                    .map_err(|err| err.to_string())?;
+
+                request
+                    .into_ok_response()
+                    .map_err(|err| format!("{:?}", err))?
+                    .write_all(SETUP_DONE_PAGE.as_bytes())
+                    .map_err(|err| format!("{:?}", err))
+            })
+            .map_err(|err| format!("Failed to register setup form: {:?}", err))?;
+
+        self.setup_server = Some(server);
+        Ok(())
+    }
+
+    /// Browses all advertised RtAudioEffect instances.
+    pub fn discover_services(&mut self) -> Result<Vec<ServiceHost>, String> {
+        let mut results: [QueryResult; Self::MAX_HOSTS] = std::array::from_fn(|_| QueryResult {
+            instance_name: None,
+            hostname: None,
+            port: 0,
+            txt: Vec::new(),
+            addr: Vec::new(),
+            interface: Interface::STA,
+            ip_protocol: Protocol::V4,
+        });
+
+        let count = self
+            .mdns
+            .query_ptr(
+                "_RtAudioEffect",
+                "_tcp",
+                Self::DISCOVERY_TIMEOUT,
+                Self::MAX_HOSTS,
+                &mut results,
+            )
+            .map_err(|err| format!("mDNS query failed: {:?}", err))?;
+
+        if count == 0 {
+            warn!("No results found");
+        }
+
+        let hosts = results[..count.min(Self::MAX_HOSTS)]
+            .iter()
+            .filter_map(|result| {
+                info!("Found: {:?}", result);
+                let ip = result.addr.last()?;
+                let instance_name = result.instance_name.as_deref().unwrap_or_default();
+                Some(ServiceHost::from_txt(
+                    instance_name,
+                    SocketAddr::new(*ip, result.port),
+                    &result.txt,
+                ))
+            })
+            .collect();
+        Ok(hosts)
+    }
+
+    fn restart(&mut self, configuration: Configuration) -> Result<(), String> {
+        if self.wifi.is_started().unwrap_or(false) {
+            self.wifi
+                .stop()
+                .map_err(|err| format!("Failed to stop Wi-Fi: {:?}", err))?;
+        }
+        self.wifi
+            .set_configuration(&configuration)
+            .map_err(|err| format!("Failed to configure Wi-Fi: {:?}", err))?;
+        self.wifi
+            .start()
+            .map_err(|err| format!("Failed to start Wi-Fi: {:?}", err))
+    }
+}
@@ -6,15 +6,18 @@ use esp_idf_hal::{
     peripheral::Peripheral,
 };
 use esp_idf_sys::EspError;
+use headlight_core::calibration::PulseOutput;
 
 pub struct Servo<'d> {
     driver: LedcDriver<'d>,
-    min_limit: f32,
-    max_limit: f32,
+    max_duty: u32,
     _p: PhantomData<&'d mut ()>,
 }
 
 impl<'d> Servo<'d> {
+    // Timer has to be configured to 50Hz
+    const PERIOD_US: f32 = 20_000.0;
+
     pub fn new<Channel, Timer, TimerDriver>(
         timer_driver: TimerDriver,
         channel: impl Peripheral<P = Channel> + 'd,
@@ -27,24 +30,27 @@ impl<'d> Servo<'d> {
     {
         let driver = LedcDriver::new(channel, timer_driver, pin)?;
         let max_duty = driver.get_max_duty();
-        let min_limit = max_duty as f32 * 0.025; // 25ms
-        let max_limit = max_duty as f32 * 0.125; // 125ms
         Ok(Self {
             driver,
-            min_limit,
-            max_limit,
+            max_duty,
             _p: PhantomData,
         })
     }
 
-    pub fn set_duty(&mut self, set_point: f32) -> Result<(), EspError> {
-        self.driver.set_duty(self.map(set_point))
+    pub fn set_pulse_width(&mut self, pulse_width_us: f32) -> Result<(), EspError> {
+        self.driver.set_duty(self.map(pulse_width_us))
+    }
+
+    fn map(&self, pulse_width_us: f32) -> u32 {
+        let duty = pulse_width_us.max(0.0).min(Self::PERIOD_US) / Self::PERIOD_US; // clamp
+        (self.max_duty as f32 * duty) as u32
     }
+}
+
+impl<'d> PulseOutput for Servo<'d> {
+    type Error = EspError;
 
-    fn map(&self, set_point: f32) -> u32 {
-        let set_point = set_point.max(-1.0).min(1.0); // clamp
-        let set_point = (set_point + 1.0) / 2.0; // normalize
-        let range = self.max_limit - self.min_limit; // calculate range
-        (self.min_limit as f32 + range as f32 * set_point) as u32 //
+    fn set_pulse_width(&mut self, pulse_width_us: f32) -> Result<(), EspError> {
+        Servo::set_pulse_width(self, pulse_width_us)
     }
 }
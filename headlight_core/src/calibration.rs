@@ -0,0 +1,276 @@
+use headlight_if::ServoCalibration;
+
+use crate::{
+    motion::{ServoDriver, ServoMotion},
+    trajectory::MotionLimits,
+};
+
+/// Raw PWM output of a servo. Implemented by the firmware LEDC driver.
+pub trait PulseOutput {
+    type Error;
+
+    fn set_pulse_width(&mut self, pulse_width_us: f32) -> Result<(), Self::Error>;
+}
+
+/// Checks ranges of all fields. Comparisons are written to fail for NaN too.
+pub fn validate(calibration: &ServoCalibration) -> Result<(), String> {
+    if calibration.min_pulse_us >= calibration.max_pulse_us {
+        return Err(format!(
+            "Min pulse ({} us) has to be lower than max pulse ({} us)",
+            calibration.min_pulse_us, calibration.max_pulse_us
+        ));
+    }
+    if !((-1.0..=1.0).contains(&calibration.soft_min)
+        && (-1.0..=1.0).contains(&calibration.soft_max)
+        && calibration.soft_min < calibration.soft_max)
+    {
+        return Err(format!(
+            "Invalid soft limits: [{}, {}]",
+            calibration.soft_min, calibration.soft_max
+        ));
+    }
+    if !(-1.0..=1.0).contains(&calibration.center_trim) {
+        return Err(format!("Invalid center trim: {}", calibration.center_trim));
+    }
+    if !(calibration.max_speed.is_finite() && calibration.max_speed > 0.0) {
+        return Err(format!("Invalid max speed: {}", calibration.max_speed));
+    }
+    Ok(())
+}
+
+/// Motion limits of a servo with given calibration. Acceleration is not calibrated.
+pub fn motion_limits(calibration: &ServoCalibration) -> MotionLimits {
+    MotionLimits {
+        max_velocity: calibration.max_speed,
+        min_position: calibration.soft_min,
+        max_position: calibration.soft_max,
+        ..Default::default()
+    }
+}
+
+/// Maps set point into pulse width.
+/// Soft limits are applied first, then direction and trim, so limits don't move with the trim.
+pub fn pulse_width_us(calibration: &ServoCalibration, set_point: f32) -> f32 {
+    let set_point = set_point.clamp(calibration.soft_min, calibration.soft_max);
+    let set_point = if calibration.inverted {
+        -set_point
+    } else {
+        set_point
+    };
+    let set_point = (set_point + calibration.center_trim).clamp(-1.0, 1.0);
+    let set_point = (set_point + 1.0) / 2.0; // normalize
+
+    let min_pulse = calibration.min_pulse_us as f32;
+    let max_pulse = calibration.max_pulse_us as f32;
+    min_pulse + (max_pulse - min_pulse) * set_point
+}
+
+pub struct CalibratedServo<P: PulseOutput> {
+    output: P,
+    calibration: ServoCalibration,
+}
+
+impl<P: PulseOutput> CalibratedServo<P> {
+    pub fn new(output: P, calibration: ServoCalibration) -> Self {
+        Self {
+            output,
+            calibration,
+        }
+    }
+
+    pub fn get_calibration(&self) -> &ServoCalibration {
+        &self.calibration
+    }
+
+    pub fn set_calibration(&mut self, calibration: ServoCalibration) {
+        self.calibration = calibration;
+    }
+
+    /// Bypasses calibration. Used while searching for the servo endpoints.
+    pub fn set_raw_pulse_width(&mut self, pulse_width_us: f32) -> Result<(), P::Error> {
+        self.output.set_pulse_width(pulse_width_us)
+    }
+}
+
+impl<P: PulseOutput> ServoDriver for CalibratedServo<P> {
+    type Error = P::Error;
+
+    fn set_duty(&mut self, set_point: f32) -> Result<(), Self::Error> {
+        let pulse_width = pulse_width_us(&self.calibration, set_point);
+        self.output.set_pulse_width(pulse_width)
+    }
+}
+
+impl<P: PulseOutput> ServoMotion<CalibratedServo<P>> {
+    pub fn get_calibration(&self) -> &ServoCalibration {
+        self.get_driver().get_calibration()
+    }
+
+    /// Replaces calibration and motion limits. Servo is moved back into new soft limits.
+    pub fn set_calibration(&mut self, calibration: ServoCalibration) -> Result<(), P::Error> {
+        self.set_limits(motion_limits(&calibration));
+        self.get_driver_mut().set_calibration(calibration);
+        self.set_position(self.get_position())
+    }
+
+    pub fn set_raw_pulse_width(&mut self, pulse_width_us: f32) -> Result<(), P::Error> {
+        self.stop();
+        self.get_driver_mut().set_raw_pulse_width(pulse_width_us)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use headlight_if::Easing;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct OutputMock {
+        pulses: Vec<f32>,
+    }
+
+    impl PulseOutput for &mut OutputMock {
+        type Error = ();
+
+        fn set_pulse_width(&mut self, pulse_width_us: f32) -> Result<(), Self::Error> {
+            self.pulses.push(pulse_width_us);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_default_mapping() {
+        let calibration = ServoCalibration::default();
+
+        assert_eq!(pulse_width_us(&calibration, -1.0), 500.0);
+        assert_eq!(pulse_width_us(&calibration, 0.0), 1500.0);
+        assert_eq!(pulse_width_us(&calibration, 1.0), 2500.0);
+        assert_eq!(pulse_width_us(&calibration, 2.0), 2500.0);
+    }
+
+    #[test]
+    fn test_calibrated_mapping() {
+        let calibration = ServoCalibration {
+            min_pulse_us: 1000,
+            max_pulse_us: 2000,
+            center_trim: 0.1,
+            soft_min: -0.5,
+            soft_max: 0.8,
+            inverted: true,
+            ..Default::default()
+        };
+
+        // inverted: 0.5 => -0.5, trim => -0.4
+        assert_eq!(pulse_width_us(&calibration, 0.5), 1300.0);
+        // soft limit: -1.0 => -0.5, inverted => 0.5, trim => 0.6
+        assert_eq!(pulse_width_us(&calibration, -1.0), 1800.0);
+        // soft limit: 1.0 => 0.8, inverted => -0.8, trim => -0.7
+        assert!((pulse_width_us(&calibration, 1.0) - 1150.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&ServoCalibration::default()).is_ok());
+
+        let calibration = ServoCalibration {
+            min_pulse_us: 2000,
+            max_pulse_us: 1000,
+            ..Default::default()
+        };
+        assert!(validate(&calibration).is_err());
+
+        let calibration = ServoCalibration {
+            soft_min: 0.5,
+            soft_max: 0.5,
+            ..Default::default()
+        };
+        assert!(validate(&calibration).is_err());
+
+        let calibration = ServoCalibration {
+            max_speed: 0.0,
+            ..Default::default()
+        };
+        assert!(validate(&calibration).is_err());
+
+        for invalid in [f32::NAN, f32::INFINITY] {
+            let calibrations = [
+                ServoCalibration {
+                    center_trim: invalid,
+                    ..Default::default()
+                },
+                ServoCalibration {
+                    soft_min: invalid,
+                    ..Default::default()
+                },
+                ServoCalibration {
+                    soft_max: invalid,
+                    ..Default::default()
+                },
+                ServoCalibration {
+                    max_speed: invalid,
+                    ..Default::default()
+                },
+            ];
+            for calibration in calibrations {
+                assert!(validate(&calibration).is_err(), "{:?}", calibration);
+            }
+        }
+    }
+
+    #[test]
+    fn test_motion_respects_calibration() {
+        let mut output = OutputMock::default();
+        let calibration = ServoCalibration {
+            soft_min: -0.5,
+            soft_max: 0.5,
+            max_speed: 1.0,
+            ..Default::default()
+        };
+        let mut motion = ServoMotion::new(
+            CalibratedServo::new(&mut output, ServoCalibration::default()),
+            MotionLimits::default(),
+        );
+        motion.set_position(1.0).unwrap();
+        motion.set_calibration(calibration).unwrap();
+        assert_eq!(motion.get_position(), 0.5);
+
+        motion
+            .move_to(-1.0, Duration::ZERO, Easing::Linear)
+            .unwrap();
+        for _ in 0..200 {
+            motion.update(Duration::from_millis(10)).unwrap();
+        }
+        assert_eq!(motion.get_position(), -0.5);
+        drop(motion);
+
+        // 1.0 => 0.5 soft limit, then linear move with 1.0/s max speed
+        assert_eq!(output.pulses[0], 2500.0);
+        assert_eq!(output.pulses[1], 2000.0);
+        assert_eq!(*output.pulses.last().unwrap(), 1000.0);
+        output.pulses[1..].windows(2).for_each(|pair| {
+            // 1.0 units/s => 1000 us/s => 10 us per 10 ms
+            assert!((pair[0] - pair[1]).abs() <= 10.0 + 1e-2);
+        });
+    }
+
+    #[test]
+    fn test_raw_pulse_width_bypasses_calibration() {
+        let mut output = OutputMock::default();
+        let mut motion = ServoMotion::new(
+            CalibratedServo::new(&mut output, ServoCalibration::default()),
+            MotionLimits::default(),
+        );
+
+        motion
+            .move_to(1.0, Duration::from_secs(1), Easing::Linear)
+            .unwrap();
+        motion.set_raw_pulse_width(3000.0).unwrap();
+        assert!(!motion.is_moving());
+        drop(motion);
+
+        assert_eq!(output.pulses, vec![3000.0]);
+    }
+}
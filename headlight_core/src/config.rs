@@ -0,0 +1,291 @@
+use headlight_if::ServoCalibration;
+use log::{info, warn};
+use serializer::ByteMessage;
+
+use crate::calibration;
+
+#[derive(ByteMessage, Default, Debug, Clone, PartialEq)]
+pub struct WifiCredentials {
+    pub ssid: String,
+    pub password: String,
+}
+
+/// Persistent configuration of the device.
+#[derive(ByteMessage, Debug, Clone, PartialEq)]
+pub struct DeviceConfig {
+    pub version: u32,
+    pub servos: Vec<ServoCalibration>,
+    /// Instance id of the RtAudioEffect host to prefer, empty for none.
+    pub preferred_host: String,
+    // nested struct consumes all remaining bytes, so it has to be the last field
+    pub wifi: WifiCredentials,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            version: Self::VERSION,
+            servos: Vec::new(),
+            preferred_host: String::new(),
+            wifi: WifiCredentials::default(),
+        }
+    }
+}
+
+impl DeviceConfig {
+    /// Has to be increased whenever layout of the config changes, the previous layout is kept
+    /// so `load` can migrate it.
+    pub const VERSION: u32 = 3;
+
+    pub fn get_servo_calibration(&self, id: u8) -> ServoCalibration {
+        self.servos.get(id as usize).cloned().unwrap_or_default()
+    }
+
+    pub fn set_servo_calibration(&mut self, id: u8, calibration: ServoCalibration) {
+        let id = id as usize;
+        if self.servos.len() <= id {
+            self.servos.resize(id + 1, ServoCalibration::default());
+        }
+        self.servos[id] = calibration;
+    }
+
+    pub fn get_preferred_host(&self) -> Option<&str> {
+        if self.preferred_host.is_empty() {
+            None
+        } else {
+            Some(&self.preferred_host)
+        }
+    }
+
+    /// None when the device hasn't been provisioned yet.
+    pub fn get_wifi_credentials(&self) -> Option<&WifiCredentials> {
+        if self.wifi.ssid.is_empty() {
+            None
+        } else {
+            Some(&self.wifi)
+        }
+    }
+}
+
+/// Layout of version 1, before Wi-Fi credentials.
+#[derive(ByteMessage, Default)]
+struct DeviceConfigV1 {
+    version: u32,
+    servos: Vec<ServoCalibration>,
+}
+
+/// Layout of version 2, before the preferred host.
+#[derive(ByteMessage, Default)]
+struct DeviceConfigV2 {
+    version: u32,
+    servos: Vec<ServoCalibration>,
+    wifi: WifiCredentials,
+}
+
+/// Non-volatile storage for a single config blob. Implemented by the firmware with NVS.
+pub trait ConfigStorage {
+    fn read(&mut self) -> Result<Option<Vec<u8>>, String>;
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String>;
+}
+
+/// Loads config from the storage. Falls back to default when there is no valid config stored,
+/// invalid servo calibrations are replaced with the default one. Older versions are migrated,
+/// fields they don't have are left default.
+pub fn load(storage: &mut impl ConfigStorage) -> DeviceConfig {
+    let bytes = match storage.read() {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => {
+            info!("No config stored, using default");
+            return DeviceConfig::default();
+        }
+        Err(err) => {
+            warn!("Failed to read config: {}, using default", err);
+            return DeviceConfig::default();
+        }
+    };
+
+    let mut version = 0u32;
+    if let Err(err) = version.from_bytes(bytes.clone()) {
+        warn!("Failed to parse config version: {}, using default", err);
+        return DeviceConfig::default();
+    }
+    let parsed = match version {
+        1 => parse::<DeviceConfigV1>(bytes).map(|old| DeviceConfig {
+            servos: old.servos,
+            ..DeviceConfig::default()
+        }),
+        2 => parse::<DeviceConfigV2>(bytes).map(|old| DeviceConfig {
+            servos: old.servos,
+            wifi: old.wifi,
+            ..DeviceConfig::default()
+        }),
+        DeviceConfig::VERSION => parse::<DeviceConfig>(bytes),
+        _ => Err(format!("Unknown version {}", version)),
+    };
+    let mut config = match parsed {
+        Ok(config) => config,
+        Err(err) => {
+            warn!("Failed to parse config: {}, using default", err);
+            return DeviceConfig::default();
+        }
+    };
+    if version != DeviceConfig::VERSION {
+        info!(
+            "Migrated config from version {} to {}",
+            version,
+            DeviceConfig::VERSION
+        );
+    }
+
+    for (id, servo) in config.servos.iter_mut().enumerate() {
+        if let Err(err) = calibration::validate(servo) {
+            warn!(
+                "Invalid calibration of servo {}: {}, using default",
+                id, err
+            );
+            *servo = ServoCalibration::default();
+        }
+    }
+    config
+}
+
+fn parse<T: ByteMessage + Default>(bytes: Vec<u8>) -> Result<T, String> {
+    let mut value = T::default();
+    value.from_bytes(bytes)?;
+    Ok(value)
+}
+
+pub fn store(storage: &mut impl ConfigStorage, config: &DeviceConfig) -> Result<(), String> {
+    storage.write(&config.to_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct StorageMock {
+        bytes: Option<Vec<u8>>,
+    }
+
+    impl ConfigStorage for StorageMock {
+        fn read(&mut self) -> Result<Option<Vec<u8>>, String> {
+            Ok(self.bytes.clone())
+        }
+
+        fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+            self.bytes = Some(bytes.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let mut storage = StorageMock::default();
+        assert_eq!(load(&mut storage), DeviceConfig::default());
+
+        let mut config = DeviceConfig::default();
+        config.set_servo_calibration(
+            1,
+            ServoCalibration {
+                min_pulse_us: 800,
+                inverted: true,
+                ..Default::default()
+            },
+        );
+        config.wifi = WifiCredentials {
+            ssid: String::from("ssid"),
+            password: String::from("password"),
+        };
+        config.preferred_host = String::from("main");
+        store(&mut storage, &config).unwrap();
+
+        let loaded = load(&mut storage);
+        assert_eq!(loaded, config);
+        assert_eq!(loaded.get_servo_calibration(0), ServoCalibration::default());
+        assert_eq!(loaded.get_servo_calibration(1).min_pulse_us, 800);
+        assert_eq!(loaded.get_servo_calibration(5), ServoCalibration::default());
+        assert_eq!(loaded.get_wifi_credentials(), Some(&config.wifi));
+        assert_eq!(DeviceConfig::default().get_wifi_credentials(), None);
+        assert_eq!(loaded.get_preferred_host(), Some("main"));
+        assert_eq!(DeviceConfig::default().get_preferred_host(), None);
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let mut storage = StorageMock {
+            bytes: Some(vec![1, 2, 3]),
+        };
+        assert_eq!(load(&mut storage), DeviceConfig::default());
+
+        let mut bytes = DeviceConfig::default().to_bytes();
+        bytes[0] = 0xff;
+        let mut storage = StorageMock { bytes: Some(bytes) };
+        assert_eq!(load(&mut storage), DeviceConfig::default());
+    }
+
+    #[test]
+    fn test_load_invalid_calibration() {
+        let valid = ServoCalibration {
+            min_pulse_us: 800,
+            ..Default::default()
+        };
+        let mut config = DeviceConfig::default();
+        config.set_servo_calibration(0, valid.clone());
+        config.set_servo_calibration(
+            1,
+            ServoCalibration {
+                max_speed: f32::NAN,
+                ..valid.clone()
+            },
+        );
+        let mut storage = StorageMock::default();
+        store(&mut storage, &config).unwrap();
+
+        let loaded = load(&mut storage);
+        assert_eq!(loaded.get_servo_calibration(0), valid);
+        assert_eq!(loaded.get_servo_calibration(1), ServoCalibration::default());
+    }
+
+    #[test]
+    fn test_migrate_older_versions() {
+        let calibration = ServoCalibration {
+            min_pulse_us: 800,
+            max_speed: 2.0,
+            ..Default::default()
+        };
+        let wifi = WifiCredentials {
+            ssid: String::from("ssid"),
+            password: String::from("password"),
+        };
+
+        let mut storage = StorageMock {
+            bytes: Some(
+                DeviceConfigV1 {
+                    version: 1,
+                    servos: vec![calibration.clone()],
+                }
+                .to_bytes(),
+            ),
+        };
+        let loaded = load(&mut storage);
+        assert_eq!(loaded.version, DeviceConfig::VERSION);
+        assert_eq!(loaded.get_servo_calibration(0), calibration);
+        assert_eq!(loaded.get_wifi_credentials(), None);
+
+        let mut storage = StorageMock {
+            bytes: Some(
+                DeviceConfigV2 {
+                    version: 2,
+                    servos: vec![calibration.clone()],
+                    wifi: wifi.clone(),
+                }
+                .to_bytes(),
+            ),
+        };
+        let loaded = load(&mut storage);
+        assert_eq!(loaded.get_servo_calibration(0), calibration);
+        assert_eq!(loaded.get_wifi_credentials(), Some(&wifi));
+        assert_eq!(loaded.get_preferred_host(), None);
+    }
+}
@@ -0,0 +1,622 @@
+use std::{net::SocketAddr, time::Duration};
+
+use headlight_if::KEEPALIVE_PERIOD;
+use log::{info, warn};
+
+use crate::{
+    config::WifiCredentials,
+    discovery::{HostSelector, ServiceHost},
+};
+
+/// Exponential back-off between reconnection attempts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    attempt: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), Duration::from_secs(60))
+    }
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            attempt: 0,
+        }
+    }
+
+    /// Number of failed attempts since the last reset.
+    pub fn get_attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self
+            .initial
+            .saturating_mul(1 << self.attempt.min(16))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    /// Soft-AP and serial console are waiting for credentials.
+    Provisioning,
+    ConnectingWifi,
+    WifiBackoff,
+    Discovering,
+    DiscoveryBackoff,
+    ConnectingService(ServiceHost),
+    Connected(ServiceHost),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    CredentialsReceived(WifiCredentials),
+    WifiConnected,
+    WifiFailed,
+    WifiLost,
+    /// Result of the discovery, empty when nothing was found.
+    ServicesFound(Vec<ServiceHost>),
+    ServiceConnected,
+    ServiceFailed,
+    ServiceLost,
+    /// Nothing was received from the connected host within the `Serve` timeout.
+    ServiceTimedOut,
+    /// `Wait` or provisioning timeout has elapsed.
+    TimedOut,
+    PreferredHostChanged(Option<String>),
+}
+
+/// What the firmware has to do next. Result of the action is reported back as an event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionAction {
+    /// Start soft-AP with setup page. Report `TimedOut` after the timeout, if any.
+    StartProvisioning(Option<Duration>),
+    ConnectWifi(WifiCredentials),
+    Wait(Duration),
+    DiscoverService,
+    ConnectService(SocketAddr),
+    /// Handle commands until the connection is lost. Report `ServiceTimedOut` when nothing
+    /// is received for the duration.
+    Serve(Duration),
+}
+
+/// Provisioning and reconnection logic of the headlight.
+///
+/// Wi-Fi failures are retried with back-off. After `max_wifi_attempts` failures the device falls
+/// back to provisioning, which times out back to Wi-Fi, because the access point may be just down.
+/// Lost service is always re-discovered, as the PC app may come back on a different address.
+/// When the chosen host can't be connected, the next discovered one is tried right away.
+/// A host which stops sending is treated as failed too, so the next one is chosen after it.
+pub struct ConnectionManager {
+    state: ConnectionState,
+    credentials: Option<WifiCredentials>,
+    selector: HostSelector,
+    hosts: Vec<ServiceHost>,
+    wifi_backoff: Backoff,
+    service_backoff: Backoff,
+    max_wifi_attempts: u32,
+    provisioning_timeout: Duration,
+    service_timeout: Duration,
+}
+
+impl ConnectionManager {
+    pub const DEFAULT_MAX_WIFI_ATTEMPTS: u32 = 5;
+    pub const DEFAULT_PROVISIONING_TIMEOUT: Duration = Duration::from_secs(300);
+    /// Several keepalives missed in a row mean the host hung or the link dropped without
+    /// a reset, other traffic may pause for long, e.g. in the calibration wizard.
+    pub const DEFAULT_SERVICE_TIMEOUT: Duration = KEEPALIVE_PERIOD.saturating_mul(5);
+
+    pub fn new(credentials: Option<WifiCredentials>) -> Self {
+        Self {
+            state: ConnectionState::Provisioning,
+            credentials,
+            selector: HostSelector::default(),
+            hosts: Vec::new(),
+            wifi_backoff: Backoff::default(),
+            service_backoff: Backoff::default(),
+            max_wifi_attempts: Self::DEFAULT_MAX_WIFI_ATTEMPTS,
+            provisioning_timeout: Self::DEFAULT_PROVISIONING_TIMEOUT,
+            service_timeout: Self::DEFAULT_SERVICE_TIMEOUT,
+        }
+    }
+
+    pub fn with_backoff(mut self, wifi_backoff: Backoff, service_backoff: Backoff) -> Self {
+        self.wifi_backoff = wifi_backoff;
+        self.service_backoff = service_backoff;
+        self
+    }
+
+    pub fn with_preferred_host(mut self, preferred: Option<String>) -> Self {
+        self.selector.set_preferred(preferred);
+        self
+    }
+
+    pub fn with_max_wifi_attempts(mut self, max_wifi_attempts: u32) -> Self {
+        self.max_wifi_attempts = max_wifi_attempts;
+        self
+    }
+
+    pub fn with_provisioning_timeout(mut self, provisioning_timeout: Duration) -> Self {
+        self.provisioning_timeout = provisioning_timeout;
+        self
+    }
+
+    pub fn with_service_timeout(mut self, service_timeout: Duration) -> Self {
+        self.service_timeout = service_timeout;
+        self
+    }
+
+    pub fn get_state(&self) -> &ConnectionState {
+        &self.state
+    }
+
+    pub fn get_credentials(&self) -> Option<&WifiCredentials> {
+        self.credentials.as_ref()
+    }
+
+    pub fn get_preferred_host(&self) -> Option<&str> {
+        self.selector.get_preferred()
+    }
+
+    /// First action after boot.
+    pub fn start(&mut self) -> ConnectionAction {
+        self.connect_wifi()
+    }
+
+    pub fn handle(&mut self, event: ConnectionEvent) -> ConnectionAction {
+        info!("Connection event {:?} in state {:?}", event, self.state);
+
+        match (&self.state, event) {
+            // new credentials are accepted at any time, e.g. from the serial console
+            (_, ConnectionEvent::CredentialsReceived(credentials)) => {
+                self.credentials = Some(credentials);
+                self.wifi_backoff.reset();
+                self.connect_wifi()
+            }
+            (_, ConnectionEvent::PreferredHostChanged(preferred)) => {
+                self.selector.set_preferred(preferred);
+                self.repeat()
+            }
+
+            (ConnectionState::Provisioning, ConnectionEvent::TimedOut) => {
+                self.wifi_backoff.reset();
+                self.connect_wifi()
+            }
+
+            (ConnectionState::ConnectingWifi, ConnectionEvent::WifiConnected) => {
+                self.wifi_backoff.reset();
+                self.discover_service()
+            }
+            (ConnectionState::ConnectingWifi, ConnectionEvent::WifiFailed) => {
+                if self.wifi_backoff.get_attempt() + 1 >= self.max_wifi_attempts {
+                    warn!("Wi-Fi connection failed too many times");
+                    self.start_provisioning()
+                } else {
+                    self.state = ConnectionState::WifiBackoff;
+                    ConnectionAction::Wait(self.wifi_backoff.next_delay())
+                }
+            }
+            (ConnectionState::WifiBackoff, ConnectionEvent::TimedOut) => self.connect_wifi(),
+
+            // Wi-Fi may drop at any point after it was connected
+            (
+                ConnectionState::Discovering
+                | ConnectionState::DiscoveryBackoff
+                | ConnectionState::ConnectingService(_)
+                | ConnectionState::Connected(_),
+                ConnectionEvent::WifiLost,
+            ) => {
+                self.service_backoff.reset();
+                self.connect_wifi()
+            }
+
+            (ConnectionState::Discovering, ConnectionEvent::ServicesFound(hosts)) => {
+                self.hosts = hosts;
+                self.connect_service()
+            }
+            (ConnectionState::ConnectingService(host), ConnectionEvent::ServiceFailed) => {
+                let host = host.clone();
+                self.selector.report_failure(&host);
+                self.hosts
+                    .retain(|other| other.instance_id != host.instance_id);
+                self.connect_service()
+            }
+            (ConnectionState::DiscoveryBackoff, ConnectionEvent::TimedOut) => {
+                self.discover_service()
+            }
+
+            (ConnectionState::ConnectingService(host), ConnectionEvent::ServiceConnected) => {
+                let host = host.clone();
+                self.selector.report_success(&host);
+                self.state = ConnectionState::Connected(host);
+                self.service_backoff.reset();
+                ConnectionAction::Serve(self.service_timeout)
+            }
+            (ConnectionState::Connected(_), ConnectionEvent::ServiceLost) => {
+                self.discover_service()
+            }
+            // hung host may still be advertised, the others are tried first
+            (ConnectionState::Connected(host), ConnectionEvent::ServiceTimedOut) => {
+                let host = host.clone();
+                warn!("{} stopped responding", host.instance_id);
+                self.selector.report_failure(&host);
+                self.discover_service()
+            }
+
+            (state, event) => {
+                warn!("Unexpected event {:?} in state {:?}", event, state);
+                self.repeat()
+            }
+        }
+    }
+
+    fn connect_wifi(&mut self) -> ConnectionAction {
+        match self.credentials.clone() {
+            Some(credentials) => {
+                self.state = ConnectionState::ConnectingWifi;
+                ConnectionAction::ConnectWifi(credentials)
+            }
+            None => self.start_provisioning(),
+        }
+    }
+
+    fn start_provisioning(&mut self) -> ConnectionAction {
+        self.state = ConnectionState::Provisioning;
+        // without credentials there is nothing to go back to
+        let timeout = self.credentials.as_ref().map(|_| self.provisioning_timeout);
+        ConnectionAction::StartProvisioning(timeout)
+    }
+
+    fn discover_service(&mut self) -> ConnectionAction {
+        self.state = ConnectionState::Discovering;
+        ConnectionAction::DiscoverService
+    }
+
+    fn connect_service(&mut self) -> ConnectionAction {
+        match self.selector.select(&self.hosts) {
+            Some(host) => {
+                info!("Connecting to {} at {}", host.instance_id, host.address);
+                let address = host.address;
+                self.state = ConnectionState::ConnectingService(host);
+                ConnectionAction::ConnectService(address)
+            }
+            None => {
+                self.state = ConnectionState::DiscoveryBackoff;
+                ConnectionAction::Wait(self.service_backoff.next_delay())
+            }
+        }
+    }
+
+    /// Action of the current state, used to recover from unexpected events.
+    fn repeat(&mut self) -> ConnectionAction {
+        match self.state.clone() {
+            ConnectionState::Provisioning => self.start_provisioning(),
+            ConnectionState::ConnectingWifi | ConnectionState::WifiBackoff => self.connect_wifi(),
+            ConnectionState::Discovering | ConnectionState::DiscoveryBackoff => {
+                self.discover_service()
+            }
+            ConnectionState::ConnectingService(host) => {
+                ConnectionAction::ConnectService(host.address)
+            }
+            ConnectionState::Connected(_) => ConnectionAction::Serve(self.service_timeout),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials() -> WifiCredentials {
+        WifiCredentials {
+            ssid: String::from("ssid"),
+            password: String::from("password"),
+        }
+    }
+
+    fn host(instance_id: &str, priority: u8) -> ServiceHost {
+        ServiceHost {
+            instance_id: instance_id.to_string(),
+            address: SocketAddr::from(([192, 168, 1, priority], 5000)),
+            priority,
+            version: headlight_if::PROTOCOL_VERSION,
+        }
+    }
+
+    fn main_host() -> ServiceHost {
+        host("main", 1)
+    }
+
+    fn backup_host() -> ServiceHost {
+        host("backup", 10)
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(10));
+
+        let delays: Vec<u64> = (0..6).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+        assert_eq!(backoff.get_attempt(), 6);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+
+        for _ in 0..100 {
+            backoff.next_delay();
+        }
+        assert_eq!(backoff.next_delay(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_unprovisioned() {
+        let mut manager = ConnectionManager::new(None);
+
+        assert_eq!(manager.start(), ConnectionAction::StartProvisioning(None));
+        assert_eq!(
+            manager.handle(ConnectionEvent::TimedOut),
+            ConnectionAction::StartProvisioning(None)
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::CredentialsReceived(credentials())),
+            ConnectionAction::ConnectWifi(credentials())
+        );
+        assert_eq!(manager.get_credentials(), Some(&credentials()));
+    }
+
+    #[test]
+    fn test_happy_path() {
+        let mut manager = ConnectionManager::new(Some(credentials()));
+
+        assert_eq!(
+            manager.start(),
+            ConnectionAction::ConnectWifi(credentials())
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::WifiConnected),
+            ConnectionAction::DiscoverService
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServicesFound(vec![main_host()])),
+            ConnectionAction::ConnectService(main_host().address)
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServiceConnected),
+            ConnectionAction::Serve(ConnectionManager::DEFAULT_SERVICE_TIMEOUT)
+        );
+        assert_eq!(
+            manager.get_state(),
+            &ConnectionState::Connected(main_host())
+        );
+    }
+
+    #[test]
+    fn test_wifi_backoff_and_fallback() {
+        let mut manager = ConnectionManager::new(Some(credentials()))
+            .with_max_wifi_attempts(3)
+            .with_provisioning_timeout(Duration::from_secs(30));
+
+        manager.start();
+        assert_eq!(
+            manager.handle(ConnectionEvent::WifiFailed),
+            ConnectionAction::Wait(Duration::from_secs(1))
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::TimedOut),
+            ConnectionAction::ConnectWifi(credentials())
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::WifiFailed),
+            ConnectionAction::Wait(Duration::from_secs(2))
+        );
+        manager.handle(ConnectionEvent::TimedOut);
+        assert_eq!(
+            manager.handle(ConnectionEvent::WifiFailed),
+            ConnectionAction::StartProvisioning(Some(Duration::from_secs(30)))
+        );
+
+        // provisioning times out back to the stored credentials with fresh back-off
+        assert_eq!(
+            manager.handle(ConnectionEvent::TimedOut),
+            ConnectionAction::ConnectWifi(credentials())
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::WifiFailed),
+            ConnectionAction::Wait(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn test_service_lost_is_rediscovered() {
+        let mut manager = ConnectionManager::new(Some(credentials()));
+        manager.start();
+        manager.handle(ConnectionEvent::WifiConnected);
+
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServicesFound(Vec::new())),
+            ConnectionAction::Wait(Duration::from_secs(1))
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::TimedOut),
+            ConnectionAction::DiscoverService
+        );
+        manager.handle(ConnectionEvent::ServicesFound(vec![main_host()]));
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServiceFailed),
+            ConnectionAction::Wait(Duration::from_secs(2))
+        );
+        manager.handle(ConnectionEvent::TimedOut);
+        manager.handle(ConnectionEvent::ServicesFound(vec![main_host()]));
+        manager.handle(ConnectionEvent::ServiceConnected);
+
+        // PC app restarted
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServiceLost),
+            ConnectionAction::DiscoverService
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServicesFound(Vec::new())),
+            ConnectionAction::Wait(Duration::from_secs(1))
+        );
+        manager.handle(ConnectionEvent::TimedOut);
+        manager.handle(ConnectionEvent::ServicesFound(vec![main_host()]));
+        manager.handle(ConnectionEvent::ServiceConnected);
+
+        // PC app hung without closing the connection
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServiceTimedOut),
+            ConnectionAction::DiscoverService
+        );
+    }
+
+    #[test]
+    fn test_wifi_lost() {
+        let mut manager = ConnectionManager::new(Some(credentials()));
+        manager.start();
+        manager.handle(ConnectionEvent::WifiConnected);
+        manager.handle(ConnectionEvent::ServicesFound(vec![main_host()]));
+        manager.handle(ConnectionEvent::ServiceConnected);
+
+        assert_eq!(
+            manager.handle(ConnectionEvent::WifiLost),
+            ConnectionAction::ConnectWifi(credentials())
+        );
+        assert_eq!(manager.get_state(), &ConnectionState::ConnectingWifi);
+    }
+
+    #[test]
+    fn test_unexpected_event_repeats_action() {
+        let mut manager = ConnectionManager::new(Some(credentials()));
+        manager.start();
+        manager.handle(ConnectionEvent::WifiConnected);
+
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServiceConnected),
+            ConnectionAction::DiscoverService
+        );
+        assert_eq!(manager.get_state(), &ConnectionState::Discovering);
+    }
+
+    #[test]
+    fn test_failover_to_backup() {
+        let mut manager = ConnectionManager::new(Some(credentials()));
+        manager.start();
+        manager.handle(ConnectionEvent::WifiConnected);
+
+        let hosts = vec![backup_host(), main_host()];
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServicesFound(hosts.clone())),
+            ConnectionAction::ConnectService(main_host().address)
+        );
+        // stale mDNS record of the main host, backup is tried right away
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServiceFailed),
+            ConnectionAction::ConnectService(backup_host().address)
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServiceConnected),
+            ConnectionAction::Serve(ConnectionManager::DEFAULT_SERVICE_TIMEOUT)
+        );
+
+        // main host disappeared
+        manager.handle(ConnectionEvent::ServiceLost);
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServicesFound(vec![backup_host()])),
+            ConnectionAction::ConnectService(backup_host().address)
+        );
+        manager.handle(ConnectionEvent::ServiceConnected);
+
+        // main host is back, but it failed before, so it is tried only after the backup fails
+        manager.handle(ConnectionEvent::ServiceLost);
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServicesFound(hosts.clone())),
+            ConnectionAction::ConnectService(backup_host().address)
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServiceFailed),
+            ConnectionAction::ConnectService(main_host().address)
+        );
+    }
+
+    #[test]
+    fn test_timed_out_host_fails_over() {
+        let timeout = Duration::from_secs(2);
+        let mut manager = ConnectionManager::new(Some(credentials())).with_service_timeout(timeout);
+        manager.start();
+        manager.handle(ConnectionEvent::WifiConnected);
+
+        let hosts = vec![backup_host(), main_host()];
+        manager.handle(ConnectionEvent::ServicesFound(hosts.clone()));
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServiceConnected),
+            ConnectionAction::Serve(timeout)
+        );
+
+        // main host hung, but its mDNS record is still there
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServiceTimedOut),
+            ConnectionAction::DiscoverService
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServicesFound(hosts.clone())),
+            ConnectionAction::ConnectService(backup_host().address)
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServiceConnected),
+            ConnectionAction::Serve(timeout)
+        );
+        assert_eq!(
+            manager.get_state(),
+            &ConnectionState::Connected(backup_host())
+        );
+
+        // backup hangs too, both are tried again starting with the main one
+        manager.handle(ConnectionEvent::ServiceTimedOut);
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServicesFound(hosts)),
+            ConnectionAction::ConnectService(main_host().address)
+        );
+    }
+
+    #[test]
+    fn test_preferred_host() {
+        let mut manager =
+            ConnectionManager::new(Some(credentials())).with_preferred_host(Some("backup".into()));
+        manager.start();
+        manager.handle(ConnectionEvent::WifiConnected);
+
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServicesFound(vec![
+                main_host(),
+                backup_host()
+            ])),
+            ConnectionAction::ConnectService(backup_host().address)
+        );
+        assert_eq!(manager.get_preferred_host(), Some("backup"));
+
+        manager.handle(ConnectionEvent::ServiceFailed);
+        assert_eq!(
+            manager.handle(ConnectionEvent::ServiceFailed),
+            ConnectionAction::Wait(Duration::from_secs(1))
+        );
+        assert_eq!(
+            manager.handle(ConnectionEvent::PreferredHostChanged(None)),
+            ConnectionAction::DiscoverService
+        );
+        assert_eq!(manager.get_preferred_host(), None);
+    }
+}
@@ -0,0 +1,201 @@
+use std::net::SocketAddr;
+
+use headlight_if::{PROTOCOL_VERSION, TXT_INSTANCE_ID, TXT_PRIORITY, TXT_VERSION};
+use log::{debug, warn};
+
+/// RtAudioEffect host advertised over mDNS.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceHost {
+    pub instance_id: String,
+    pub address: SocketAddr,
+    /// Lower value is preferred.
+    pub priority: u8,
+    pub version: u32,
+}
+
+impl ServiceHost {
+    /// Hosts without TXT records are assumed to be compatible with the lowest priority.
+    pub fn from_txt(instance_name: &str, address: SocketAddr, txt: &[(String, String)]) -> Self {
+        let get = |key: &str| {
+            txt.iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value.as_str())
+        };
+
+        Self {
+            instance_id: get(TXT_INSTANCE_ID).unwrap_or(instance_name).to_string(),
+            address,
+            priority: get(TXT_PRIORITY)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(u8::MAX),
+            version: get(TXT_VERSION)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(PROTOCOL_VERSION),
+        }
+    }
+}
+
+/// Chooses host to connect to.
+///
+/// Configured host is preferred whenever it is advertised, otherwise the one with the lowest
+/// priority. Hosts which failed to connect are skipped until the others fail as well.
+#[derive(Debug, Clone, Default)]
+pub struct HostSelector {
+    preferred: Option<String>,
+    failed: Vec<String>,
+}
+
+impl HostSelector {
+    pub fn new(preferred: Option<String>) -> Self {
+        Self {
+            preferred,
+            failed: Vec::new(),
+        }
+    }
+
+    pub fn get_preferred(&self) -> Option<&str> {
+        self.preferred.as_deref()
+    }
+
+    pub fn set_preferred(&mut self, preferred: Option<String>) {
+        self.preferred = preferred;
+        self.failed.clear();
+    }
+
+    pub fn select(&mut self, hosts: &[ServiceHost]) -> Option<ServiceHost> {
+        let compatible: Vec<&ServiceHost> = hosts
+            .iter()
+            .filter(|host| {
+                let compatible = host.version == PROTOCOL_VERSION;
+                if !compatible {
+                    warn!(
+                        "Skipping {}: protocol version {} doesn't match {}",
+                        host.instance_id, host.version, PROTOCOL_VERSION
+                    );
+                }
+                compatible
+            })
+            .collect();
+
+        if !compatible.is_empty()
+            && compatible
+                .iter()
+                .all(|host| self.failed.contains(&host.instance_id))
+        {
+            debug!("All hosts failed, trying them again");
+            self.failed.clear();
+        }
+
+        compatible
+            .into_iter()
+            .filter(|host| !self.failed.contains(&host.instance_id))
+            .min_by(|a, b| {
+                let rank = |host: &ServiceHost| {
+                    let preferred = self.preferred.as_deref() == Some(host.instance_id.as_str());
+                    (!preferred, host.priority)
+                };
+                // instance id makes the choice deterministic
+                rank(a)
+                    .cmp(&rank(b))
+                    .then_with(|| a.instance_id.cmp(&b.instance_id))
+            })
+            .cloned()
+    }
+
+    pub fn report_failure(&mut self, host: &ServiceHost) {
+        if !self.failed.contains(&host.instance_id) {
+            self.failed.push(host.instance_id.clone());
+        }
+    }
+
+    pub fn report_success(&mut self, host: &ServiceHost) {
+        self.failed
+            .retain(|instance_id| *instance_id != host.instance_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(instance_id: &str, priority: u8) -> ServiceHost {
+        ServiceHost {
+            instance_id: instance_id.to_string(),
+            address: SocketAddr::from(([192, 168, 1, priority], 5000)),
+            priority,
+            version: PROTOCOL_VERSION,
+        }
+    }
+
+    #[test]
+    fn test_from_txt() {
+        let address = SocketAddr::from(([192, 168, 1, 2], 5000));
+        let txt = vec![
+            (String::from("priority"), String::from("10")),
+            (String::from("version"), String::from("7")),
+            (String::from("id"), String::from("backup")),
+        ];
+        let host = ServiceHost::from_txt("RtAudioEffect", address, &txt);
+        assert_eq!(host.instance_id, "backup");
+        assert_eq!(host.priority, 10);
+        assert_eq!(host.version, 7);
+
+        let host = ServiceHost::from_txt("RtAudioEffect", address, &[]);
+        assert_eq!(host.instance_id, "RtAudioEffect");
+        assert_eq!(host.priority, u8::MAX);
+        assert_eq!(host.version, PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn test_priority() {
+        let mut selector = HostSelector::default();
+        let hosts = vec![host("backup", 10), host("main", 0), host("other", 10)];
+
+        assert_eq!(selector.select(&hosts), Some(host("main", 0)));
+        assert_eq!(selector.select(&hosts[2..]), Some(host("other", 10)));
+        assert_eq!(selector.select(&[]), None);
+    }
+
+    #[test]
+    fn test_preferred() {
+        let mut selector = HostSelector::new(Some(String::from("backup")));
+        let hosts = vec![host("main", 0), host("backup", 10)];
+        assert_eq!(selector.select(&hosts), Some(host("backup", 10)));
+
+        // preferred host disappeared
+        assert_eq!(selector.select(&hosts[..1]), Some(host("main", 0)));
+
+        selector.set_preferred(None);
+        assert_eq!(selector.select(&hosts), Some(host("main", 0)));
+    }
+
+    #[test]
+    fn test_incompatible_version() {
+        let mut selector = HostSelector::default();
+        let mut main = host("main", 0);
+        main.version = PROTOCOL_VERSION + 1;
+
+        assert_eq!(
+            selector.select(&[main.clone(), host("backup", 10)]),
+            Some(host("backup", 10))
+        );
+        assert_eq!(selector.select(&[main]), None);
+    }
+
+    #[test]
+    fn test_failover() {
+        let mut selector = HostSelector::default();
+        let hosts = vec![host("main", 0), host("backup", 10)];
+
+        selector.report_failure(&hosts[0]);
+        assert_eq!(selector.select(&hosts), Some(host("backup", 10)));
+
+        // all failed, start over from the best one
+        selector.report_failure(&hosts[1]);
+        assert_eq!(selector.select(&hosts), Some(host("main", 0)));
+
+        selector.report_failure(&hosts[0]);
+        selector.report_success(&hosts[0]);
+        assert_eq!(selector.select(&hosts), Some(host("main", 0)));
+    }
+}
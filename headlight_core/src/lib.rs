@@ -0,0 +1,9 @@
+//! Hardware independent part of the headlight firmware.
+//! Everything here builds and runs on the host, so it can be unit tested without ESP32.
+pub mod calibration;
+pub mod config;
+pub mod connection;
+pub mod discovery;
+pub mod motion;
+pub mod provisioning;
+pub mod trajectory;
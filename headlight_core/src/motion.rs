@@ -0,0 +1,298 @@
+use std::time::Duration;
+
+use headlight_if::{Easing, ServoKeyframe};
+use log::{debug, warn};
+
+use crate::trajectory::{MotionLimits, Trajectory};
+
+/// Output of the motion controller. Implemented by the firmware servo driver.
+pub trait ServoDriver {
+    type Error;
+
+    fn set_duty(&mut self, set_point: f32) -> Result<(), Self::Error>;
+}
+
+/// Drives single servo along trajectories. `update` has to be called periodically.
+pub struct ServoMotion<D: ServoDriver> {
+    driver: D,
+    limits: MotionLimits,
+    position: f32,
+    trajectory: Option<Trajectory>,
+    /// Path restarted whenever its trajectory finishes.
+    looped_path: Option<Vec<ServoKeyframe>>,
+    elapsed: Duration,
+}
+
+impl<D: ServoDriver> ServoMotion<D> {
+    pub fn new(driver: D, limits: MotionLimits) -> Self {
+        Self {
+            driver,
+            limits,
+            position: 0.0,
+            trajectory: None,
+            looped_path: None,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn get_position(&self) -> f32 {
+        self.position
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.trajectory.is_some()
+    }
+
+    pub fn get_limits(&self) -> MotionLimits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: MotionLimits) {
+        self.limits = limits;
+    }
+
+    pub fn get_driver(&self) -> &D {
+        &self.driver
+    }
+
+    pub fn get_driver_mut(&mut self) -> &mut D {
+        &mut self.driver
+    }
+
+    /// Jumps to the position immediately. Cancels current trajectory.
+    /// Non-finite positions are ignored.
+    pub fn set_position(&mut self, position: f32) -> Result<(), D::Error> {
+        if !position.is_finite() {
+            warn!("Ignoring invalid position: {}", position);
+            return Ok(());
+        }
+        self.stop();
+        self.apply(self.limits.clamp(position))
+    }
+
+    /// Cancels current trajectory. Servo stays where it is.
+    pub fn stop(&mut self) {
+        self.trajectory = None;
+        self.looped_path = None;
+    }
+
+    /// On error the current trajectory is kept.
+    pub fn move_to(
+        &mut self,
+        target: f32,
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<(), String> {
+        let trajectory =
+            Trajectory::new_move(self.position, target, duration, easing, &self.limits)?;
+        self.looped_path = None;
+        self.start(trajectory);
+        Ok(())
+    }
+
+    /// Looping paths are restarted from the position where they ended, limits may stretch
+    /// every round differently. On error the current trajectory is kept.
+    pub fn follow_path(
+        &mut self,
+        keyframes: &[ServoKeyframe],
+        looping: bool,
+    ) -> Result<(), String> {
+        let trajectory = Trajectory::new_path(self.position, keyframes, &self.limits)?;
+        self.looped_path = (looping && !keyframes.is_empty()).then(|| keyframes.to_vec());
+        self.start(trajectory);
+        Ok(())
+    }
+
+    pub fn update(&mut self, time_step: Duration) -> Result<(), D::Error> {
+        let Some(trajectory) = &self.trajectory else {
+            return Ok(());
+        };
+
+        self.elapsed += time_step;
+        let position = trajectory.sample(self.elapsed);
+        let finished = trajectory.is_finished(self.elapsed);
+
+        let result = match position {
+            Some(position) => self.apply(position),
+            None => Ok(()),
+        };
+        if finished {
+            debug!("Trajectory finished after {:?}", self.elapsed);
+            self.trajectory = None;
+            if let Some(keyframes) = self.looped_path.take() {
+                // keyframes were validated when the path was received
+                self.follow_path(&keyframes, true).unwrap();
+            }
+        }
+        result
+    }
+
+    fn start(&mut self, trajectory: Trajectory) {
+        debug!(
+            "Starting trajectory to {:?}, duration {:?}",
+            trajectory.target(),
+            trajectory.duration()
+        );
+        self.trajectory = Some(trajectory);
+        self.elapsed = Duration::ZERO;
+    }
+
+    fn apply(&mut self, position: f32) -> Result<(), D::Error> {
+        self.position = position;
+        self.driver.set_duty(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct DriverMock {
+        duties: Vec<f32>,
+    }
+
+    impl ServoDriver for &mut DriverMock {
+        type Error = ();
+
+        fn set_duty(&mut self, set_point: f32) -> Result<(), Self::Error> {
+            self.duties.push(set_point);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_move_drives_servo() {
+        let mut driver = DriverMock::default();
+        let mut motion = ServoMotion::new(&mut driver, MotionLimits::default());
+
+        motion.set_position(-1.0).unwrap();
+        motion
+            .move_to(1.0, Duration::from_millis(1000), Easing::Trapezoidal)
+            .unwrap();
+        assert!(motion.is_moving());
+
+        for _ in 0..110 {
+            motion.update(Duration::from_millis(10)).unwrap();
+        }
+
+        assert!(!motion.is_moving());
+        assert_eq!(motion.get_position(), 1.0);
+        drop(motion);
+
+        assert_eq!(driver.duties.len(), 101);
+        assert_eq!(driver.duties.first(), Some(&-1.0));
+        assert_eq!(driver.duties.last(), Some(&1.0));
+        driver
+            .duties
+            .windows(2)
+            .for_each(|pair| assert!(pair[1] >= pair[0]));
+    }
+
+    #[test]
+    fn test_set_position_cancels_move() {
+        let mut driver = DriverMock::default();
+        let mut motion = ServoMotion::new(&mut driver, MotionLimits::default());
+
+        motion
+            .move_to(1.0, Duration::from_millis(1000), Easing::Linear)
+            .unwrap();
+        motion.update(Duration::from_millis(100)).unwrap();
+        motion.set_position(-0.5).unwrap();
+        motion.update(Duration::from_millis(100)).unwrap();
+
+        assert!(!motion.is_moving());
+        assert_eq!(motion.get_position(), -0.5);
+    }
+
+    #[test]
+    fn test_path_starts_from_current_position() {
+        let mut driver = DriverMock::default();
+        let mut motion = ServoMotion::new(&mut driver, MotionLimits::default());
+
+        motion.set_position(0.5).unwrap();
+        motion
+            .follow_path(
+                &[ServoKeyframe {
+                    position: 0.0,
+                    duration_ms: 500,
+                    easing: Easing::EaseInOut,
+                }],
+                false,
+            )
+            .unwrap();
+        motion.update(Duration::from_millis(1)).unwrap();
+
+        let position = motion.get_position();
+        assert!(position < 0.5 && position > 0.49);
+    }
+
+    #[test]
+    fn test_looping_path_repeats_within_limits() {
+        let mut driver = DriverMock::default();
+        let limits = MotionLimits {
+            max_velocity: 1.0,
+            ..MotionLimits::default()
+        };
+        let mut motion = ServoMotion::new(&mut driver, limits);
+        let keyframe = |position| ServoKeyframe {
+            position,
+            duration_ms: 500,
+            easing: Easing::EaseInOut,
+        };
+
+        motion.set_position(-1.0).unwrap();
+        motion
+            .follow_path(&[keyframe(1.0), keyframe(-1.0)], true)
+            .unwrap();
+
+        // a round takes 4 s at the limited speed instead of 1 s
+        let mut rounds = 0;
+        let mut at_end = false;
+        for _ in 0..1000 {
+            motion.update(Duration::from_millis(10)).unwrap();
+            let reached = motion.get_position() > 0.999;
+            if reached && !at_end {
+                rounds += 1;
+            }
+            at_end = reached;
+        }
+        assert!(motion.is_moving());
+        assert!((2..=3).contains(&rounds), "{rounds}");
+
+        motion.stop();
+        motion.update(Duration::from_millis(10)).unwrap();
+        assert!(!motion.is_moving());
+    }
+
+    #[test]
+    fn test_invalid_commands_are_dropped() {
+        let mut driver = DriverMock::default();
+        let mut motion = ServoMotion::new(&mut driver, MotionLimits::default());
+
+        motion.set_position(0.5).unwrap();
+        motion.set_position(f32::NAN).unwrap();
+        assert_eq!(motion.get_position(), 0.5);
+
+        motion
+            .move_to(-0.5, Duration::from_millis(100), Easing::Linear)
+            .unwrap();
+        assert!(motion
+            .move_to(f32::NAN, Duration::from_millis(100), Easing::Linear)
+            .is_err());
+        let nan_keyframe = ServoKeyframe {
+            position: f32::NAN,
+            duration_ms: 100,
+            easing: Easing::Linear,
+        };
+        assert!(motion.follow_path(&[nan_keyframe], true).is_err());
+
+        // the valid move goes on
+        for _ in 0..50 {
+            motion.update(Duration::from_millis(10)).unwrap();
+        }
+        assert_eq!(motion.get_position(), -0.5);
+        drop(motion);
+        assert!(driver.duties.iter().all(|duty| duty.is_finite()));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{config::WifiCredentials, connection::ConnectionEvent};
+
+/// SSID of the soft-AP started when the device has no working credentials.
+pub const ACCESS_POINT_SSID: &str = "headlight-setup";
+
+pub const SETUP_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta name="viewport" content="width=device-width, initial-scale=1"><title>Headlight setup</title></head>
+<body>
+<h1>Headlight setup</h1>
+<form method="post" action="/">
+<p><label>SSID <input name="ssid" maxlength="32" required></label></p>
+<p><label>Password <input name="password" type="password" maxlength="64"></label></p>
+<p><input type="submit" value="Connect"></p>
+</form>
+</body>
+</html>
+"#;
+
+pub const SETUP_DONE_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<body><h1>Credentials saved, connecting...</h1></body>
+</html>
+"#;
+
+/// Parses serial console command:
+/// - `wifi <ssid> [password]`, SSID with spaces can be quoted: `wifi "my network" secret`
+/// - `host [instance id]`, sets or clears preferred RtAudioEffect host
+pub fn parse_serial_command(line: &str) -> Result<ConnectionEvent, String> {
+    let line = line.trim();
+    let (command, arguments) = line.split_once(' ').unwrap_or((line, ""));
+    let arguments = arguments.trim();
+
+    match command {
+        "wifi" => {
+            let (ssid, password) = match arguments.strip_prefix('"') {
+                Some(quoted) => quoted
+                    .split_once('"')
+                    .ok_or(String::from("Missing closing quote"))?,
+                None => arguments.split_once(' ').unwrap_or((arguments, "")),
+            };
+            credentials(ssid, password.trim()).map(ConnectionEvent::CredentialsReceived)
+        }
+        "host" => Ok(ConnectionEvent::PreferredHostChanged(
+            Some(arguments.to_string()).filter(|host| !host.is_empty()),
+        )),
+        _ => Err(format!("Unknown command: {}", line)),
+    }
+}
+
+/// Parses `application/x-www-form-urlencoded` body of the setup page.
+pub fn parse_setup_form(body: &str) -> Result<WifiCredentials, String> {
+    let mut ssid = None;
+    let mut password = String::new();
+
+    for pair in body.trim().split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        match key {
+            "ssid" => ssid = Some(url_decode(value)?),
+            "password" => password = url_decode(value)?,
+            _ => {}
+        }
+    }
+
+    credentials(&ssid.ok_or(String::from("Missing SSID"))?, &password)
+}
+
+fn credentials(ssid: &str, password: &str) -> Result<WifiCredentials, String> {
+    // limits of the 802.11 and WPA2
+    if ssid.is_empty() || ssid.len() > 32 {
+        return Err(format!("Invalid SSID length: {}", ssid.len()));
+    }
+    if password.len() > 64 {
+        return Err(format!("Invalid password length: {}", password.len()));
+    }
+
+    Ok(WifiCredentials {
+        ssid: ssid.to_string(),
+        password: password.to_string(),
+    })
+}
+
+fn url_decode(value: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+
+    while let Some(byte) = input.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = [
+                    input.next().ok_or("Truncated escape sequence")?,
+                    input.next().ok_or("Truncated escape sequence")?,
+                ];
+                let hex = std::str::from_utf8(&hex).map_err(|e| e.to_string())?;
+                let decoded = u8::from_str_radix(hex, 16)
+                    .map_err(|e| format!("Invalid escape sequence {}: {}", hex, e))?;
+                bytes.push(decoded);
+            }
+            _ => bytes.push(byte),
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|e| format!("Invalid UTF-8: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial_command() {
+        let wifi = |ssid: &str, password: &str| {
+            Ok(ConnectionEvent::CredentialsReceived(WifiCredentials {
+                ssid: ssid.to_string(),
+                password: password.to_string(),
+            }))
+        };
+
+        assert_eq!(
+            parse_serial_command("wifi home secret123\r\n"),
+            wifi("home", "secret123")
+        );
+        assert_eq!(
+            parse_serial_command("wifi \"my home\" pass word"),
+            wifi("my home", "pass word")
+        );
+        assert_eq!(parse_serial_command("wifi open"), wifi("open", ""));
+
+        assert!(parse_serial_command("reboot").is_err());
+        assert!(parse_serial_command("wifi ").is_err());
+        assert!(parse_serial_command("wifi \"unterminated").is_err());
+    }
+
+    #[test]
+    fn test_host_command() {
+        assert_eq!(
+            parse_serial_command("host backup\n"),
+            Ok(ConnectionEvent::PreferredHostChanged(Some(String::from(
+                "backup"
+            ))))
+        );
+        assert_eq!(
+            parse_serial_command("host"),
+            Ok(ConnectionEvent::PreferredHostChanged(None))
+        );
+    }
+
+    #[test]
+    fn test_setup_form() {
+        let credentials = parse_setup_form("ssid=my+home%21&password=p%40ss%3D").unwrap();
+        assert_eq!(credentials.ssid, "my home!");
+        assert_eq!(credentials.password, "p@ss=");
+
+        let credentials = parse_setup_form("password=&ssid=open").unwrap();
+        assert_eq!(credentials.ssid, "open");
+        assert_eq!(credentials.password, "");
+
+        assert!(parse_setup_form("password=secret").is_err());
+        assert!(parse_setup_form("ssid=a%2").is_err());
+        assert!(parse_setup_form(&format!("ssid={}", "a".repeat(33))).is_err());
+    }
+}
@@ -0,0 +1,486 @@
+use std::{f32::consts::PI, time::Duration};
+
+use headlight_if::{Easing, ServoKeyframe};
+
+/// Limits of servo motion. Position is expressed in servo set point units (-1.0..1.0).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MotionLimits {
+    pub max_velocity: f32,     // units per second
+    pub max_acceleration: f32, // units per second^2
+    pub min_position: f32,
+    pub max_position: f32,
+}
+
+impl MotionLimits {
+    pub fn clamp(&self, position: f32) -> f32 {
+        position.clamp(self.min_position, self.max_position)
+    }
+}
+
+impl Default for MotionLimits {
+    fn default() -> Self {
+        Self {
+            max_velocity: 4.0,
+            max_acceleration: 20.0,
+            min_position: -1.0,
+            max_position: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Profile {
+    Linear,
+    EaseInOut,
+    Trapezoidal {
+        acceleration: f32,
+        cruise_velocity: f32,
+        acceleration_time: f32,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Segment {
+    start: f32,
+    end: f32,
+    duration: f32,
+    profile: Profile,
+}
+
+impl Segment {
+    /// Creates segment from `start` to `end`. Requested duration is extended when
+    /// the motion would not fit into given limits.
+    /// Linear easing has a step change of velocity, so only velocity limit is applied for it.
+    fn new(
+        start: f32,
+        end: f32,
+        duration: Duration,
+        easing: Easing,
+        limits: &MotionLimits,
+    ) -> Self {
+        assert!(
+            limits.max_velocity > 0.0 && limits.max_acceleration > 0.0,
+            "Motion limits have to be greater than 0"
+        );
+
+        let distance = (end - start).abs();
+        let requested_duration = duration.as_secs_f32();
+        let max_velocity = limits.max_velocity;
+        let max_acceleration = limits.max_acceleration;
+
+        if distance == 0.0 {
+            return Self {
+                start,
+                end,
+                duration: requested_duration,
+                profile: Profile::Linear,
+            };
+        }
+
+        match easing {
+            Easing::Linear => Self {
+                start,
+                end,
+                duration: requested_duration.max(distance / max_velocity),
+                profile: Profile::Linear,
+            },
+            Easing::EaseInOut => {
+                // x(t) = d * (1 - cos(PI * t / T)) / 2
+                // v_max = PI * d / (2 * T), a_max = PI^2 * d / (2 * T^2)
+                let min_duration = (PI * distance / (2.0 * max_velocity))
+                    .max(PI * (distance / (2.0 * max_acceleration)).sqrt());
+                Self {
+                    start,
+                    end,
+                    duration: requested_duration.max(min_duration),
+                    profile: Profile::EaseInOut,
+                }
+            }
+            Easing::Trapezoidal => {
+                let min_duration = if distance >= max_velocity * max_velocity / max_acceleration {
+                    distance / max_velocity + max_velocity / max_acceleration
+                } else {
+                    // triangular profile, max velocity is never reached
+                    2.0 * (distance / max_acceleration).sqrt()
+                };
+                let duration = requested_duration.max(min_duration);
+
+                // d = v * (T - v / a) => v^2 / a - v * T + d = 0
+                let delta = (max_acceleration * max_acceleration * duration * duration
+                    - 4.0 * max_acceleration * distance)
+                    .max(0.0);
+                let cruise_velocity = (max_acceleration * duration - delta.sqrt()) / 2.0;
+                let acceleration_time = cruise_velocity / max_acceleration;
+
+                Self {
+                    start,
+                    end,
+                    duration,
+                    profile: Profile::Trapezoidal {
+                        acceleration: max_acceleration,
+                        cruise_velocity,
+                        acceleration_time,
+                    },
+                }
+            }
+        }
+    }
+
+    fn sample(&self, time: f32) -> f32 {
+        let distance = (self.end - self.start).abs();
+        if self.duration <= 0.0 || distance == 0.0 || time >= self.duration {
+            return self.end;
+        }
+        let time = time.max(0.0);
+
+        let progress = match self.profile {
+            Profile::Linear => time / self.duration,
+            Profile::EaseInOut => (1.0 - (PI * time / self.duration).cos()) / 2.0,
+            Profile::Trapezoidal {
+                acceleration,
+                cruise_velocity,
+                acceleration_time,
+            } => {
+                let travelled = if time < acceleration_time {
+                    0.5 * acceleration * time * time
+                } else if time < self.duration - acceleration_time {
+                    0.5 * acceleration * acceleration_time * acceleration_time
+                        + cruise_velocity * (time - acceleration_time)
+                } else {
+                    let time_left = self.duration - time;
+                    distance - 0.5 * acceleration * time_left * time_left
+                };
+                travelled / distance
+            }
+        };
+
+        self.start + (self.end - self.start) * progress.clamp(0.0, 1.0)
+    }
+}
+
+/// Servo position as a function of time. Built from `ServoMove` and `ServoPath` commands.
+#[derive(Clone, Debug)]
+pub struct Trajectory {
+    segments: Vec<Segment>,
+}
+
+// positions come from the network, NaN would pass clamping and reach the servo
+fn check_position(position: f32) -> Result<f32, String> {
+    if position.is_finite() {
+        Ok(position)
+    } else {
+        Err(format!("Invalid position: {}", position))
+    }
+}
+
+impl Trajectory {
+    pub fn new_move(
+        start: f32,
+        target: f32,
+        duration: Duration,
+        easing: Easing,
+        limits: &MotionLimits,
+    ) -> Result<Trajectory, String> {
+        Ok(Trajectory {
+            segments: vec![Segment::new(
+                limits.clamp(check_position(start)?),
+                limits.clamp(check_position(target)?),
+                duration,
+                easing,
+                limits,
+            )],
+        })
+    }
+
+    /// Every keyframe is reached `duration_ms` after the previous one, starting at `start`.
+    pub fn new_path(
+        start: f32,
+        keyframes: &[ServoKeyframe],
+        limits: &MotionLimits,
+    ) -> Result<Trajectory, String> {
+        let mut segments = Vec::with_capacity(keyframes.len());
+        let mut position = limits.clamp(check_position(start)?);
+
+        for keyframe in keyframes {
+            let target = limits.clamp(check_position(keyframe.position)?);
+            segments.push(Segment::new(
+                position,
+                target,
+                Duration::from_millis(keyframe.duration_ms as u64),
+                keyframe.easing,
+                limits,
+            ));
+            position = target;
+        }
+
+        Ok(Trajectory { segments })
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f32(self.segments.iter().map(|segment| segment.duration).sum())
+    }
+
+    pub fn target(&self) -> Option<f32> {
+        self.segments.last().map(|segment| segment.end)
+    }
+
+    pub fn sample(&self, time: Duration) -> Option<f32> {
+        let mut time = time.as_secs_f32();
+        for segment in self.segments.iter() {
+            if time < segment.duration {
+                return Some(segment.sample(time));
+            }
+            time -= segment.duration;
+        }
+        self.target()
+    }
+
+    pub fn is_finished(&self, time: Duration) -> bool {
+        time >= self.duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f32 = 0.01;
+    const TOLERANCE: f32 = 1.02;
+
+    fn sample_positions(trajectory: &Trajectory) -> Vec<f32> {
+        let steps = (trajectory.duration().as_secs_f32() / STEP).ceil() as usize;
+        (0..=steps + 1)
+            .map(|step| {
+                trajectory
+                    .sample(Duration::from_secs_f32(step as f32 * STEP))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn max_velocity(positions: &[f32]) -> f32 {
+        positions
+            .windows(2)
+            .map(|pair| ((pair[1] - pair[0]) / STEP).abs())
+            .fold(0.0, f32::max)
+    }
+
+    fn max_acceleration(positions: &[f32]) -> f32 {
+        positions
+            .windows(3)
+            .map(|triple| ((triple[2] - 2.0 * triple[1] + triple[0]) / (STEP * STEP)).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let limits = MotionLimits::default();
+        for easing in [Easing::Linear, Easing::EaseInOut, Easing::Trapezoidal] {
+            let trajectory =
+                Trajectory::new_move(-0.5, 0.5, Duration::from_millis(1000), easing, &limits)
+                    .unwrap();
+
+            assert_eq!(trajectory.sample(Duration::ZERO), Some(-0.5));
+            assert_eq!(trajectory.sample(trajectory.duration()), Some(0.5));
+            assert_eq!(trajectory.sample(Duration::from_secs(10)), Some(0.5));
+            assert!((trajectory.duration().as_secs_f32() - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_target_is_clamped() {
+        let trajectory = Trajectory::new_move(
+            0.0,
+            3.0,
+            Duration::from_millis(1000),
+            Easing::Linear,
+            &MotionLimits::default(),
+        )
+        .unwrap();
+
+        assert_eq!(trajectory.target(), Some(1.0));
+
+        let limits = MotionLimits {
+            min_position: -0.5,
+            max_position: 0.25,
+            ..Default::default()
+        };
+        let trajectory = Trajectory::new_move(
+            -1.0,
+            1.0,
+            Duration::from_millis(1000),
+            Easing::Linear,
+            &limits,
+        )
+        .unwrap();
+
+        assert_eq!(trajectory.sample(Duration::ZERO), Some(-0.5));
+        assert_eq!(trajectory.target(), Some(0.25));
+    }
+
+    #[test]
+    fn test_trapezoidal_respects_limits() {
+        let limits = MotionLimits {
+            max_velocity: 2.0,
+            max_acceleration: 8.0,
+            ..Default::default()
+        };
+
+        // too short to be reachable, has to be stretched
+        let trajectory = Trajectory::new_move(
+            -1.0,
+            1.0,
+            Duration::from_millis(100),
+            Easing::Trapezoidal,
+            &limits,
+        )
+        .unwrap();
+        // d / v + v / a = 2 / 2 + 2 / 8
+        assert!((trajectory.duration().as_secs_f32() - 1.25).abs() < 1e-4);
+
+        let positions = sample_positions(&trajectory);
+        assert!(max_velocity(&positions) <= limits.max_velocity * TOLERANCE);
+        assert!(max_acceleration(&positions) <= limits.max_acceleration * TOLERANCE);
+        assert_eq!(*positions.last().unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_trapezoidal_triangular_profile() {
+        let limits = MotionLimits {
+            max_velocity: 10.0,
+            max_acceleration: 4.0,
+            ..Default::default()
+        };
+
+        let trajectory =
+            Trajectory::new_move(0.0, 0.25, Duration::ZERO, Easing::Trapezoidal, &limits).unwrap();
+        // 2 * sqrt(d / a)
+        assert!((trajectory.duration().as_secs_f32() - 0.5).abs() < 1e-4);
+
+        let positions = sample_positions(&trajectory);
+        assert!(max_velocity(&positions) <= limits.max_velocity * TOLERANCE);
+        assert!(max_acceleration(&positions) <= limits.max_acceleration * TOLERANCE);
+    }
+
+    #[test]
+    fn test_trapezoidal_slower_than_limits() {
+        let limits = MotionLimits::default();
+        let trajectory = Trajectory::new_move(
+            1.0,
+            -1.0,
+            Duration::from_secs(4),
+            Easing::Trapezoidal,
+            &limits,
+        )
+        .unwrap();
+
+        assert!((trajectory.duration().as_secs_f32() - 4.0).abs() < 1e-4);
+
+        let positions = sample_positions(&trajectory);
+        assert!(max_velocity(&positions) <= limits.max_velocity * TOLERANCE);
+        assert!(max_acceleration(&positions) <= limits.max_acceleration * TOLERANCE);
+        positions
+            .windows(2)
+            .for_each(|pair| assert!(pair[1] <= pair[0], "Motion should be monotonic"));
+    }
+
+    #[test]
+    fn test_ease_in_out_respects_limits() {
+        let limits = MotionLimits {
+            max_velocity: 3.0,
+            max_acceleration: 6.0,
+            ..Default::default()
+        };
+        let trajectory =
+            Trajectory::new_move(-1.0, 1.0, Duration::ZERO, Easing::EaseInOut, &limits).unwrap();
+
+        let positions = sample_positions(&trajectory);
+        assert!(max_velocity(&positions) <= limits.max_velocity * TOLERANCE);
+        assert!(max_acceleration(&positions) <= limits.max_acceleration * TOLERANCE);
+    }
+
+    #[test]
+    fn test_linear_respects_velocity_limit() {
+        let limits = MotionLimits {
+            max_velocity: 1.0,
+            max_acceleration: 1.0,
+            ..Default::default()
+        };
+        let trajectory = Trajectory::new_move(
+            -1.0,
+            1.0,
+            Duration::from_millis(500),
+            Easing::Linear,
+            &limits,
+        )
+        .unwrap();
+
+        assert!((trajectory.duration().as_secs_f32() - 2.0).abs() < 1e-4);
+        let positions = sample_positions(&trajectory);
+        assert!(max_velocity(&positions) <= limits.max_velocity * TOLERANCE);
+    }
+
+    #[test]
+    fn test_path() {
+        let limits = MotionLimits::default();
+        let keyframes = [
+            ServoKeyframe {
+                position: 1.0,
+                duration_ms: 1000,
+                easing: Easing::EaseInOut,
+            },
+            ServoKeyframe {
+                position: 1.0,
+                duration_ms: 500,
+                easing: Easing::Linear,
+            },
+            ServoKeyframe {
+                position: -1.0,
+                duration_ms: 1000,
+                easing: Easing::Trapezoidal,
+            },
+        ];
+        let trajectory = Trajectory::new_path(0.0, &keyframes, &limits).unwrap();
+
+        assert!((trajectory.duration().as_secs_f32() - 2.5).abs() < 1e-4);
+        assert_eq!(trajectory.sample(Duration::from_millis(1000)), Some(1.0));
+        assert_eq!(trajectory.sample(Duration::from_millis(1250)), Some(1.0));
+        assert_eq!(trajectory.target(), Some(-1.0));
+        assert!(!trajectory.is_finished(Duration::from_millis(2000)));
+        assert!(trajectory.is_finished(Duration::from_millis(2500)));
+
+        let positions = sample_positions(&trajectory);
+        assert!(max_velocity(&positions) <= limits.max_velocity * TOLERANCE);
+    }
+
+    #[test]
+    fn test_empty_path() {
+        let trajectory = Trajectory::new_path(0.3, &[], &MotionLimits::default()).unwrap();
+
+        assert_eq!(trajectory.duration(), Duration::ZERO);
+        assert_eq!(trajectory.sample(Duration::ZERO), None);
+    }
+
+    #[test]
+    fn test_non_finite_positions_are_rejected() {
+        let limits = MotionLimits::default();
+        let duration = Duration::from_millis(100);
+        for invalid in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert!(Trajectory::new_move(0.0, invalid, duration, Easing::Linear, &limits).is_err());
+
+            let keyframes = [
+                ServoKeyframe {
+                    position: 0.5,
+                    duration_ms: 100,
+                    easing: Easing::Linear,
+                },
+                ServoKeyframe {
+                    position: invalid,
+                    duration_ms: 100,
+                    easing: Easing::Linear,
+                },
+            ];
+            assert!(Trajectory::new_path(0.0, &keyframes, &limits).is_err());
+        }
+    }
+}
@@ -1,5 +1,22 @@
+use std::time::Duration;
+
 use serializer::ByteMessage;
 
+pub mod service_properties;
+
+pub use service_properties::*;
+
+/// Advertised by the PC app in the mDNS TXT record. Has to be increased on incompatible changes.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Hosts send `Keepalive` this often, so devices can tell an idle host from a hung one.
+pub const KEEPALIVE_PERIOD: Duration = Duration::from_secs(1);
+
+/// Keys of the mDNS TXT records of the RtAudioEffect service.
+pub const TXT_PRIORITY: &str = "priority";
+pub const TXT_VERSION: &str = "version";
+pub const TXT_INSTANCE_ID: &str = "id";
+
 #[derive(ByteMessage, Default, Debug)]
 pub struct EchoMessage {
     pub message: String,
@@ -14,6 +31,9 @@ pub struct IdentityMessage {
 #[derive(ByteMessage, Default, Debug)]
 pub struct IdentityRequestMessage {}
 
+#[derive(ByteMessage, Default, Debug)]
+pub struct KeepaliveMessage {}
+
 #[derive(ByteMessage, Default, Debug)]
 pub struct SetColorMessage {
     pub r: u8,
@@ -27,6 +47,99 @@ pub struct SetServo {
     pub position: f32,
 }
 
+#[derive(ByteMessage, Default, Debug, Clone, Copy, PartialEq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseInOut,
+    Trapezoidal,
+}
+
+#[derive(ByteMessage, Default, Debug, Clone, PartialEq)]
+pub struct ServoMove {
+    pub id: u8,
+    pub target: f32,
+    pub duration_ms: u32,
+    pub easing: Easing,
+}
+
+#[derive(ByteMessage, Default, Debug, Clone, PartialEq)]
+pub struct ServoKeyframe {
+    pub position: f32,
+    pub duration_ms: u32,
+    pub easing: Easing,
+}
+
+#[derive(ByteMessage, Default, Debug, Clone, PartialEq)]
+pub struct ServoPath {
+    pub id: u8,
+    pub keyframes: Vec<ServoKeyframe>,
+    /// Played again from the first keyframe until another command.
+    pub looping: bool,
+}
+
+#[derive(ByteMessage, Default, Debug, Clone, PartialEq)]
+pub struct SetServoPulse {
+    pub id: u8,
+    pub pulse_us: u32,
+}
+
+/// Set points are mapped into pulse width in `min_pulse_us..max_pulse_us` range.
+/// Soft limits and max speed are expressed in set point units (-1.0..1.0).
+#[derive(ByteMessage, Debug, Clone, PartialEq)]
+pub struct ServoCalibration {
+    pub min_pulse_us: u32,
+    pub max_pulse_us: u32,
+    pub center_trim: f32,
+    pub soft_min: f32,
+    pub soft_max: f32,
+    pub max_speed: f32,
+    pub inverted: bool,
+}
+
+impl Default for ServoCalibration {
+    fn default() -> Self {
+        Self {
+            min_pulse_us: 500,
+            max_pulse_us: 2500,
+            center_trim: 0.0,
+            soft_min: -1.0,
+            soft_max: 1.0,
+            max_speed: 4.0,
+            inverted: false,
+        }
+    }
+}
+
+#[derive(ByteMessage, Default, Debug, Clone, PartialEq)]
+pub struct ServoCalibrationRequestMessage {
+    pub id: u8,
+}
+
+#[derive(ByteMessage, Default, Debug, Clone, PartialEq)]
+pub struct ServoCalibrationMessage {
+    pub id: u8,
+    pub calibration: ServoCalibration,
+}
+
+/// Lights the headlight for a latency measurement, the device replies with `FlashAck`.
+#[derive(ByteMessage, Default, Debug, Clone, PartialEq)]
+pub struct FlashMessage {
+    pub id: u32,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// Timestamps of the device clock, which has an arbitrary origin, so only their difference
+/// is meaningful.
+#[derive(ByteMessage, Default, Debug, Clone, PartialEq)]
+pub struct FlashAckMessage {
+    pub id: u32,
+    pub received_us: u64,
+    pub lit_us: u64,
+}
+
 #[derive(ByteMessage, Debug, Default)]
 pub enum Message {
     #[default]
@@ -38,6 +151,15 @@ pub enum Message {
     Identity(IdentityMessage),
     SetColor(SetColorMessage),
     SetServo(SetServo),
+    ServoMove(ServoMove),
+    ServoPath(ServoPath),
+    SetServoPulse(SetServoPulse),
+    ServoCalibrationRequest(ServoCalibrationRequestMessage),
+    ServoCalibration(ServoCalibrationMessage),
+    SetServoCalibration(ServoCalibrationMessage),
+    Flash(FlashMessage),
+    FlashAck(FlashAckMessage),
+    Keepalive(KeepaliveMessage),
 }
 
 impl TryFrom<Vec<u8>> for Message {
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use log::warn;
+
+use crate::{PROTOCOL_VERSION, TXT_INSTANCE_ID, TXT_PRIORITY, TXT_VERSION};
+
+/// Published in TXT records, so devices can choose between multiple hosts.
+#[derive(Clone, Debug)]
+pub struct ServiceProperties {
+    /// Lower value is preferred by devices.
+    pub priority: u8,
+    /// Has to be unique in the network. Devices can be configured to prefer given instance.
+    pub instance_id: String,
+}
+
+impl ServiceProperties {
+    const PRIORITY_VARIABLE: &'static str = "RT_AUDIO_EFFECT_PRIORITY";
+    const INSTANCE_VARIABLE: &'static str = "RT_AUDIO_EFFECT_INSTANCE";
+
+    /// Reads `RT_AUDIO_EFFECT_PRIORITY` and `RT_AUDIO_EFFECT_INSTANCE` environment variables.
+    /// Defaults to priority 0 and the computer name.
+    pub fn from_env() -> Self {
+        let priority = match std::env::var(Self::PRIORITY_VARIABLE) {
+            Ok(value) => value.parse().unwrap_or_else(|err| {
+                warn!("Invalid {}: {}", Self::PRIORITY_VARIABLE, err);
+                0
+            }),
+            Err(_) => 0,
+        };
+
+        let instance_id = std::env::var(Self::INSTANCE_VARIABLE)
+            .or_else(|_| std::env::var("COMPUTERNAME"))
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or(String::from("default"));
+
+        Self {
+            priority,
+            instance_id,
+        }
+    }
+
+    pub fn to_txt(&self) -> HashMap<String, String> {
+        HashMap::from([
+            (TXT_PRIORITY.to_string(), self.priority.to_string()),
+            (TXT_VERSION.to_string(), PROTOCOL_VERSION.to_string()),
+            (TXT_INSTANCE_ID.to_string(), self.instance_id.clone()),
+        ])
+    }
+
+    // instance id may contain characters not allowed in the host name
+    pub fn host_label(&self) -> String {
+        self.instance_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect()
+    }
+}
@@ -0,0 +1,73 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rustfft::{num_complex::Complex, FftPlanner};
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/audio_analyzer/real_fft.rs"]
+mod real_fft;
+
+use real_fft::RealFft;
+
+/// Typical spectrum widths, one channel analyzed per iteration.
+const WIDTHS: [usize; 4] = [1024, 2048, 4096, 8192];
+
+fn samples(width: usize) -> Vec<f32> {
+    (0..width).map(|n| (n as f32 * 0.1).sin()).collect()
+}
+
+/// Real input packed into a complex FFT of half the size, magnitudes of size/2 bins.
+fn real_fft(c: &mut Criterion) {
+    let mut group = c.benchmark_group("real_fft");
+    for width in WIDTHS {
+        group.throughput(Throughput::Elements(width as u64));
+        let mut fft = RealFft::new(width);
+        let mut output = vec![Complex::default(); width / 2 + 1];
+        let mut magnitudes = vec![0.0f32; width / 2];
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(width),
+            &samples(width),
+            |b, data| {
+                b.iter(|| {
+                    fft.process(black_box(data), &mut output);
+                    for (magnitude, bin) in magnitudes.iter_mut().zip(&output) {
+                        *magnitude = bin.norm();
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Previous approach, complex FFT with zero imaginary parts, half of the bins thrown away.
+fn complex_fft(c: &mut Criterion) {
+    let mut group = c.benchmark_group("complex_fft");
+    for width in WIDTHS {
+        group.throughput(Throughput::Elements(width as u64));
+        let fft = FftPlanner::<f32>::new().plan_fft_forward(width);
+        let mut buffer = vec![Complex::default(); width];
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(width),
+            &samples(width),
+            |b, data| {
+                b.iter(|| {
+                    for (value, sample) in buffer.iter_mut().zip(black_box(data)) {
+                        *value = Complex::new(*sample, 0.0);
+                    }
+                    fft.process(&mut buffer);
+                    let magnitudes: Vec<f32> = buffer
+                        .iter()
+                        .map(|bin| bin.norm())
+                        .take(width / 2)
+                        .collect();
+                    black_box(magnitudes);
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, real_fft, complex_fft);
+criterion_main!(benches);
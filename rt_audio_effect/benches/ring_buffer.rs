@@ -0,0 +1,71 @@
+use std::sync::{Arc, Mutex};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/audio/ring_buffer.rs"]
+mod ring_buffer;
+
+use ring_buffer::ring_buffer;
+
+/// Samples of a 10 ms stereo callback at 48 kHz.
+const CALLBACK_SAMPLES: usize = 960;
+const CONSUMERS: [usize; 3] = [1, 2, 4];
+
+/// Ring buffers written from the audio callback, consumers drain them every callback.
+fn ring_buffer_send(c: &mut Criterion) {
+    let data = vec![0.5f32; CALLBACK_SAMPLES];
+    let mut group = c.benchmark_group("ring_buffer_send");
+    group.throughput(Throughput::Elements(CALLBACK_SAMPLES as u64));
+
+    for consumers in CONSUMERS {
+        let (mut producers, mut ring_consumers): (Vec<_>, Vec<_>) = (0..consumers)
+            .map(|_| ring_buffer::<f32>(48000 * 2))
+            .unzip();
+        let mut output = Vec::with_capacity(CALLBACK_SAMPLES);
+
+        group.bench_with_input(BenchmarkId::from_parameter(consumers), &data, |b, data| {
+            b.iter(|| {
+                for producer in producers.iter_mut() {
+                    producer.push_slice(black_box(data));
+                }
+                for consumer in ring_consumers.iter_mut() {
+                    output.clear();
+                    consumer.pop_into(&mut output, usize::MAX);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Previous approach, the data is cloned for every consumer and stored under its mutex.
+fn mutex_vec_send(c: &mut Criterion) {
+    let data = vec![0.5f32; CALLBACK_SAMPLES];
+    let mut group = c.benchmark_group("mutex_vec_send");
+    group.throughput(Throughput::Elements(CALLBACK_SAMPLES as u64));
+
+    for consumers in CONSUMERS {
+        let buffers: Vec<_> = (0..consumers)
+            .map(|_| Arc::new(Mutex::new(Vec::<f32>::with_capacity(48000 * 2))))
+            .collect();
+
+        group.bench_with_input(BenchmarkId::from_parameter(consumers), &data, |b, data| {
+            b.iter(|| {
+                for buffer in buffers.iter() {
+                    let samples = black_box(data.clone());
+                    buffer.lock().unwrap().extend(samples);
+                }
+                for buffer in buffers.iter() {
+                    let mut buffer = buffer.lock().unwrap();
+                    let len = buffer.len();
+                    buffer.drain(0..len);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, ring_buffer_send, mutex_vec_send);
+criterion_main!(benches);
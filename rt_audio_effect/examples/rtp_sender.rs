@@ -0,0 +1,74 @@
+//! Streams a WAV file over RTP in real time, companion of the network audio source.
+//!
+//! `cargo run --example rtp_sender -- <file.wav> [address] [L16|L24]`
+
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[allow(dead_code)]
+#[path = "../src/audio/rtp.rs"]
+mod rtp;
+
+use rtp::{PayloadFormat, RtpSender};
+
+/// 1 ms packets, as in AES67.
+const PACKET_DURATION: Duration = Duration::from_millis(1);
+
+fn read_wav(path: &str) -> Result<(hound::WavSpec, Vec<f32>), String> {
+    let reader = hound::WavReader::open(path).map_err(|err| err.to_string())?;
+    let spec = reader.spec();
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .into_samples::<f32>()
+            .collect::<Result<Vec<_>, _>>(),
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / scale))
+                .collect()
+        }
+    }
+    .map_err(|err| err.to_string())?;
+    Ok((spec, samples))
+}
+
+fn main() -> Result<(), String> {
+    let mut args = std::env::args().skip(1);
+    let path = args
+        .next()
+        .ok_or("Usage: rtp_sender <file.wav> [address] [L16|L24]")?;
+    let address: SocketAddr = args
+        .next()
+        .unwrap_or(String::from("127.0.0.1:5004"))
+        .parse()
+        .map_err(|err| format!("Invalid address: {err}"))?;
+    let format = match args.next().as_deref() {
+        Some("L16") => PayloadFormat::L16,
+        Some("L24") | None => PayloadFormat::L24,
+        Some(format) => return Err(format!("Unsupported format: {format}")),
+    };
+
+    let (spec, samples) = read_wav(&path)?;
+    let channels = spec.channels as usize;
+    let frames_per_packet =
+        (spec.sample_rate as f32 * PACKET_DURATION.as_secs_f32()).round() as usize;
+    println!(
+        "Streaming {path} ({} Hz, {channels} ch) as RTP {format} to {address}, looping",
+        spec.sample_rate
+    );
+
+    let mut sender = RtpSender::new(address, format, channels, frames_per_packet)?;
+    let packet_samples = frames_per_packet * channels;
+    let mut next_packet = Instant::now();
+    loop {
+        for packet in samples.chunks_exact(packet_samples) {
+            sender.send(packet)?;
+            next_packet += PACKET_DURATION;
+            thread::sleep(next_packet.saturating_duration_since(Instant::now()));
+        }
+    }
+}
@@ -4,16 +4,76 @@ use log::{trace, warn};
 
 use crate::audio_analyzer::MultiChannel;
 
-use super::{ChannelSamples, MixedChannelsSamples, StreamParameters};
+use super::{
+    input_conditioning::InputConditioning,
+    ring_buffer::{ring_buffer, RingConsumer, RingProducer},
+    statistics::BufferStatistics,
+    BlockTimestamp, ChannelSamples, MixedChannelsSamples, Sample, StreamParameters, Timestamp,
+};
+
+/// Producer side of the connection between a stream and an `AudioBuffer`, used on the audio
+/// thread.
+pub struct AudioBufferInput {
+    samples: RingProducer<Sample>,
+    timestamps: RingProducer<BlockTimestamp>,
+    channels: usize,
+    frames: u64,
+}
+
+impl AudioBufferInput {
+    /// Sends interleaved samples captured at `timestamp`, returns false when they were
+    /// dropped because the buffer was full.
+    pub fn push(&mut self, data: &[Sample], timestamp: Timestamp) -> bool {
+        let frame = self.frames;
+        if !self.samples.push_slice(data) {
+            return false;
+        }
+        self.frames += (data.len() / self.channels) as u64;
+        // a lost timestamp only makes the consumer extrapolate from an older one
+        self.timestamps
+            .push_slice(&[BlockTimestamp { frame, timestamp }]);
+        true
+    }
+
+    /// Number of interleaved samples which can be pushed without an overrun.
+    pub fn get_free_len(&self) -> usize {
+        self.samples.get_free_len()
+    }
+
+    /// False once the buffer connected to another stream, or was dropped.
+    pub fn is_connected(&self) -> bool {
+        !self.samples.is_abandoned()
+    }
+}
+
+struct BufferInput {
+    samples: RingConsumer<Sample>,
+    timestamps: RingConsumer<BlockTimestamp>,
+}
 
 pub struct AudioBuffer {
     channels: u16,
     buffer_duration_in_samples: usize,
     channels_buffers: MultiChannel<ChannelSamples>,
     new_samples_count: usize,
+    sample_rate: u32,
+    /// Frames stored since the creation, the newest frame is `stored_frames - 1`.
+    stored_frames: u64,
+    /// Latest known capture time, timestamps of other frames are extrapolated from it.
+    anchor: BlockTimestamp,
+    input: Option<BufferInput>,
+    input_samples: Vec<Sample>,
+    input_timestamps: Vec<BlockTimestamp>,
+    conditioning: Option<InputConditioning>,
+    statistics: BufferStatistics,
+    /// Overruns of the current ring buffer already counted in `statistics`.
+    counted_overruns: usize,
 }
 
 impl AudioBuffer {
+    /// Callbacks between two fetches, the oldest timestamps are lost when it overflows.
+    const TIMESTAMPS_CAPACITY: usize = 256;
+
     pub fn new(stream_parameters: Arc<StreamParameters>, buffer_duration: Duration) -> AudioBuffer {
         let buffer_duration_in_samples =
             (stream_parameters.sample_rate as f32 * buffer_duration.as_secs_f32()) as usize;
@@ -27,17 +87,130 @@ impl AudioBuffer {
             buffer_duration_in_samples,
             channels_buffers: empty_channels_buffers.into(),
             new_samples_count: 0,
+            sample_rate: stream_parameters.sample_rate,
+            stored_frames: 0,
+            anchor: BlockTimestamp::default(),
+            input: None,
+            input_samples: Vec::new(),
+            input_timestamps: Vec::new(),
+            conditioning: None,
+            statistics: BufferStatistics::default(),
+            counted_overruns: 0,
         }
     }
 
+    /// Ring buffers for blocks sent by a stream, replacing the previous ones.
+    /// The input is meant for the audio thread, `fetch` moves the samples into this buffer.
+    pub fn connect(&mut self) -> AudioBufferInput {
+        let (samples, samples_consumer) =
+            ring_buffer(self.buffer_duration_in_samples * self.channels as usize);
+        let (timestamps, timestamps_consumer) = ring_buffer(Self::TIMESTAMPS_CAPACITY);
+        self.statistics.dropped_samples += self.get_overrun_count() - self.counted_overruns;
+        self.counted_overruns = 0;
+        self.input = Some(BufferInput {
+            samples: samples_consumer,
+            timestamps: timestamps_consumer,
+        });
+        AudioBufferInput {
+            samples,
+            timestamps,
+            channels: self.channels as usize,
+            frames: self.stored_frames,
+        }
+    }
+
+    /// Moves samples sent by the stream into channel buffers. Unread samples are never
+    /// overwritten, what doesn't fit stays in the ring buffer.
+    pub fn fetch(&mut self) {
+        let Some(input) = &mut self.input else {
+            return;
+        };
+
+        self.input_timestamps.clear();
+        input
+            .timestamps
+            .pop_into(&mut self.input_timestamps, usize::MAX);
+        if let Some(timestamp) = self.input_timestamps.last() {
+            self.anchor = *timestamp;
+        }
+
+        let free = self.buffer_duration_in_samples - self.new_samples_count;
+        self.input_samples.clear();
+        input
+            .samples
+            .pop_into(&mut self.input_samples, free * self.channels as usize);
+        if self.input_samples.is_empty() {
+            return;
+        }
+
+        let data = std::mem::take(&mut self.input_samples);
+        self.store_slice(&data);
+        self.input_samples = data;
+    }
+
+    /// Number of samples dropped by the stream because the ring buffer was full.
+    pub fn get_overrun_count(&self) -> usize {
+        self.input
+            .as_ref()
+            .map_or(0, |input| input.samples.get_overrun_count())
+    }
+
+    /// Samples lost since the creation or the last reset.
+    pub fn get_statistics(&self) -> BufferStatistics {
+        BufferStatistics {
+            dropped_samples: self.statistics.dropped_samples + self.get_overrun_count()
+                - self.counted_overruns,
+            ..self.statistics
+        }
+    }
+
+    pub fn reset_statistics(&mut self) {
+        self.statistics = BufferStatistics::default();
+        self.counted_overruns = self.get_overrun_count();
+    }
+
+    /// Conditioning applied to new samples as they are stored, before anyone reads them.
+    pub fn set_conditioning(&mut self, conditioning: Option<InputConditioning>) {
+        self.conditioning = conditioning;
+    }
+
+    pub fn get_conditioning(&self) -> Option<&InputConditioning> {
+        self.conditioning.as_ref()
+    }
+
+    /// Stores samples captured now, without the ring buffer.
     pub fn store(&mut self, data: MixedChannelsSamples) {
+        self.anchor = BlockTimestamp {
+            frame: self.stored_frames,
+            timestamp: Timestamp::now(),
+        };
+        self.store_slice(data.inner());
+    }
+
+    fn store_slice(&mut self, data: &[Sample]) {
         let new_samples = self.distribute_into_channels(data);
+        if let Some(conditioning) = &mut self.conditioning {
+            let mut channels: Vec<&mut [Sample]> = self
+                .channels_buffers
+                .inner_mut()
+                .iter_mut()
+                .map(|buffer| {
+                    let buffer = buffer.inner_mut();
+                    let start = buffer.len() - new_samples;
+                    &mut buffer[start..]
+                })
+                .collect();
+            conditioning.process(&mut channels);
+        }
         self.trim_buffers();
 
+        self.stored_frames += new_samples as u64;
         self.new_samples_count += new_samples;
         if self.new_samples_count > self.buffer_duration_in_samples {
             let overrun = self.new_samples_count - self.buffer_duration_in_samples;
             self.new_samples_count = self.buffer_duration_in_samples;
+            self.statistics.overruns += 1;
+            self.statistics.skipped_samples += overrun;
 
             warn!("Buffer overrun by: {overrun:#?}");
         }
@@ -47,11 +220,39 @@ impl AudioBuffer {
         self.new_samples_count
     }
 
+    pub fn get_capacity(&self) -> usize {
+        self.buffer_duration_in_samples
+    }
+
+    /// Resizes channel buffers, keeping the newest samples. A connected stream keeps sending
+    /// into the same ring buffer.
+    pub fn set_buffer_duration(&mut self, buffer_duration: Duration) {
+        let buffer_duration_in_samples =
+            (self.sample_rate as f32 * buffer_duration.as_secs_f32()) as usize;
+        for buffer in self.channels_buffers.inner_mut() {
+            let buffer = buffer.inner_mut();
+            if buffer_duration_in_samples > buffer.len() {
+                let missing = buffer_duration_in_samples - buffer.len();
+                buffer.splice(0..0, std::iter::repeat_n(0.0, missing));
+            } else {
+                buffer.drain(0..buffer.len() - buffer_duration_in_samples);
+            }
+        }
+        self.buffer_duration_in_samples = buffer_duration_in_samples;
+        self.new_samples_count = self.new_samples_count.min(buffer_duration_in_samples);
+    }
+
+    /// Capture time of the frame, `frame` counts from the creation of the buffer.
+    pub fn get_frame_timestamp(&self, frame: u64) -> Timestamp {
+        self.anchor.get_frame_timestamp(frame, self.sample_rate)
+    }
+
+    /// Returns the window with capture time of its newest sample.
     pub fn read_new_samples(
         &mut self,
         new_samples: usize,
         total_sample_count: usize,
-    ) -> Result<MultiChannel<ChannelSamples>, String> {
+    ) -> Result<(MultiChannel<ChannelSamples>, Timestamp), String> {
         trace!("Getting {total_sample_count} for all channels, with new samples: {new_samples}");
 
         assert!(
@@ -65,12 +266,22 @@ impl AudioBuffer {
         );
 
         if self.new_samples_count < new_samples {
+            self.statistics.underruns += 1;
             return Err(String::from("Not enough new data"));
         }
 
-        let start_index =
-            self.buffer_duration_in_samples - self.new_samples_count - total_sample_count
-                + new_samples;
+        // the oldest new samples are already out of the window, the consumer fell behind
+        let max_new_samples_count =
+            self.buffer_duration_in_samples - total_sample_count + new_samples;
+        if self.new_samples_count > max_new_samples_count {
+            let skipped = self.new_samples_count - max_new_samples_count;
+            self.statistics.overruns += 1;
+            self.statistics.skipped_samples += skipped;
+            warn!("Skipping {skipped} samples");
+            self.new_samples_count = max_new_samples_count;
+        }
+
+        let start_index = max_new_samples_count - self.new_samples_count;
         let end_index = start_index + total_sample_count;
 
         let mut channels_samples: Vec<ChannelSamples> = Vec::new();
@@ -80,9 +291,14 @@ impl AudioBuffer {
             channels_samples.push(samples.into());
         }
 
+        let last_frame =
+            self.stored_frames - self.new_samples_count as u64 + new_samples as u64 - 1;
         self.new_samples_count -= new_samples;
 
-        Ok(channels_samples.into())
+        Ok((
+            channels_samples.into(),
+            self.get_frame_timestamp(last_frame),
+        ))
     }
 
     fn trim_buffers(&mut self) {
@@ -99,15 +315,15 @@ impl AudioBuffer {
         }
     }
 
-    fn distribute_into_channels(&mut self, data: MixedChannelsSamples) -> usize {
-        let new_samples_per_channel = data.inner().len() / self.channels as usize;
+    fn distribute_into_channels(&mut self, data: &[Sample]) -> usize {
+        let new_samples_per_channel = data.len() / self.channels as usize;
         trace!(
             "Distributing samples into separate channels. Channel count {}, new sample count per channel {}",
             self.channels,
             new_samples_per_channel
         );
 
-        for (sample_number, sample) in data.inner().into_iter().enumerate() {
+        for (sample_number, sample) in data.iter().enumerate() {
             let channel = sample_number % self.channels as usize;
             self.channels_buffers
                 .get_channel_mut(channel)
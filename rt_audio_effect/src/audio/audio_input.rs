@@ -0,0 +1,12 @@
+use std::sync::{Arc, Mutex};
+
+use super::{AudioStreamConsumer, StreamParameters};
+
+/// Source of samples delivered to `AudioStreamConsumer`s, e.g. a live device or a file.
+pub trait AudioInput {
+    fn start(&self);
+    fn stop(&self);
+    fn is_running(&self) -> bool;
+    fn get_parameters(&self) -> Arc<StreamParameters>;
+    fn add_stream_consumer(&mut self, stream_consumer: Arc<Mutex<dyn AudioStreamConsumer>>);
+}
@@ -1,15 +1,81 @@
+use std::fmt::Display;
+
 use cpal::{
     self,
     traits::{DeviceTrait, HostTrait},
-    Device,
+    Device, HostId, SupportedStreamConfigRange,
 };
 
-use log::{debug, info};
+use log::{debug, info, warn};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioDirection {
+    Input,
+    /// Output device captured as a loopback. Supported by WASAPI only.
+    Output,
+}
+
+/// Identifies audio device, so it can be found again after enumeration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AudioSource {
+    pub host: HostId,
+    pub device_name: String,
+    pub direction: AudioDirection,
+}
+
+impl Display for AudioSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let direction = match self.direction {
+            AudioDirection::Input => "input",
+            AudioDirection::Output => "loopback",
+        };
+        write!(
+            f,
+            "{} / {} ({})",
+            self.host.name(),
+            self.device_name,
+            direction
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AudioDeviceInfo {
+    pub source: AudioSource,
+    pub is_default: bool,
+    pub supported_configs: Vec<SupportedStreamConfigRange>,
+}
 
 pub struct AudioManager {}
 
 impl AudioManager {
-    pub fn get_default_loopback() -> Result<Device, &'static str> {
+    pub fn get_default_source(
+        host_id: HostId,
+        direction: AudioDirection,
+    ) -> Result<AudioSource, &'static str> {
+        let Ok(host) = cpal::host_from_id(host_id) else {
+            return Err("Failed to find Host");
+        };
+
+        let device = match direction {
+            AudioDirection::Input => host.default_input_device(),
+            AudioDirection::Output => host.default_output_device(),
+        };
+        let Some(device) = device else {
+            return Err("No default device");
+        };
+        let Ok(device_name) = device.name() else {
+            return Err("Failed to get device name");
+        };
+
+        Ok(AudioSource {
+            host: host_id,
+            device_name,
+            direction,
+        })
+    }
+
+    pub fn get_default_loopback() -> Result<AudioSource, &'static str> {
         let available_hosts = cpal::available_hosts();
         info!("Searching for default loopback device");
 
@@ -38,8 +104,100 @@ impl AudioManager {
             debug!("Audio device: {}", output_device.name().unwrap());
         }
 
-        info!("Selected audio device: {}", device.name().unwrap());
+        let Ok(device_name) = device.name() else {
+            return Err("Failed to get device name");
+        };
+        info!("Selected audio device: {}", device_name);
+
+        Ok(AudioSource {
+            host: selected_host_id,
+            device_name,
+            direction: AudioDirection::Output,
+        })
+    }
+
+    pub fn get_hosts() -> Vec<HostId> {
+        cpal::available_hosts()
+    }
+
+    /// Lists input and output devices of the host, with configs supported for capturing.
+    pub fn get_devices(host_id: HostId) -> Result<Vec<AudioDeviceInfo>, &'static str> {
+        let Ok(host) = cpal::host_from_id(host_id) else {
+            return Err("Failed to find Host");
+        };
+
+        let default_input = host.default_input_device().and_then(|d| d.name().ok());
+        let default_output = host.default_output_device().and_then(|d| d.name().ok());
+
+        let mut devices = Vec::new();
+        for (direction, default_name) in [
+            (AudioDirection::Input, default_input),
+            (AudioDirection::Output, default_output),
+        ] {
+            let host_devices = match direction {
+                AudioDirection::Input => host.input_devices(),
+                AudioDirection::Output => host.output_devices(),
+            };
+            let Ok(host_devices) = host_devices else {
+                warn!(
+                    "Failed to enumerate {direction:?} devices of {}",
+                    host_id.name()
+                );
+                continue;
+            };
+
+            for device in host_devices {
+                let Ok(device_name) = device.name() else {
+                    continue;
+                };
+                let supported_configs: Vec<SupportedStreamConfigRange> = match direction {
+                    AudioDirection::Input => device
+                        .supported_input_configs()
+                        .map(|configs| configs.collect()),
+                    AudioDirection::Output => device
+                        .supported_output_configs()
+                        .map(|configs| configs.collect()),
+                }
+                .unwrap_or_default();
+
+                devices.push(AudioDeviceInfo {
+                    is_default: default_name.as_ref() == Some(&device_name),
+                    source: AudioSource {
+                        host: host_id,
+                        device_name,
+                        direction,
+                    },
+                    supported_configs,
+                });
+            }
+        }
+
+        Ok(devices)
+    }
+
+    pub fn get_all_devices() -> Vec<AudioDeviceInfo> {
+        Self::get_hosts()
+            .into_iter()
+            .filter_map(|host_id| Self::get_devices(host_id).ok())
+            .flatten()
+            .collect()
+    }
+
+    pub fn find_device(source: &AudioSource) -> Result<Device, &'static str> {
+        let Ok(host) = cpal::host_from_id(source.host) else {
+            return Err("Failed to find Host");
+        };
+
+        let devices = match source.direction {
+            AudioDirection::Input => host.input_devices(),
+            AudioDirection::Output => host.output_devices(),
+        };
+        let Ok(mut devices) = devices else {
+            return Err("Failed to enumerate devices");
+        };
 
-        Ok(device)
+        devices
+            .find(|device| device.name().ok().as_ref() == Some(&source.device_name))
+            .ok_or("Failed to find device")
     }
 }
@@ -1,114 +1,389 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
 
 use cpal::{
     traits::{DeviceTrait, StreamTrait},
-    Device, InputCallbackInfo, Stream,
+    Device, FromSample, InputCallbackInfo, SampleFormat, SizedSample, Stream, StreamConfig,
+    SupportedStreamConfig,
 };
 use log::{error, info, trace};
 
-use super::{AudioBuffer, AudioStreamConsumer, StreamParameters};
+use super::{
+    sample_conversion::{convert_samples_into, select_config},
+    statistics::CallbackStatistics,
+    AudioBufferInput, AudioDirection, AudioInput, AudioManager, AudioSource, AudioStreamConsumer,
+    Sample, StreamParameters, Timestamp,
+};
 
-struct AudioStreamSender {
-    data_stream_receivers: Vec<Arc<Mutex<AudioBuffer>>>,
+/// Sends samples to the ring buffers of the consumers. Used on the audio thread, so it
+/// neither blocks nor allocates once `converted_samples` is large enough.
+pub(super) struct AudioStreamSender {
+    data_stream_receivers: Vec<AudioBufferInput>,
+    converted_samples: Vec<Sample>,
 }
 
 impl AudioStreamSender {
     pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// `capacity` is the expected number of samples in one callback.
+    pub fn with_capacity(capacity: usize) -> Self {
         AudioStreamSender {
             data_stream_receivers: Vec::new(),
+            converted_samples: Vec::with_capacity(capacity),
         }
     }
 
+    /// Receivers whose consumers connected to another stream since are dropped here, not on
+    /// the audio thread. Reconnecting a consumer replaces its receiver.
     pub fn add_stream_receiver(&mut self, stream_receiver: Arc<Mutex<dyn AudioStreamConsumer>>) {
         info!(
             "Adding new stream receiver: {}",
             stream_receiver.lock().unwrap().get_name()
         );
+        let receiver = stream_receiver.lock().unwrap().connect();
+        self.data_stream_receivers
+            .retain(AudioBufferInput::is_connected);
+        self.data_stream_receivers.push(receiver);
+    }
+
+    /// Whether all connected receivers can take `samples` more interleaved samples without
+    /// an overrun. Disconnected ones never drain, so they don't hold back the source.
+    pub fn has_room(&self, samples: usize) -> bool {
         self.data_stream_receivers
-            .push(stream_receiver.lock().unwrap().get_audio_buffer());
+            .iter()
+            .filter(|receiver| receiver.is_connected())
+            .all(|receiver| receiver.get_free_len() >= samples)
+    }
+
+    #[cfg(test)]
+    pub fn get_receiver_count(&self) -> usize {
+        self.data_stream_receivers.len()
+    }
+
+    /// Interleaved samples captured at `timestamp` are dropped as a whole by receivers which
+    /// are full.
+    pub fn send_data(&mut self, data: &[Sample], timestamp: Timestamp) {
+        for data_stream_receiver in self.data_stream_receivers.iter_mut() {
+            data_stream_receiver.push(data, timestamp);
+        }
     }
 
-    pub fn send_data(&mut self, data: Vec<f32>) {
-        for data_stream_receiver in self.data_stream_receivers.iter() {
-            data_stream_receiver
-                .lock()
-                .unwrap()
-                .store(data.clone().into());
+    pub fn send_converted_data<T>(&mut self, data: &[T], timestamp: Timestamp)
+    where
+        T: SizedSample,
+        Sample: FromSample<T>,
+    {
+        convert_samples_into(data, &mut self.converted_samples);
+        for data_stream_receiver in self.data_stream_receivers.iter_mut() {
+            data_stream_receiver.push(&self.converted_samples, timestamp);
         }
     }
 }
 
 pub struct AudioStream {
     _device: Device,
+    source: AudioSource,
     stream: Stream,
     parameters: Arc<StreamParameters>,
     stream_sender: Arc<Mutex<AudioStreamSender>>,
+    statistics: Arc<Mutex<CallbackStatistics>>,
+    failed: Arc<AtomicBool>,
+    running: AtomicBool,
 }
 
 impl AudioStream {
-    pub fn new(device: Device) -> Result<AudioStream, &'static str> {
-        let Ok(config) = device.default_output_config() else {
-            return Err("Failed to get default config");
+    /// Callbacks are usually shorter, the conversion buffer grows in the rare case they aren't.
+    const MAX_CALLBACK_DURATION_MS: usize = 100;
+
+    pub fn new(source: &AudioSource) -> Result<AudioStream, &'static str> {
+        let device = AudioManager::find_device(source)?;
+        let (default_config, supported_configs) = match source.direction {
+            AudioDirection::Input => (
+                device.default_input_config().ok(),
+                device
+                    .supported_input_configs()
+                    .map(|configs| configs.collect::<Vec<_>>()),
+            ),
+            AudioDirection::Output => (
+                device.default_output_config().ok(),
+                device
+                    .supported_output_configs()
+                    .map(|configs| configs.collect::<Vec<_>>()),
+            ),
         };
 
-        let sample_rate = config.sample_rate().0;
-        let channels = config.channels();
-        let parameters = Arc::new(StreamParameters {
-            sample_rate,
-            channels,
-        });
+        // e.g. WASAPI loopback accepts only the mix format, so the default one is the fallback
+        let mut candidates = Vec::new();
+        candidates.extend(select_config(
+            default_config.clone(),
+            &supported_configs.unwrap_or_default(),
+        ));
+        candidates.extend(default_config.filter(|config| !candidates.contains(config)));
+        if candidates.is_empty() {
+            return Err("Failed to get supported config");
+        }
 
-        info!("Creating new audio stream with: {parameters}");
+        let failed = Arc::new(AtomicBool::new(false));
+        let statistics = Arc::new(Mutex::new(CallbackStatistics::default()));
 
-        let cpal::SampleFormat::F32 = config.sample_format() else {
-            error!("Unsupported format");
-            panic!("Unsupported format");
+        let mut built = None;
+        for config in candidates {
+            info!(
+                "Creating new audio stream from {source} with: {} ch, {} Hz, {}",
+                config.channels(),
+                config.sample_rate().0,
+                config.sample_format()
+            );
+            let callback_samples = config.sample_rate().0 as usize
+                * config.channels() as usize
+                * Self::MAX_CALLBACK_DURATION_MS
+                / 1000;
+            let stream_sender = Arc::new(Mutex::new(AudioStreamSender::with_capacity(
+                callback_samples,
+            )));
+            match Self::build_stream(
+                &device,
+                &config,
+                stream_sender.clone(),
+                statistics.clone(),
+                failed.clone(),
+            ) {
+                Ok(stream) => {
+                    built = Some((stream, config, stream_sender));
+                    break;
+                }
+                Err(err) => error!("Failed to build in/out stream: {err}"),
+            }
+        }
+        let Some((stream, config, stream_sender)) = built else {
+            return Err("Failed to build in/out stream");
         };
 
-        let stream_sender = Arc::new(Mutex::new(AudioStreamSender::new()));
-        let stream_sender_copy = stream_sender.clone();
-        let Ok(stream) = device.build_input_stream(
-            &config.into(),
-            move |data, _info: &InputCallbackInfo| {
-                trace!(target:"cpal::Stream", "Sending new data with len: {}", data.len());
-                stream_sender_copy.lock().unwrap().send_data(data.to_vec());
-            },
-            move |err| error!("A error occured on stream: {err:?}"),
-            None,
-        ) else {
-            error!("Failed to build in/out stream");
-            panic!("Failed to build in/out stream");
-        };
+        let parameters = Arc::new(StreamParameters {
+            sample_rate: config.sample_rate().0,
+            channels: config.channels(),
+        });
 
         Ok(AudioStream {
             _device: device,
+            source: source.clone(),
             stream,
             parameters,
             stream_sender,
+            statistics,
+            failed,
+            running: AtomicBool::new(false),
         })
     }
 
-    pub fn start(&self) {
+    fn build_stream(
+        device: &Device,
+        config: &SupportedStreamConfig,
+        stream_sender: Arc<Mutex<AudioStreamSender>>,
+        statistics: Arc<Mutex<CallbackStatistics>>,
+        failed: Arc<AtomicBool>,
+    ) -> Result<Stream, String> {
+        let stream_config = config.config();
+        match config.sample_format() {
+            SampleFormat::I8 => Self::build_typed_stream::<i8>(
+                device,
+                &stream_config,
+                stream_sender,
+                statistics,
+                failed,
+            ),
+            SampleFormat::I16 => Self::build_typed_stream::<i16>(
+                device,
+                &stream_config,
+                stream_sender,
+                statistics,
+                failed,
+            ),
+            SampleFormat::I32 => Self::build_typed_stream::<i32>(
+                device,
+                &stream_config,
+                stream_sender,
+                statistics,
+                failed,
+            ),
+            SampleFormat::I64 => Self::build_typed_stream::<i64>(
+                device,
+                &stream_config,
+                stream_sender,
+                statistics,
+                failed,
+            ),
+            SampleFormat::U8 => Self::build_typed_stream::<u8>(
+                device,
+                &stream_config,
+                stream_sender,
+                statistics,
+                failed,
+            ),
+            SampleFormat::U16 => Self::build_typed_stream::<u16>(
+                device,
+                &stream_config,
+                stream_sender,
+                statistics,
+                failed,
+            ),
+            SampleFormat::U32 => Self::build_typed_stream::<u32>(
+                device,
+                &stream_config,
+                stream_sender,
+                statistics,
+                failed,
+            ),
+            SampleFormat::U64 => Self::build_typed_stream::<u64>(
+                device,
+                &stream_config,
+                stream_sender,
+                statistics,
+                failed,
+            ),
+            SampleFormat::F32 => Self::build_typed_stream::<f32>(
+                device,
+                &stream_config,
+                stream_sender,
+                statistics,
+                failed,
+            ),
+            SampleFormat::F64 => Self::build_typed_stream::<f64>(
+                device,
+                &stream_config,
+                stream_sender,
+                statistics,
+                failed,
+            ),
+            sample_format => Err(format!("Unsupported format: {sample_format}")),
+        }
+    }
+
+    fn build_typed_stream<T>(
+        device: &Device,
+        config: &StreamConfig,
+        stream_sender: Arc<Mutex<AudioStreamSender>>,
+        statistics: Arc<Mutex<CallbackStatistics>>,
+        failed: Arc<AtomicBool>,
+    ) -> Result<Stream, String>
+    where
+        T: SizedSample,
+        Sample: FromSample<T>,
+    {
+        let sample_rate = config.sample_rate.0;
+        let channels = config.channels as usize;
+        // kept by the callback, the shared statistics are only updated when not being read
+        let mut last_callback: Option<Instant> = None;
+        let mut frames = 0;
+        let mut dropped_callbacks = 0;
+        device
+            .build_input_stream(
+                config,
+                move |data: &[T], info: &InputCallbackInfo| {
+                    let now = Instant::now();
+                    let timestamp = Self::get_capture_timestamp(info);
+                    trace!(target:"cpal::Stream", "Sending new data with len: {}", data.len());
+                    // locked only while a consumer is added, the data is dropped then
+                    if let Ok(mut stream_sender) = stream_sender.try_lock() {
+                        stream_sender.send_converted_data(data, timestamp);
+                    } else {
+                        dropped_callbacks += 1;
+                    }
+
+                    let callback_frames = data.len() / channels;
+                    frames += callback_frames as u64;
+                    if let Ok(mut statistics) = statistics.try_lock() {
+                        if let Some(last_callback) = last_callback {
+                            statistics.push(now - last_callback, callback_frames, sample_rate);
+                        }
+                        statistics.frames = frames;
+                        statistics.dropped_callbacks = dropped_callbacks;
+                    }
+                    last_callback = Some(now);
+                },
+                move |err| {
+                    error!("A error occured on stream: {err:?}");
+                    failed.store(true, Ordering::Relaxed);
+                },
+                None,
+            )
+            .map_err(|err| err.to_string())
+    }
+
+    /// Stream instants have an unspecified origin, so the capture time is the time of
+    /// the callback moved back by the delay reported by the host.
+    fn get_capture_timestamp(info: &InputCallbackInfo) -> Timestamp {
+        let now = Instant::now();
+        let timestamp = info.timestamp();
+        let delay = timestamp
+            .callback
+            .duration_since(&timestamp.capture)
+            .unwrap_or_default();
+        Timestamp::from_instant(now) - delay
+    }
+
+    /// Stream reported an error, e.g. the device was unplugged, and has to be rebuilt.
+    pub fn has_failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn get_source(&self) -> &AudioSource {
+        &self.source
+    }
+
+    /// Timing of callbacks since the stream was built or the last reset.
+    pub fn get_statistics(&self) -> CallbackStatistics {
+        *self.statistics.lock().unwrap()
+    }
+
+    /// Totals kept by the callback are not reset.
+    pub fn reset_statistics(&self) {
+        let mut statistics = self.statistics.lock().unwrap();
+        *statistics = CallbackStatistics {
+            frames: statistics.frames,
+            dropped_callbacks: statistics.dropped_callbacks,
+            ..Default::default()
+        };
+    }
+}
+
+impl AudioInput for AudioStream {
+    fn start(&self) {
         info!("Starting stream");
 
         if let Err(err) = self.stream.play() {
             error!("Error occured during start(): {err:#?}");
+            self.failed.store(true, Ordering::Relaxed);
         }
+        self.running.store(true, Ordering::Relaxed);
     }
 
-    pub fn stop(&self) {
+    fn stop(&self) {
         info!("Stopping stream");
 
         if let Err(err) = self.stream.pause() {
             error!("Error occured during stop(): {err:#?}");
         }
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    /// Stream was started by user, its state is carried over when the stream is rebuilt.
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
     }
 
-    pub fn get_parameters(&self) -> Arc<StreamParameters> {
+    fn get_parameters(&self) -> Arc<StreamParameters> {
         self.parameters.clone()
     }
 
-    pub fn add_stream_consumer(&mut self, stream_consumer: Arc<Mutex<dyn AudioStreamConsumer>>) {
+    fn add_stream_consumer(&mut self, stream_consumer: Arc<Mutex<dyn AudioStreamConsumer>>) {
         self.stream_sender
             .lock()
             .unwrap()
@@ -1,9 +1,17 @@
 use std::sync::{Arc, Mutex};
 
-use super::AudioBuffer;
+use super::{AudioBuffer, AudioBufferInput, StreamParameters};
 
 pub trait AudioStreamConsumer: Send {
     fn process_new_samples(&mut self);
     fn get_audio_buffer(&self) -> Arc<Mutex<AudioBuffer>>;
+    /// Creates ring buffers between a stream and the consumer. The stream writes to the returned
+    /// input, `process_new_samples` reads it.
+    fn connect(&self) -> AudioBufferInput {
+        self.get_audio_buffer().lock().unwrap().connect()
+    }
     fn get_name(&self) -> String;
+    /// Called when the stream was rebuilt with different parameters, before samples of the new
+    /// stream arrive. The consumer may replace its audio buffer.
+    fn on_stream_parameters_changed(&mut self, parameters: Arc<StreamParameters>);
 }
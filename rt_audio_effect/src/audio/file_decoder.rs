@@ -0,0 +1,244 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use hound::{SampleFormat, WavReader};
+
+use super::{Sample, StreamParameters};
+
+/// Decodes audio file into interleaved full scale samples.
+pub trait AudioFileDecoder: Send {
+    fn get_parameters(&self) -> StreamParameters;
+    /// Length in frames, if known upfront.
+    fn get_length(&self) -> Option<u64>;
+    /// Reads up to `frames` frames, empty at the end of file.
+    fn read(&mut self, frames: usize) -> Result<Vec<Sample>, String>;
+    fn seek(&mut self, frame: u64) -> Result<(), String>;
+}
+
+/// Chooses decoder by the file extension.
+pub fn open_decoder(path: &Path) -> Result<Box<dyn AudioFileDecoder>, String> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "wav" | "wave" => Ok(Box::new(WavDecoder::open(path)?)),
+        #[cfg(feature = "flac")]
+        "flac" => Ok(Box::new(flac::FlacDecoder::open(path)?)),
+        #[cfg(feature = "ogg")]
+        "ogg" | "oga" => Ok(Box::new(ogg::OggDecoder::open(path)?)),
+        _ => Err(format!("Unsupported file type: {}", path.display())),
+    }
+}
+
+fn int_to_sample(sample: i32, bits_per_sample: u32) -> Sample {
+    sample as Sample / (1u64 << (bits_per_sample - 1)) as Sample
+}
+
+pub struct WavDecoder {
+    reader: WavReader<BufReader<File>>,
+}
+
+impl WavDecoder {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let reader = WavReader::open(path)
+            .map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+        Ok(Self { reader })
+    }
+}
+
+impl AudioFileDecoder for WavDecoder {
+    fn get_parameters(&self) -> StreamParameters {
+        let spec = self.reader.spec();
+        StreamParameters {
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+        }
+    }
+
+    fn get_length(&self) -> Option<u64> {
+        Some(self.reader.duration() as u64)
+    }
+
+    fn read(&mut self, frames: usize) -> Result<Vec<Sample>, String> {
+        let spec = self.reader.spec();
+        let count = frames * spec.channels as usize;
+
+        match spec.sample_format {
+            SampleFormat::Float => self
+                .reader
+                .samples::<f32>()
+                .take(count)
+                .map(|sample| sample.map_err(|err| err.to_string()))
+                .collect(),
+            SampleFormat::Int => self
+                .reader
+                .samples::<i32>()
+                .take(count)
+                .map(|sample| {
+                    sample
+                        .map(|sample| int_to_sample(sample, spec.bits_per_sample as u32))
+                        .map_err(|err| err.to_string())
+                })
+                .collect(),
+        }
+    }
+
+    fn seek(&mut self, frame: u64) -> Result<(), String> {
+        let frame = u32::try_from(frame).map_err(|_| String::from("Position out of range"))?;
+        self.reader.seek(frame).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(feature = "flac")]
+mod flac {
+    use std::{fs::File, path::Path};
+
+    use claxon::FlacReader;
+
+    use super::{int_to_sample, AudioFileDecoder, Sample, StreamParameters};
+
+    pub struct FlacDecoder {
+        path: std::path::PathBuf,
+        reader: FlacReader<File>,
+        pending: Vec<Sample>,
+    }
+
+    impl FlacDecoder {
+        pub fn open(path: &Path) -> Result<Self, String> {
+            let reader = FlacReader::open(path)
+                .map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+            Ok(Self {
+                path: path.to_path_buf(),
+                reader,
+                pending: Vec::new(),
+            })
+        }
+
+        /// Appends the next block to pending samples, false at the end of stream.
+        fn decode_block(&mut self) -> Result<bool, String> {
+            let bits_per_sample = self.reader.streaminfo().bits_per_sample;
+            let Some(block) = self
+                .reader
+                .blocks()
+                .read_next_or_eof(Vec::new())
+                .map_err(|err| err.to_string())?
+            else {
+                return Ok(false);
+            };
+
+            for frame in 0..block.duration() {
+                for channel in 0..block.channels() {
+                    self.pending
+                        .push(int_to_sample(block.sample(channel, frame), bits_per_sample));
+                }
+            }
+            Ok(true)
+        }
+    }
+
+    impl AudioFileDecoder for FlacDecoder {
+        fn get_parameters(&self) -> StreamParameters {
+            let info = self.reader.streaminfo();
+            StreamParameters {
+                sample_rate: info.sample_rate,
+                channels: info.channels as u16,
+            }
+        }
+
+        fn get_length(&self) -> Option<u64> {
+            self.reader.streaminfo().samples
+        }
+
+        fn read(&mut self, frames: usize) -> Result<Vec<Sample>, String> {
+            let count = frames * self.get_parameters().channels as usize;
+            while self.pending.len() < count && self.decode_block()? {}
+
+            let count = count.min(self.pending.len());
+            Ok(self.pending.drain(..count).collect())
+        }
+
+        /// FLAC stream has no index here, so the file is decoded again from the start.
+        fn seek(&mut self, frame: u64) -> Result<(), String> {
+            *self = Self::open(&self.path.clone())?;
+
+            let channels = self.get_parameters().channels as usize;
+            let mut remaining = frame as usize;
+            while remaining > 0 {
+                let skipped = self.read(remaining.min(u16::MAX as usize))?.len() / channels;
+                if skipped == 0 {
+                    break;
+                }
+                remaining -= skipped;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "ogg")]
+mod ogg {
+    use std::{fs::File, path::Path};
+
+    use lewton::inside_ogg::OggStreamReader;
+
+    use super::{AudioFileDecoder, Sample, StreamParameters};
+
+    pub struct OggDecoder {
+        reader: OggStreamReader<File>,
+        pending: Vec<Sample>,
+    }
+
+    impl OggDecoder {
+        pub fn open(path: &Path) -> Result<Self, String> {
+            let file = File::open(path)
+                .map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+            let reader = OggStreamReader::new(file).map_err(|err| err.to_string())?;
+            Ok(Self {
+                reader,
+                pending: Vec::new(),
+            })
+        }
+    }
+
+    impl AudioFileDecoder for OggDecoder {
+        fn get_parameters(&self) -> StreamParameters {
+            StreamParameters {
+                sample_rate: self.reader.ident_hdr.audio_sample_rate,
+                channels: self.reader.ident_hdr.audio_channels as u16,
+            }
+        }
+
+        fn get_length(&self) -> Option<u64> {
+            None
+        }
+
+        fn read(&mut self, frames: usize) -> Result<Vec<Sample>, String> {
+            let count = frames * self.get_parameters().channels as usize;
+            while self.pending.len() < count {
+                match self
+                    .reader
+                    .read_dec_packet_itl()
+                    .map_err(|err| err.to_string())?
+                {
+                    Some(packet) => self
+                        .pending
+                        .extend(packet.into_iter().map(|sample| sample as Sample / 32768.0)),
+                    None => break,
+                }
+            }
+
+            let count = count.min(self.pending.len());
+            Ok(self.pending.drain(..count).collect())
+        }
+
+        /// Seeks with the page granularity.
+        fn seek(&mut self, frame: u64) -> Result<(), String> {
+            self.pending.clear();
+            self.reader
+                .seek_absgp_pg(frame)
+                .map_err(|err| err.to_string())
+        }
+    }
+}
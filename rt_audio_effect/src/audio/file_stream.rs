@@ -0,0 +1,470 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{error, info};
+
+use super::{
+    audio_stream::AudioStreamSender,
+    file_decoder::{open_decoder, AudioFileDecoder},
+    AudioInput, AudioStreamConsumer, StreamParameters, Timestamp,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackMode {
+    /// Samples are delivered at the pace of the sample rate, like from a device.
+    RealTime,
+    /// Samples are delivered as soon as consumers have room for them.
+    AsFastAsPossible,
+}
+
+/// Audio file given on the command line, played in real time instead of the device.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileStreamConfig {
+    pub path: PathBuf,
+    pub looping: bool,
+}
+
+impl FileStreamConfig {
+    pub const USAGE: &'static str = "--file <path> [--loop]";
+
+    /// Takes `--file` and `--loop` out of command line arguments, the others are returned.
+    pub fn from_args(
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<(Option<Self>, Vec<String>), String> {
+        let mut path = None;
+        let mut looping = false;
+        let mut others = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--file" => {
+                    path = Some(PathBuf::from(
+                        args.next().ok_or(format!("Missing value of {arg}"))?,
+                    ))
+                }
+                "--loop" => looping = true,
+                _ => others.push(arg),
+            }
+        }
+
+        if looping && path.is_none() {
+            return Err(String::from("--loop requires --file"));
+        }
+        Ok((path.map(|path| FileStreamConfig { path, looping }), others))
+    }
+
+    /// Opens the file for real time playback.
+    pub fn open(&self) -> Result<FileStream, String> {
+        let stream = FileStream::new(&self.path, PlaybackMode::RealTime)?;
+        stream.set_looping(self.looping);
+        Ok(stream)
+    }
+}
+
+struct FilePlayback {
+    decoder: Box<dyn AudioFileDecoder>,
+    position: u64,
+    looping: bool,
+}
+
+impl FilePlayback {
+    /// Reads next chunk, rewinding at the end when looping. Empty at the end of file.
+    fn read(&mut self, frames: usize, channels: usize) -> Result<Vec<f32>, String> {
+        let mut samples = self.decoder.read(frames)?;
+        if samples.is_empty() && self.looping && self.position > 0 {
+            self.decoder.seek(0)?;
+            self.position = 0;
+            samples = self.decoder.read(frames)?;
+        }
+        self.position += (samples.len() / channels) as u64;
+        Ok(samples)
+    }
+}
+
+/// Audio file played as an input stream, for offline and reproducible analysis.
+pub struct FileStream {
+    path: Option<PathBuf>,
+    mode: PlaybackMode,
+    parameters: Arc<StreamParameters>,
+    playback: Arc<Mutex<FilePlayback>>,
+    stream_sender: Arc<Mutex<AudioStreamSender>>,
+    running: Arc<AtomicBool>,
+    alive: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl FileStream {
+    const CHUNK_DURATION: Duration = Duration::from_millis(10);
+    const IDLE_PERIOD: Duration = Duration::from_millis(1);
+
+    pub fn new(path: &Path, mode: PlaybackMode) -> Result<FileStream, String> {
+        let decoder = open_decoder(path)?;
+        info!(
+            "Opened {} with: {}, length: {:?} frames",
+            path.display(),
+            decoder.get_parameters(),
+            decoder.get_length()
+        );
+
+        let mut stream = Self::from_decoder(decoder, mode);
+        stream.path = Some(path.to_path_buf());
+        Ok(stream)
+    }
+
+    /// Plays samples of any decoder, e.g. a `SignalGenerator`.
+    pub fn from_decoder(decoder: Box<dyn AudioFileDecoder>, mode: PlaybackMode) -> FileStream {
+        let parameters = Arc::new(decoder.get_parameters());
+
+        FileStream {
+            path: None,
+            mode,
+            parameters,
+            playback: Arc::new(Mutex::new(FilePlayback {
+                decoder,
+                position: 0,
+                looping: false,
+            })),
+            stream_sender: Arc::new(Mutex::new(AudioStreamSender::new())),
+            running: Arc::new(AtomicBool::new(false)),
+            alive: Arc::new(AtomicBool::new(true)),
+            thread: Mutex::new(None),
+        }
+    }
+
+    pub fn get_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn get_name(&self) -> String {
+        self.path
+            .as_ref()
+            .map_or(String::from("generated signal"), |path| {
+                path.display().to_string()
+            })
+    }
+
+    pub fn set_looping(&self, looping: bool) {
+        self.playback.lock().unwrap().looping = looping;
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.playback.lock().unwrap().looping
+    }
+
+    pub fn seek(&self, position: Duration) -> Result<(), String> {
+        let frame = (position.as_secs_f64() * self.parameters.sample_rate as f64) as u64;
+        let mut playback = self.playback.lock().unwrap();
+        playback.decoder.seek(frame)?;
+        playback.position = frame;
+        Ok(())
+    }
+
+    pub fn get_position(&self) -> Duration {
+        self.frames_to_duration(self.playback.lock().unwrap().position)
+    }
+
+    pub fn get_duration(&self) -> Option<Duration> {
+        let length = self.playback.lock().unwrap().decoder.get_length()?;
+        Some(self.frames_to_duration(length))
+    }
+
+    /// Synchronously delivers up to `frames` frames to consumers, returns number of delivered
+    /// frames, 0 at the end of file. Meant for tests and offline processing without `start()`.
+    pub fn pump(&self, frames: usize) -> Result<usize, String> {
+        Self::deliver(
+            &self.playback,
+            &self.stream_sender,
+            frames,
+            self.parameters.channels as usize,
+        )
+    }
+
+    fn frames_to_duration(&self, frames: u64) -> Duration {
+        Duration::from_secs_f64(frames as f64 / self.parameters.sample_rate as f64)
+    }
+
+    fn deliver(
+        playback: &Mutex<FilePlayback>,
+        stream_sender: &Mutex<AudioStreamSender>,
+        frames: usize,
+        channels: usize,
+    ) -> Result<usize, String> {
+        let samples = playback.lock().unwrap().read(frames, channels)?;
+        let delivered = samples.len() / channels;
+        if delivered > 0 {
+            stream_sender
+                .lock()
+                .unwrap()
+                .send_data(&samples, Timestamp::now());
+        }
+        Ok(delivered)
+    }
+
+    fn spawn_player(&self) -> JoinHandle<()> {
+        let playback = self.playback.clone();
+        let stream_sender = self.stream_sender.clone();
+        let running = self.running.clone();
+        let alive = self.alive.clone();
+        let mode = self.mode;
+        let channels = self.parameters.channels as usize;
+        let chunk_frames = (self.parameters.sample_rate as f32 * Self::CHUNK_DURATION.as_secs_f32())
+            .max(1.0) as usize;
+
+        thread::spawn(move || {
+            let mut next_chunk = Instant::now();
+            while alive.load(Ordering::Relaxed) {
+                if !running.load(Ordering::Relaxed)
+                    || (mode == PlaybackMode::AsFastAsPossible
+                        && !stream_sender
+                            .lock()
+                            .unwrap()
+                            .has_room(chunk_frames * channels))
+                {
+                    thread::sleep(Self::IDLE_PERIOD);
+                    next_chunk = Instant::now();
+                    continue;
+                }
+
+                match Self::deliver(&playback, &stream_sender, chunk_frames, channels) {
+                    Ok(0) => {
+                        info!("End of file reached");
+                        running.store(false, Ordering::Relaxed);
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        error!("Failed to decode file: {err}");
+                        running.store(false, Ordering::Relaxed);
+                    }
+                }
+
+                if mode == PlaybackMode::RealTime {
+                    next_chunk += Self::CHUNK_DURATION;
+                    thread::sleep(next_chunk.saturating_duration_since(Instant::now()));
+                }
+            }
+        })
+    }
+}
+
+impl AudioInput for FileStream {
+    fn start(&self) {
+        info!("Starting playback of {}", self.get_name());
+        self.running.store(true, Ordering::Relaxed);
+
+        let mut thread = self.thread.lock().unwrap();
+        if thread.is_none() {
+            *thread = Some(self.spawn_player());
+        }
+    }
+
+    fn stop(&self) {
+        info!("Stopping playback of {}", self.get_name());
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    fn get_parameters(&self) -> Arc<StreamParameters> {
+        self.parameters.clone()
+    }
+
+    fn add_stream_consumer(&mut self, stream_consumer: Arc<Mutex<dyn AudioStreamConsumer>>) {
+        self.stream_sender
+            .lock()
+            .unwrap()
+            .add_stream_receiver(stream_consumer)
+    }
+}
+
+impl Drop for FileStream {
+    fn drop(&mut self) {
+        self.alive.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use hound::{WavSpec, WavWriter};
+
+    use crate::audio_analyzer::{AudioAnalyzysProvider, StreamAnalyzer};
+
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// Writes stereo 16 bit sine, left channel at `frequency`, right one silent.
+    fn write_sine(name: &str, frequency: f32, duration: Duration) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}_{}.wav", name, std::process::id()));
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        let frames = (duration.as_secs_f32() * SAMPLE_RATE as f32) as usize;
+        for frame in 0..frames {
+            let value = (2.0 * PI * frequency * frame as f32 / SAMPLE_RATE as f32).sin();
+            writer.write_sample((value * 16384.0) as i16).unwrap();
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_arguments() {
+        assert_eq!(
+            FileStreamConfig::from_args(args("--rate 44100")),
+            Ok((None, args("--rate 44100")))
+        );
+        assert_eq!(
+            FileStreamConfig::from_args(args("--loop --file /tmp/song.wav")),
+            Ok((
+                Some(FileStreamConfig {
+                    path: PathBuf::from("/tmp/song.wav"),
+                    looping: true
+                }),
+                Vec::new()
+            ))
+        );
+        assert!(FileStreamConfig::from_args(args("--file")).is_err());
+        assert!(FileStreamConfig::from_args(args("--loop --stdin")).is_err());
+
+        let path = write_sine("file_config", 1000.0, Duration::from_millis(100));
+        let config =
+            FileStreamConfig::from_args(args(&format!("--file {} --loop", path.display())))
+                .unwrap()
+                .0
+                .unwrap();
+        let stream = config.open().unwrap();
+        assert!(stream.is_looping());
+        assert_eq!(stream.get_path(), Some(path.as_path()));
+        assert!(FileStreamConfig {
+            path: std::env::temp_dir().join("missing_file_config.wav"),
+            looping: false
+        }
+        .open()
+        .is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_wav_decoding() {
+        let path = write_sine("file_stream_decoding", 1000.0, Duration::from_millis(100));
+        let stream = FileStream::new(&path, PlaybackMode::AsFastAsPossible).unwrap();
+
+        assert_eq!(
+            *stream.get_parameters(),
+            StreamParameters {
+                sample_rate: SAMPLE_RATE,
+                channels: 2
+            }
+        );
+        assert_eq!(stream.get_duration(), Some(Duration::from_millis(100)));
+
+        assert_eq!(stream.pump(4000).unwrap(), 4000);
+        assert_eq!(stream.pump(4000).unwrap(), 800);
+        assert_eq!(stream.pump(4000).unwrap(), 0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_loop_and_seek() {
+        let path = write_sine("file_stream_loop", 1000.0, Duration::from_millis(100));
+        let stream = FileStream::new(&path, PlaybackMode::AsFastAsPossible).unwrap();
+
+        stream.seek(Duration::from_millis(50)).unwrap();
+        assert_eq!(stream.get_position(), Duration::from_millis(50));
+        assert_eq!(stream.pump(4800).unwrap(), 2400);
+
+        stream.set_looping(true);
+        assert_eq!(stream.pump(4800).unwrap(), 4800);
+        assert_eq!(stream.get_position(), Duration::from_millis(100));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_analyzer_on_file() {
+        let path = write_sine("file_stream_analyzer", 1000.0, Duration::from_secs(1));
+        let mut stream = FileStream::new(&path, PlaybackMode::AsFastAsPossible).unwrap();
+
+        let spectrum_width = 4800;
+        let analyzer = Arc::new(Mutex::new(StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            spectrum_width,
+            stream.get_parameters(),
+        )));
+        stream.add_stream_consumer(analyzer.clone());
+
+        while stream.pump(960).unwrap() > 0 {
+            analyzer.lock().unwrap().process_new_samples();
+        }
+
+        let spectrums = analyzer.lock().unwrap().get_latest_spectrum();
+        let left = spectrums.get_channel(0).as_slice();
+        let peak = (0..left.len())
+            .max_by(|a, b| left[*a].total_cmp(&left[*b]))
+            .unwrap();
+        let peak_frequency = peak as f32 * SAMPLE_RATE as f32 / spectrum_width as f32;
+
+        assert_eq!(peak_frequency, 1000.0);
+        // 0.5 amplitude sine
+        assert!((left[peak] - 0.5).abs() < 0.01, "{}", left[peak]);
+        assert!(spectrums
+            .get_channel(1)
+            .as_slice()
+            .iter()
+            .all(|v| *v == 0.0));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_realtime_playback() {
+        let path = write_sine("file_stream_realtime", 1000.0, Duration::from_millis(100));
+        let mut stream = FileStream::new(&path, PlaybackMode::RealTime).unwrap();
+        let analyzer = Arc::new(Mutex::new(StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            960,
+            stream.get_parameters(),
+        )));
+        stream.add_stream_consumer(analyzer.clone());
+
+        let start = Instant::now();
+        stream.start();
+        while stream.is_running() {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        let audio_buffer = analyzer.lock().unwrap().get_audio_buffer();
+        audio_buffer.lock().unwrap().fetch();
+        assert_eq!(audio_buffer.lock().unwrap().get_new_samples_count(), 4800);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,394 @@
+use std::{f32::consts::PI, time::Duration};
+
+use super::{Sample, StreamParameters};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterSettings {
+    pub enabled: bool,
+    pub frequency: f32,
+    pub q: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AgcSettings {
+    pub enabled: bool,
+    /// Peak level the signal is brought to.
+    pub target_level_db: f32,
+    pub max_gain_db: f32,
+    pub attack: Duration,
+    pub release: Duration,
+}
+
+/// Processing applied to samples before the analysis, every stage can be bypassed.
+/// Order: gain, DC blocker, high-pass, low-pass, AGC.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConditioningSettings {
+    pub gain_enabled: bool,
+    pub gain_db: f32,
+    pub dc_blocker_enabled: bool,
+    pub high_pass: FilterSettings,
+    pub low_pass: FilterSettings,
+    pub agc: AgcSettings,
+}
+
+impl Default for ConditioningSettings {
+    fn default() -> Self {
+        Self {
+            gain_enabled: false,
+            gain_db: 0.0,
+            dc_blocker_enabled: false,
+            high_pass: FilterSettings {
+                enabled: false,
+                frequency: 20.0,
+                q: std::f32::consts::FRAC_1_SQRT_2,
+            },
+            low_pass: FilterSettings {
+                enabled: false,
+                frequency: 16000.0,
+                q: std::f32::consts::FRAC_1_SQRT_2,
+            },
+            agc: AgcSettings {
+                enabled: false,
+                target_level_db: -6.0,
+                max_gain_db: 30.0,
+                attack: Duration::from_millis(50),
+                release: Duration::from_secs(2),
+            },
+        }
+    }
+}
+
+/// Levels of the last processed block, for the UI.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConditioningStatus {
+    pub input_peak: Sample,
+    pub output_peak: Sample,
+    pub agc_gain_db: f32,
+}
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-10).log10()
+}
+
+/// First order DC blocking filter with the corner at `CORNER_FREQUENCY`.
+#[derive(Clone, Default)]
+struct DcBlocker {
+    previous_input: Sample,
+    previous_output: Sample,
+}
+
+impl DcBlocker {
+    const CORNER_FREQUENCY: f32 = 10.0;
+
+    fn process(&mut self, samples: &mut [Sample], pole: f32) {
+        for sample in samples {
+            let output = *sample - self.previous_input + pole * self.previous_output;
+            self.previous_input = *sample;
+            self.previous_output = output;
+            *sample = output;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BiquadType {
+    HighPass,
+    LowPass,
+}
+
+/// RBJ cookbook biquad, transposed direct form II.
+#[derive(Clone)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    state: [f32; 2],
+}
+
+impl Biquad {
+    fn new(biquad_type: BiquadType, settings: &FilterSettings, sample_rate: f32) -> Self {
+        let frequency = settings.frequency.clamp(1.0, sample_rate * 0.49);
+        let omega = 2.0 * PI * frequency / sample_rate;
+        let alpha = omega.sin() / (2.0 * settings.q.max(0.01));
+        let cos = omega.cos();
+
+        let b = match biquad_type {
+            BiquadType::HighPass => [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            BiquadType::LowPass => [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+        };
+        let a0 = 1.0 + alpha;
+
+        Self {
+            b: b.map(|b| b / a0),
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            state: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, samples: &mut [Sample]) {
+        for sample in samples {
+            let input = *sample;
+            let output = self.b[0] * input + self.state[0];
+            self.state[0] = self.b[1] * input - self.a[0] * output + self.state[1];
+            self.state[1] = self.b[2] * input - self.a[1] * output;
+            *sample = output;
+        }
+    }
+}
+
+/// Peak envelope follower with the gain shared by all channels, so the balance between
+/// them is kept.
+struct Agc {
+    attack: f32,
+    release: f32,
+    target: f32,
+    max_gain: f32,
+    envelope: f32,
+}
+
+impl Agc {
+    /// Envelope floor, the gain of silence is limited by `max_gain` anyway.
+    const MIN_ENVELOPE: f32 = 1e-6;
+
+    fn new(settings: &AgcSettings, sample_rate: f32) -> Self {
+        let coefficient =
+            |time: Duration| 1.0 - (-1.0 / (time.as_secs_f32() * sample_rate).max(1.0)).exp();
+        Self {
+            attack: coefficient(settings.attack),
+            release: coefficient(settings.release),
+            target: db_to_gain(settings.target_level_db),
+            max_gain: db_to_gain(settings.max_gain_db),
+            envelope: db_to_gain(settings.target_level_db),
+        }
+    }
+
+    fn get_gain(&self) -> f32 {
+        (self.target / self.envelope.max(Self::MIN_ENVELOPE)).min(self.max_gain)
+    }
+
+    fn process(&mut self, channels: &mut [&mut [Sample]]) {
+        let frames = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+        for frame in 0..frames {
+            let level = channels
+                .iter()
+                .map(|channel| channel[frame].abs())
+                .fold(0.0, f32::max);
+            let coefficient = if level > self.envelope {
+                self.attack
+            } else {
+                self.release
+            };
+            self.envelope += (level - self.envelope) * coefficient;
+
+            let gain = self.get_gain();
+            for channel in channels.iter_mut() {
+                channel[frame] *= gain;
+            }
+        }
+    }
+}
+
+/// Input conditioning of all channels of a stream, stateful so it has to see every sample
+/// exactly once.
+pub struct InputConditioning {
+    settings: ConditioningSettings,
+    gain: f32,
+    dc_blocker_pole: f32,
+    dc_blockers: Vec<DcBlocker>,
+    high_pass: Vec<Biquad>,
+    low_pass: Vec<Biquad>,
+    agc: Agc,
+    status: ConditioningStatus,
+}
+
+impl InputConditioning {
+    pub fn new(settings: ConditioningSettings, parameters: &StreamParameters) -> Self {
+        let sample_rate = parameters.sample_rate as f32;
+        let channels = parameters.channels as usize;
+
+        Self {
+            gain: db_to_gain(settings.gain_db),
+            dc_blocker_pole: 1.0 - 2.0 * PI * DcBlocker::CORNER_FREQUENCY / sample_rate,
+            dc_blockers: vec![DcBlocker::default(); channels],
+            high_pass: vec![
+                Biquad::new(BiquadType::HighPass, &settings.high_pass, sample_rate);
+                channels
+            ],
+            low_pass: vec![
+                Biquad::new(BiquadType::LowPass, &settings.low_pass, sample_rate);
+                channels
+            ],
+            agc: Agc::new(&settings.agc, sample_rate),
+            status: ConditioningStatus::default(),
+            settings,
+        }
+    }
+
+    pub fn get_settings(&self) -> &ConditioningSettings {
+        &self.settings
+    }
+
+    pub fn get_status(&self) -> ConditioningStatus {
+        self.status
+    }
+
+    /// Processes new samples of every channel in place, all slices have the same length.
+    pub fn process(&mut self, channels: &mut [&mut [Sample]]) {
+        let peak = |channels: &[&mut [Sample]]| {
+            channels
+                .iter()
+                .flat_map(|channel| channel.iter())
+                .fold(0.0, |peak: Sample, sample| peak.max(sample.abs()))
+        };
+        self.status.input_peak = peak(channels);
+
+        for (channel, samples) in channels.iter_mut().enumerate() {
+            if self.settings.gain_enabled {
+                samples.iter_mut().for_each(|sample| *sample *= self.gain);
+            }
+            if self.settings.dc_blocker_enabled {
+                self.dc_blockers[channel].process(samples, self.dc_blocker_pole);
+            }
+            if self.settings.high_pass.enabled {
+                self.high_pass[channel].process(samples);
+            }
+            if self.settings.low_pass.enabled {
+                self.low_pass[channel].process(samples);
+            }
+        }
+
+        if self.settings.agc.enabled {
+            self.agc.process(channels);
+            self.status.agc_gain_db = gain_to_db(self.agc.get_gain());
+        } else {
+            self.status.agc_gain_db = 0.0;
+        }
+
+        self.status.output_peak = peak(channels);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::signal_generator::{Signal, SignalGenerator};
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn sine(frequency: f32, amplitude: Sample, seconds: f32) -> Vec<Sample> {
+        SignalGenerator::new(
+            Signal::Sine { frequency },
+            amplitude,
+            StreamParameters {
+                sample_rate: SAMPLE_RATE,
+                channels: 1,
+            },
+        )
+        .generate((SAMPLE_RATE as f32 * seconds) as usize)
+    }
+
+    /// Processes mono samples in 10 ms blocks, returns the peak of the last 100 ms.
+    fn process(settings: ConditioningSettings, mut samples: Vec<Sample>) -> Sample {
+        let mut conditioning = InputConditioning::new(
+            settings,
+            &StreamParameters {
+                sample_rate: SAMPLE_RATE,
+                channels: 1,
+            },
+        );
+        for block in samples.chunks_mut(480) {
+            conditioning.process(&mut [block]);
+        }
+        samples[samples.len() - 4800..]
+            .iter()
+            .fold(0.0, |peak, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn test_bypass_and_gain() {
+        let samples = sine(1000.0, 0.25, 0.5);
+        assert!((process(ConditioningSettings::default(), samples.clone()) - 0.25).abs() < 1e-3);
+
+        let settings = ConditioningSettings {
+            gain_enabled: true,
+            gain_db: 6.0206,
+            ..Default::default()
+        };
+        assert!((process(settings, samples) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_dc_blocker() {
+        let settings = ConditioningSettings {
+            dc_blocker_enabled: true,
+            ..Default::default()
+        };
+        let offset: Vec<Sample> = sine(1000.0, 0.25, 1.0).iter().map(|v| v + 0.5).collect();
+        assert!((process(settings, offset) - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_filters() {
+        let high_pass = ConditioningSettings {
+            high_pass: FilterSettings {
+                enabled: true,
+                frequency: 500.0,
+                q: std::f32::consts::FRAC_1_SQRT_2,
+            },
+            ..Default::default()
+        };
+        assert!(process(high_pass.clone(), sine(50.0, 0.5, 0.5)) < 0.01);
+        assert!((process(high_pass.clone(), sine(5000.0, 0.5, 0.5)) - 0.5).abs() < 0.01);
+        // -3 dB at the corner of Butterworth filter
+        let corner = process(high_pass, sine(500.0, 0.5, 0.5));
+        assert!((gain_to_db(corner / 0.5) + 3.0).abs() < 0.1, "{corner}");
+
+        let low_pass = ConditioningSettings {
+            low_pass: FilterSettings {
+                enabled: true,
+                frequency: 500.0,
+                q: std::f32::consts::FRAC_1_SQRT_2,
+            },
+            ..Default::default()
+        };
+        assert!((process(low_pass.clone(), sine(50.0, 0.5, 0.5)) - 0.5).abs() < 0.01);
+        assert!(process(low_pass, sine(5000.0, 0.5, 0.5)) < 0.01);
+    }
+
+    #[test]
+    fn test_agc_is_independent_of_level() {
+        let settings = ConditioningSettings {
+            agc: AgcSettings {
+                enabled: true,
+                ..ConditioningSettings::default().agc
+            },
+            ..Default::default()
+        };
+        let target = db_to_gain(settings.agc.target_level_db);
+
+        for amplitude in [0.02, 0.1, 0.9] {
+            let peak = process(settings.clone(), sine(1000.0, amplitude, 10.0));
+            assert!(
+                (gain_to_db(peak / target)).abs() < 1.0,
+                "{amplitude}: {peak}"
+            );
+        }
+
+        // silence is not amplified above max gain
+        let mut conditioning = InputConditioning::new(
+            settings,
+            &StreamParameters {
+                sample_rate: SAMPLE_RATE,
+                channels: 2,
+            },
+        );
+        let (mut left, mut right) = (vec![0.0; 480000], vec![1e-6; 480000]);
+        conditioning.process(&mut [&mut left, &mut right]);
+        assert!((conditioning.get_status().agc_gain_db - 30.0).abs() < 1e-3);
+        let last = right.last().unwrap();
+        assert!((last - 1e-6 * db_to_gain(30.0)).abs() < 1e-6);
+    }
+}
@@ -1,12 +1,28 @@
 pub mod audio_buffer;
+pub mod audio_input;
 pub mod audio_manager;
 pub mod audio_stream;
 pub mod audio_stream_consumer;
+pub mod file_decoder;
+pub mod file_stream;
+pub mod input_conditioning;
+pub mod network_stream;
+pub mod pipe_stream;
+pub mod ring_buffer;
+pub mod rtp;
+pub mod sample_conversion;
+pub mod signal_generator;
+pub mod statistics;
 pub mod stream_parameters;
+pub mod stream_recorder;
+pub mod stream_supervisor;
+pub mod timestamp;
 pub mod types;
 
 pub use audio_buffer::*;
+pub use audio_input::*;
 pub use audio_manager::*;
 pub use audio_stream_consumer::*;
 pub use stream_parameters::*;
+pub use timestamp::*;
 pub use types::*;
@@ -0,0 +1,574 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::ErrorKind,
+    net::{SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{error, info, warn};
+
+use super::{
+    audio_stream::AudioStreamSender,
+    rtp::{PayloadFormat, RtpPacket},
+    AudioInput, AudioStreamConsumer, Sample, StreamParameters, Timestamp,
+};
+
+/// RTP does not describe the stream, so its parameters have to match the sender.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkStreamConfig {
+    pub address: SocketAddr,
+    pub format: PayloadFormat,
+    pub parameters: StreamParameters,
+    /// Depth of the jitter buffer.
+    pub latency: Duration,
+}
+
+impl Default for NetworkStreamConfig {
+    fn default() -> Self {
+        Self {
+            address: SocketAddr::from(([0, 0, 0, 0], 5004)),
+            format: PayloadFormat::L24,
+            parameters: StreamParameters {
+                sample_rate: 48000,
+                channels: 2,
+            },
+            latency: Duration::from_millis(20),
+        }
+    }
+}
+
+impl Display for NetworkStreamConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "RTP {} on {}, {} Hz, {} ch",
+            self.format, self.address, self.parameters.sample_rate, self.parameters.channels
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NetworkStatistics {
+    pub received_packets: u64,
+    pub invalid_packets: u64,
+    /// Arrived after their frames were played out.
+    pub late_packets: u64,
+    pub concealed_frames: u64,
+    /// Frames skipped because the sender runs faster than the playout.
+    pub skipped_frames: u64,
+    /// Playout restarts after the sender stopped or changed.
+    pub resets: u64,
+}
+
+/// Orders packets by RTP timestamp and plays them out with a fixed delay. Missing frames
+/// are concealed by repeating the last packet with a fade out.
+struct JitterBuffer {
+    channels: usize,
+    latency: u64,
+    fade_frames: u64,
+    ssrc: Option<u32>,
+    /// Extended timestamp of the newest packet, for unwrapping 32 bit timestamps.
+    reference: Option<u64>,
+    packets: BTreeMap<u64, Vec<Sample>>,
+    next_frame: Option<u64>,
+    last_packet: Vec<Sample>,
+    concealed: u64,
+    statistics: NetworkStatistics,
+}
+
+impl JitterBuffer {
+    const FADE_DURATION: Duration = Duration::from_millis(20);
+
+    fn new(parameters: &StreamParameters, latency: Duration) -> Self {
+        let frames = |duration: Duration| {
+            (duration.as_secs_f64() * parameters.sample_rate as f64).round() as u64
+        };
+        Self {
+            channels: parameters.channels as usize,
+            latency: frames(latency).max(1),
+            fade_frames: frames(Self::FADE_DURATION).max(1),
+            ssrc: None,
+            reference: None,
+            packets: BTreeMap::new(),
+            next_frame: None,
+            last_packet: Vec::new(),
+            concealed: 0,
+            statistics: NetworkStatistics::default(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reference = None;
+        self.packets.clear();
+        self.next_frame = None;
+        self.last_packet.clear();
+        self.concealed = 0;
+    }
+
+    /// Timestamps start in the middle of the range, so reordered packets before the first one
+    /// don't underflow.
+    fn extend_timestamp(&mut self, timestamp: u32) -> u64 {
+        let extended = match self.reference {
+            None => (1 << 32) + timestamp as u64,
+            Some(reference) => {
+                let delta = timestamp.wrapping_sub(reference as u32) as i32;
+                reference.wrapping_add_signed(delta as i64)
+            }
+        };
+        if self.reference.is_none_or(|reference| extended > reference) {
+            self.reference = Some(extended);
+        }
+        extended
+    }
+
+    fn get_frames(&self, samples: &[Sample]) -> u64 {
+        (samples.len() / self.channels) as u64
+    }
+
+    fn insert(&mut self, ssrc: u32, timestamp: u32, samples: Vec<Sample>) {
+        if self.ssrc != Some(ssrc) {
+            if self.ssrc.is_some() {
+                info!("RTP sender changed, restarting playout");
+                self.statistics.resets += 1;
+            }
+            self.reset();
+            self.ssrc = Some(ssrc);
+        }
+
+        let frame = self.extend_timestamp(timestamp);
+        let frames = self.get_frames(&samples);
+        if self.next_frame.is_some_and(|next| frame + frames <= next) {
+            self.statistics.late_packets += 1;
+            return;
+        }
+        self.statistics.received_packets += 1;
+        self.packets.entry(frame).or_insert(samples);
+    }
+
+    fn get_buffered_frames(&self) -> u64 {
+        let (Some((&first, _)), Some((&last, samples))) = (
+            self.packets.first_key_value(),
+            self.packets.last_key_value(),
+        ) else {
+            return 0;
+        };
+        let start = self.next_frame.unwrap_or(first);
+        (last + self.get_frames(samples)).saturating_sub(start)
+    }
+
+    /// Next `frames` frames, `None` until the buffer is filled up to the latency.
+    fn pop(&mut self, frames: usize) -> Option<Vec<Sample>> {
+        let buffered = self.get_buffered_frames();
+        let mut position = match self.next_frame {
+            Some(next_frame) => next_frame,
+            None if buffered >= self.latency => *self.packets.keys().next().unwrap(),
+            None => return None,
+        };
+        if buffered > 2 * self.latency + frames as u64 {
+            let skipped = buffered - self.latency;
+            self.statistics.skipped_frames += skipped;
+            position += skipped;
+        }
+
+        let end = position + frames as u64;
+        let mut output = Vec::with_capacity(frames * self.channels);
+        while position < end {
+            let covering = self
+                .packets
+                .range(..=position)
+                .next_back()
+                .filter(|(&start, samples)| start + self.get_frames(samples) > position);
+            match covering {
+                Some((&start, samples)) => {
+                    let count = (start + self.get_frames(samples) - position).min(end - position);
+                    let offset = (position - start) as usize * self.channels;
+                    output.extend_from_slice(
+                        &samples[offset..offset + count as usize * self.channels],
+                    );
+                    self.concealed = 0;
+                    position += count;
+                }
+                None => {
+                    let gap_end = self
+                        .packets
+                        .range(position..)
+                        .next()
+                        .map_or(end, |(&start, _)| start.min(end));
+                    self.conceal(gap_end - position, &mut output);
+                    position = gap_end;
+                }
+            }
+        }
+
+        while let Some(entry) = self.packets.first_entry() {
+            if *entry.key() + (entry.get().len() / self.channels) as u64 > end {
+                break;
+            }
+            self.last_packet = entry.remove();
+        }
+        self.next_frame = Some(end);
+
+        if self.packets.is_empty() && self.concealed > self.latency + self.fade_frames {
+            warn!("RTP stream interrupted, waiting for packets");
+            self.statistics.resets += 1;
+            self.reset();
+        }
+        Some(output)
+    }
+
+    fn conceal(&mut self, frames: u64, output: &mut Vec<Sample>) {
+        self.statistics.concealed_frames += frames;
+        let packet_frames = self.get_frames(&self.last_packet);
+        for _ in 0..frames {
+            let fade = 1.0 - self.concealed as f32 / self.fade_frames as f32;
+            if packet_frames == 0 || fade <= 0.0 {
+                output.extend(std::iter::repeat_n(0.0, self.channels));
+            } else {
+                let offset = (self.concealed % packet_frames) as usize * self.channels;
+                output.extend(
+                    self.last_packet[offset..offset + self.channels]
+                        .iter()
+                        .map(|sample| sample * fade),
+                );
+            }
+            self.concealed += 1;
+        }
+    }
+}
+
+/// Linear PCM received over RTP/UDP, e.g. from the FOH machine.
+pub struct NetworkStream {
+    config: NetworkStreamConfig,
+    parameters: Arc<StreamParameters>,
+    socket: UdpSocket,
+    jitter_buffer: Arc<Mutex<JitterBuffer>>,
+    stream_sender: Arc<Mutex<AudioStreamSender>>,
+    running: Arc<AtomicBool>,
+    alive: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl NetworkStream {
+    const CHUNK_DURATION: Duration = Duration::from_millis(10);
+    const RECEIVE_TIMEOUT: Duration = Duration::from_millis(1);
+    const MAX_DATAGRAM_SIZE: usize = 65536;
+
+    pub fn new(config: NetworkStreamConfig) -> Result<NetworkStream, String> {
+        let socket = UdpSocket::bind(config.address)
+            .map_err(|err| format!("Failed to bind {}: {err}", config.address))?;
+        socket
+            .set_read_timeout(Some(Self::RECEIVE_TIMEOUT))
+            .map_err(|err| err.to_string())?;
+        info!("Listening for {config}");
+
+        Ok(NetworkStream {
+            parameters: Arc::new(config.parameters.clone()),
+            jitter_buffer: Arc::new(Mutex::new(JitterBuffer::new(
+                &config.parameters,
+                config.latency,
+            ))),
+            config,
+            socket,
+            stream_sender: Arc::new(Mutex::new(AudioStreamSender::new())),
+            running: Arc::new(AtomicBool::new(false)),
+            alive: Arc::new(AtomicBool::new(true)),
+            thread: Mutex::new(None),
+        })
+    }
+
+    pub fn get_config(&self) -> &NetworkStreamConfig {
+        &self.config
+    }
+
+    /// Address the socket is bound to, with the actual port when the config asked for any.
+    pub fn get_local_address(&self) -> Result<SocketAddr, String> {
+        self.socket.local_addr().map_err(|err| err.to_string())
+    }
+
+    pub fn get_statistics(&self) -> NetworkStatistics {
+        self.jitter_buffer.lock().unwrap().statistics
+    }
+
+    fn receive(jitter_buffer: &Mutex<JitterBuffer>, format: PayloadFormat, datagram: &[u8]) {
+        let mut jitter_buffer = jitter_buffer.lock().unwrap();
+        let frame_size = format.get_sample_size() * jitter_buffer.channels;
+        let packet = match RtpPacket::parse(datagram) {
+            Ok(packet) if packet.payload.len() % frame_size == 0 => packet,
+            Ok(_) => {
+                jitter_buffer.statistics.invalid_packets += 1;
+                return;
+            }
+            Err(err) => {
+                warn!("Invalid RTP packet: {err}");
+                jitter_buffer.statistics.invalid_packets += 1;
+                return;
+            }
+        };
+
+        let mut samples = Vec::with_capacity(packet.payload.len() / format.get_sample_size());
+        format.decode(packet.payload, &mut samples);
+        jitter_buffer.insert(packet.ssrc, packet.timestamp, samples);
+    }
+
+    fn spawn_receiver(&self) -> Result<JoinHandle<()>, String> {
+        let socket = self.socket.try_clone().map_err(|err| err.to_string())?;
+        let jitter_buffer = self.jitter_buffer.clone();
+        let stream_sender = self.stream_sender.clone();
+        let running = self.running.clone();
+        let alive = self.alive.clone();
+        let format = self.config.format;
+        let latency = self.config.latency;
+        let chunk_frames = (self.parameters.sample_rate as f32 * Self::CHUNK_DURATION.as_secs_f32())
+            .max(1.0) as usize;
+
+        Ok(thread::spawn(move || {
+            let mut datagram = vec![0; Self::MAX_DATAGRAM_SIZE];
+            let mut next_chunk: Option<Instant> = None;
+            while alive.load(Ordering::Relaxed) {
+                match socket.recv(&mut datagram) {
+                    Ok(length) if running.load(Ordering::Relaxed) => {
+                        Self::receive(&jitter_buffer, format, &datagram[..length])
+                    }
+                    Ok(_) => {}
+                    Err(err)
+                        if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                    Err(err) => {
+                        error!("Failed to receive RTP packet: {err}");
+                        thread::sleep(Self::RECEIVE_TIMEOUT);
+                    }
+                }
+
+                if !running.load(Ordering::Relaxed) {
+                    jitter_buffer.lock().unwrap().reset();
+                    next_chunk = None;
+                    continue;
+                }
+
+                // playout paced by the local clock, drift is absorbed by the jitter buffer
+                while next_chunk.is_none_or(|next_chunk| Instant::now() >= next_chunk) {
+                    let Some(samples) = jitter_buffer.lock().unwrap().pop(chunk_frames) else {
+                        next_chunk = None;
+                        break;
+                    };
+                    // captured one jitter buffer ago, the network delay is unknown
+                    stream_sender
+                        .lock()
+                        .unwrap()
+                        .send_data(&samples, Timestamp::now() - latency);
+                    next_chunk =
+                        Some(next_chunk.unwrap_or_else(Instant::now) + Self::CHUNK_DURATION);
+                }
+            }
+        }))
+    }
+}
+
+impl AudioInput for NetworkStream {
+    fn start(&self) {
+        info!("Starting {}", self.config);
+        self.running.store(true, Ordering::Relaxed);
+
+        let mut thread = self.thread.lock().unwrap();
+        if thread.is_none() {
+            match self.spawn_receiver() {
+                Ok(receiver) => *thread = Some(receiver),
+                Err(err) => {
+                    error!("Failed to start RTP receiver: {err}");
+                    self.running.store(false, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    fn stop(&self) {
+        info!(
+            "Stopping {}, statistics: {:?}",
+            self.config,
+            self.get_statistics()
+        );
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    fn get_parameters(&self) -> Arc<StreamParameters> {
+        self.parameters.clone()
+    }
+
+    fn add_stream_consumer(&mut self, stream_consumer: Arc<Mutex<dyn AudioStreamConsumer>>) {
+        self.stream_sender
+            .lock()
+            .unwrap()
+            .add_stream_receiver(stream_consumer)
+    }
+}
+
+impl Drop for NetworkStream {
+    fn drop(&mut self) {
+        self.alive.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        audio::{
+            rtp::RtpSender,
+            signal_generator::{Signal, SignalGenerator},
+        },
+        audio_analyzer::{AudioAnalyzysProvider, StreamAnalyzer},
+    };
+
+    use super::*;
+
+    const PARAMETERS: StreamParameters = StreamParameters {
+        sample_rate: 48000,
+        channels: 2,
+    };
+
+    /// Packets of 48 frames numbered by the first sample of both channels.
+    fn packet(index: u32) -> (u32, Vec<Sample>) {
+        let samples = (0..48 * 2)
+            .map(|i| index as f32 + i as f32 * 1e-3)
+            .collect();
+        (index.wrapping_mul(48), samples)
+    }
+
+    fn jitter_buffer() -> JitterBuffer {
+        // 96 frames, two packets
+        JitterBuffer::new(&PARAMETERS, Duration::from_millis(2))
+    }
+
+    #[test]
+    fn test_reordering() {
+        let mut buffer = jitter_buffer();
+        let (timestamp, samples) = packet(1);
+        buffer.insert(1, timestamp, samples);
+        assert_eq!(buffer.pop(48), None);
+
+        for index in [0, 3, 2] {
+            let (timestamp, samples) = packet(index);
+            buffer.insert(1, timestamp, samples);
+        }
+        for index in 0..4 {
+            let output = buffer.pop(48).unwrap();
+            assert_eq!(output, packet(index).1);
+        }
+        assert_eq!(buffer.statistics.concealed_frames, 0);
+        assert_eq!(buffer.statistics.received_packets, 4);
+    }
+
+    #[test]
+    fn test_loss_concealment() {
+        let mut buffer = jitter_buffer();
+        for index in [0, 2, 3] {
+            let (timestamp, samples) = packet(index);
+            buffer.insert(1, timestamp, samples);
+        }
+        assert_eq!(buffer.pop(48).unwrap(), packet(0).1);
+
+        // the lost packet is the previous one faded out
+        let concealed = buffer.pop(48).unwrap();
+        assert_eq!(buffer.statistics.concealed_frames, 48);
+        assert_eq!(concealed[0], packet(0).1[0]);
+        assert!(concealed[94] < packet(0).1[94]);
+        assert_eq!(buffer.pop(48).unwrap(), packet(2).1);
+
+        // too late to be played
+        let (timestamp, samples) = packet(1);
+        buffer.insert(1, timestamp, samples);
+        assert_eq!(buffer.statistics.late_packets, 1);
+        assert_eq!(buffer.pop(48).unwrap(), packet(3).1);
+
+        // interrupted stream fades to silence and stops the playout
+        let mut frames = 0;
+        while let Some(output) = buffer.pop(48) {
+            frames += 48;
+            assert!(frames < 48000);
+            if frames > 960 {
+                assert!(output.iter().all(|sample| *sample == 0.0));
+            }
+        }
+        assert_eq!(buffer.statistics.resets, 1);
+    }
+
+    #[test]
+    fn test_timestamp_wrap_around() {
+        let mut buffer = jitter_buffer();
+        let first = u32::MAX / 48 - 1;
+        for index in first..first + 4 {
+            let (timestamp, samples) = packet(index);
+            buffer.insert(1, timestamp, samples);
+        }
+        for index in first..first + 4 {
+            assert_eq!(buffer.pop(48).unwrap(), packet(index).1);
+        }
+        assert_eq!(buffer.statistics.concealed_frames, 0);
+    }
+
+    #[test]
+    fn test_rtp_over_localhost() {
+        let config = NetworkStreamConfig {
+            address: "127.0.0.1:0".parse().unwrap(),
+            format: PayloadFormat::L16,
+            parameters: PARAMETERS,
+            latency: Duration::from_millis(20),
+        };
+        let mut stream = NetworkStream::new(config).unwrap();
+        let spectrum_width = 4800;
+        let analyzer = Arc::new(Mutex::new(StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            spectrum_width,
+            stream.get_parameters(),
+        )));
+        stream.add_stream_consumer(analyzer.clone());
+        stream.start();
+
+        let mut sender = RtpSender::new(
+            stream.get_local_address().unwrap(),
+            PayloadFormat::L16,
+            2,
+            48,
+        )
+        .unwrap();
+        let mut generator =
+            SignalGenerator::new(Signal::Sine { frequency: 1000.0 }, 0.5, PARAMETERS);
+        let start = Instant::now();
+        for chunk in 1..=30 {
+            sender.send(&generator.generate(480)).unwrap();
+            thread::sleep(
+                (start + Duration::from_millis(10 * chunk))
+                    .saturating_duration_since(Instant::now()),
+            );
+        }
+        thread::sleep(Duration::from_millis(50));
+        stream.stop();
+
+        analyzer.lock().unwrap().process_new_samples();
+        let spectrums = analyzer.lock().unwrap().get_latest_spectrum();
+        let left = spectrums.get_channel(0).as_slice();
+        let peak = (0..left.len())
+            .max_by(|a, b| left[*a].total_cmp(&left[*b]))
+            .unwrap();
+        assert_eq!(
+            peak as u32 * PARAMETERS.sample_rate / spectrum_width as u32,
+            1000
+        );
+        // late packets are possible when the test threads are starved
+        let statistics = stream.get_statistics();
+        assert_eq!(statistics.received_packets + statistics.late_packets, 300);
+        assert_eq!(statistics.invalid_packets, 0);
+    }
+}
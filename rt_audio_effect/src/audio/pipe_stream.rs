@@ -0,0 +1,467 @@
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{ErrorKind, Read},
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use log::{error, info};
+
+use super::{
+    audio_stream::AudioStreamSender, AudioInput, AudioStreamConsumer, MixedChannelsSamples,
+    StreamParameters, Timestamp,
+};
+
+/// Interleaved little endian PCM, named like ffmpeg and parec formats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RawFormat {
+    U8,
+    S16Le,
+    S24Le,
+    S32Le,
+    F32Le,
+}
+
+impl RawFormat {
+    pub fn get_sample_size(&self) -> usize {
+        match self {
+            RawFormat::U8 => 1,
+            RawFormat::S16Le => 2,
+            RawFormat::S24Le => 3,
+            RawFormat::S32Le | RawFormat::F32Le => 4,
+        }
+    }
+
+    /// Trailing bytes of an incomplete sample are ignored.
+    pub fn decode(&self, data: &[u8]) -> MixedChannelsSamples {
+        let samples = data.chunks_exact(self.get_sample_size());
+        match self {
+            RawFormat::U8 => samples
+                .map(|bytes| (bytes[0] as f32 - 128.0) / 128.0)
+                .collect::<Vec<_>>(),
+            RawFormat::S16Le => samples
+                .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0)
+                .collect(),
+            RawFormat::S24Le => samples
+                .map(|bytes| {
+                    (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as f32 / 8388608.0
+                })
+                .collect(),
+            RawFormat::S32Le => samples
+                .map(|bytes| {
+                    i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32
+                        / 2147483648.0
+                })
+                .collect(),
+            RawFormat::F32Le => samples
+                .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .collect(),
+        }
+        .into()
+    }
+}
+
+impl FromStr for RawFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "u8" => Ok(RawFormat::U8),
+            "s16le" | "s16" => Ok(RawFormat::S16Le),
+            "s24le" | "s24" => Ok(RawFormat::S24Le),
+            "s32le" | "s32" => Ok(RawFormat::S32Le),
+            "f32le" | "f32" | "float32le" => Ok(RawFormat::F32Le),
+            _ => Err(format!("Unsupported raw format: {format}")),
+        }
+    }
+}
+
+impl Display for RawFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            RawFormat::U8 => "u8",
+            RawFormat::S16Le => "s16le",
+            RawFormat::S24Le => "s24le",
+            RawFormat::S32Le => "s32le",
+            RawFormat::F32Le => "f32le",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PipeInput {
+    Stdin,
+    /// Named pipe, reopened for the next writer when the current one closes it.
+    Fifo(PathBuf),
+}
+
+impl Display for PipeInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PipeInput::Stdin => write!(f, "stdin"),
+            PipeInput::Fifo(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PipeStreamConfig {
+    pub input: PipeInput,
+    pub format: RawFormat,
+    pub parameters: StreamParameters,
+}
+
+impl PipeStreamConfig {
+    pub const USAGE: &'static str = "--stdin | --pipe <path> [--format u8|s16le|s24le|s32le|f32le] [--rate <Hz>] [--channels <count>]";
+
+    /// Parses command line arguments, `None` when neither `--stdin` nor `--pipe` is given.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut input = None;
+        let mut format = RawFormat::F32Le;
+        let mut parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 2,
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value of {arg}"));
+            match arg.as_str() {
+                "--stdin" => input = Some(PipeInput::Stdin),
+                "--pipe" => input = Some(PipeInput::Fifo(PathBuf::from(value()?))),
+                "--format" => format = value()?.parse()?,
+                "--rate" => {
+                    parameters.sample_rate = value()?
+                        .parse()
+                        .map_err(|err| format!("Invalid rate: {err}"))?
+                }
+                "--channels" => {
+                    parameters.channels = value()?
+                        .parse()
+                        .map_err(|err| format!("Invalid channel count: {err}"))?
+                }
+                _ => return Err(format!("Unknown argument: {arg}")),
+            }
+        }
+
+        if parameters.sample_rate == 0 || parameters.channels == 0 {
+            return Err(String::from("Rate and channel count have to be positive"));
+        }
+        Ok(input.map(|input| PipeStreamConfig {
+            input,
+            format,
+            parameters,
+        }))
+    }
+}
+
+impl Display for PipeStreamConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}, {} Hz, {} ch",
+            self.input, self.format, self.parameters.sample_rate, self.parameters.channels
+        )
+    }
+}
+
+enum PipeSource {
+    Input(PipeInput),
+    /// Any reader, e.g. for tests. It can't be reopened.
+    Reader(Option<Box<dyn Read + Send>>),
+}
+
+impl PipeSource {
+    /// Opening a FIFO blocks until a writer opens it.
+    fn open(&mut self) -> Result<Box<dyn Read + Send>, String> {
+        match self {
+            PipeSource::Input(PipeInput::Stdin) => Ok(Box::new(std::io::stdin())),
+            PipeSource::Input(PipeInput::Fifo(path)) => File::open(&*path)
+                .map(|file| Box::new(file) as Box<dyn Read + Send>)
+                .map_err(|err| format!("Failed to open {}: {err}", path.display())),
+            PipeSource::Reader(reader) => reader.take().ok_or(String::from("Reader already used")),
+        }
+    }
+
+    fn can_reopen(&self) -> bool {
+        matches!(self, PipeSource::Input(PipeInput::Fifo(_)))
+    }
+}
+
+/// Raw PCM read from stdin or a named pipe, so any tool can feed the analysis without cpal.
+/// The writer is blocked while consumers have no room, so files can be piped as fast as
+/// possible.
+pub struct PipeStream {
+    name: String,
+    format: RawFormat,
+    parameters: Arc<StreamParameters>,
+    source: Mutex<Option<PipeSource>>,
+    stream_sender: Arc<Mutex<AudioStreamSender>>,
+    running: Arc<AtomicBool>,
+    alive: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl PipeStream {
+    const CHUNK_DURATION: Duration = Duration::from_millis(10);
+    const IDLE_PERIOD: Duration = Duration::from_millis(1);
+
+    pub fn new(config: PipeStreamConfig) -> PipeStream {
+        Self::with_source(
+            config.to_string(),
+            PipeSource::Input(config.input),
+            config.format,
+            config.parameters,
+        )
+    }
+
+    pub fn from_reader(
+        reader: Box<dyn Read + Send>,
+        format: RawFormat,
+        parameters: StreamParameters,
+    ) -> PipeStream {
+        Self::with_source(
+            format!("reader {format}, {parameters}"),
+            PipeSource::Reader(Some(reader)),
+            format,
+            parameters,
+        )
+    }
+
+    fn with_source(
+        name: String,
+        source: PipeSource,
+        format: RawFormat,
+        parameters: StreamParameters,
+    ) -> PipeStream {
+        PipeStream {
+            name,
+            format,
+            parameters: Arc::new(parameters),
+            source: Mutex::new(Some(source)),
+            stream_sender: Arc::new(Mutex::new(AudioStreamSender::new())),
+            running: Arc::new(AtomicBool::new(false)),
+            alive: Arc::new(AtomicBool::new(true)),
+            thread: Mutex::new(None),
+        }
+    }
+
+    fn spawn_reader(&self, mut source: PipeSource) -> JoinHandle<()> {
+        let stream_sender = self.stream_sender.clone();
+        let running = self.running.clone();
+        let alive = self.alive.clone();
+        let format = self.format;
+        let channels = self.parameters.channels as usize;
+        let frame_size = format.get_sample_size() * channels;
+        let chunk_frames = (self.parameters.sample_rate as f32 * Self::CHUNK_DURATION.as_secs_f32())
+            .max(1.0) as usize;
+
+        thread::spawn(move || {
+            let stop = |err: String| {
+                error!("{err}");
+                running.store(false, Ordering::Relaxed);
+            };
+            let mut reader = match source.open() {
+                Ok(reader) => reader,
+                Err(err) => return stop(err),
+            };
+
+            let mut data = vec![0; chunk_frames * frame_size];
+            let mut filled = 0;
+            while alive.load(Ordering::Relaxed) {
+                if !running.load(Ordering::Relaxed)
+                    || !stream_sender
+                        .lock()
+                        .unwrap()
+                        .has_room(chunk_frames * channels)
+                {
+                    thread::sleep(Self::IDLE_PERIOD);
+                    continue;
+                }
+
+                match reader.read(&mut data[filled..]) {
+                    Ok(0) if source.can_reopen() => {
+                        info!("Writer closed the pipe, waiting for the next one");
+                        // the incomplete frame of the previous writer is dropped
+                        filled = 0;
+                        reader = match source.open() {
+                            Ok(reader) => reader,
+                            Err(err) => return stop(err),
+                        };
+                    }
+                    Ok(0) => {
+                        info!("End of the input");
+                        running.store(false, Ordering::Relaxed);
+                        return;
+                    }
+                    Ok(length) => {
+                        filled += length;
+                        let complete = filled - filled % frame_size;
+                        let samples = format.decode(&data[..complete]);
+                        stream_sender
+                            .lock()
+                            .unwrap()
+                            .send_data(samples.inner(), Timestamp::now());
+                        data.copy_within(complete..filled, 0);
+                        filled -= complete;
+                    }
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return stop(format!("Failed to read the input: {err}")),
+                }
+            }
+        })
+    }
+}
+
+impl AudioInput for PipeStream {
+    fn start(&self) {
+        info!("Starting {}", self.name);
+        self.running.store(true, Ordering::Relaxed);
+
+        if let Some(source) = self.source.lock().unwrap().take() {
+            *self.thread.lock().unwrap() = Some(self.spawn_reader(source));
+        }
+    }
+
+    fn stop(&self) {
+        info!("Stopping {}", self.name);
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    fn get_parameters(&self) -> Arc<StreamParameters> {
+        self.parameters.clone()
+    }
+
+    fn add_stream_consumer(&mut self, stream_consumer: Arc<Mutex<dyn AudioStreamConsumer>>) {
+        self.stream_sender
+            .lock()
+            .unwrap()
+            .add_stream_receiver(stream_consumer)
+    }
+}
+
+impl Drop for PipeStream {
+    /// The thread may be blocked in a read, so it is left to finish after the next one.
+    fn drop(&mut self) {
+        self.alive.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, time::Instant};
+
+    use crate::{
+        audio::signal_generator::{Signal, SignalGenerator},
+        audio_analyzer::{AudioAnalyzysProvider, StreamAnalyzer},
+    };
+
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_arguments() {
+        assert_eq!(PipeStreamConfig::from_args(args("")), Ok(None));
+        assert_eq!(
+            PipeStreamConfig::from_args(args(
+                "--pipe /tmp/audio --format s16le --rate 44100 --channels 1"
+            )),
+            Ok(Some(PipeStreamConfig {
+                input: PipeInput::Fifo(PathBuf::from("/tmp/audio")),
+                format: RawFormat::S16Le,
+                parameters: StreamParameters {
+                    sample_rate: 44100,
+                    channels: 1
+                },
+            }))
+        );
+        assert_eq!(
+            PipeStreamConfig::from_args(args("--stdin"))
+                .unwrap()
+                .unwrap()
+                .format,
+            RawFormat::F32Le
+        );
+        assert!(PipeStreamConfig::from_args(args("--stdin --format f64le")).is_err());
+        assert!(PipeStreamConfig::from_args(args("--stdin --rate")).is_err());
+        assert!(PipeStreamConfig::from_args(args("--stdin --channels 0")).is_err());
+    }
+
+    #[test]
+    fn test_decoding() {
+        let decode = |format: RawFormat, data: &[u8]| format.decode(data).inner().clone();
+        assert_eq!(decode(RawFormat::U8, &[0, 128, 192]), vec![-1.0, 0.0, 0.5]);
+        assert_eq!(
+            decode(RawFormat::S16Le, &[0x00, 0x80, 0x00, 0x40, 0xff]),
+            vec![-1.0, 0.5]
+        );
+        assert_eq!(
+            decode(RawFormat::S24Le, &[0x00, 0x00, 0xc0, 0xff, 0xff, 0xff]),
+            vec![-0.5, -1.0 / 8388608.0]
+        );
+        assert_eq!(decode(RawFormat::S32Le, &[0, 0, 0, 0x40]), vec![0.5]);
+        assert_eq!(decode(RawFormat::F32Le, &0.25f32.to_le_bytes()), vec![0.25]);
+    }
+
+    #[test]
+    fn test_analyzer_on_pipe() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 2,
+        };
+        let mut generator =
+            SignalGenerator::new(Signal::Sine { frequency: 1000.0 }, 0.5, parameters.clone());
+        let data: Vec<u8> = generator
+            .generate(48000)
+            .iter()
+            .flat_map(|sample| ((sample * 32768.0) as i16).to_le_bytes())
+            // incomplete frame at the end
+            .chain([1, 2, 3])
+            .collect();
+
+        let mut stream =
+            PipeStream::from_reader(Box::new(Cursor::new(data)), RawFormat::S16Le, parameters);
+        let analyzer = Arc::new(Mutex::new(StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            4800,
+            stream.get_parameters(),
+        )));
+        stream.add_stream_consumer(analyzer.clone());
+
+        let start = Instant::now();
+        stream.start();
+        while stream.is_running() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        let audio_buffer = analyzer.lock().unwrap().get_audio_buffer();
+        audio_buffer.lock().unwrap().fetch();
+        assert_eq!(audio_buffer.lock().unwrap().get_new_samples_count(), 48000);
+
+        analyzer.lock().unwrap().process_new_samples();
+        let spectrums = analyzer.lock().unwrap().get_latest_spectrum();
+        let left = spectrums.get_channel(0).as_slice();
+        let peak = (0..left.len())
+            .max_by(|a, b| left[*a].total_cmp(&left[*b]))
+            .unwrap();
+        assert_eq!(peak * 10, 1000);
+        assert!((left[peak] - 0.5).abs() < 0.01, "{}", left[peak]);
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    cell::UnsafeCell,
+    ptr, slice,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Lock-free single producer, single consumer ring buffer. Preallocated, so the producer can be
+/// used on the real-time audio thread.
+struct Shared<T> {
+    buffer: Box<[UnsafeCell<T>]>,
+    mask: usize,
+    /// Total number of written items, modified by the producer only. Wraps around.
+    write_position: AtomicUsize,
+    /// Total number of read items, modified by the consumer only. Wraps around.
+    read_position: AtomicUsize,
+    overrun: AtomicUsize,
+}
+
+// Producer writes only the free slots and consumer reads only the filled ones, positions are
+// published with release/acquire ordering.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn len(&self) -> usize {
+        self.write_position
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.read_position.load(Ordering::Acquire))
+    }
+
+    fn storage(&self) -> *mut T {
+        UnsafeCell::raw_get(self.buffer.as_ptr())
+    }
+
+    /// Splits `count` items starting at `position` into the part before the end of the storage
+    /// and the part wrapped to its beginning.
+    fn split(&self, position: usize, count: usize) -> (usize, usize, usize) {
+        let start = position & self.mask;
+        let first = count.min(self.capacity() - start);
+        (start, first, count - first)
+    }
+}
+
+pub struct RingProducer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct RingConsumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Creates ring buffer holding at least `capacity` items, rounded up to a power of two.
+pub fn ring_buffer<T: Copy + Default>(capacity: usize) -> (RingProducer<T>, RingConsumer<T>) {
+    let capacity = capacity.max(1).next_power_of_two();
+    let shared = Arc::new(Shared {
+        buffer: (0..capacity)
+            .map(|_| UnsafeCell::new(T::default()))
+            .collect(),
+        mask: capacity - 1,
+        write_position: AtomicUsize::new(0),
+        read_position: AtomicUsize::new(0),
+        overrun: AtomicUsize::new(0),
+    });
+
+    (
+        RingProducer {
+            shared: shared.clone(),
+        },
+        RingConsumer { shared },
+    )
+}
+
+impl<T: Copy> RingProducer<T> {
+    /// Wait-free. Writes all items or none of them, so interleaved frames are never split.
+    /// Items which don't fit are counted as overrun.
+    pub fn push_slice(&mut self, items: &[T]) -> bool {
+        let shared = &*self.shared;
+        let write = shared.write_position.load(Ordering::Relaxed);
+        let read = shared.read_position.load(Ordering::Acquire);
+        let free = shared.capacity() - write.wrapping_sub(read);
+
+        if items.len() > free {
+            shared.overrun.fetch_add(items.len(), Ordering::Relaxed);
+            return false;
+        }
+
+        let (start, first, second) = shared.split(write, items.len());
+        // the slots are free, consumer doesn't access them until the position is published
+        unsafe {
+            ptr::copy_nonoverlapping(items.as_ptr(), shared.storage().add(start), first);
+            ptr::copy_nonoverlapping(items.as_ptr().add(first), shared.storage(), second);
+        }
+        shared
+            .write_position
+            .store(write.wrapping_add(items.len()), Ordering::Release);
+        true
+    }
+
+    pub fn get_free_len(&self) -> usize {
+        self.shared.capacity() - self.shared.len()
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    /// Consumer was dropped, nothing reads the pushed items anymore.
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+}
+
+impl<T: Copy> RingConsumer<T> {
+    /// Appends up to `max_count` items to `output`, returns number of read items.
+    pub fn pop_into(&mut self, output: &mut Vec<T>, max_count: usize) -> usize {
+        let shared = &*self.shared;
+        let read = shared.read_position.load(Ordering::Relaxed);
+        let write = shared.write_position.load(Ordering::Acquire);
+        let count = write.wrapping_sub(read).min(max_count);
+
+        let (start, first, second) = shared.split(read, count);
+        // the slots are filled, producer doesn't access them until the position is published
+        unsafe {
+            output.extend_from_slice(slice::from_raw_parts(shared.storage().add(start), first));
+            output.extend_from_slice(slice::from_raw_parts(shared.storage(), second));
+        }
+        shared
+            .read_position
+            .store(read.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    pub fn get_len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.get_len() == 0
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    /// Number of items dropped because the buffer was full.
+    pub fn get_overrun_count(&self) -> usize {
+        self.shared.overrun.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_push_and_pop() {
+        let (mut producer, mut consumer) = ring_buffer::<f32>(6);
+        assert_eq!(producer.get_capacity(), 8);
+
+        assert!(producer.push_slice(&[1.0, 2.0, 3.0]));
+        assert_eq!(consumer.get_len(), 3);
+
+        let mut output = Vec::new();
+        assert_eq!(consumer.pop_into(&mut output, 2), 2);
+        assert_eq!(output, vec![1.0, 2.0]);
+
+        // wraps around the end of the storage
+        assert!(producer.push_slice(&[4.0, 5.0, 6.0, 7.0, 8.0, 9.0]));
+        assert_eq!(consumer.pop_into(&mut output, usize::MAX), 7);
+        assert_eq!(output, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]);
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn test_overrun() {
+        let (mut producer, mut consumer) = ring_buffer::<i32>(4);
+
+        assert!(producer.push_slice(&[1, 2, 3]));
+        // whole slice is dropped, so frames are never split
+        assert!(!producer.push_slice(&[4, 5]));
+        assert_eq!(consumer.get_overrun_count(), 2);
+        assert_eq!(producer.get_free_len(), 1);
+
+        let mut output = Vec::new();
+        consumer.pop_into(&mut output, usize::MAX);
+        assert_eq!(output, vec![1, 2, 3]);
+        assert!(producer.push_slice(&[4, 5]));
+        assert_eq!(consumer.get_overrun_count(), 2);
+    }
+
+    #[test]
+    fn test_position_wrap_around() {
+        let (mut producer, mut consumer) = ring_buffer::<u8>(4);
+        producer
+            .shared
+            .write_position
+            .store(usize::MAX - 1, Ordering::Relaxed);
+        consumer
+            .shared
+            .read_position
+            .store(usize::MAX - 1, Ordering::Relaxed);
+
+        assert!(producer.push_slice(&[1, 2, 3]));
+        assert_eq!(consumer.get_len(), 3);
+        let mut output = Vec::new();
+        consumer.pop_into(&mut output, usize::MAX);
+        assert_eq!(output, vec![1, 2, 3]);
+    }
+
+    /// Producer and consumer run concurrently with varying chunk sizes, every item has to
+    /// arrive exactly once and in order.
+    #[test]
+    fn test_stress_no_loss_no_reordering() {
+        const COUNT: u64 = 2_000_000;
+        let (mut producer, mut consumer) = ring_buffer::<u64>(1024);
+
+        let writer = thread::spawn(move || {
+            let mut next = 0;
+            let mut chunk = Vec::new();
+            let mut chunk_size = 1;
+            while next < COUNT {
+                chunk_size = chunk_size % 509 + 7;
+                chunk.clear();
+                chunk.extend(next..(next + chunk_size).min(COUNT));
+                while !producer.push_slice(&chunk) {
+                    thread::yield_now();
+                }
+                next += chunk.len() as u64;
+            }
+            producer
+        });
+
+        let mut expected = 0;
+        let mut output = Vec::new();
+        let mut read_size = 1;
+        while expected < COUNT {
+            read_size = read_size % 331 + 1;
+            output.clear();
+            if consumer.pop_into(&mut output, read_size) == 0 {
+                thread::yield_now();
+            }
+            for item in &output {
+                assert_eq!(*item, expected);
+                expected += 1;
+            }
+        }
+
+        writer.join().unwrap();
+        assert!(consumer.is_empty());
+        // retried pushes are counted as overrun, but nothing was lost
+        assert_eq!(expected, COUNT);
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    fmt::Display,
+    net::{SocketAddr, UdpSocket},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Linear PCM payloads of RTP, big endian signed integers (RFC 3551 L16, RFC 3190 L24).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayloadFormat {
+    L16,
+    L24,
+}
+
+impl PayloadFormat {
+    pub fn get_sample_size(&self) -> usize {
+        match self {
+            PayloadFormat::L16 => 2,
+            PayloadFormat::L24 => 3,
+        }
+    }
+
+    /// Appends decoded samples, trailing bytes of an incomplete sample are ignored.
+    pub fn decode(&self, payload: &[u8], output: &mut Vec<f32>) {
+        match self {
+            PayloadFormat::L16 => output.extend(
+                payload
+                    .chunks_exact(2)
+                    .map(|bytes| i16::from_be_bytes([bytes[0], bytes[1]]) as f32 / 32768.0),
+            ),
+            PayloadFormat::L24 => output.extend(payload.chunks_exact(3).map(|bytes| {
+                // sign extended by the arithmetic shift
+                let value = i32::from_be_bytes([bytes[0], bytes[1], bytes[2], 0]) >> 8;
+                value as f32 / 8388608.0
+            })),
+        }
+    }
+
+    pub fn encode(&self, samples: &[f32], output: &mut Vec<u8>) {
+        for sample in samples {
+            let sample = sample.clamp(-1.0, 1.0);
+            match self {
+                PayloadFormat::L16 => {
+                    let value = (sample * 32768.0).round().min(i16::MAX as f32) as i16;
+                    output.extend_from_slice(&value.to_be_bytes());
+                }
+                PayloadFormat::L24 => {
+                    let value = (sample * 8388608.0).round().min(8388607.0) as i32;
+                    output.extend_from_slice(&value.to_be_bytes()[1..]);
+                }
+            }
+        }
+    }
+}
+
+impl Display for PayloadFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PayloadFormat::L16 => write!(f, "L16"),
+            PayloadFormat::L24 => write!(f, "L24"),
+        }
+    }
+}
+
+/// RTP packet (RFC 3550), CSRCs and header extension are skipped when parsing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RtpPacket<'a> {
+    pub payload_type: u8,
+    pub marker: bool,
+    pub sequence: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+    pub payload: &'a [u8],
+}
+
+impl<'a> RtpPacket<'a> {
+    const VERSION: u8 = 2;
+    const HEADER_LEN: usize = 12;
+
+    pub fn parse(data: &'a [u8]) -> Result<Self, &'static str> {
+        if data.len() < Self::HEADER_LEN {
+            return Err("Packet shorter than RTP header");
+        }
+        if data[0] >> 6 != Self::VERSION {
+            return Err("Unsupported RTP version");
+        }
+        let padding = data[0] & 0x20 != 0;
+        let extension = data[0] & 0x10 != 0;
+        let csrc_count = (data[0] & 0x0f) as usize;
+
+        let mut start = Self::HEADER_LEN + 4 * csrc_count;
+        if extension {
+            let Some(header) = data.get(start..start + 4) else {
+                return Err("Truncated RTP header extension");
+            };
+            start += 4 + 4 * u16::from_be_bytes([header[2], header[3]]) as usize;
+        }
+        let mut end = data.len();
+        if padding {
+            end = end.saturating_sub(data[data.len() - 1] as usize);
+        }
+        if start > end {
+            return Err("Truncated RTP packet");
+        }
+
+        Ok(Self {
+            payload_type: data[1] & 0x7f,
+            marker: data[1] & 0x80 != 0,
+            sequence: u16::from_be_bytes([data[2], data[3]]),
+            timestamp: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            ssrc: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            payload: &data[start..end],
+        })
+    }
+
+    pub fn write(&self, output: &mut Vec<u8>) {
+        output.push(Self::VERSION << 6);
+        output.push((self.marker as u8) << 7 | self.payload_type & 0x7f);
+        output.extend_from_slice(&self.sequence.to_be_bytes());
+        output.extend_from_slice(&self.timestamp.to_be_bytes());
+        output.extend_from_slice(&self.ssrc.to_be_bytes());
+        output.extend_from_slice(self.payload);
+    }
+}
+
+/// Streams interleaved samples as RTP packets of fixed number of frames, the RTP clock is
+/// the sample rate.
+pub struct RtpSender {
+    socket: UdpSocket,
+    format: PayloadFormat,
+    channels: usize,
+    frames_per_packet: usize,
+    sequence: u16,
+    timestamp: u32,
+    ssrc: u32,
+    pending: Vec<f32>,
+    payload: Vec<u8>,
+    packet: Vec<u8>,
+}
+
+impl RtpSender {
+    /// First of the dynamic payload types, the format is agreed out of band.
+    pub const PAYLOAD_TYPE: u8 = 96;
+
+    pub fn new(
+        target: SocketAddr,
+        format: PayloadFormat,
+        channels: usize,
+        frames_per_packet: usize,
+    ) -> Result<RtpSender, String> {
+        let local: SocketAddr = if target.is_ipv4() {
+            "0.0.0.0:0".parse().unwrap()
+        } else {
+            "[::]:0".parse().unwrap()
+        };
+        let socket = UdpSocket::bind(local).map_err(|err| err.to_string())?;
+        socket.connect(target).map_err(|err| err.to_string())?;
+
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+
+        Ok(RtpSender {
+            socket,
+            format,
+            channels,
+            frames_per_packet,
+            sequence: seed as u16,
+            timestamp: seed.rotate_left(16),
+            ssrc: seed ^ std::process::id(),
+            pending: Vec::new(),
+            payload: Vec::new(),
+            packet: Vec::new(),
+        })
+    }
+
+    /// Sends all complete packets, the rest waits for more samples. Returns number of sent
+    /// packets.
+    pub fn send(&mut self, samples: &[f32]) -> Result<usize, String> {
+        self.pending.extend_from_slice(samples);
+
+        let packet_samples = self.frames_per_packet * self.channels;
+        let mut sent = 0;
+        while self.pending.len() >= packet_samples {
+            self.payload.clear();
+            self.format
+                .encode(&self.pending[..packet_samples], &mut self.payload);
+            self.packet.clear();
+            RtpPacket {
+                payload_type: Self::PAYLOAD_TYPE,
+                marker: false,
+                sequence: self.sequence,
+                timestamp: self.timestamp,
+                ssrc: self.ssrc,
+                payload: &self.payload,
+            }
+            .write(&mut self.packet);
+            self.socket
+                .send(&self.packet)
+                .map_err(|err| err.to_string())?;
+
+            self.pending.drain(..packet_samples);
+            self.sequence = self.sequence.wrapping_add(1);
+            self.timestamp = self.timestamp.wrapping_add(self.frames_per_packet as u32);
+            sent += 1;
+        }
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_round_trip() {
+        let samples = [0.0, 0.5, -0.5, 1.0, -1.0, 0.25];
+        for (format, tolerance) in [(PayloadFormat::L16, 1e-4), (PayloadFormat::L24, 1e-6)] {
+            let mut payload = Vec::new();
+            format.encode(&samples, &mut payload);
+            assert_eq!(payload.len(), samples.len() * format.get_sample_size());
+
+            let mut decoded = Vec::new();
+            format.decode(&payload, &mut decoded);
+            for (decoded, sample) in decoded.iter().zip(samples) {
+                assert!((decoded - sample).abs() < tolerance, "{format}: {decoded}");
+            }
+        }
+
+        let mut decoded = Vec::new();
+        PayloadFormat::L24.decode(&[0xff, 0xff, 0xff, 0x40, 0x00, 0x00], &mut decoded);
+        assert_eq!(decoded, vec![-1.0 / 8388608.0, 0.5]);
+    }
+
+    #[test]
+    fn test_packet_parsing() {
+        let payload = [1, 2, 3, 4];
+        let packet = RtpPacket {
+            payload_type: 96,
+            marker: true,
+            sequence: 65535,
+            timestamp: 0xdeadbeef,
+            ssrc: 42,
+            payload: &payload,
+        };
+        let mut data = Vec::new();
+        packet.write(&mut data);
+        assert_eq!(RtpPacket::parse(&data), Ok(packet.clone()));
+
+        // one CSRC, extension of one word and two bytes of padding
+        let mut data = Vec::new();
+        packet.write(&mut data);
+        data[0] |= 0x30 | 1;
+        let header = data[..12].to_vec();
+        let mut extended = header;
+        extended.extend_from_slice(&[0, 0, 0, 7]);
+        extended.extend_from_slice(&[0xbe, 0xde, 0, 1, 9, 9, 9, 9]);
+        extended.extend_from_slice(&payload);
+        extended.extend_from_slice(&[0, 2]);
+        assert_eq!(RtpPacket::parse(&extended).unwrap().payload, &payload);
+
+        assert!(RtpPacket::parse(&data[..8]).is_err());
+        data[0] = 0x40;
+        assert!(RtpPacket::parse(&data).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use cpal::{
+    FromSample, SampleFormat, SampleRate, SizedSample, SupportedStreamConfig,
+    SupportedStreamConfigRange,
+};
+
+use super::Sample;
+
+/// Converts interleaved samples of any cpal format to full scale `Sample`s.
+/// Values outside of the full scale are clipped and NaNs are replaced with silence.
+pub fn convert_samples<T>(data: &[T]) -> Vec<Sample>
+where
+    T: SizedSample,
+    Sample: FromSample<T>,
+{
+    data.iter().map(|sample| convert_sample(*sample)).collect()
+}
+
+/// Same as `convert_samples`, but reuses `output`, so it doesn't allocate once its capacity
+/// is sufficient.
+pub fn convert_samples_into<T>(data: &[T], output: &mut Vec<Sample>)
+where
+    T: SizedSample,
+    Sample: FromSample<T>,
+{
+    output.clear();
+    output.extend(data.iter().map(|sample| convert_sample(*sample)));
+}
+
+fn convert_sample<T>(sample: T) -> Sample
+where
+    T: SizedSample,
+    Sample: FromSample<T>,
+{
+    let sample = sample.to_sample::<Sample>();
+    if sample.is_nan() {
+        0.0
+    } else {
+        sample.clamp(-1.0, 1.0)
+    }
+}
+
+/// Preference of the sample format, 0 when not supported. Resolution goes first, `f32` is the
+/// best as it needs no conversion.
+pub fn format_rank(sample_format: SampleFormat) -> u8 {
+    match sample_format {
+        SampleFormat::F32 => 10,
+        SampleFormat::F64 => 9,
+        SampleFormat::I32 => 8,
+        SampleFormat::U32 => 7,
+        SampleFormat::I64 => 6,
+        SampleFormat::U64 => 5,
+        SampleFormat::I16 => 4,
+        SampleFormat::U16 => 3,
+        SampleFormat::I8 => 2,
+        SampleFormat::U8 => 1,
+        _ => 0,
+    }
+}
+
+/// Chooses config with the channels and sample rate of the default one (stereo 48 kHz if there
+/// is none) and the best sample format.
+pub fn select_config(
+    default: Option<SupportedStreamConfig>,
+    supported: &[SupportedStreamConfigRange],
+) -> Option<SupportedStreamConfig> {
+    const FALLBACK_SAMPLE_RATE: SampleRate = SampleRate(48000);
+    const FALLBACK_CHANNELS: u16 = 2;
+
+    let (sample_rate, channels) = default
+        .as_ref()
+        .map(|config| (config.sample_rate(), config.channels()))
+        .unwrap_or((FALLBACK_SAMPLE_RATE, FALLBACK_CHANNELS));
+
+    supported
+        .iter()
+        .filter(|range| format_rank(range.sample_format()) > 0)
+        .max_by_key(|range| {
+            (
+                range.channels() == channels,
+                range.min_sample_rate() <= sample_rate && sample_rate <= range.max_sample_rate(),
+                format_rank(range.sample_format()),
+            )
+        })
+        .map(|range| {
+            let sample_rate = SampleRate(
+                sample_rate
+                    .0
+                    .clamp(range.min_sample_rate().0, range.max_sample_rate().0),
+            );
+            range.with_sample_rate(sample_rate)
+        })
+        .or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use cpal::SupportedBufferSize;
+
+    use super::*;
+
+    fn range(
+        channels: u16,
+        min: u32,
+        max: u32,
+        format: SampleFormat,
+    ) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(
+            channels,
+            SampleRate(min),
+            SampleRate(max),
+            SupportedBufferSize::Unknown,
+            format,
+        )
+    }
+
+    #[test]
+    fn test_integer_conversion() {
+        assert_eq!(
+            convert_samples(&[i16::MIN, 0, i16::MAX / 2 + 1]),
+            vec![-1.0, 0.0, 0.5]
+        );
+        assert_eq!(
+            convert_samples(&[i32::MIN, 0, i32::MAX / 2 + 1]),
+            vec![-1.0, 0.0, 0.5]
+        );
+        assert_eq!(convert_samples(&[i8::MIN, 0, 64]), vec![-1.0, 0.0, 0.5]);
+        assert_eq!(
+            convert_samples(&[i64::MIN, 0, i64::MAX / 2 + 1]),
+            vec![-1.0, 0.0, 0.5]
+        );
+    }
+
+    #[test]
+    fn test_unsigned_conversion() {
+        assert_eq!(convert_samples(&[0u8, 128, 192]), vec![-1.0, 0.0, 0.5]);
+        assert_eq!(convert_samples(&[0u16, 32768, 49152]), vec![-1.0, 0.0, 0.5]);
+        assert_eq!(
+            convert_samples(&[0u32, 1 << 31, 3 << 30]),
+            vec![-1.0, 0.0, 0.5]
+        );
+        assert_eq!(
+            convert_samples(&[0u64, 1 << 63, 3 << 62]),
+            vec![-1.0, 0.0, 0.5]
+        );
+    }
+
+    #[test]
+    fn test_full_scale_accuracy() {
+        for samples in [
+            convert_samples(&[i16::MAX]),
+            convert_samples(&[u16::MAX]),
+            convert_samples(&[i32::MAX]),
+            convert_samples(&[u32::MAX]),
+        ] {
+            assert!((samples[0] - 1.0).abs() < 1e-4, "{}", samples[0]);
+        }
+
+        // every 16 bit step is preserved
+        let samples = convert_samples(&[1i16, 2, 3]);
+        assert_eq!(samples[1] - samples[0], 1.0 / 32768.0);
+        assert_eq!(samples[2] - samples[1], 1.0 / 32768.0);
+    }
+
+    #[test]
+    fn test_float_clipping() {
+        assert_eq!(
+            convert_samples(&[-1.5f32, -1.0, 0.25, 1.0, 2.0, f32::NAN]),
+            vec![-1.0, -1.0, 0.25, 1.0, 1.0, 0.0]
+        );
+        assert_eq!(
+            convert_samples(&[f64::NEG_INFINITY, 0.5, f64::INFINITY]),
+            vec![-1.0, 0.5, 1.0]
+        );
+    }
+
+    #[test]
+    fn test_convert_into_reuses_output() {
+        let mut output = Vec::with_capacity(8);
+        convert_samples_into(&[0i16, i16::MIN], &mut output);
+        convert_samples_into(&[i16::MIN, 0, 16384], &mut output);
+        assert_eq!(output, vec![-1.0, 0.0, 0.5]);
+        assert_eq!(output.capacity(), 8);
+    }
+
+    #[test]
+    fn test_select_best_format() {
+        let default = range(2, 48000, 48000, SampleFormat::I16).with_sample_rate(SampleRate(48000));
+        let supported = [
+            range(2, 44100, 96000, SampleFormat::I16),
+            range(2, 44100, 96000, SampleFormat::I32),
+            range(1, 44100, 96000, SampleFormat::F32),
+            range(2, 8000, 22050, SampleFormat::F32),
+        ];
+
+        let config = select_config(Some(default), &supported).unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::I32);
+        assert_eq!(config.channels(), 2);
+        assert_eq!(config.sample_rate(), SampleRate(48000));
+    }
+
+    #[test]
+    fn test_select_without_default() {
+        let supported = [
+            range(1, 8000, 192000, SampleFormat::U8),
+            range(2, 8000, 44100, SampleFormat::I16),
+        ];
+        let config = select_config(None, &supported).unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::I16);
+        assert_eq!(config.sample_rate(), SampleRate(44100));
+
+        let default = range(2, 48000, 48000, SampleFormat::F32).with_sample_rate(SampleRate(48000));
+        assert_eq!(select_config(Some(default.clone()), &[]), Some(default));
+        assert_eq!(select_config(None, &[]), None);
+    }
+}
@@ -0,0 +1,333 @@
+use std::{f32::consts::PI, fmt::Display, time::Duration};
+
+use super::{file_decoder::AudioFileDecoder, Sample, StreamParameters};
+
+/// Known input for testing and calibration of the analysis.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Signal {
+    Sine {
+        frequency: f32,
+    },
+    /// Sum of sines, each with equal share of the amplitude.
+    MultiTone {
+        frequencies: Vec<f32>,
+    },
+    /// Logarithmic sweep, restarted after `duration`.
+    Sweep {
+        start_frequency: f32,
+        end_frequency: f32,
+        duration: Duration,
+    },
+    WhiteNoise,
+    /// Noise with equal energy per octave.
+    PinkNoise,
+    /// Single full amplitude sample every `period`.
+    ImpulseTrain {
+        period: Duration,
+    },
+    /// Metronome-like kick drum, a decaying sine with falling pitch on every beat.
+    Kick {
+        beats_per_minute: f32,
+    },
+}
+
+impl Display for Signal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Signal::Sine { frequency } => write!(f, "Sine {frequency} Hz"),
+            Signal::MultiTone { frequencies } => {
+                let frequencies: Vec<String> = frequencies.iter().map(|f| f.to_string()).collect();
+                write!(f, "Multi-tone {} Hz", frequencies.join(", "))
+            }
+            Signal::Sweep {
+                start_frequency,
+                end_frequency,
+                duration,
+            } => write!(
+                f,
+                "Sweep {start_frequency}-{end_frequency} Hz in {} s",
+                duration.as_secs_f32()
+            ),
+            Signal::WhiteNoise => write!(f, "White noise"),
+            Signal::PinkNoise => write!(f, "Pink noise"),
+            Signal::ImpulseTrain { period } => {
+                write!(f, "Impulse train every {} ms", period.as_millis())
+            }
+            Signal::Kick { beats_per_minute } => write!(f, "Kick {beats_per_minute} BPM"),
+        }
+    }
+}
+
+/// Xorshift generator, noise is reproducible between runs.
+struct Noise {
+    state: u32,
+    pink_filter: [f32; 7],
+}
+
+impl Noise {
+    fn new(seed: u32) -> Self {
+        Self {
+            state: seed.wrapping_mul(0x9E37_79B9).max(1),
+            pink_filter: [0.0; 7],
+        }
+    }
+
+    /// Uniform in [-1, 1).
+    fn white(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+    }
+
+    /// Paul Kellet's refined filter, -3 dB per octave within 0.05 dB above 9 Hz at 44.1 kHz.
+    fn pink(&mut self) -> f32 {
+        const GAIN: f32 = 0.11;
+        let white = self.white();
+        let b = &mut self.pink_filter;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        (pink * GAIN).clamp(-1.0, 1.0)
+    }
+}
+
+/// Generates interleaved samples of a `Signal`. Tonal signals are the same on all channels,
+/// noise is independent per channel. Played like an endless file, so it can be used as a
+/// source of `FileStream`.
+pub struct SignalGenerator {
+    signal: Signal,
+    amplitude: Sample,
+    parameters: StreamParameters,
+    position: u64,
+    noise: Vec<Noise>,
+}
+
+impl SignalGenerator {
+    const KICK_START_FREQUENCY: f32 = 150.0;
+    const KICK_END_FREQUENCY: f32 = 50.0;
+    const KICK_PITCH_DECAY: f32 = 0.03;
+    const KICK_DECAY: f32 = 0.1;
+
+    pub fn new(signal: Signal, amplitude: Sample, parameters: StreamParameters) -> Self {
+        Self {
+            noise: (0..parameters.channels as u32).map(Noise::new).collect(),
+            signal,
+            amplitude,
+            parameters,
+            position: 0,
+        }
+    }
+
+    pub fn get_signal(&self) -> &Signal {
+        &self.signal
+    }
+
+    /// Generates `frames` interleaved frames.
+    pub fn generate(&mut self, frames: usize) -> Vec<Sample> {
+        let channels = self.parameters.channels as usize;
+        let mut samples = Vec::with_capacity(frames * channels);
+        for _ in 0..frames {
+            match self.signal {
+                Signal::WhiteNoise | Signal::PinkNoise => {
+                    for noise in self.noise.iter_mut() {
+                        let value = if self.signal == Signal::WhiteNoise {
+                            noise.white()
+                        } else {
+                            noise.pink()
+                        };
+                        samples.push(value * self.amplitude);
+                    }
+                }
+                _ => {
+                    let value = self.tone(self.position) * self.amplitude;
+                    samples.extend(std::iter::repeat_n(value, channels));
+                }
+            }
+            self.position += 1;
+        }
+        samples
+    }
+
+    /// Deterministic signals in range [-1, 1] at `position` frame.
+    fn tone(&self, position: u64) -> f32 {
+        let sample_rate = self.parameters.sample_rate as f64;
+        let time = position as f64 / sample_rate;
+
+        match &self.signal {
+            Signal::Sine { frequency } => phase_sin(*frequency as f64 * time),
+            Signal::MultiTone { frequencies } => {
+                frequencies
+                    .iter()
+                    .map(|frequency| phase_sin(*frequency as f64 * time))
+                    .sum::<f32>()
+                    / frequencies.len().max(1) as f32
+            }
+            Signal::Sweep {
+                start_frequency,
+                end_frequency,
+                duration,
+            } => {
+                let duration = duration.as_secs_f64();
+                let time = time % duration;
+                let ratio = (*end_frequency as f64 / *start_frequency as f64).ln();
+                let cycles = *start_frequency as f64 * duration / ratio
+                    * ((time / duration * ratio).exp() - 1.0);
+                phase_sin(cycles)
+            }
+            Signal::ImpulseTrain { period } => {
+                let period = (period.as_secs_f64() * sample_rate).round().max(1.0) as u64;
+                if position.is_multiple_of(period) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Signal::Kick { beats_per_minute } => {
+                let beat = (60.0 / *beats_per_minute as f64 * sample_rate)
+                    .round()
+                    .max(1.0) as u64;
+                let time = (position % beat) as f64 / sample_rate;
+                let (start, end) = (
+                    Self::KICK_START_FREQUENCY as f64,
+                    Self::KICK_END_FREQUENCY as f64,
+                );
+                let pitch_decay = Self::KICK_PITCH_DECAY as f64;
+                // integral of frequency falling exponentially from start to end
+                let cycles =
+                    end * time + (start - end) * pitch_decay * (1.0 - (-time / pitch_decay).exp());
+                let envelope = (-time / Self::KICK_DECAY as f64).exp() as f32;
+                envelope * phase_sin(cycles)
+            }
+            Signal::WhiteNoise | Signal::PinkNoise => 0.0,
+        }
+    }
+}
+
+/// Sine of phase given in cycles, the fraction is taken first to keep the precision.
+fn phase_sin(cycles: f64) -> f32 {
+    (2.0 * PI * cycles.fract() as f32).sin()
+}
+
+impl AudioFileDecoder for SignalGenerator {
+    fn get_parameters(&self) -> StreamParameters {
+        self.parameters.clone()
+    }
+
+    fn get_length(&self) -> Option<u64> {
+        None
+    }
+
+    fn read(&mut self, frames: usize) -> Result<Vec<Sample>, String> {
+        Ok(self.generate(frames))
+    }
+
+    fn seek(&mut self, frame: u64) -> Result<(), String> {
+        self.position = frame;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator(signal: Signal, sample_rate: u32, channels: u16) -> SignalGenerator {
+        SignalGenerator::new(
+            signal,
+            0.5,
+            StreamParameters {
+                sample_rate,
+                channels,
+            },
+        )
+    }
+
+    fn rms(samples: &[Sample]) -> f32 {
+        (samples.iter().map(|v| v * v).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_sine() {
+        let mut sine = generator(Signal::Sine { frequency: 1000.0 }, 48000, 2);
+        let samples = sine.generate(4800);
+        assert_eq!(samples.len(), 9600);
+
+        // same on both channels, quarter period is the peak
+        assert_eq!(samples[24], samples[25]);
+        assert!((samples[24] - 0.5).abs() < 1e-6);
+        assert!((rms(&samples) - 0.5 / 2f32.sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_impulse_train() {
+        let mut impulses = generator(
+            Signal::ImpulseTrain {
+                period: Duration::from_millis(10),
+            },
+            8000,
+            1,
+        );
+        let samples = impulses.generate(200);
+        let positions: Vec<usize> = (0..samples.len()).filter(|i| samples[*i] != 0.0).collect();
+        assert_eq!(positions, vec![0, 80, 160]);
+        assert_eq!(samples[80], 0.5);
+    }
+
+    #[test]
+    fn test_noise_is_independent_per_channel() {
+        let mut noise = generator(Signal::WhiteNoise, 48000, 2);
+        let samples = noise.generate(48000);
+        let (left, right): (Vec<Sample>, Vec<Sample>) =
+            samples.chunks(2).map(|frame| (frame[0], frame[1])).unzip();
+
+        assert!(samples.iter().all(|v| v.abs() <= 0.5));
+        assert!((rms(&left) - 0.5 / 3f32.sqrt()).abs() < 0.01);
+        let correlation: f32 = left.iter().zip(&right).map(|(l, r)| l * r).sum::<f32>()
+            / (rms(&left) * rms(&right) * left.len() as f32);
+        assert!(correlation.abs() < 0.05, "{correlation}");
+
+        let mut pink = generator(Signal::PinkNoise, 48000, 1);
+        assert!(pink.generate(48000).iter().all(|v| v.abs() <= 0.5));
+    }
+
+    #[test]
+    fn test_kick_on_beats() {
+        let mut kick = generator(
+            Signal::Kick {
+                beats_per_minute: 120.0,
+            },
+            1000,
+            1,
+        );
+        let samples = kick.generate(2000);
+
+        // beats at 0, 0.5, 1.0 and 1.5 s, silent before the next one
+        for beat in [0, 500, 1000, 1500] {
+            assert!(rms(&samples[beat..beat + 50]) > 0.1);
+            assert!(rms(&samples[beat + 400..beat + 500]) < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_seek_and_sweep_restart() {
+        let signal = Signal::Sweep {
+            start_frequency: 20.0,
+            end_frequency: 2000.0,
+            duration: Duration::from_secs(1),
+        };
+        let mut sweep = generator(signal, 8000, 1);
+        let first = sweep.generate(100);
+        sweep.generate(7900);
+        assert_eq!(sweep.generate(100), first);
+
+        sweep.seek(0).unwrap();
+        assert_eq!(sweep.read(100).unwrap(), first);
+        assert_eq!(sweep.get_length(), None);
+    }
+}
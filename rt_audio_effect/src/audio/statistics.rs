@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+/// Smoothed average and maximum of durations, e.g. callback periods or processing times.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DurationStatistics {
+    pub count: u64,
+    pub last: Duration,
+    pub average: Duration,
+    pub max: Duration,
+}
+
+impl DurationStatistics {
+    /// Weight of a new value in the exponential average.
+    const SMOOTHING: f64 = 0.05;
+
+    pub fn push(&mut self, duration: Duration) {
+        self.average = if self.count == 0 {
+            duration
+        } else {
+            Duration::from_secs_f64(
+                self.average.as_secs_f64() * (1.0 - Self::SMOOTHING)
+                    + duration.as_secs_f64() * Self::SMOOTHING,
+            )
+        };
+        self.count += 1;
+        self.last = duration;
+        self.max = self.max.max(duration);
+    }
+}
+
+/// Timing of audio callbacks of a device stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CallbackStatistics {
+    pub frames: u64,
+    /// Interval between callbacks.
+    pub period: DurationStatistics,
+    /// Difference between the interval and the duration of frames delivered by the callback.
+    pub jitter: DurationStatistics,
+    /// Callbacks dropped because consumers were being connected.
+    pub dropped_callbacks: u64,
+}
+
+impl CallbackStatistics {
+    pub fn push(&mut self, period: Duration, frames: usize, sample_rate: u32) {
+        let expected = Duration::from_secs_f64(frames as f64 / sample_rate as f64);
+        self.period.push(period);
+        self.jitter.push(period.abs_diff(expected));
+    }
+}
+
+/// Samples lost between a stream and a consumer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferStatistics {
+    /// Samples dropped by the stream because the ring buffer was full.
+    pub dropped_samples: usize,
+    /// Times new samples were discarded before the consumer read them.
+    pub overruns: usize,
+    pub skipped_samples: usize,
+    /// Reads without enough new samples.
+    pub underruns: usize,
+}
+
+impl BufferStatistics {
+    pub fn is_healthy(&self) -> bool {
+        self.dropped_samples == 0 && self.overruns == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_callback_statistics() {
+        let mut statistics = CallbackStatistics::default();
+        statistics.push(Duration::from_millis(10), 480, 48000);
+        assert_eq!(statistics.period.average, Duration::from_millis(10));
+        assert_eq!(statistics.jitter.average, Duration::ZERO);
+
+        // 2 ms late and 2 ms early callbacks are the same jitter
+        statistics.push(Duration::from_millis(12), 480, 48000);
+        statistics.push(Duration::from_millis(8), 480, 48000);
+        assert_eq!(statistics.jitter.max, Duration::from_millis(2));
+        assert_eq!(statistics.jitter.last, Duration::from_millis(2));
+        assert_eq!(statistics.period.max, Duration::from_millis(12));
+        assert_eq!(statistics.period.count, 3);
+
+        let average = statistics.jitter.average.as_secs_f64();
+        assert!(average > 0.0 && average < 0.002, "{average}");
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct StreamParameters {
     pub sample_rate: u32,
     pub channels: u16,
@@ -0,0 +1,452 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use log::{error, info};
+
+use super::{AudioBuffer, AudioStreamConsumer, Sample, StreamParameters};
+
+struct Recording {
+    path: PathBuf,
+    writer: WavWriter<BufWriter<File>>,
+    metadata: RecordingMetadata,
+}
+
+/// Written next to the WAV file as `<name>.json`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordingMetadata {
+    pub device_name: String,
+    pub parameters: StreamParameters,
+    pub started: SystemTime,
+    pub frames: u64,
+}
+
+impl RecordingMetadata {
+    pub fn get_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / self.parameters.sample_rate as f64)
+    }
+
+    pub fn to_json(&self) -> String {
+        let started = self.started.duration_since(UNIX_EPOCH).unwrap_or_default();
+        format!(
+            concat!(
+                "{{\n",
+                "  \"device\": \"{}\",\n",
+                "  \"sample_rate\": {},\n",
+                "  \"channels\": {},\n",
+                "  \"sample_format\": \"f32\",\n",
+                "  \"started\": \"{}\",\n",
+                "  \"started_unix_ms\": {},\n",
+                "  \"frames\": {},\n",
+                "  \"duration_s\": {:.3}\n",
+                "}}\n"
+            ),
+            escape_json(&self.device_name),
+            self.parameters.sample_rate,
+            self.parameters.channels,
+            format_utc(started),
+            started.as_millis(),
+            self.frames,
+            self.get_duration().as_secs_f64(),
+        )
+    }
+
+    fn write_sidecar(&self, wav_path: &Path) -> Result<(), String> {
+        fs::write(wav_path.with_extension("json"), self.to_json())
+            .map_err(|err| format!("Failed to write metadata: {err}"))
+    }
+}
+
+/// Records the stream to WAV on demand and keeps the last `pre_roll` of audio, so what just
+/// happened can be saved after the fact.
+pub struct StreamRecorder {
+    audio_buffer: Arc<Mutex<AudioBuffer>>,
+    parameters: Arc<StreamParameters>,
+    directory: PathBuf,
+    pre_roll: Duration,
+    /// Interleaved samples of the last `pre_roll`.
+    history: VecDeque<Sample>,
+    recording: Option<Recording>,
+}
+
+impl StreamRecorder {
+    const BUFFER_DURATION: Duration = Duration::from_secs(1);
+    const DIRECTORY_VARIABLE: &'static str = "RT_AUDIO_EFFECT_RECORDINGS";
+
+    pub fn new(
+        stream_parameters: Arc<StreamParameters>,
+        directory: PathBuf,
+        pre_roll: Duration,
+    ) -> Self {
+        Self {
+            audio_buffer: Arc::new(Mutex::new(AudioBuffer::new(
+                stream_parameters.clone(),
+                Self::BUFFER_DURATION,
+            ))),
+            history: VecDeque::with_capacity(Self::history_len(&stream_parameters, pre_roll)),
+            parameters: stream_parameters,
+            directory,
+            pre_roll,
+            recording: None,
+        }
+    }
+
+    /// Directory from `RT_AUDIO_EFFECT_RECORDINGS` environment variable, `recordings` by default.
+    pub fn directory_from_env() -> PathBuf {
+        std::env::var(Self::DIRECTORY_VARIABLE)
+            .map(PathBuf::from)
+            .unwrap_or(PathBuf::from("recordings"))
+    }
+
+    pub fn get_pre_roll(&self) -> Duration {
+        self.pre_roll
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn get_recording_duration(&self) -> Option<Duration> {
+        self.recording
+            .as_ref()
+            .map(|recording| recording.metadata.get_duration())
+    }
+
+    pub fn start_recording(&mut self, device_name: &str) -> Result<PathBuf, String> {
+        if let Some(recording) = &self.recording {
+            return Err(format!("Already recording to {}", recording.path.display()));
+        }
+
+        let metadata = self.metadata(device_name, SystemTime::now(), 0);
+        let path = self.create_path("recording", metadata.started)?;
+        let writer = WavWriter::create(&path, self.wav_spec())
+            .map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+        // written again with the final length when stopped
+        metadata.write_sidecar(&path)?;
+
+        info!("Recording to {}", path.display());
+        self.recording = Some(Recording {
+            path: path.clone(),
+            writer,
+            metadata,
+        });
+        Ok(path)
+    }
+
+    pub fn stop_recording(&mut self) -> Result<Option<PathBuf>, String> {
+        let Some(recording) = self.recording.take() else {
+            return Ok(None);
+        };
+
+        recording
+            .writer
+            .finalize()
+            .map_err(|err| format!("Failed to finalize {}: {}", recording.path.display(), err))?;
+        recording.metadata.write_sidecar(&recording.path)?;
+
+        info!(
+            "Recorded {:.1} s to {}",
+            recording.metadata.get_duration().as_secs_f32(),
+            recording.path.display()
+        );
+        Ok(Some(recording.path))
+    }
+
+    /// Writes the last `pre_roll` of audio, or less if not captured yet.
+    pub fn save_replay(&self, device_name: &str) -> Result<PathBuf, String> {
+        let frames = self.history.len() / self.parameters.channels as usize;
+        let started = SystemTime::now()
+            - Duration::from_secs_f64(frames as f64 / self.parameters.sample_rate as f64);
+        let metadata = self.metadata(device_name, started, frames as u64);
+        let path = self.create_path("replay", SystemTime::now())?;
+
+        let mut writer = WavWriter::create(&path, self.wav_spec())
+            .map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+        for sample in &self.history {
+            writer
+                .write_sample(*sample)
+                .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+        }
+        writer
+            .finalize()
+            .map_err(|err| format!("Failed to finalize {}: {}", path.display(), err))?;
+        metadata.write_sidecar(&path)?;
+
+        info!(
+            "Saved last {:.1} s to {}",
+            metadata.get_duration().as_secs_f32(),
+            path.display()
+        );
+        Ok(path)
+    }
+
+    fn history_len(parameters: &StreamParameters, pre_roll: Duration) -> usize {
+        (pre_roll.as_secs_f64() * parameters.sample_rate as f64) as usize
+            * parameters.channels as usize
+    }
+
+    fn wav_spec(&self) -> WavSpec {
+        WavSpec {
+            channels: self.parameters.channels,
+            sample_rate: self.parameters.sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        }
+    }
+
+    fn metadata(&self, device_name: &str, started: SystemTime, frames: u64) -> RecordingMetadata {
+        RecordingMetadata {
+            device_name: device_name.to_string(),
+            parameters: (*self.parameters).clone(),
+            started,
+            frames,
+        }
+    }
+
+    fn create_path(&self, prefix: &str, time: SystemTime) -> Result<PathBuf, String> {
+        fs::create_dir_all(&self.directory)
+            .map_err(|err| format!("Failed to create {}: {}", self.directory.display(), err))?;
+
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        // ':' is not allowed in file names on Windows
+        let timestamp = format_utc(since_epoch).replace(':', "-");
+        let mut path = self.directory.join(format!("{prefix}_{timestamp}.wav"));
+        let mut index = 1;
+        while path.exists() {
+            path = self
+                .directory
+                .join(format!("{prefix}_{timestamp}_{index}.wav"));
+            index += 1;
+        }
+        Ok(path)
+    }
+
+    fn store(&mut self, samples: &[Sample]) {
+        let history_len = Self::history_len(&self.parameters, self.pre_roll);
+        self.history.extend(samples);
+        if self.history.len() > history_len {
+            let oversize = self.history.len() - history_len;
+            self.history.drain(..oversize);
+        }
+
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+        let result = samples
+            .iter()
+            .try_for_each(|sample| recording.writer.write_sample(*sample));
+        match result {
+            Ok(()) => {
+                recording.metadata.frames +=
+                    (samples.len() / self.parameters.channels as usize) as u64
+            }
+            Err(err) => {
+                error!("Recording to {} failed: {}", recording.path.display(), err);
+                let _ = self.stop_recording();
+            }
+        }
+    }
+}
+
+impl AudioStreamConsumer for StreamRecorder {
+    fn process_new_samples(&mut self) {
+        let new_samples = {
+            let mut audio_buffer = self.audio_buffer.lock().unwrap();
+            audio_buffer.fetch();
+            audio_buffer.get_new_samples_count()
+        };
+        if new_samples == 0 {
+            return;
+        }
+
+        let Ok((channels, _)) = self
+            .audio_buffer
+            .lock()
+            .unwrap()
+            .read_new_samples(new_samples, new_samples)
+        else {
+            return;
+        };
+
+        let channels: Vec<_> = channels.into_iter().collect();
+        let interleaved: Vec<Sample> = (0..new_samples)
+            .flat_map(|i| channels.iter().map(move |channel| channel[i]))
+            .collect();
+        self.store(&interleaved);
+    }
+
+    fn get_audio_buffer(&self) -> Arc<Mutex<AudioBuffer>> {
+        self.audio_buffer.clone()
+    }
+
+    fn get_name(&self) -> String {
+        String::from("Stream recorder")
+    }
+
+    fn on_stream_parameters_changed(&mut self, parameters: Arc<StreamParameters>) {
+        // a WAV file can't change its format
+        if let Err(err) = self.stop_recording() {
+            error!("{err}");
+        }
+        *self = Self::new(parameters, self.directory.clone(), self.pre_roll);
+    }
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats time since the UNIX epoch as ISO 8601 UTC, e.g. `2024-03-01T12:30:05Z`.
+fn format_utc(since_epoch: Duration) -> String {
+    let seconds = since_epoch.as_secs();
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use hound::WavReader;
+
+    use super::*;
+
+    fn parameters() -> Arc<StreamParameters> {
+        Arc::new(StreamParameters {
+            sample_rate: 1000,
+            channels: 2,
+        })
+    }
+
+    fn feed(recorder: &mut StreamRecorder, frames: std::ops::Range<usize>) {
+        let samples: Vec<Sample> = frames
+            .flat_map(|frame| [frame as Sample / 10000.0, -(frame as Sample) / 10000.0])
+            .collect();
+        recorder
+            .get_audio_buffer()
+            .lock()
+            .unwrap()
+            .store(samples.into());
+        recorder.process_new_samples();
+    }
+
+    fn test_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("stream_recorder_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn read_wav(path: &Path) -> Vec<Sample> {
+        WavReader::open(path)
+            .unwrap()
+            .samples::<f32>()
+            .map(|sample| sample.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_replay_keeps_last_pre_roll() {
+        let directory = test_directory("replay");
+        let mut recorder =
+            StreamRecorder::new(parameters(), directory.clone(), Duration::from_millis(500));
+
+        feed(&mut recorder, 0..400);
+        feed(&mut recorder, 400..800);
+        let path = recorder.save_replay("Speakers \"front\"").unwrap();
+
+        let samples = read_wav(&path);
+        assert_eq!(samples.len(), 1000);
+        assert_eq!(samples[0], 300.0 / 10000.0);
+        assert_eq!(samples[1], -300.0 / 10000.0);
+        assert_eq!(samples[998], 799.0 / 10000.0);
+
+        let metadata = fs::read_to_string(path.with_extension("json")).unwrap();
+        assert!(metadata.contains("\"device\": \"Speakers \\\"front\\\"\""));
+        assert!(metadata.contains("\"sample_rate\": 1000"));
+        assert!(metadata.contains("\"channels\": 2"));
+        assert!(metadata.contains("\"duration_s\": 0.500"));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_recording() {
+        let directory = test_directory("recording");
+        let mut recorder =
+            StreamRecorder::new(parameters(), directory.clone(), Duration::from_millis(100));
+
+        feed(&mut recorder, 0..100);
+        let path = recorder.start_recording("Microphone").unwrap();
+        assert!(recorder.start_recording("Microphone").is_err());
+        feed(&mut recorder, 100..350);
+        assert_eq!(
+            recorder.get_recording_duration(),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(recorder.stop_recording().unwrap(), Some(path.clone()));
+        assert_eq!(recorder.stop_recording().unwrap(), None);
+        feed(&mut recorder, 350..400);
+
+        let samples = read_wav(&path);
+        assert_eq!(samples.len(), 500);
+        assert_eq!(samples[0], 100.0 / 10000.0);
+        assert_eq!(samples[499], -349.0 / 10000.0);
+
+        let metadata = fs::read_to_string(path.with_extension("json")).unwrap();
+        assert!(metadata.contains("\"frames\": 250"));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(Duration::ZERO), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_utc(Duration::from_secs(951_827_696)),
+            "2000-02-29T12:34:56Z"
+        );
+        assert_eq!(
+            format_utc(Duration::from_secs(1_735_689_599)),
+            "2024-12-31T23:59:59Z"
+        );
+    }
+}
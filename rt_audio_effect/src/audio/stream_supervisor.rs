@@ -0,0 +1,662 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use cpal::HostId;
+use log::{info, warn};
+
+use super::{
+    audio_stream::AudioStream,
+    file_stream::{FileStream, PlaybackMode},
+    network_stream::{NetworkStream, NetworkStreamConfig},
+    signal_generator::{Signal, SignalGenerator},
+    AudioDirection, AudioInput, AudioManager, AudioSource, AudioStreamConsumer, Sample,
+    StreamParameters,
+};
+
+/// Device stream rebuilt by the supervisor.
+pub trait DeviceStream: AudioInput + Sized {
+    fn open(source: &AudioSource) -> Result<Self, &'static str>;
+    fn get_default_loopback() -> Result<AudioSource, &'static str>;
+    fn get_default_source(
+        host: HostId,
+        direction: AudioDirection,
+    ) -> Result<AudioSource, &'static str>;
+    fn get_source(&self) -> &AudioSource;
+    fn has_failed(&self) -> bool;
+}
+
+impl DeviceStream for AudioStream {
+    fn open(source: &AudioSource) -> Result<Self, &'static str> {
+        AudioStream::new(source)
+    }
+
+    fn get_default_loopback() -> Result<AudioSource, &'static str> {
+        AudioManager::get_default_loopback()
+    }
+
+    fn get_default_source(
+        host: HostId,
+        direction: AudioDirection,
+    ) -> Result<AudioSource, &'static str> {
+        AudioManager::get_default_source(host, direction)
+    }
+
+    fn get_source(&self) -> &AudioSource {
+        AudioStream::get_source(self)
+    }
+
+    fn has_failed(&self) -> bool {
+        AudioStream::has_failed(self)
+    }
+}
+
+/// Keeps the audio stream alive. Rebuilds it after stream errors (e.g. unplugged device) and,
+/// in follow-default mode, whenever the default device of the host changes.
+pub struct StreamSupervisor<S: DeviceStream = AudioStream> {
+    /// Present whenever no external source is set, opened lazily after starting with one.
+    audio_stream: Option<Arc<Mutex<S>>>,
+    consumers: Vec<Arc<Mutex<dyn AudioStreamConsumer>>>,
+    /// Source used instead of the device, e.g. network or pipe. The device keeps running.
+    external_source: Option<Box<dyn AudioInput>>,
+    /// Generator fed to consumers instead of the source, with parameters of the source.
+    test_signal: Option<(Signal, FileStream)>,
+    follow_default: bool,
+    retry_delay: Duration,
+    next_retry: Option<Instant>,
+}
+
+impl StreamSupervisor {
+    pub const POLL_PERIOD: Duration = Duration::from_secs(1);
+}
+
+impl<S: DeviceStream> StreamSupervisor<S> {
+    const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+    const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+    const TEST_SIGNAL_AMPLITUDE: Sample = 0.5;
+
+    pub fn new(audio_stream: Arc<Mutex<S>>, follow_default: bool) -> Self {
+        Self {
+            audio_stream: Some(audio_stream),
+            consumers: Vec::new(),
+            external_source: None,
+            test_signal: None,
+            follow_default,
+            retry_delay: Self::INITIAL_RETRY_DELAY,
+            next_retry: None,
+        }
+    }
+
+    /// Feeds consumers from the source and starts it, the device isn't opened until the source
+    /// is removed or a device is selected.
+    pub fn with_external_source(source: Box<dyn AudioInput>, follow_default: bool) -> Self {
+        info!("External source enabled: {}", source.get_parameters());
+        source.start();
+        Self {
+            audio_stream: None,
+            consumers: Vec::new(),
+            external_source: Some(source),
+            test_signal: None,
+            follow_default,
+            retry_delay: Self::INITIAL_RETRY_DELAY,
+            next_retry: None,
+        }
+    }
+
+    pub fn get_audio_stream(&self) -> Option<Arc<Mutex<S>>> {
+        self.audio_stream.clone()
+    }
+
+    /// Consumers are carried over to every rebuilt stream.
+    pub fn add_stream_consumer(&mut self, stream_consumer: Arc<Mutex<dyn AudioStreamConsumer>>) {
+        match &mut self.test_signal {
+            Some((_, test_stream)) => test_stream.add_stream_consumer(stream_consumer.clone()),
+            None => self.with_source(|source| source.add_stream_consumer(stream_consumer.clone())),
+        }
+        self.consumers.push(stream_consumer);
+    }
+
+    /// Parameters of the external source or the device, whichever feeds consumers.
+    pub fn get_source_parameters(&self) -> Arc<StreamParameters> {
+        match (&self.external_source, &self.audio_stream) {
+            (Some(external_source), _) => external_source.get_parameters(),
+            (None, Some(audio_stream)) => audio_stream.lock().unwrap().get_parameters(),
+            (None, None) => {
+                unreachable!("the device is opened before the external source is removed")
+            }
+        }
+    }
+
+    /// Runs `f` on the external source or the device, whichever feeds consumers.
+    fn with_source<R>(&mut self, f: impl FnOnce(&mut dyn AudioInput) -> R) -> R {
+        match (&mut self.external_source, &self.audio_stream) {
+            (Some(external_source), _) => f(external_source.as_mut()),
+            (None, Some(audio_stream)) => f(&mut *audio_stream.lock().unwrap()),
+            (None, None) => {
+                unreachable!("the device is opened before the external source is removed")
+            }
+        }
+    }
+
+    /// Receivers left in the previous source are replaced, as consumers read one stream only.
+    fn connect_consumers_to_source(&mut self) {
+        let consumers = self.consumers.clone();
+        self.with_source(|source| {
+            for consumer in consumers {
+                source.add_stream_consumer(consumer);
+            }
+        });
+    }
+
+    /// The default loopback device is opened and started when consumers switch to the device
+    /// for the first time.
+    fn open_device(&mut self) -> Result<(), String> {
+        if self.audio_stream.is_some() {
+            return Ok(());
+        }
+        let device = S::get_default_loopback()
+            .and_then(|source| S::open(&source))
+            .map_err(|err| format!("Failed to open the device: {err}"))?;
+        device.start();
+        self.audio_stream = Some(Arc::new(Mutex::new(device)));
+        Ok(())
+    }
+
+    /// Receives audio over the network instead of the device, `None` switches back.
+    /// On error the current source is kept.
+    pub fn set_network_source(
+        &mut self,
+        config: Option<NetworkStreamConfig>,
+    ) -> Result<(), String> {
+        let network_stream = config.map(NetworkStream::new).transpose()?;
+        self.set_external_source(
+            network_stream.map(|network_stream| Box::new(network_stream) as Box<dyn AudioInput>),
+        )
+    }
+
+    /// Feeds consumers from the source instead of the device and starts it, `None` switches
+    /// back to the device. On error the current source is kept.
+    pub fn set_external_source(
+        &mut self,
+        source: Option<Box<dyn AudioInput>>,
+    ) -> Result<(), String> {
+        // dropping a stream stops its thread
+        self.replace_external_source(source).map(|_| ())
+    }
+
+    /// Like `set_external_source`, but the previous source is stopped and returned, so it can
+    /// be restored later.
+    pub fn replace_external_source(
+        &mut self,
+        source: Option<Box<dyn AudioInput>>,
+    ) -> Result<Option<Box<dyn AudioInput>>, String> {
+        if source.is_none() {
+            self.open_device()?;
+        }
+        let previous_parameters = self.get_source_parameters();
+        let test_signal = self.test_signal.take().map(|(signal, _)| signal);
+
+        let previous = std::mem::replace(&mut self.external_source, source);
+        if let Some(previous) = &previous {
+            previous.stop();
+        }
+        match &self.external_source {
+            Some(external_source) => {
+                info!(
+                    "External source enabled: {}",
+                    external_source.get_parameters()
+                );
+                external_source.start();
+            }
+            None => info!("External source disabled"),
+        }
+
+        let parameters = self.get_source_parameters();
+        if *parameters != *previous_parameters {
+            for consumer in &self.consumers {
+                consumer
+                    .lock()
+                    .unwrap()
+                    .on_stream_parameters_changed(parameters.clone());
+            }
+        }
+
+        match test_signal {
+            Some(_) => self.set_test_signal(test_signal),
+            None => self.connect_consumers_to_source(),
+        }
+        Ok(previous)
+    }
+
+    /// Feeds consumers with the generated signal instead of the source, `None` switches back.
+    /// The source keeps running, so the switch back is seamless.
+    pub fn set_test_signal(&mut self, signal: Option<Signal>) {
+        // dropping the stream joins its thread
+        self.test_signal = None;
+
+        let Some(signal) = signal else {
+            info!("Test signal disabled");
+            self.connect_consumers_to_source();
+            return;
+        };
+
+        info!("Test signal enabled: {signal}");
+        let generator = SignalGenerator::new(
+            signal.clone(),
+            Self::TEST_SIGNAL_AMPLITUDE,
+            (*self.get_source_parameters()).clone(),
+        );
+        let mut test_stream = FileStream::from_decoder(Box::new(generator), PlaybackMode::RealTime);
+        for consumer in &self.consumers {
+            test_stream.add_stream_consumer(consumer.clone());
+        }
+        test_stream.start();
+        self.test_signal = Some((signal, test_stream));
+    }
+
+    pub fn get_test_signal(&self) -> Option<&Signal> {
+        self.test_signal.as_ref().map(|(signal, _)| signal)
+    }
+
+    pub fn set_follow_default(&mut self, follow_default: bool) {
+        info!("Follow default device: {follow_default}");
+        self.follow_default = follow_default;
+        self.next_retry = None;
+    }
+
+    pub fn is_follow_default(&self) -> bool {
+        self.follow_default
+    }
+
+    /// Replaces the stream, consumers are notified only when stream parameters differ.
+    /// While an external source is active, consumers stay connected to it.
+    pub fn switch_source(&mut self, source: &AudioSource) -> Result<(), &'static str> {
+        info!("Switching audio source to: {source}");
+
+        let mut new_stream = S::open(source)?;
+        let Some(audio_stream) = &self.audio_stream else {
+            // consumers stay connected to the external source
+            self.audio_stream = Some(Arc::new(Mutex::new(new_stream)));
+            return Ok(());
+        };
+
+        // restarted with parameters of the new stream
+        let test_signal = self.test_signal.take().map(|(signal, _)| signal);
+        let device_feeds_consumers = self.external_source.is_none();
+
+        let mut audio_stream = audio_stream.lock().unwrap();
+        let was_running = audio_stream.is_running();
+        audio_stream.stop();
+
+        let new_parameters = new_stream.get_parameters();
+        let parameters_changed = *new_parameters != *audio_stream.get_parameters();
+
+        for consumer in &self.consumers {
+            if parameters_changed && device_feeds_consumers {
+                consumer
+                    .lock()
+                    .unwrap()
+                    .on_stream_parameters_changed(new_parameters.clone());
+            }
+            if test_signal.is_none() && device_feeds_consumers {
+                new_stream.add_stream_consumer(consumer.clone());
+            }
+        }
+
+        if was_running {
+            new_stream.start();
+        }
+        *audio_stream = new_stream;
+        drop(audio_stream);
+
+        if test_signal.is_some() {
+            self.set_test_signal(test_signal);
+        }
+
+        Ok(())
+    }
+
+    /// Checks stream health and default device, should be called periodically.
+    pub fn poll(&mut self) {
+        if self
+            .next_retry
+            .is_some_and(|next_retry| Instant::now() < next_retry)
+        {
+            return;
+        }
+
+        // nothing to supervise until the device is needed
+        let Some(audio_stream) = &self.audio_stream else {
+            return;
+        };
+        let (current, failed) = {
+            let audio_stream = audio_stream.lock().unwrap();
+            (audio_stream.get_source().clone(), audio_stream.has_failed())
+        };
+
+        let default = S::get_default_source(current.host, current.direction);
+        let target = match (&default, self.follow_default) {
+            (Ok(default), true) => default.clone(),
+            _ => current.clone(),
+        };
+
+        if target == current && !failed {
+            return;
+        }
+
+        let result = if target != current {
+            info!("Default device changed from {current} to {target}");
+            self.switch_source(&target)
+        } else {
+            warn!("Stream of {current} failed, rebuilding");
+            self.switch_source(&current).or_else(|err| match default {
+                // selected device is gone, fall back to the default one until user picks another
+                Ok(default) if default != current => {
+                    warn!("Failed to reopen {current}: {err}, falling back to {default}");
+                    self.switch_source(&default)
+                }
+                _ => Err(err),
+            })
+        };
+
+        match result {
+            Ok(()) => {
+                self.retry_delay = Self::INITIAL_RETRY_DELAY;
+                self.next_retry = None;
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to rebuild stream: {err}, retrying in {} s",
+                    self.retry_delay.as_secs()
+                );
+                self.next_retry = Some(Instant::now() + self.retry_delay);
+                self.retry_delay = (self.retry_delay * 2).min(Self::MAX_RETRY_DELAY);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+    use crate::audio::{
+        audio_stream::AudioStreamSender,
+        pipe_stream::{PipeStream, PipeStreamConfig},
+        AudioBuffer,
+    };
+
+    thread_local! {
+        /// Devices which can be opened, with their sample rates.
+        static DEVICES: RefCell<Vec<(&'static str, u32)>> = const { RefCell::new(Vec::new()) };
+        static DEFAULT_DEVICE: Cell<&'static str> = const { Cell::new("") };
+        static OPEN_ATTEMPTS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn source(device_name: &str) -> AudioSource {
+        AudioSource {
+            host: cpal::ALL_HOSTS[0],
+            device_name: device_name.to_string(),
+            direction: AudioDirection::Input,
+        }
+    }
+
+    struct FakeDevice {
+        source: AudioSource,
+        parameters: Arc<StreamParameters>,
+        sender: AudioStreamSender,
+        running: Cell<bool>,
+        failed: bool,
+    }
+
+    impl DeviceStream for FakeDevice {
+        fn open(source: &AudioSource) -> Result<Self, &'static str> {
+            OPEN_ATTEMPTS.set(OPEN_ATTEMPTS.get() + 1);
+            let sample_rate = DEVICES.with_borrow(|devices| {
+                devices
+                    .iter()
+                    .find(|(name, _)| *name == source.device_name)
+                    .map(|(_, sample_rate)| *sample_rate)
+            });
+            Ok(FakeDevice {
+                source: source.clone(),
+                parameters: Arc::new(StreamParameters {
+                    sample_rate: sample_rate.ok_or("Device not found")?,
+                    channels: 2,
+                }),
+                sender: AudioStreamSender::new(),
+                running: Cell::new(false),
+                failed: false,
+            })
+        }
+
+        fn get_default_loopback() -> Result<AudioSource, &'static str> {
+            Ok(source(DEFAULT_DEVICE.get()))
+        }
+
+        fn get_default_source(
+            _host: HostId,
+            _direction: AudioDirection,
+        ) -> Result<AudioSource, &'static str> {
+            Ok(source(DEFAULT_DEVICE.get()))
+        }
+
+        fn get_source(&self) -> &AudioSource {
+            &self.source
+        }
+
+        fn has_failed(&self) -> bool {
+            self.failed
+        }
+    }
+
+    impl AudioInput for FakeDevice {
+        fn start(&self) {
+            self.running.set(true);
+        }
+
+        fn stop(&self) {
+            self.running.set(false);
+        }
+
+        fn is_running(&self) -> bool {
+            self.running.get()
+        }
+
+        fn get_parameters(&self) -> Arc<StreamParameters> {
+            self.parameters.clone()
+        }
+
+        fn add_stream_consumer(&mut self, stream_consumer: Arc<Mutex<dyn AudioStreamConsumer>>) {
+            self.sender.add_stream_receiver(stream_consumer);
+        }
+    }
+
+    struct TestConsumer {
+        buffer: Arc<Mutex<AudioBuffer>>,
+        parameter_changes: usize,
+    }
+
+    impl AudioStreamConsumer for TestConsumer {
+        fn process_new_samples(&mut self) {}
+
+        fn get_audio_buffer(&self) -> Arc<Mutex<AudioBuffer>> {
+            self.buffer.clone()
+        }
+
+        fn get_name(&self) -> String {
+            String::from("Test consumer")
+        }
+
+        fn on_stream_parameters_changed(&mut self, parameters: Arc<StreamParameters>) {
+            self.parameter_changes += 1;
+            self.buffer = Arc::new(Mutex::new(AudioBuffer::new(
+                parameters,
+                Duration::from_secs(1),
+            )));
+        }
+    }
+
+    type Supervised = (
+        StreamSupervisor<FakeDevice>,
+        Arc<Mutex<FakeDevice>>,
+        Vec<Arc<Mutex<TestConsumer>>>,
+    );
+
+    fn supervise(device_name: &str, follow_default: bool) -> Supervised {
+        let device = Arc::new(Mutex::new(FakeDevice::open(&source(device_name)).unwrap()));
+        device.lock().unwrap().start();
+        let mut supervisor = StreamSupervisor::new(device.clone(), follow_default);
+        let consumers: Vec<Arc<Mutex<TestConsumer>>> = (0..2)
+            .map(|_| {
+                let parameters = device.lock().unwrap().get_parameters();
+                Arc::new(Mutex::new(TestConsumer {
+                    buffer: Arc::new(Mutex::new(AudioBuffer::new(
+                        parameters,
+                        Duration::from_secs(1),
+                    ))),
+                    parameter_changes: 0,
+                }))
+            })
+            .collect();
+        for consumer in &consumers {
+            supervisor.add_stream_consumer(consumer.clone());
+        }
+        (supervisor, device, consumers)
+    }
+
+    #[test]
+    fn test_test_signal_toggle_keeps_receivers() {
+        DEVICES.set(vec![("Microphone", 48000)]);
+        let (mut supervisor, device, _consumers) = supervise("Microphone", false);
+        assert_eq!(device.lock().unwrap().sender.get_receiver_count(), 2);
+
+        for _ in 0..2 {
+            supervisor.set_test_signal(Some(Signal::Sine { frequency: 1000.0 }));
+            assert!(supervisor.get_test_signal().is_some());
+            // consumers read the generator, the device doesn't wait for them
+            assert!(device.lock().unwrap().sender.has_room(usize::MAX));
+
+            supervisor.set_test_signal(None);
+            let device = device.lock().unwrap();
+            assert_eq!(device.sender.get_receiver_count(), 2);
+            assert!(!device.sender.has_room(usize::MAX));
+        }
+    }
+
+    #[test]
+    fn test_follows_default_device() {
+        DEVICES.set(vec![("Speakers", 48000), ("Headphones", 44100)]);
+        DEFAULT_DEVICE.set("Speakers");
+        let (mut supervisor, device, consumers) = supervise("Speakers", true);
+
+        OPEN_ATTEMPTS.set(0);
+        supervisor.poll();
+        assert_eq!(OPEN_ATTEMPTS.get(), 0);
+
+        // consumers move to the new default and learn its sample rate
+        DEFAULT_DEVICE.set("Headphones");
+        supervisor.poll();
+        {
+            let device = device.lock().unwrap();
+            assert_eq!(device.get_source(), &source("Headphones"));
+            assert!(device.is_running());
+            assert_eq!(device.get_parameters().sample_rate, 44100);
+            assert_eq!(device.sender.get_receiver_count(), 2);
+        }
+        assert!(consumers
+            .iter()
+            .all(|consumer| consumer.lock().unwrap().parameter_changes == 1));
+
+        // a device picked by the user is kept
+        supervisor.set_follow_default(false);
+        DEFAULT_DEVICE.set("Speakers");
+        supervisor.poll();
+        assert_eq!(device.lock().unwrap().get_source(), &source("Headphones"));
+    }
+
+    #[test]
+    fn test_recovers_failed_stream() {
+        DEVICES.set(vec![("Speakers", 48000), ("Headphones", 48000)]);
+        DEFAULT_DEVICE.set("Speakers");
+        let (mut supervisor, device, consumers) = supervise("Headphones", false);
+
+        // rebuilt on the same device, parameters are the same
+        device.lock().unwrap().failed = true;
+        supervisor.poll();
+        assert!(!device.lock().unwrap().has_failed());
+        assert_eq!(device.lock().unwrap().get_source(), &source("Headphones"));
+        assert_eq!(consumers[0].lock().unwrap().parameter_changes, 0);
+
+        // unplugged, falls back to the default device
+        DEVICES.with_borrow_mut(|devices| devices.retain(|(name, _)| *name != "Headphones"));
+        device.lock().unwrap().failed = true;
+        supervisor.poll();
+        assert_eq!(device.lock().unwrap().get_source(), &source("Speakers"));
+
+        // nothing to open, the next attempt waits for the retry delay
+        DEVICES.set(Vec::new());
+        device.lock().unwrap().failed = true;
+        OPEN_ATTEMPTS.set(0);
+        supervisor.poll();
+        assert_eq!(OPEN_ATTEMPTS.get(), 1);
+        supervisor.poll();
+        assert_eq!(OPEN_ATTEMPTS.get(), 1);
+        assert!(device.lock().unwrap().has_failed());
+    }
+
+    #[test]
+    fn test_pipe_without_device() {
+        let path = std::env::temp_dir().join("stream_supervisor_pipe.raw");
+        std::fs::write(&path, vec![0u8; 44100 * 2]).unwrap();
+        let config = PipeStreamConfig::from_args(
+            format!(
+                "--pipe {} --format s16le --rate 44100 --channels 1",
+                path.display()
+            )
+            .split_whitespace()
+            .map(String::from),
+        )
+        .unwrap()
+        .unwrap();
+
+        // built the way the app does it with `--pipe`
+        DEVICES.set(vec![("Speakers", 48000)]);
+        DEFAULT_DEVICE.set("Speakers");
+        OPEN_ATTEMPTS.set(0);
+        let mut supervisor: StreamSupervisor<FakeDevice> =
+            StreamSupervisor::with_external_source(Box::new(PipeStream::new(config)), true);
+        let parameters = supervisor.get_source_parameters();
+        assert_eq!(parameters.sample_rate, 44100);
+        let consumer = Arc::new(Mutex::new(TestConsumer {
+            buffer: Arc::new(Mutex::new(AudioBuffer::new(
+                parameters,
+                Duration::from_secs(1),
+            ))),
+            parameter_changes: 0,
+        }));
+        supervisor.add_stream_consumer(consumer.clone());
+        supervisor.poll();
+        assert!(supervisor.get_audio_stream().is_none());
+        assert_eq!(OPEN_ATTEMPTS.get(), 0);
+
+        let audio_buffer = consumer.lock().unwrap().get_audio_buffer();
+        let start = Instant::now();
+        while audio_buffer.lock().unwrap().get_new_samples_count() == 0 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            audio_buffer.lock().unwrap().fetch();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        // the device is needed once the pipe is removed
+        supervisor.set_external_source(None).unwrap();
+        let device = supervisor.get_audio_stream().unwrap();
+        assert_eq!(device.lock().unwrap().get_source(), &source("Speakers"));
+        assert!(device.lock().unwrap().is_running());
+        assert_eq!(device.lock().unwrap().sender.get_receiver_count(), 1);
+        assert_eq!(consumer.lock().unwrap().parameter_changes, 1);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    fmt::Display,
+    ops::{Add, Sub},
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+/// Point in time relative to the start of the application. Unlike `Instant` it is plain data
+/// with a default, so it can be passed through ring buffers and sent to devices.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(Duration);
+
+impl Timestamp {
+    fn epoch() -> Instant {
+        static EPOCH: OnceLock<Instant> = OnceLock::new();
+        *EPOCH.get_or_init(Instant::now)
+    }
+
+    pub fn now() -> Self {
+        Self::from_instant(Instant::now())
+    }
+
+    /// Instants before the start of the application are clamped to it.
+    pub fn from_instant(instant: Instant) -> Self {
+        Self(instant.saturating_duration_since(Self::epoch()))
+    }
+
+    pub fn from_duration(duration: Duration) -> Self {
+        Self(duration)
+    }
+
+    pub fn as_duration(&self) -> Duration {
+        self.0
+    }
+
+    /// Zero when `earlier` is later than `self`.
+    pub fn duration_since(&self, earlier: Timestamp) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    /// Moves the timestamp by signed number of seconds.
+    pub fn offset(&self, seconds: f64) -> Self {
+        if seconds >= 0.0 {
+            *self + Duration::from_secs_f64(seconds)
+        } else {
+            *self - Duration::from_secs_f64(-seconds)
+        }
+    }
+}
+
+impl Add<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn add(self, duration: Duration) -> Self::Output {
+        Timestamp(self.0 + duration)
+    }
+}
+
+impl Sub<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn sub(self, duration: Duration) -> Self::Output {
+        Timestamp(self.0.saturating_sub(duration))
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.6} s", self.0.as_secs_f64())
+    }
+}
+
+/// Capture time of the first frame of a block sent by a stream. Frames are counted from
+/// the start of the connection.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BlockTimestamp {
+    pub frame: u64,
+    pub timestamp: Timestamp,
+}
+
+impl BlockTimestamp {
+    /// Extrapolates the timestamp of another frame of the same stream.
+    pub fn get_frame_timestamp(&self, frame: u64, sample_rate: u32) -> Timestamp {
+        let frames = frame as i64 - self.frame as i64;
+        self.timestamp.offset(frames as f64 / sample_rate as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_timestamp() {
+        let block = BlockTimestamp {
+            frame: 48000,
+            timestamp: Timestamp::from_duration(Duration::from_secs(10)),
+        };
+        assert_eq!(
+            block.get_frame_timestamp(72000, 48000),
+            Timestamp::from_duration(Duration::from_millis(10500))
+        );
+        assert_eq!(
+            block.get_frame_timestamp(0, 48000),
+            Timestamp::from_duration(Duration::from_secs(9))
+        );
+        assert_eq!(
+            (block.timestamp - Duration::from_secs(20)).as_duration(),
+            Duration::ZERO
+        );
+    }
+}
@@ -1,5 +1,11 @@
 use std::time::Duration;
 
+use super::{
+    ConstantQParameters, MelParameters, OctaveBandParameters, SpectrumScaling, WindowFunction,
+    ZeroPadding,
+};
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct AnalyzerParameters {
     pub spectrum_width: usize,
     pub refresh_time_in_samples: usize,
@@ -7,4 +13,212 @@ pub struct AnalyzerParameters {
     pub refresh_time: Duration,
     pub spectrogram_duration: Duration,
     pub sample_rate: u32,
+    pub window: WindowFunction,
+    pub scaling: SpectrumScaling,
+    pub zero_padding: ZeroPadding,
+    pub constant_q: ConstantQParameters,
+    pub octave_bands: OctaveBandParameters,
+    pub mel: MelParameters,
+}
+
+impl AnalyzerParameters {
+    pub const MIN_SPECTRUM_WIDTH: usize = 64;
+    pub const MAX_SPECTROGRAM_DURATION: Duration = Duration::from_secs(60);
+    pub const MAX_FFT_SIZE: usize = 1 << 20;
+
+    /// Derives sample counts and the number of spectrums in history from durations.
+    pub fn new(
+        spectrum_width: usize,
+        refresh_time: Duration,
+        spectrogram_duration: Duration,
+        sample_rate: u32,
+    ) -> Self {
+        AnalyzerParameters {
+            spectrum_width,
+            refresh_time_in_samples: 0,
+            length_of_history: 0,
+            refresh_time,
+            spectrogram_duration,
+            sample_rate,
+            window: WindowFunction::default(),
+            scaling: SpectrumScaling::default(),
+            zero_padding: ZeroPadding::default(),
+            constant_q: ConstantQParameters::default(),
+            octave_bands: OctaveBandParameters::default(),
+            mel: MelParameters::default(),
+        }
+        .with_sample_rate(sample_rate)
+    }
+
+    /// Same parameters, with sample counts and the number of spectrums in history derived
+    /// again from durations.
+    pub fn with_sample_rate(&self, sample_rate: u32) -> Self {
+        let refresh_time_in_samples =
+            (sample_rate as f64 * self.refresh_time.as_secs_f64()).round() as usize;
+        let length_of_history = match self.refresh_time.is_zero() {
+            true => 0,
+            false => {
+                (self.spectrogram_duration.as_secs_f64() / self.refresh_time.as_secs_f64()) as usize
+            }
+        };
+        AnalyzerParameters {
+            refresh_time_in_samples,
+            length_of_history,
+            sample_rate,
+            ..self.clone()
+        }
+    }
+
+    /// Same parameters, with the refresh time set so consecutive spectrums share `overlap`
+    /// (0 - 1) of their samples.
+    pub fn with_overlap(&self, overlap: f32) -> Self {
+        let refresh_time_in_samples =
+            ((self.spectrum_width as f32 * (1.0 - overlap)).round() as usize).max(1);
+        AnalyzerParameters {
+            refresh_time: Duration::from_secs_f64(
+                refresh_time_in_samples as f64 / self.sample_rate as f64,
+            ),
+            ..self.clone()
+        }
+        .with_sample_rate(self.sample_rate)
+    }
+
+    /// Number of samples of the FFT, the spectrum width with zero padding.
+    pub fn get_fft_size(&self) -> usize {
+        self.zero_padding.get_fft_size(self.spectrum_width)
+    }
+
+    /// Frequency step between spectrum bins, in Hz.
+    pub fn get_bin_width(&self) -> f32 {
+        self.sample_rate as f32 / self.get_fft_size() as f32
+    }
+
+    /// Center frequencies of the spectrum bins, in Hz.
+    pub fn get_bin_frequencies(&self) -> Vec<f32> {
+        let bin_width = self.get_bin_width();
+        (0..self.get_fft_size() / 2)
+            .map(|bin| bin as f32 * bin_width)
+            .collect()
+    }
+
+    /// Fraction of samples shared by consecutive spectrums, 0 when samples are skipped.
+    pub fn get_overlap(&self) -> f32 {
+        1.0 - (self.refresh_time_in_samples as f32 / self.spectrum_width as f32).min(1.0)
+    }
+
+    pub fn get_spectrogram_duration_in_samples(&self) -> usize {
+        (self.sample_rate as f64 * self.spectrogram_duration.as_secs_f64()) as usize
+    }
+
+    /// Every spectrum has to fit into the history and every refresh has to bring new samples.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.spectrum_width < Self::MIN_SPECTRUM_WIDTH {
+            return Err(format!(
+                "Spectrum width has to be at least {}",
+                Self::MIN_SPECTRUM_WIDTH
+            ));
+        }
+        if self.get_fft_size() > Self::MAX_FFT_SIZE {
+            return Err(format!("FFT size can be at most {}", Self::MAX_FFT_SIZE));
+        }
+        if self.spectrogram_duration > Self::MAX_SPECTROGRAM_DURATION {
+            return Err(format!(
+                "History can be at most {} s",
+                Self::MAX_SPECTROGRAM_DURATION.as_secs()
+            ));
+        }
+        if self.spectrum_width > self.get_spectrogram_duration_in_samples() {
+            return Err(format!(
+                "History of {} samples is shorter than the spectrum width",
+                self.get_spectrogram_duration_in_samples()
+            ));
+        }
+        if self.refresh_time_in_samples == 0 || self.refresh_time_in_samples > self.spectrum_width {
+            return Err(String::from(
+                "Refresh has to be between 1 sample and the spectrum width",
+            ));
+        }
+        self.constant_q.validate()?;
+        self.mel.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlap() {
+        let parameters = AnalyzerParameters::new(
+            4800,
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            48000,
+        );
+        assert_eq!(parameters.refresh_time_in_samples, 960);
+        assert_eq!(parameters.length_of_history, 50);
+        assert!((parameters.get_overlap() - 0.8).abs() < 1e-6);
+
+        let parameters = AnalyzerParameters {
+            window: WindowFunction::Hann,
+            ..parameters
+        }
+        .with_overlap(0.5);
+        assert_eq!(parameters.refresh_time_in_samples, 2400);
+        assert_eq!(parameters.refresh_time, Duration::from_millis(50));
+        assert_eq!(parameters.length_of_history, 20);
+        assert_eq!(parameters.window, WindowFunction::Hann);
+        assert!(parameters.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate() {
+        let history = Duration::from_secs(1);
+        let refresh = Duration::from_millis(20);
+        assert!(
+            AnalyzerParameters::new(32, Duration::from_micros(100), history, 48000)
+                .validate()
+                .is_err()
+        );
+        assert!(AnalyzerParameters::new(96000, refresh, history, 48000)
+            .validate()
+            .is_err());
+        assert!(
+            AnalyzerParameters::new(4800, refresh, Duration::from_secs(61), 48000)
+                .validate()
+                .is_err()
+        );
+        assert!(
+            AnalyzerParameters::new(4800, Duration::from_millis(200), history, 48000)
+                .validate()
+                .is_err()
+        );
+        assert!(
+            AnalyzerParameters::new(48000, Duration::from_millis(200), history, 48000)
+                .validate()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_fft_size() {
+        let parameters = AnalyzerParameters {
+            zero_padding: ZeroPadding::NextPowerOfTwo,
+            ..AnalyzerParameters::new(
+                4800,
+                Duration::from_millis(20),
+                Duration::from_secs(1),
+                48000,
+            )
+        };
+        assert_eq!(parameters.get_fft_size(), 8192);
+        assert!((parameters.get_bin_width() - 5.859375).abs() < 1e-6);
+        assert!(parameters.validate().is_ok());
+        assert!(AnalyzerParameters {
+            zero_padding: ZeroPadding::Size(1 << 21),
+            ..parameters
+        }
+        .validate()
+        .is_err());
+    }
 }
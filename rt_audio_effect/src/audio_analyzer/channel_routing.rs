@@ -0,0 +1,235 @@
+use std::fmt::Display;
+
+use crate::audio::{ChannelSamples, Sample};
+
+use super::MultiChannel;
+
+/// Which channels are analyzed, derived from the input channels of the stream.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ChannelRouting {
+    /// Every input channel is analyzed on its own.
+    #[default]
+    AllChannels,
+    /// Average of all input channels.
+    MonoDownmix,
+    /// Mid (L+R)/2 and side (L-R)/2 of the first two input channels.
+    MidSide,
+    /// Every analyzed channel is a weighted sum of input channels, one row of weights per
+    /// analyzed channel. Missing weights are 0.
+    Matrix(Vec<Vec<Sample>>),
+}
+
+impl ChannelRouting {
+    /// Analyzes only the given input channels, in the given order.
+    pub fn select(channels: &[usize]) -> Self {
+        ChannelRouting::Matrix(
+            channels
+                .iter()
+                .map(|channel| {
+                    let mut weights = vec![0.0; channel + 1];
+                    weights[*channel] = 1.0;
+                    weights
+                })
+                .collect(),
+        )
+    }
+
+    /// Weights of input channels for every analyzed channel.
+    pub fn get_weights(&self, input_channels: usize) -> Vec<Vec<Sample>> {
+        let unit = |channel: usize| {
+            let mut weights = vec![0.0; input_channels];
+            if channel < input_channels {
+                weights[channel] = 1.0;
+            }
+            weights
+        };
+
+        match self {
+            ChannelRouting::AllChannels => (0..input_channels).map(unit).collect(),
+            ChannelRouting::MonoDownmix => {
+                vec![vec![1.0 / input_channels.max(1) as Sample; input_channels]]
+            }
+            ChannelRouting::MidSide if input_channels < 2 => {
+                vec![unit(0), vec![0.0; input_channels]]
+            }
+            ChannelRouting::MidSide => {
+                let mut mid = vec![0.0; input_channels];
+                let mut side = vec![0.0; input_channels];
+                (mid[0], mid[1]) = (0.5, 0.5);
+                (side[0], side[1]) = (0.5, -0.5);
+                vec![mid, side]
+            }
+            ChannelRouting::Matrix(matrix) => matrix
+                .iter()
+                .map(|row| {
+                    let mut weights = row.clone();
+                    weights.resize(input_channels, 0.0);
+                    weights
+                })
+                .collect(),
+        }
+    }
+
+    pub fn get_channel_names(&self, input_channels: usize) -> Vec<String> {
+        match self {
+            ChannelRouting::AllChannels if input_channels == 2 => {
+                vec![String::from("Left"), String::from("Right")]
+            }
+            ChannelRouting::AllChannels => (1..=input_channels)
+                .map(|channel| format!("Channel {channel}"))
+                .collect(),
+            ChannelRouting::MonoDownmix => vec![String::from("Mono")],
+            ChannelRouting::MidSide => vec![String::from("Mid"), String::from("Side")],
+            ChannelRouting::Matrix(matrix) => matrix
+                .iter()
+                .enumerate()
+                .map(|(index, row)| {
+                    let selected: Vec<usize> = (0..row.len())
+                        .filter(|channel| row[*channel] != 0.0)
+                        .collect();
+                    match selected.as_slice() {
+                        [channel] if row[*channel] == 1.0 => format!("Channel {}", channel + 1),
+                        _ => format!("Mix {}", index + 1),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Display for ChannelRouting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelRouting::AllChannels => write!(f, "All channels"),
+            ChannelRouting::MonoDownmix => write!(f, "Mono downmix"),
+            ChannelRouting::MidSide => write!(f, "Mid/Side"),
+            ChannelRouting::Matrix(_) => {
+                write!(f, "{}", self.get_channel_names(0).join(", "))
+            }
+        }
+    }
+}
+
+/// Applies `ChannelRouting` to samples read from `AudioBuffer`, before the analysis.
+pub struct ChannelRouter {
+    routing: ChannelRouting,
+    input_channels: usize,
+    weights: Vec<Vec<Sample>>,
+}
+
+impl ChannelRouter {
+    pub fn new(routing: ChannelRouting, input_channels: usize) -> Self {
+        Self {
+            weights: routing.get_weights(input_channels),
+            routing,
+            input_channels,
+        }
+    }
+
+    pub fn get_routing(&self) -> &ChannelRouting {
+        &self.routing
+    }
+
+    pub fn get_output_channels(&self) -> usize {
+        self.weights.len()
+    }
+
+    pub fn get_channel_names(&self) -> Vec<String> {
+        self.routing.get_channel_names(self.input_channels)
+    }
+
+    pub fn route(&self, input: MultiChannel<ChannelSamples>) -> MultiChannel<ChannelSamples> {
+        if self.routing == ChannelRouting::AllChannels {
+            return input;
+        }
+
+        let length = input.channels.first().map_or(0, |c| c.inner().len());
+        self.weights
+            .iter()
+            .map(|weights| {
+                let mut output = vec![0.0; length];
+                for (channel, weight) in weights.iter().enumerate() {
+                    if *weight == 0.0 {
+                        continue;
+                    }
+                    let samples = input.get_channel(channel).inner();
+                    for (output, sample) in output.iter_mut().zip(samples) {
+                        *output += sample * weight;
+                    }
+                }
+                ChannelSamples::from(output)
+            })
+            .collect::<Vec<ChannelSamples>>()
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stereo() -> MultiChannel<ChannelSamples> {
+        vec![
+            ChannelSamples::from(vec![1.0, 0.5, 0.0]),
+            ChannelSamples::from(vec![1.0, -0.5, 0.25]),
+        ]
+        .into()
+    }
+
+    fn route(routing: ChannelRouting, input: MultiChannel<ChannelSamples>) -> Vec<Vec<Sample>> {
+        let router = ChannelRouter::new(routing, input.len());
+        let output = router.route(input);
+        assert_eq!(output.len(), router.get_output_channels());
+        output
+            .channels
+            .iter()
+            .map(|channel| channel.inner().clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_downmix_and_mid_side() {
+        assert_eq!(
+            route(ChannelRouting::MonoDownmix, stereo()),
+            vec![vec![1.0, 0.0, 0.125]]
+        );
+        assert_eq!(
+            route(ChannelRouting::MidSide, stereo()),
+            vec![vec![1.0, 0.0, 0.125], vec![0.0, 0.5, -0.125]]
+        );
+        assert_eq!(
+            route(ChannelRouting::AllChannels, stereo()),
+            vec![vec![1.0, 0.5, 0.0], vec![1.0, -0.5, 0.25]]
+        );
+    }
+
+    #[test]
+    fn test_mono_input() {
+        let mono: MultiChannel<ChannelSamples> = vec![ChannelSamples::from(vec![0.5, -0.5])].into();
+        assert_eq!(
+            route(ChannelRouting::MidSide, mono.clone()),
+            vec![vec![0.5, -0.5], vec![0.0, 0.0]]
+        );
+        // missing input channels are silent
+        assert_eq!(
+            route(ChannelRouting::select(&[1, 0]), mono),
+            vec![vec![0.0, 0.0], vec![0.5, -0.5]]
+        );
+    }
+
+    #[test]
+    fn test_matrix_and_names() {
+        let routing = ChannelRouting::Matrix(vec![vec![0.0, 0.0, 1.0], vec![0.5, 0.5]]);
+        let surround: MultiChannel<ChannelSamples> = (0..6)
+            .map(|channel| ChannelSamples::from(vec![channel as Sample]))
+            .collect::<Vec<ChannelSamples>>()
+            .into();
+        assert_eq!(route(routing.clone(), surround), vec![vec![2.0], vec![0.5]]);
+
+        assert_eq!(
+            routing.get_channel_names(6),
+            vec![String::from("Channel 3"), String::from("Mix 2")]
+        );
+        assert_eq!(ChannelRouting::AllChannels.get_channel_names(1).len(), 1);
+    }
+}
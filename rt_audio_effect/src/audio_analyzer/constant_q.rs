@@ -0,0 +1,244 @@
+use super::{Spectrum, SpectrumScaling};
+
+/// Range and resolution of the constant-Q spectrum, 12 bins per octave are semitones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConstantQParameters {
+    pub bins_per_octave: usize,
+    pub min_frequency: f32,
+    pub max_frequency: f32,
+}
+
+impl Default for ConstantQParameters {
+    /// Semitones from A0 to A9.
+    fn default() -> Self {
+        Self {
+            bins_per_octave: 12,
+            min_frequency: 27.5,
+            max_frequency: 14080.0,
+        }
+    }
+}
+
+impl ConstantQParameters {
+    pub const MAX_BINS_PER_OCTAVE: usize = 48;
+
+    /// Center frequency of `band`, in Hz.
+    pub fn get_center_frequency(&self, band: usize) -> f32 {
+        self.min_frequency * 2.0f32.powf(band as f32 / self.bins_per_octave as f32)
+    }
+
+    /// Number of bands up to the maximum frequency, or to the Nyquist frequency when lower.
+    pub fn get_band_count(&self, sample_rate: f32) -> usize {
+        let max_frequency = self.max_frequency.min(sample_rate / 2.0);
+        if max_frequency < self.min_frequency {
+            return 0;
+        }
+        let octaves = (max_frequency / self.min_frequency).log2();
+        (octaves * self.bins_per_octave as f32 + 1e-4).floor() as usize + 1
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bins_per_octave == 0 || self.bins_per_octave > Self::MAX_BINS_PER_OCTAVE {
+            return Err(format!(
+                "Constant-Q bins per octave have to be between 1 and {}",
+                Self::MAX_BINS_PER_OCTAVE
+            ));
+        }
+        if !(self.min_frequency > 0.0 && self.min_frequency < self.max_frequency) {
+            return Err(String::from(
+                "Constant-Q range has to start above 0 Hz and below its end",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Bins of the linear spectrum summed into one band.
+struct Band {
+    start: usize,
+    weights: Vec<f32>,
+    norm: f32,
+}
+
+impl Band {
+    fn apply(&self, spectrum: &Spectrum) -> f32 {
+        let power: f32 = self
+            .weights
+            .iter()
+            .zip(spectrum.as_slice().iter().skip(self.start))
+            .map(|(weight, magnitude)| weight * magnitude * magnitude)
+            .sum();
+        (power / self.norm).sqrt()
+    }
+}
+
+/// Log-frequency spectrum resampled from the linear one, every band spans the same fraction
+/// of an octave.
+///
+/// Bands sum the power of the bins they cover, so a tone reads its amplitude and noise the
+/// power in the band; spectral densities are averaged instead. Bands narrower than the noise
+/// bandwidth of the window are widened to it, zero padding doesn't make them sharper.
+pub struct ConstantQBank {
+    bands: Vec<Band>,
+    frequencies: Vec<f32>,
+}
+
+impl ConstantQBank {
+    /// `nenbw` is the noise bandwidth of the window in bins of the `fft_size` point FFT.
+    pub fn new(
+        parameters: &ConstantQParameters,
+        fft_size: usize,
+        sample_rate: f32,
+        nenbw: f32,
+        scaling: SpectrumScaling,
+    ) -> ConstantQBank {
+        let bins = fft_size / 2;
+        let bin_width = sample_rate / fft_size as f32;
+        // edges lie half a band around the center
+        let half_band = 2.0f32.powf(0.5 / parameters.bins_per_octave as f32);
+
+        let frequencies: Vec<f32> = (0..parameters.get_band_count(sample_rate))
+            .map(|band| parameters.get_center_frequency(band))
+            .collect();
+        let bands = frequencies
+            .iter()
+            .map(|frequency| {
+                let center = frequency / bin_width;
+                let width = (center * (half_band - 1.0 / half_band)).max(nenbw);
+                let (low, high) = (center - width / 2.0, center + width / 2.0);
+
+                // bin 0 is DC and the last one is below Nyquist
+                let start = ((low + 0.5).floor() as usize).clamp(1, bins);
+                let end = ((high + 0.5).ceil() as usize).clamp(start, bins);
+                let weights = (start..end)
+                    .map(|bin| {
+                        let bin = bin as f32;
+                        (high.min(bin + 0.5) - low.max(bin - 0.5)).max(0.0)
+                    })
+                    .collect::<Vec<f32>>();
+                let norm = match scaling {
+                    SpectrumScaling::Amplitude => nenbw,
+                    SpectrumScaling::PowerSpectralDensity => weights.iter().sum(),
+                };
+
+                Band {
+                    start,
+                    weights,
+                    norm: norm.max(f32::EPSILON),
+                }
+            })
+            .collect();
+
+        ConstantQBank { bands, frequencies }
+    }
+
+    /// Center frequencies of the bands, in Hz.
+    pub fn get_frequencies(&self) -> &[f32] {
+        &self.frequencies
+    }
+
+    pub fn apply(&self, spectrum: &Spectrum) -> Spectrum {
+        self.bands
+            .iter()
+            .map(|band| band.apply(spectrum))
+            .collect::<Spectrum>()
+            .with_timestamp(spectrum.get_timestamp())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio::{
+            signal_generator::{Signal, SignalGenerator},
+            StreamParameters,
+        },
+        audio_analyzer::{FftAnalyzer, WindowFunction},
+    };
+
+    fn sine(frequency: f32, amplitude: f32, width: usize) -> Vec<f32> {
+        let mut generator = SignalGenerator::new(
+            Signal::Sine { frequency },
+            amplitude,
+            StreamParameters {
+                sample_rate: 48000,
+                channels: 1,
+            },
+        );
+        generator.generate(width)
+    }
+
+    #[test]
+    fn test_band_count() {
+        let parameters = ConstantQParameters::default();
+        assert_eq!(parameters.get_band_count(48000.0), 109);
+        assert_eq!(parameters.get_center_frequency(12), 55.0);
+        assert!((parameters.get_center_frequency(108) - 14080.0).abs() < 0.01);
+        // limited by Nyquist
+        assert_eq!(parameters.get_band_count(8000.0), 87);
+        assert!(parameters.validate().is_ok());
+        assert!(ConstantQParameters {
+            bins_per_octave: 0,
+            ..parameters
+        }
+        .validate()
+        .is_err());
+        assert!(ConstantQParameters {
+            min_frequency: 20000.0,
+            ..parameters
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_tones_in_semitones() {
+        let (width, fft_size) = (4800, 16384);
+        let mut analyzer = FftAnalyzer::new(
+            width,
+            fft_size,
+            48000,
+            WindowFunction::default(),
+            SpectrumScaling::Amplitude,
+        );
+        let nenbw = analyzer.get_nenbw() * fft_size as f32 / width as f32;
+        let parameters = ConstantQParameters::default();
+        let bank = ConstantQBank::new(
+            &parameters,
+            fft_size,
+            48000.0,
+            nenbw,
+            SpectrumScaling::Amplitude,
+        );
+
+        // A4, C6 and A7, the bands are narrower than the noise bandwidth below ~350 Hz
+        for (band, frequency) in [(48, 440.0), (63, 1046.5), (84, 3520.0)] {
+            let spectrum = analyzer.analyze(&sine(frequency, 0.5, width).into());
+            let bands = bank.apply(&spectrum);
+            assert_eq!(bands.len(), 109);
+            let loudest = (0..bands.len())
+                .max_by(|a, b| bands[*a].total_cmp(&bands[*b]))
+                .unwrap();
+            assert_eq!(loudest, band, "{frequency} Hz");
+            assert!((bank.get_frequencies()[band] - frequency).abs() < 0.1);
+            // a tone reads its amplitude
+            assert!((bands[band] - 0.5).abs() < 0.05, "{}", bands[band]);
+        }
+    }
+
+    #[test]
+    fn test_noise_density() {
+        // white density spectrum, the bands average it
+        let spectrum = Spectrum::from(vec![0.01; 2048]);
+        let bank = ConstantQBank::new(
+            &ConstantQParameters::default(),
+            4096,
+            48000.0,
+            2.0,
+            SpectrumScaling::PowerSpectralDensity,
+        );
+        let bands = bank.apply(&spectrum);
+        assert!(bands.into_iter().all(|&band| (band - 0.01).abs() < 1e-6));
+    }
+}
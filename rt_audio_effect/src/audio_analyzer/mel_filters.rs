@@ -1,78 +1,179 @@
-use converters::{hz_to_mel, mel_to_hz};
+use std::fmt::Display;
 
-use super::Spectrum;
+use converters::{hz_to_mel, hz_to_mel_slaney, mel_to_hz, mel_to_hz_slaney};
 
-struct FilterWeights(Vec<f32>);
+use super::Spectrum;
 
-pub struct MelFilterBank {
-    filters: Vec<FilterWeights>,
+/// Formula of the mel scale.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MelScale {
+    /// O'Shaughnessy's formula of HTK, logarithmic over the whole range.
+    #[default]
+    Htk,
+    /// Auditory toolbox of Slaney, linear below 1 kHz and logarithmic above.
+    Slaney,
 }
 
-impl MelFilterBank {
-    const MIN_FREQUNCY: f32 = 0.0;
-    const MAX_FREQUENCY: f32 = 20000.0;
+impl MelScale {
+    pub const ALL: [MelScale; 2] = [MelScale::Htk, MelScale::Slaney];
 
-    pub fn new(num_of_filters: usize, fft_width: usize, sample_rate: f32) -> MelFilterBank {
-        let min_mel = hz_to_mel(Self::MIN_FREQUNCY);
-        let max_mel = hz_to_mel(Self::MAX_FREQUENCY);
+    pub fn hz_to_mel(&self, hz: f32) -> f32 {
+        match self {
+            MelScale::Htk => hz_to_mel(hz),
+            MelScale::Slaney => hz_to_mel_slaney(hz),
+        }
+    }
 
-        // Create points of start/center/end points of each filter
-        let mel_points = (0..=num_of_filters + 1)
-            .map(|i| min_mel + i as f32 * (max_mel - min_mel) / (num_of_filters as f32))
-            .collect::<Vec<f32>>();
+    pub fn mel_to_hz(&self, mel: f32) -> f32 {
+        match self {
+            MelScale::Htk => mel_to_hz(mel),
+            MelScale::Slaney => mel_to_hz_slaney(mel),
+        }
+    }
+}
 
-        let hz_points = mel_points
-            .iter()
-            .map(|&mel| mel_to_hz(mel))
-            .collect::<Vec<f32>>();
-        let bin_points = hz_points
-            .iter()
-            .map(|&hz| (hz * fft_width as f32 / sample_rate as f32).floor() as usize)
-            .collect::<Vec<usize>>();
+impl Display for MelScale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MelScale::Htk => write!(f, "HTK"),
+            MelScale::Slaney => write!(f, "Slaney"),
+        }
+    }
+}
 
-        // Vec of filters. Filter = Vec of weights over spectrum
-        let mut filters_weights = vec![vec![0.0; fft_width]; num_of_filters];
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MelParameters {
+    pub filters: usize,
+    pub min_frequency: f32,
+    /// Limited to the Nyquist frequency of the stream.
+    pub max_frequency: f32,
+    pub scale: MelScale,
+    /// Filters of equal area, bands average the bins they cover instead of summing them.
+    /// On by default, otherwise wide high filters read louder than narrow low ones.
+    pub normalize: bool,
+}
 
-        for i in 1..=num_of_filters {
-            let start = bin_points[i - 1];
-            let center = bin_points[i];
-            let end = bin_points[i + 1];
+impl Default for MelParameters {
+    fn default() -> Self {
+        Self {
+            filters: 40,
+            min_frequency: 0.0,
+            max_frequency: 20000.0,
+            scale: MelScale::default(),
+            normalize: true,
+        }
+    }
+}
 
-            for j in start..center {
-                filters_weights[i - 1][j] = (j - start) as f32 / (center - start) as f32;
-            }
+impl MelParameters {
+    pub const MAX_FILTERS: usize = 256;
 
-            for j in center..end {
-                filters_weights[i - 1][j] = (end - j) as f32 / (end - center) as f32;
-            }
+    pub fn validate(&self) -> Result<(), String> {
+        if self.filters == 0 || self.filters > Self::MAX_FILTERS {
+            return Err(format!(
+                "Number of mel filters has to be between 1 and {}",
+                Self::MAX_FILTERS
+            ));
         }
+        if !(self.min_frequency >= 0.0 && self.min_frequency < self.max_frequency) {
+            return Err(String::from(
+                "Mel range has to start at 0 Hz or above and below its end",
+            ));
+        }
+        Ok(())
+    }
+}
 
-        let filters = filters_weights
-            .into_iter()
-            .map(|weights| FilterWeights(weights))
+/// Weights of the spectrum bins from `start` on, the others are zero.
+struct FilterWeights {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+/// Triangular filters spaced evenly on the mel scale, each spanning from the center of its
+/// lower neighbour to the center of the upper one.
+pub struct MelFilterBank {
+    filters: Vec<FilterWeights>,
+    frequencies: Vec<f32>,
+}
+
+impl MelFilterBank {
+    pub fn new(parameters: &MelParameters, fft_size: usize, sample_rate: f32) -> MelFilterBank {
+        let bins = fft_size / 2;
+        let bin_width = sample_rate / fft_size as f32;
+        // filters above Nyquist would reach past the last bin
+        let max_frequency = parameters
+            .max_frequency
+            .min(sample_rate / 2.0)
+            .max(parameters.min_frequency);
+        let scale = parameters.scale;
+        let min_mel = scale.hz_to_mel(parameters.min_frequency);
+        let max_mel = scale.hz_to_mel(max_frequency);
+
+        // Create points of start/center/end points of each filter
+        let hz_points = (0..=parameters.filters + 1)
+            .map(|i| {
+                let mel =
+                    min_mel + i as f32 * (max_mel - min_mel) / (parameters.filters + 1) as f32;
+                scale.mel_to_hz(mel)
+            })
+            .collect::<Vec<f32>>();
+
+        let filters = hz_points
+            .windows(3)
+            .map(|points| {
+                let (low, center, high) = (points[0], points[1], points[2]);
+                let start = ((low / bin_width).floor() as usize + 1).min(bins);
+                let end = ((high / bin_width).ceil() as usize).clamp(start, bins);
+                let weights = (start..end)
+                    .map(|bin| {
+                        let frequency = bin as f32 * bin_width;
+                        let rising = (frequency - low) / (center - low).max(f32::EPSILON);
+                        let falling = (high - frequency) / (high - center).max(f32::EPSILON);
+                        rising.min(falling).max(0.0)
+                    })
+                    .collect::<Vec<f32>>();
+                FilterWeights {
+                    start,
+                    weights: match parameters.normalize {
+                        true => FilterWeights::normalize(weights),
+                        false => weights,
+                    },
+                }
+            })
             .collect::<Vec<FilterWeights>>();
 
-        MelFilterBank { filters }
+        MelFilterBank {
+            filters,
+            frequencies: hz_points[1..=parameters.filters].to_vec(),
+        }
+    }
+
+    /// Center frequencies of the filters, in Hz.
+    pub fn get_frequencies(&self) -> &[f32] {
+        &self.frequencies
     }
 
     pub fn apply(&self, spectrum: &Spectrum) -> Spectrum {
         self.filters
             .iter()
             .map(|filter| filter.apply(spectrum))
-            .collect()
+            .collect::<Spectrum>()
+            .with_timestamp(spectrum.get_timestamp())
     }
 }
 
 impl FilterWeights {
     fn normalize(weights: Vec<f32>) -> Vec<f32> {
-        let sum: f32 = weights.iter().sum();
+        // filters narrower than a bin may cover none
+        let sum: f32 = weights.iter().sum::<f32>().max(f32::EPSILON);
         weights.iter().map(|&weight| weight / sum).collect()
     }
 
     fn apply(&self, spectrum: &Spectrum) -> f32 {
-        self.0
+        self.weights
             .iter()
-            .zip(spectrum)
+            .zip(spectrum.as_slice().iter().skip(self.start))
             .map(|(&weight, &spectrum_value)| weight * spectrum_value)
             .sum()
     }
@@ -80,7 +181,7 @@ impl FilterWeights {
 
 impl From<Vec<f32>> for FilterWeights {
     fn from(weights: Vec<f32>) -> FilterWeights {
-        FilterWeights(weights)
+        FilterWeights { start: 0, weights }
     }
 }
 
@@ -92,11 +193,40 @@ mod converters {
     pub fn mel_to_hz(mel: f32) -> f32 {
         700.0 * (10.0_f32.powf(mel / 2595.0) - 1.0)
     }
+
+    const SLANEY_HZ_PER_MEL: f32 = 200.0 / 3.0;
+    const SLANEY_MIN_LOG_HZ: f32 = 1000.0;
+    const SLANEY_MIN_LOG_MEL: f32 = SLANEY_MIN_LOG_HZ / SLANEY_HZ_PER_MEL;
+    /// ln(6.4) / 27, 27 mels per step of 6.4 in frequency.
+    const SLANEY_LOG_STEP: f32 = 0.068751777;
+
+    pub fn hz_to_mel_slaney(hz: f32) -> f32 {
+        if hz < SLANEY_MIN_LOG_HZ {
+            hz / SLANEY_HZ_PER_MEL
+        } else {
+            SLANEY_MIN_LOG_MEL + (hz / SLANEY_MIN_LOG_HZ).ln() / SLANEY_LOG_STEP
+        }
+    }
+
+    pub fn mel_to_hz_slaney(mel: f32) -> f32 {
+        if mel < SLANEY_MIN_LOG_MEL {
+            mel * SLANEY_HZ_PER_MEL
+        } else {
+            SLANEY_MIN_LOG_HZ * (SLANEY_LOG_STEP * (mel - SLANEY_MIN_LOG_MEL)).exp()
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        audio::{
+            signal_generator::{Signal, SignalGenerator},
+            StreamParameters,
+        },
+        audio_analyzer::{FftAnalyzer, SpectrumScaling, WindowFunction},
+    };
 
     #[test]
     fn test_mel_filter_bank_new() {
@@ -104,15 +234,20 @@ mod tests {
         let fft_width = 1024;
         let sample_rate = 44100.0;
 
-        let mel_filter_bank = MelFilterBank::new(num_of_filters, fft_width, sample_rate);
+        let parameters = MelParameters {
+            filters: num_of_filters,
+            ..Default::default()
+        };
+        let mel_filter_bank = MelFilterBank::new(&parameters, fft_width, sample_rate);
 
         // Assert the number of filters
         assert_eq!(mel_filter_bank.filters.len(), num_of_filters);
+        assert_eq!(mel_filter_bank.get_frequencies().len(), num_of_filters);
 
         // Assert the weights of each filter
         for filter in mel_filter_bank.filters {
-            assert_eq!(filter.0.len(), fft_width);
-            filter.0.iter().for_each(|&weight| {
+            assert!(filter.start + filter.weights.len() <= fft_width / 2);
+            filter.weights.iter().for_each(|&weight| {
                 assert!(weight >= 0.0 && weight <= 1.0);
             });
         }
@@ -133,7 +268,7 @@ mod tests {
     #[test]
     fn test_mel_filter_apply() {
         let weights = vec![0.0, 0.25, 0.5, 0.75, 1.0, 0.75, 0.5, 0.25, 0.0];
-        let mel_filter = FilterWeights(weights);
+        let mel_filter = FilterWeights::from(FilterWeights::normalize(weights));
 
         let spectrum = vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9].into();
         let filtered_spectrum = mel_filter.apply(&spectrum);
@@ -150,6 +285,95 @@ mod tests {
         assert_eq!(filtered_spectrum, expected_value);
     }
 
+    #[test]
+    fn test_mel_filter_bank_on_tones() {
+        let (sample_rate, width) = (48000, 4800);
+        let mel_filter_bank =
+            MelFilterBank::new(&MelParameters::default(), width, sample_rate as f32);
+        let mut analyzer = FftAnalyzer::new(
+            width,
+            width,
+            sample_rate,
+            WindowFunction::default(),
+            SpectrumScaling::default(),
+        );
+
+        let mut previous_filter = None;
+        for frequency in [200.0, 1000.0, 5000.0] {
+            let mut sine = SignalGenerator::new(
+                Signal::Sine { frequency },
+                0.5,
+                StreamParameters {
+                    sample_rate: sample_rate as u32,
+                    channels: 1,
+                },
+            );
+            let spectrum = analyzer.analyze(&sine.generate(width).into());
+            let mel_spectrum = mel_filter_bank.apply(&spectrum);
+
+            let filter = (0..mel_spectrum.len())
+                .max_by(|a, b| mel_spectrum[*a].total_cmp(&mel_spectrum[*b]))
+                .unwrap();
+            let bin = (frequency * width as f32 / sample_rate as f32) as usize;
+            let weights = &mel_filter_bank.filters[filter];
+            assert!(weights.weights[bin - weights.start] > 0.0, "{frequency} Hz");
+            assert!(previous_filter < Some(filter));
+            previous_filter = Some(filter);
+        }
+    }
+
+    #[test]
+    fn test_mel_filter_bank_range() {
+        // default range reaches above Nyquist of 16 kHz
+        let mel_filter_bank = MelFilterBank::new(&MelParameters::default(), 1600, 16000.0);
+        assert!(mel_filter_bank
+            .filters
+            .iter()
+            .all(|filter| filter.start + filter.weights.len() <= 800));
+        assert!(*mel_filter_bank.get_frequencies().last().unwrap() < 8000.0);
+
+        let parameters = MelParameters {
+            filters: 20,
+            min_frequency: 100.0,
+            max_frequency: 8000.0,
+            scale: MelScale::Slaney,
+            normalize: true,
+        };
+        assert!(parameters.validate().is_ok());
+        let mel_filter_bank = MelFilterBank::new(&parameters, 4800, 48000.0);
+        let frequencies = mel_filter_bank.get_frequencies();
+        assert!(frequencies[0] > 100.0 && frequencies[19] < 8000.0);
+        // areas are equal, a flat spectrum reads its level in every band
+        let mel_spectrum = mel_filter_bank.apply(&Spectrum::from(vec![0.1; 2400]));
+        assert!(mel_spectrum
+            .into_iter()
+            .all(|&band| (band - 0.1).abs() < 1e-5));
+
+        assert!(MelParameters {
+            filters: 0,
+            ..parameters
+        }
+        .validate()
+        .is_err());
+        assert!(MelParameters {
+            min_frequency: 9000.0,
+            ..parameters
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_slaney_scale() {
+        let scale = MelScale::Slaney;
+        assert!((scale.hz_to_mel(500.0) - 7.5).abs() < 1e-5);
+        assert!((scale.hz_to_mel(1000.0) - 15.0).abs() < 1e-5);
+        assert!((scale.hz_to_mel(6400.0) - 42.0).abs() < 1e-3);
+        for hz in [20.0, 700.0, 1000.0, 4000.0, 20000.0] {
+            assert!((scale.mel_to_hz(scale.hz_to_mel(hz)) - hz).abs() < hz * 1e-5);
+        }
+    }
+
     // Validated with https://www.homepages.ucl.ac.uk/~sslyjjt/speech/Mel2Hz.html
     #[test]
     fn test_hz_to_mel() {
@@ -1,13 +1,23 @@
 pub mod analyzer_parameters;
+pub mod channel_routing;
+pub mod constant_q;
 pub mod mel_filters;
+pub mod octave_bands;
+pub mod real_fft;
 pub mod spectrogram;
 pub mod spectrum;
+pub mod spectrum_format;
 pub mod stream_analyzer;
 pub mod utils;
 
 pub use analyzer_parameters::*;
+pub use channel_routing::*;
+pub use constant_q::*;
 pub use mel_filters::*;
+pub use octave_bands::*;
+pub use real_fft::*;
 pub use spectrogram::*;
 pub use spectrum::*;
+pub use spectrum_format::*;
 pub use stream_analyzer::*;
 pub use utils::*;
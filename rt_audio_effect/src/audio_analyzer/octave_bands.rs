@@ -0,0 +1,335 @@
+use std::{f64::consts::PI, fmt::Display, time::Duration};
+
+use rustfft::num_complex::Complex;
+
+use crate::audio::Sample;
+
+use super::Spectrum;
+
+/// Width of the bands of a real time analyzer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OctaveFraction {
+    Full,
+    #[default]
+    Third,
+    Sixth,
+}
+
+impl OctaveFraction {
+    pub const ALL: [OctaveFraction; 3] = [
+        OctaveFraction::Full,
+        OctaveFraction::Third,
+        OctaveFraction::Sixth,
+    ];
+
+    pub fn get_bands_per_octave(&self) -> usize {
+        match self {
+            OctaveFraction::Full => 1,
+            OctaveFraction::Third => 3,
+            OctaveFraction::Sixth => 6,
+        }
+    }
+}
+
+impl Display for OctaveFraction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "1/{} octave", self.get_bands_per_octave())
+    }
+}
+
+/// Exponential averaging of the band power, as in sound level meters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeWeighting {
+    #[default]
+    Fast,
+    Slow,
+}
+
+impl TimeWeighting {
+    pub const ALL: [TimeWeighting; 2] = [TimeWeighting::Fast, TimeWeighting::Slow];
+
+    pub fn get_time_constant(&self) -> Duration {
+        match self {
+            TimeWeighting::Fast => Duration::from_millis(125),
+            TimeWeighting::Slow => Duration::from_secs(1),
+        }
+    }
+}
+
+impl Display for TimeWeighting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeWeighting::Fast => write!(f, "Fast (125 ms)"),
+            TimeWeighting::Slow => write!(f, "Slow (1 s)"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OctaveBandParameters {
+    pub fraction: OctaveFraction,
+    pub time_weighting: TimeWeighting,
+}
+
+impl OctaveBandParameters {
+    /// Octave frequency ratio of the base ten system of IEC 61260-1.
+    const OCTAVE_RATIO: f64 = 1.9952623149688795;
+    const REFERENCE_FREQUENCY: f64 = 1000.0;
+    const MIN_FREQUENCY: f64 = 19.0;
+    const MAX_FREQUENCY: f64 = 21000.0;
+
+    /// Exact mid-band frequencies of IEC 61260-1 in the audible range, bands reaching the
+    /// Nyquist frequency are left out.
+    pub fn get_center_frequencies(&self, sample_rate: f32) -> Vec<f32> {
+        let bands = self.fraction.get_bands_per_octave() as f64;
+        let half_band = Self::OCTAVE_RATIO.powf(0.5 / bands);
+        // bands are centered on the reference for odd fractions and next to it for even ones
+        let offset = match self.fraction.get_bands_per_octave() % 2 {
+            1 => 0.0,
+            _ => 0.5,
+        };
+        let first = ((Self::MIN_FREQUENCY / Self::REFERENCE_FREQUENCY).log(Self::OCTAVE_RATIO)
+            * bands
+            - offset)
+            .ceil() as i32;
+        (first..)
+            .map(|x| {
+                Self::REFERENCE_FREQUENCY * Self::OCTAVE_RATIO.powf((x as f64 + offset) / bands)
+            })
+            .take_while(|&frequency| {
+                frequency <= Self::MAX_FREQUENCY && frequency * half_band < sample_rate as f64 / 2.0
+            })
+            .map(|frequency| frequency as f32)
+            .collect()
+    }
+}
+
+/// Second order section in transposed direct form II, in double precision as the poles of
+/// narrow low bands are close to the unit circle.
+#[derive(Clone)]
+struct Section {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Section {
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.state[0];
+        self.state[0] = self.b[1] * input - self.a[0] * output + self.state[1];
+        self.state[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+
+    fn response(&self, z: Complex<f64>) -> Complex<f64> {
+        let z1 = z.inv();
+        let z2 = z1 * z1;
+        (self.b[0] + self.b[1] * z1 + self.b[2] * z2) / (1.0 + self.a[0] * z1 + self.a[1] * z2)
+    }
+}
+
+/// Butterworth band pass of the class 1 filters of IEC 61260-1, with its mean square output
+/// averaged by the time weighting.
+#[derive(Clone)]
+struct BandFilter {
+    sections: Vec<Section>,
+    mean_square: f64,
+}
+
+impl BandFilter {
+    const ORDER: usize = 3;
+
+    /// Bilinear transform of the analog prototype, pre-warped at the band edges.
+    fn new(center: f64, bands_per_octave: f64, sample_rate: f64) -> Self {
+        let half_band = OctaveBandParameters::OCTAVE_RATIO.powf(0.5 / bands_per_octave);
+        let warp = |frequency: f64| 2.0 * sample_rate * (PI * frequency / sample_rate).tan();
+        let (low, high) = (warp(center / half_band), warp(center * half_band));
+        let (omega, bandwidth) = ((low * high).sqrt(), high - low);
+
+        let mut sections: Vec<Section> = (0..Self::ORDER)
+            .flat_map(|k| {
+                let angle = PI * (2 * k + Self::ORDER + 1) as f64 / (2 * Self::ORDER) as f64;
+                let pole = Complex::from_polar(1.0, angle) * bandwidth / 2.0;
+                let root = (pole * pole - omega * omega).sqrt();
+                [pole + root, pole - root]
+            })
+            .map(|pole| (2.0 * sample_rate + pole) / (2.0 * sample_rate - pole))
+            // every pole makes a section with its conjugate, zeros are at DC and Nyquist
+            .filter(|pole| pole.im > 0.0)
+            .map(|pole| Section {
+                b: [1.0, 0.0, -1.0],
+                a: [-2.0 * pole.re, pole.norm_sqr()],
+                state: [0.0; 2],
+            })
+            .collect();
+
+        let z = Complex::from_polar(1.0, 2.0 * PI * center / sample_rate);
+        let gain: Complex<f64> = sections.iter().map(|section| section.response(z)).product();
+        sections[0].b = sections[0].b.map(|b| b / gain.norm());
+
+        BandFilter {
+            sections,
+            mean_square: 0.0,
+        }
+    }
+
+    fn process(&mut self, samples: &[Sample], smoothing: f64) {
+        for &sample in samples {
+            let output = self
+                .sections
+                .iter_mut()
+                .fold(sample as f64, |value, section| section.process(value));
+            self.mean_square += (output * output - self.mean_square) * smoothing;
+        }
+    }
+}
+
+/// Real time analyzer of one channel, filters the stream into fractional-octave bands.
+///
+/// Levels are the amplitude of a sine with the power of the band, a full scale sine reads 1
+/// as in the amplitude spectrum.
+#[derive(Clone)]
+pub struct OctaveBandAnalyzer {
+    bands: Vec<BandFilter>,
+    frequencies: Vec<f32>,
+    smoothing: f64,
+}
+
+impl OctaveBandAnalyzer {
+    pub fn new(parameters: &OctaveBandParameters, sample_rate: f32) -> Self {
+        let frequencies = parameters.get_center_frequencies(sample_rate);
+        let bands_per_octave = parameters.fraction.get_bands_per_octave() as f64;
+        let time_constant = parameters.time_weighting.get_time_constant().as_secs_f64();
+
+        OctaveBandAnalyzer {
+            bands: frequencies
+                .iter()
+                .map(|&center| BandFilter::new(center as f64, bands_per_octave, sample_rate as f64))
+                .collect(),
+            frequencies,
+            smoothing: 1.0 - (-1.0 / (time_constant * sample_rate as f64)).exp(),
+        }
+    }
+
+    /// Mid-band frequencies, in Hz.
+    pub fn get_frequencies(&self) -> &[f32] {
+        &self.frequencies
+    }
+
+    /// Filters samples following the previous ones.
+    pub fn process(&mut self, samples: &[Sample]) {
+        self.bands
+            .iter_mut()
+            .for_each(|band| band.process(samples, self.smoothing));
+    }
+
+    pub fn get_levels(&self) -> Spectrum {
+        self.bands
+            .iter()
+            .map(|band| (2.0 * band.mean_square).sqrt() as f32)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{
+        signal_generator::{Signal, SignalGenerator},
+        StreamParameters,
+    };
+
+    fn sine(frequency: f32, seconds: f32) -> Vec<Sample> {
+        SignalGenerator::new(
+            Signal::Sine { frequency },
+            0.5,
+            StreamParameters {
+                sample_rate: 48000,
+                channels: 1,
+            },
+        )
+        .generate((48000.0 * seconds) as usize)
+    }
+
+    #[test]
+    fn test_center_frequencies() {
+        let parameters = |fraction| OctaveBandParameters {
+            fraction,
+            ..Default::default()
+        };
+
+        let octaves = parameters(OctaveFraction::Full).get_center_frequencies(48000.0);
+        assert_eq!(octaves.len(), 10);
+        assert!((octaves[0] - 31.62).abs() < 0.01);
+        assert!((octaves[5] - 1000.0).abs() < 1e-3);
+
+        let thirds = parameters(OctaveFraction::Third).get_center_frequencies(48000.0);
+        assert_eq!(thirds.len(), 31);
+        assert!((thirds[0] - 19.95).abs() < 0.01);
+        assert!((thirds[17] - 1000.0).abs() < 1e-3);
+        // the 20 kHz band reaches above the Nyquist frequency of 44.1 kHz
+        assert_eq!(
+            parameters(OctaveFraction::Third)
+                .get_center_frequencies(44100.0)
+                .len(),
+            30
+        );
+
+        let sixths = parameters(OctaveFraction::Sixth).get_center_frequencies(48000.0);
+        assert_eq!(sixths.len(), 60);
+        // 1 kHz lies on the edge between two bands
+        assert!((sixths[34] * sixths[33] - 1e6).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_band_filter_response() {
+        // attenuation limits of class 1 filters at the band edges and an octave away
+        let filter = BandFilter::new(1000.0, 3.0, 48000.0);
+        let gain_db = |frequency: f64| {
+            let z = Complex::from_polar(1.0, 2.0 * PI * frequency / 48000.0);
+            let gain: Complex<f64> = filter.sections.iter().map(|s| s.response(z)).product();
+            20.0 * gain.norm().log10()
+        };
+        let half_band = OctaveBandParameters::OCTAVE_RATIO.powf(1.0 / 6.0);
+        assert!(gain_db(1000.0).abs() < 1e-9);
+        assert!((gain_db(1000.0 * half_band) + 3.0).abs() < 0.1);
+        assert!((gain_db(1000.0 / half_band) + 3.0).abs() < 0.1);
+        assert!(gain_db(2000.0) < -40.0);
+        assert!(gain_db(500.0) < -40.0);
+    }
+
+    #[test]
+    fn test_tone_levels() {
+        let parameters = OctaveBandParameters::default();
+        let mut analyzer = OctaveBandAnalyzer::new(&parameters, 48000.0);
+        analyzer.process(&sine(1000.0, 1.0));
+        let levels = analyzer.get_levels();
+        assert_eq!(levels.len(), 31);
+        assert!((levels[17] - 0.5).abs() < 0.01, "{}", levels[17]);
+        assert!(levels[15] < 0.01 && levels[19] < 0.01);
+
+        // low bands are narrow, but their filters stay stable
+        let mut analyzer = OctaveBandAnalyzer::new(&parameters, 48000.0);
+        analyzer.process(&sine(31.62, 2.0));
+        let levels = analyzer.get_levels();
+        assert!((levels[2] - 0.5).abs() < 0.02, "{}", levels[2]);
+    }
+
+    #[test]
+    fn test_time_weighting() {
+        let level_after = |time_weighting, seconds| {
+            let parameters = OctaveBandParameters {
+                time_weighting,
+                ..Default::default()
+            };
+            let mut analyzer = OctaveBandAnalyzer::new(&parameters, 48000.0);
+            analyzer.process(&sine(1000.0, seconds));
+            analyzer.get_levels()[17]
+        };
+        // mean square reaches 1 - 1/e of its value after one time constant
+        let expected = 0.5 * (1.0 - (-1.0f32).exp()).sqrt();
+        assert!((level_after(TimeWeighting::Fast, 0.125) - expected).abs() < 0.02);
+        assert!((level_after(TimeWeighting::Slow, 1.0) - expected).abs() < 0.02);
+        assert!(level_after(TimeWeighting::Slow, 0.125) < 0.2);
+    }
+}
@@ -0,0 +1,104 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+/// Forward FFT of real samples. Even and odd samples are packed into a complex FFT of half
+/// the size, its output is split into the spectrum of the real input.
+pub struct RealFft {
+    size: usize,
+    fft: Arc<dyn Fft<f32>>,
+    /// e^(-2πik/size) for k in 0..=size/2.
+    twiddles: Vec<Complex<f32>>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl RealFft {
+    /// `size` has to be even.
+    pub fn new(size: usize) -> Self {
+        assert!(
+            size >= 2 && size.is_multiple_of(2),
+            "Real FFT size has to be even"
+        );
+        let half = size / 2;
+        let fft = FftPlanner::<f32>::new().plan_fft_forward(half);
+        let twiddles = (0..=half)
+            .map(|k| {
+                let angle = -2.0 * PI * k as f64 / size as f64;
+                Complex::new(angle.cos() as f32, angle.sin() as f32)
+            })
+            .collect();
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+        RealFft {
+            size,
+            fft,
+            twiddles,
+            buffer: vec![Complex::default(); half],
+            scratch,
+        }
+    }
+
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    /// Writes bins 0..=size/2 of `input`, zero padded to the size, into `output`.
+    pub fn process(&mut self, input: &[f32], output: &mut [Complex<f32>]) {
+        let half = self.size / 2;
+        assert!(input.len() <= self.size, "Input longer than the FFT");
+        assert_eq!(output.len(), half + 1, "Output has to hold size/2 + 1 bins");
+
+        for (n, value) in self.buffer.iter_mut().enumerate() {
+            let re = input.get(2 * n).copied().unwrap_or(0.0);
+            let im = input.get(2 * n + 1).copied().unwrap_or(0.0);
+            *value = Complex::new(re, im);
+        }
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        for (k, bin) in output.iter_mut().enumerate() {
+            let z = self.buffer[k % half];
+            let mirrored = self.buffer[(half - k) % half].conj();
+            let even = (z + mirrored) * 0.5;
+            // (z - mirrored) / 2i
+            let odd = Complex::new(z.im - mirrored.im, mirrored.re - z.re) * 0.5;
+            *bin = even + self.twiddles[k] * odd;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complex_fft(input: &[f32], size: usize) -> Vec<Complex<f32>> {
+        let mut buffer: Vec<Complex<f32>> = (0..size)
+            .map(|n| Complex::new(input.get(n).copied().unwrap_or(0.0), 0.0))
+            .collect();
+        FftPlanner::new()
+            .plan_fft_forward(size)
+            .process(&mut buffer);
+        buffer.truncate(size / 2 + 1);
+        buffer
+    }
+
+    #[test]
+    fn test_matches_complex_fft() {
+        // padded and not padded, power of two and not
+        for (length, size) in [(16, 16), (4800, 4800), (4800, 8192), (1000, 1024), (7, 8)] {
+            let input: Vec<f32> = (0..length)
+                .map(|n| ((n * 7919) % 101) as f32 / 50.0 - 1.0)
+                .collect();
+            let mut output = vec![Complex::default(); size / 2 + 1];
+            RealFft::new(size).process(&input, &mut output);
+
+            let expected = complex_fft(&input, size);
+            for (k, (actual, expected)) in output.iter().zip(&expected).enumerate() {
+                assert!(
+                    (actual - expected).norm() < 1e-3 * (1.0 + expected.norm()),
+                    "{length}/{size} bin {k}: {actual} != {expected}"
+                );
+            }
+        }
+    }
+}
@@ -1,51 +1,66 @@
 use std::sync::Arc;
 
-use crate::audio::StreamParameters;
+use crate::audio::Timestamp;
 
 use super::{AnalyzerParameters, MultiChannel, Spectrum, TimeSeries};
 
 pub type Magnitude = f32;
 
 pub struct Spectrogram {
-    stream_parameters: Arc<StreamParameters>,
+    channels: usize,
     spectrum_history: MultiChannel<TimeSeries<Magnitude>>,
+    latest_timestamp: Timestamp,
 }
 
 impl Spectrogram {
-    pub fn new(
+    /// `channels` is the number of analyzed channels.
+    pub fn new(analyzer_parameters: Arc<AnalyzerParameters>, channels: usize) -> Spectrogram {
+        let width = analyzer_parameters.get_fft_size() / 2;
+        Self::with_width(analyzer_parameters, channels, width)
+    }
+
+    /// History of spectrums with `width` bins, other than those of the FFT.
+    pub fn with_width(
         analyzer_parameters: Arc<AnalyzerParameters>,
-        stream_parameters: Arc<StreamParameters>,
+        channels: usize,
+        width: usize,
     ) -> Spectrogram {
         Spectrogram {
-            stream_parameters: stream_parameters.clone(),
+            channels,
             spectrum_history: MultiChannel::new(
-                stream_parameters.channels as usize,
-                TimeSeries::new(
-                    analyzer_parameters.length_of_history,
-                    analyzer_parameters.spectrum_width / 2,
-                    0.0,
-                ),
+                channels,
+                TimeSeries::new(analyzer_parameters.length_of_history, width, 0.0),
             ),
+            latest_timestamp: Timestamp::default(),
         }
     }
 
-    pub fn push_spectrums(&mut self, spectrums: MultiChannel<Spectrum>) {
-        assert!(spectrums.len() == self.stream_parameters.channels as usize);
+    pub fn push_spectrums(&mut self, spectrums: &MultiChannel<Spectrum>) {
+        assert!(spectrums.len() == self.channels);
         assert!(spectrums.len() == self.spectrum_history.len());
 
+        if let Some(spectrum) = spectrums.channels.first() {
+            self.latest_timestamp = spectrum.get_timestamp();
+        }
+
         spectrums.into_iter().enumerate().for_each(|(i, data)| {
-            self.spectrum_history.channels[i].push(data.into());
+            self.spectrum_history.channels[i].push(data.as_slice());
         });
     }
 
     pub fn get_latest_spectrum(&self) -> MultiChannel<Spectrum> {
         let mut spectrums: Vec<Spectrum> = vec![];
         self.spectrum_history.channels.iter().for_each(|channel| {
-            spectrums.push(channel.get_last().into());
+            spectrums
+                .push(Spectrum::from(channel.get_last()).with_timestamp(self.latest_timestamp));
         });
         spectrums.into()
     }
 
+    pub fn get_latest_timestamp(&self) -> Timestamp {
+        self.latest_timestamp
+    }
+
     pub fn get_spectrogram_for_channel(
         &self,
         channel: usize,
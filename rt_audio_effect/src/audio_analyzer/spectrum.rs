@@ -1,31 +1,53 @@
 use std::{
+    fmt::Display,
     ops::{Index, IndexMut},
-    sync::Arc,
 };
 
 use log::trace;
-use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use rustfft::num_complex::Complex;
 
-use crate::audio::ChannelSamples;
+use crate::audio::{ChannelSamples, Timestamp};
 
+use super::RealFft;
+
+/// Spectrum of one analysis window, stamped with capture time of its newest sample.
 #[derive(Clone)]
-pub struct Spectrum(Vec<f32>);
+pub struct Spectrum {
+    data: Vec<f32>,
+    timestamp: Timestamp,
+}
 
 impl Spectrum {
     pub fn as_slice(&self) -> &[f32] {
-        &self.0
+        &self.data
     }
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.data.len()
     }
     pub fn new() -> Self {
-        Spectrum(Vec::new())
+        Vec::new().into()
+    }
+    pub fn get_timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+    pub fn with_timestamp(mut self, timestamp: Timestamp) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+    pub fn set_timestamp(&mut self, timestamp: Timestamp) {
+        self.timestamp = timestamp;
+    }
+    /// Copies data and timestamp, keeping the allocation.
+    pub fn copy_from(&mut self, other: &Spectrum) {
+        self.data.clear();
+        self.data.extend_from_slice(&other.data);
+        self.timestamp = other.timestamp;
     }
 }
 
 impl FromIterator<f32> for Spectrum {
     fn from_iter<I: IntoIterator<Item = f32>>(iter: I) -> Self {
-        Spectrum(iter.into_iter().collect())
+        iter.into_iter().collect::<Vec<f32>>().into()
     }
 }
 
@@ -34,7 +56,7 @@ impl<'a> IntoIterator for &'a Spectrum {
     type IntoIter = std::slice::Iter<'a, f32>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.0.iter()
+        self.data.iter()
     }
 }
 impl<'a> IntoIterator for &'a mut Spectrum {
@@ -42,114 +64,511 @@ impl<'a> IntoIterator for &'a mut Spectrum {
     type IntoIter = std::slice::IterMut<'a, f32>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.0.iter_mut()
+        self.data.iter_mut()
     }
 }
 impl Index<usize> for Spectrum {
     type Output = f32;
 
     fn index(&self, index: usize) -> &Self::Output {
-        &self.0[index]
+        &self.data[index]
     }
 }
 
 impl IndexMut<usize> for Spectrum {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.0[index]
+        &mut self.data[index]
     }
 }
 
 impl Into<Vec<f32>> for Spectrum {
     fn into(self) -> Vec<f32> {
-        self.0
+        self.data
     }
 }
 
 impl From<Vec<f32>> for Spectrum {
     fn from(data: Vec<f32>) -> Spectrum {
-        Spectrum(data)
+        Spectrum {
+            data,
+            timestamp: Timestamp::default(),
+        }
     }
 }
 
 impl From<&[f32]> for Spectrum {
     fn from(data: &[f32]) -> Spectrum {
-        Spectrum(data.to_vec())
+        data.to_vec().into()
+    }
+}
+
+/// Window applied to the samples before the FFT, trading frequency resolution for leakage.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WindowFunction {
+    Rectangular,
+    Hann,
+    Hamming,
+    BlackmanHarris,
+    #[default]
+    Nuttall,
+    /// Reads the amplitude of tones between bins correctly, at the cost of a wide main lobe.
+    FlatTop,
+    /// Main lobe width and side lobe level are set by `beta`, 0 is rectangular.
+    Kaiser {
+        beta: f32,
+    },
+}
+
+impl WindowFunction {
+    pub const ALL: [WindowFunction; 7] = [
+        WindowFunction::Rectangular,
+        WindowFunction::Hann,
+        WindowFunction::Hamming,
+        WindowFunction::BlackmanHarris,
+        WindowFunction::Nuttall,
+        WindowFunction::FlatTop,
+        WindowFunction::Kaiser { beta: 8.6 },
+    ];
+
+    /// Symmetric window coefficients.
+    pub fn generate(&self, width: usize) -> Vec<f32> {
+        match self {
+            WindowFunction::Rectangular => vec![1.0; width],
+            WindowFunction::Hann => Self::cosine_sum(&[0.5, 0.5], width),
+            WindowFunction::Hamming => Self::cosine_sum(&[0.54, 0.46], width),
+            WindowFunction::BlackmanHarris => {
+                Self::cosine_sum(&[0.35875, 0.48829, 0.14128, 0.01168], width)
+            }
+            WindowFunction::Nuttall => {
+                Self::cosine_sum(&[0.355768, 0.487396, 0.144232, 0.012604], width)
+            }
+            WindowFunction::FlatTop => Self::cosine_sum(
+                &[
+                    0.21557895,
+                    0.41663158,
+                    0.277263158,
+                    0.083578947,
+                    0.006947368,
+                ],
+                width,
+            ),
+            WindowFunction::Kaiser { beta } => Self::kaiser(*beta as f64, width),
+        }
+    }
+
+    fn cosine_sum(coefficients: &[f64], width: usize) -> Vec<f32> {
+        let denominator = width.saturating_sub(1).max(1) as f64;
+        (0..width)
+            .map(|n| {
+                let x = 2.0 * std::f64::consts::PI * n as f64 / denominator;
+                coefficients
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| (-1.0f64).powi(k as i32) * a * (k as f64 * x).cos())
+                    .sum::<f64>() as f32
+            })
+            .collect()
+    }
+
+    fn kaiser(beta: f64, width: usize) -> Vec<f32> {
+        let denominator = width.saturating_sub(1).max(1) as f64;
+        let norm = bessel_i0(beta);
+        (0..width)
+            .map(|n| {
+                let x = 2.0 * n as f64 / denominator - 1.0;
+                (bessel_i0(beta * (1.0 - x * x).max(0.0).sqrt()) / norm) as f32
+            })
+            .collect()
+    }
+}
+
+impl Display for WindowFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WindowFunction::Rectangular => write!(f, "Rectangular"),
+            WindowFunction::Hann => write!(f, "Hann"),
+            WindowFunction::Hamming => write!(f, "Hamming"),
+            WindowFunction::BlackmanHarris => write!(f, "Blackman-Harris"),
+            WindowFunction::Nuttall => write!(f, "Nuttall"),
+            WindowFunction::FlatTop => write!(f, "Flat-top"),
+            WindowFunction::Kaiser { beta } => write!(f, "Kaiser (β {beta:.1})"),
+        }
+    }
+}
+
+/// Modified Bessel function of the first kind, order 0.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+/// Units of the spectrum values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpectrumScaling {
+    /// Peak amplitude of a sine, a full scale sine reads 1.0 (0 dBFS) with any window.
+    #[default]
+    Amplitude,
+    /// Square root of the one sided power spectral density, in 1/√Hz. Noise reads the same
+    /// with any window and spectrum width, squared values sum to the signal power.
+    PowerSpectralDensity,
+}
+
+impl Display for SpectrumScaling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpectrumScaling::Amplitude => write!(f, "Amplitude"),
+            SpectrumScaling::PowerSpectralDensity => write!(f, "Power spectral density"),
+        }
     }
 }
 
 struct Window {
-    weights: Vec<f32>,       // Window coefficients
-    sum_window: f32,         // Sum of window coefficients
-    _sum_square_window: f32, // Sum of squares of window coefficients
-    _nenbw: f32,             // Normalized Equivalent Noise Bandwidth
-    _enbw: f32,              // Effective Noise Bandwidth
+    weights: Vec<f32>, // Window coefficients
+    sum_window: f32,   // Sum of window coefficients
+    nenbw: f32,        // Normalized Equivalent Noise Bandwidth, in bins
+    enbw: f32,         // Effective Noise Bandwidth, in Hz
 }
 
 impl Window {
-    fn new(width: usize, sampling_frequency: usize) -> Window {
-        let window = Window::generate_hanning_window(width);
+    fn new(function: WindowFunction, width: usize, sampling_frequency: usize) -> Window {
+        let window = function.generate(width);
         let sum_window: f32 = window.iter().sum();
         let sum_square_window: f32 = window.iter().map(|v| v * v).sum();
         let nenbw = width as f32 * sum_square_window / (sum_window * sum_window);
         let enbw = nenbw * sampling_frequency as f32 / width as f32;
+        trace!("{function} window, NENBW {nenbw} bins");
         Window {
             weights: window,
             sum_window,
-            _sum_square_window: sum_square_window,
-            _nenbw: nenbw,
-            _enbw: enbw,
+            nenbw,
+            enbw,
         }
     }
+}
+
+/// Zeros appended to the windowed samples, the spectrum gets more, interpolated bins.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZeroPadding {
+    #[default]
+    None,
+    NextPowerOfTwo,
+    /// FFT size, not smaller than the spectrum width.
+    Size(usize),
+}
+
+impl ZeroPadding {
+    /// Size of the FFT for `spectrum_width` samples, always even.
+    pub fn get_fft_size(&self, spectrum_width: usize) -> usize {
+        let size = match self {
+            ZeroPadding::None => spectrum_width,
+            ZeroPadding::NextPowerOfTwo => spectrum_width.next_power_of_two(),
+            ZeroPadding::Size(size) => (*size).max(spectrum_width),
+        };
+        size.next_multiple_of(2)
+    }
+}
 
-    fn generate_hanning_window(width: usize) -> Vec<f32> {
-        apodize::nuttall_iter(width)
-            .map(|v| v as f32)
-            .collect::<Vec<f32>>()
+impl Display for ZeroPadding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ZeroPadding::None => write!(f, "None"),
+            ZeroPadding::NextPowerOfTwo => write!(f, "Next power of two"),
+            ZeroPadding::Size(size) => write!(f, "{size} points"),
+        }
     }
 }
 
 pub struct FftAnalyzer {
-    spectrum_width: usize,
     window: Window,
-    fft: Arc<dyn Fft<f32>>,
-    work_buffer: Vec<Complex<f32>>,
+    scaling: SpectrumScaling,
+    fft: RealFft,
+    windowed: Vec<f32>,
+    output: Vec<Complex<f32>>,
 }
 
 impl FftAnalyzer {
-    pub fn new(spectrum_width: usize, sampling_frequency: usize) -> FftAnalyzer {
-        let mut planner = FftPlanner::<f32>::new();
-        let fft = planner.plan_fft_forward(spectrum_width);
-        let window = Window::new(spectrum_width, sampling_frequency);
+    /// Spectrums have `fft_size / 2` bins, `fft_size` is at least `spectrum_width`.
+    pub fn new(
+        spectrum_width: usize,
+        fft_size: usize,
+        sampling_frequency: usize,
+        window: WindowFunction,
+        scaling: SpectrumScaling,
+    ) -> FftAnalyzer {
+        let fft = RealFft::new(fft_size.max(spectrum_width));
+        let window = Window::new(window, spectrum_width, sampling_frequency);
 
         FftAnalyzer {
-            spectrum_width,
             window,
+            scaling,
+            output: vec![Complex::default(); fft.get_size() / 2 + 1],
             fft,
-            work_buffer: vec![Complex { re: 0.0, im: 0.0 }; spectrum_width],
+            windowed: vec![0.0; spectrum_width],
         }
     }
 
+    /// Normalized equivalent noise bandwidth of the window, in bins.
+    pub fn get_nenbw(&self) -> f32 {
+        self.window.nenbw
+    }
+
     pub fn analyze(&mut self, new_samples: &ChannelSamples) -> Spectrum {
+        let mut spectrum = Spectrum::new();
+        self.analyze_into(new_samples, &mut spectrum);
+        spectrum
+    }
+
+    /// Like `analyze`, but reuses memory of `spectrum`.
+    pub fn analyze_into(&mut self, new_samples: &ChannelSamples, spectrum: &mut Spectrum) {
         trace!(
             "Calculating FFT of new samples. Sample count {}",
             new_samples.inner().len()
         );
 
-        for i in 0..self.spectrum_width {
-            self.work_buffer[i].re = new_samples[i] * self.window.weights[i];
-            self.work_buffer[i].im = 0.0;
+        for (i, value) in self.windowed.iter_mut().enumerate() {
+            *value = new_samples[i] * self.window.weights[i];
         }
 
-        self.fft.process(&mut self.work_buffer);
+        self.fft.process(&self.windowed, &mut self.output);
+
+        // one sided amplitude, the negative frequencies are folded onto the positive ones
+        let amplitude = 2.0 / self.window.sum_window;
+        // power of a sine is amplitude^2 / 2, spread over the ENBW
+        let scale = match self.scaling {
+            SpectrumScaling::Amplitude => amplitude,
+            SpectrumScaling::PowerSpectralDensity => amplitude / (2.0 * self.window.enbw).sqrt(),
+        };
+        let bins = self.fft.get_size() / 2;
+        spectrum.data.clear();
+        spectrum.data.extend(
+            self.output[..bins]
+                .iter()
+                .map(|number| number.norm() * scale),
+        );
+        spectrum.data[0] = 0.0;
+    }
+}
 
-        let mut spectrum: Spectrum = self
-            .work_buffer
-            .iter()
-            .map(|number| number.norm() / self.window.sum_window)
-            .take(self.spectrum_width / 2)
-            .collect();
-        spectrum[0] = 0.0;
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
 
-        spectrum
+    use super::*;
+    use crate::audio::{
+        file_decoder::AudioFileDecoder,
+        input_conditioning::gain_to_db,
+        signal_generator::{Signal, SignalGenerator},
+        StreamParameters,
+    };
+
+    const SAMPLE_RATE: usize = 48000;
+    const WIDTH: usize = 4800;
+    const BIN_WIDTH: f32 = SAMPLE_RATE as f32 / WIDTH as f32;
+
+    fn generator(signal: Signal) -> SignalGenerator {
+        generator_with_amplitude(signal, 0.5)
+    }
+
+    fn generator_with_amplitude(signal: Signal, amplitude: f32) -> SignalGenerator {
+        SignalGenerator::new(
+            signal,
+            amplitude,
+            StreamParameters {
+                sample_rate: SAMPLE_RATE as u32,
+                channels: 1,
+            },
+        )
+    }
+
+    fn analyzer(window: WindowFunction, scaling: SpectrumScaling) -> FftAnalyzer {
+        FftAnalyzer::new(WIDTH, WIDTH, SAMPLE_RATE, window, scaling)
+    }
+
+    fn analyze(generator: &mut SignalGenerator) -> Spectrum {
+        analyzer(WindowFunction::default(), SpectrumScaling::default())
+            .analyze(&generator.generate(WIDTH).into())
+    }
+
+    fn peak(spectrum: &Spectrum) -> usize {
+        (0..spectrum.len())
+            .max_by(|a, b| spectrum[*a].total_cmp(&spectrum[*b]))
+            .unwrap()
+    }
+
+    /// Power summed over bins of [low, high) Hz, averaged over `windows` spectrums.
+    fn band_power(signal: Signal, bands: &[(f32, f32)], windows: usize) -> Vec<f32> {
+        let mut generator = generator(signal);
+        let mut analyzer = analyzer(WindowFunction::default(), SpectrumScaling::default());
+        let mut power = vec![0.0; bands.len()];
+        for _ in 0..windows {
+            let spectrum = analyzer.analyze(&generator.generate(WIDTH).into());
+            for (band, (low, high)) in bands.iter().enumerate() {
+                let bins = (*low / BIN_WIDTH) as usize..(*high / BIN_WIDTH) as usize;
+                power[band] += bins.map(|bin| spectrum[bin] * spectrum[bin]).sum::<f32>();
+            }
+        }
+        power
+    }
+
+    #[test]
+    fn test_sine_amplitude() {
+        let spectrum = analyze(&mut generator(Signal::MultiTone {
+            frequencies: vec![1000.0, 5000.0],
+        }));
+
+        // every tone has 0.25 amplitude
+        for frequency in [1000.0, 5000.0] {
+            let bin = (frequency / BIN_WIDTH) as usize;
+            assert!((spectrum[bin] - 0.25).abs() < 0.001, "{}", spectrum[bin]);
+        }
+        assert_eq!(spectrum.len(), WIDTH / 2);
+    }
+
+    #[test]
+    fn test_full_scale_sine_is_0_dbfs() {
+        for window in WindowFunction::ALL {
+            let mut sine = generator_with_amplitude(Signal::Sine { frequency: 1000.0 }, 1.0);
+            let spectrum =
+                analyzer(window, SpectrumScaling::Amplitude).analyze(&sine.generate(WIDTH).into());
+
+            let level = gain_to_db(spectrum[(1000.0 / BIN_WIDTH) as usize]);
+            assert!(level.abs() < 0.01, "{window}: {level} dBFS");
+        }
+    }
+
+    #[test]
+    fn test_flat_top_between_bins() {
+        let level = |window| {
+            let mut sine = generator_with_amplitude(Signal::Sine { frequency: 1005.0 }, 1.0);
+            let spectrum =
+                analyzer(window, SpectrumScaling::Amplitude).analyze(&sine.generate(WIDTH).into());
+            gain_to_db(spectrum[peak(&spectrum)])
+        };
+
+        let flat_top = level(WindowFunction::FlatTop);
+        assert!(flat_top.abs() < 0.02, "{flat_top}");
+        // scalloping loss of Hann is 1.42 dB
+        let hann = level(WindowFunction::Hann);
+        assert!((-1.5..-1.3).contains(&hann), "{hann}");
+    }
+
+    #[test]
+    fn test_power_spectral_density() {
+        for window in WindowFunction::ALL {
+            let mut noise = generator(Signal::WhiteNoise);
+            let mut analyzer = analyzer(window, SpectrumScaling::PowerSpectralDensity);
+            let (mut signal_power, mut spectrum_power) = (0.0, 0.0);
+            for _ in 0..20 {
+                let samples = noise.generate(WIDTH);
+                signal_power += samples.iter().map(|v| v * v).sum::<f32>() / WIDTH as f32;
+                let spectrum = analyzer.analyze(&samples.into());
+                spectrum_power += spectrum.into_iter().map(|v| v * v).sum::<f32>() * BIN_WIDTH;
+            }
+
+            let ratio = spectrum_power / signal_power;
+            assert!((0.9..1.1).contains(&ratio), "{window}: {ratio}");
+        }
+    }
+
+    #[test]
+    fn test_zero_padding() {
+        assert_eq!(ZeroPadding::None.get_fft_size(4801), 4802);
+        assert_eq!(ZeroPadding::NextPowerOfTwo.get_fft_size(4800), 8192);
+        assert_eq!(ZeroPadding::Size(1024).get_fft_size(4800), 4800);
+
+        let fft_size = ZeroPadding::Size(4 * WIDTH).get_fft_size(WIDTH);
+        let mut analyzer = FftAnalyzer::new(
+            WIDTH,
+            fft_size,
+            SAMPLE_RATE,
+            WindowFunction::default(),
+            SpectrumScaling::Amplitude,
+        );
+        let mut sine = generator(Signal::Sine { frequency: 1003.0 });
+        let spectrum = analyzer.analyze(&sine.generate(WIDTH).into());
+        assert_eq!(spectrum.len(), fft_size / 2);
+
+        // 2.5 Hz bins instead of 10 Hz, the level doesn't depend on the padding
+        let bin_width = SAMPLE_RATE as f32 / fft_size as f32;
+        let frequency = peak(&spectrum) as f32 * bin_width;
+        assert!((frequency - 1003.0).abs() <= bin_width / 2.0, "{frequency}");
+        assert!((spectrum[peak(&spectrum)] - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_analyze_into_reuses_spectrum() {
+        let mut analyzer = analyzer(WindowFunction::default(), SpectrumScaling::default());
+        let mut sine = generator(Signal::Sine { frequency: 1000.0 });
+        let mut spectrum = Spectrum::new();
+        analyzer.analyze_into(&sine.generate(WIDTH).into(), &mut spectrum);
+        let data = spectrum.as_slice().as_ptr();
+
+        analyzer.analyze_into(&sine.generate(WIDTH).into(), &mut spectrum);
+        assert_eq!(spectrum.as_slice().as_ptr(), data);
+        assert!((spectrum[100] - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_kaiser_window() {
+        assert_eq!(
+            WindowFunction::Kaiser { beta: 0.0 }.generate(8),
+            WindowFunction::Rectangular.generate(8)
+        );
+        let window = WindowFunction::Kaiser { beta: 8.6 }.generate(9);
+        assert!((window[4] - 1.0).abs() < 1e-6);
+        assert!((window[0] - 1.0 / bessel_i0(8.6) as f32).abs() < 1e-6);
+        assert!((bessel_i0(1.0) - 1.2660658777520082).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_sweep_frequency() {
+        let mut sweep = generator(Signal::Sweep {
+            start_frequency: 100.0,
+            end_frequency: 10000.0,
+            duration: Duration::from_secs(10),
+        });
+        // 1 kHz in the middle of the logarithmic sweep
+        sweep
+            .seek(5 * SAMPLE_RATE as u64 - WIDTH as u64 / 2)
+            .unwrap();
+        let frequency = peak(&analyze(&mut sweep)) as f32 * BIN_WIDTH;
+        assert!((frequency - 1000.0).abs() <= 30.0, "{frequency}");
+    }
+
+    #[test]
+    fn test_impulse_is_flat() {
+        let mut impulses = generator(Signal::ImpulseTrain {
+            period: Duration::from_secs_f32(WIDTH as f32 / SAMPLE_RATE as f32),
+        });
+        // impulse in the middle of the window, where the window weight is the highest
+        impulses.seek(WIDTH as u64 / 2).unwrap();
+        let spectrum = analyze(&mut impulses);
+
+        let values = &spectrum.as_slice()[1..];
+        let max = values.iter().cloned().fold(f32::MIN, f32::max);
+        let min = values.iter().cloned().fold(f32::MAX, f32::min);
+        assert!(max / min < 1.01, "{min} - {max}");
+    }
+
+    #[test]
+    fn test_noise_octave_power() {
+        let octaves = [(1000.0, 2000.0), (8000.0, 16000.0)];
+
+        // equal power per octave
+        let pink = band_power(Signal::PinkNoise, &octaves, 50);
+        let ratio = pink[1] / pink[0];
+        assert!((0.7..1.4).contains(&ratio), "{ratio}");
+
+        // power grows with bandwidth
+        let white = band_power(Signal::WhiteNoise, &octaves, 50);
+        let ratio = white[1] / white[0];
+        assert!((6.0..10.0).contains(&ratio), "{ratio}");
     }
 }
@@ -0,0 +1,239 @@
+use std::fmt::Display;
+
+use super::{MultiChannel, Spectrum};
+
+/// Frequency weighting curves of IEC 61672, normalized to 0 dB at 1 kHz.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FrequencyWeighting {
+    /// Loudness of quiet sounds, low and very high frequencies are attenuated.
+    A,
+    /// Loudness of loud sounds, nearly flat between 31.5 Hz and 8 kHz.
+    C,
+    /// No weighting.
+    #[default]
+    Z,
+}
+
+impl FrequencyWeighting {
+    pub const ALL: [FrequencyWeighting; 3] = [
+        FrequencyWeighting::A,
+        FrequencyWeighting::C,
+        FrequencyWeighting::Z,
+    ];
+
+    /// Amplitude gain at `frequency` in Hz.
+    pub fn get_gain(&self, frequency: f32) -> f32 {
+        match self {
+            FrequencyWeighting::Z => 1.0,
+            _ => (self.response(frequency as f64) / self.response(1000.0)) as f32,
+        }
+    }
+
+    /// Gains of bins centered at `frequencies`.
+    pub fn generate(&self, frequencies: &[f32]) -> Vec<f32> {
+        frequencies
+            .iter()
+            .map(|&frequency| self.get_gain(frequency))
+            .collect()
+    }
+
+    fn response(&self, frequency: f64) -> f64 {
+        let f2 = frequency * frequency;
+        let c = (f2 + 20.6f64.powi(2)) * (f2 + 12194.0f64.powi(2));
+        match self {
+            FrequencyWeighting::A => {
+                12194.0f64.powi(2) * f2 * f2
+                    / (c * ((f2 + 107.7f64.powi(2)) * (f2 + 737.9f64.powi(2))).sqrt())
+            }
+            FrequencyWeighting::C => 12194.0f64.powi(2) * f2 / c,
+            FrequencyWeighting::Z => 1.0,
+        }
+    }
+}
+
+impl Display for FrequencyWeighting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrequencyWeighting::A => write!(f, "A"),
+            FrequencyWeighting::C => write!(f, "C"),
+            FrequencyWeighting::Z => write!(f, "Z (none)"),
+        }
+    }
+}
+
+/// Units of the magnitudes, amplitude spectrums in decibels are dBFS.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MagnitudeScale {
+    #[default]
+    Linear,
+    /// Magnitudes below `floor` dB are clamped to it.
+    Decibels { floor: f32 },
+}
+
+impl MagnitudeScale {
+    pub const DEFAULT_FLOOR: f32 = -100.0;
+
+    pub fn apply(&self, magnitude: f32) -> f32 {
+        match self {
+            MagnitudeScale::Linear => magnitude,
+            MagnitudeScale::Decibels { floor } => (20.0 * magnitude.log10()).max(*floor),
+        }
+    }
+
+    /// Fixed range of the magnitudes, linear ones have none.
+    pub fn get_range(&self) -> Option<(f32, f32)> {
+        match self {
+            MagnitudeScale::Linear => None,
+            MagnitudeScale::Decibels { floor } => Some((*floor, 0.0)),
+        }
+    }
+}
+
+impl Display for MagnitudeScale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MagnitudeScale::Linear => write!(f, "Linear"),
+            MagnitudeScale::Decibels { floor } => write!(f, "dBFS (floor {floor:.0} dB)"),
+        }
+    }
+}
+
+/// Weighting and units of the spectrums delivered to one output.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpectrumFormat {
+    pub weighting: FrequencyWeighting,
+    pub scale: MagnitudeScale,
+}
+
+impl SpectrumFormat {
+    pub fn new(weighting: FrequencyWeighting, scale: MagnitudeScale) -> Self {
+        Self { weighting, scale }
+    }
+}
+
+/// Converts the analyzer spectrums into the format of one output, buffers are reused.
+pub struct SpectrumOutput {
+    format: SpectrumFormat,
+    gains: Vec<f32>,
+    /// Bin frequencies the gains were generated for.
+    frequencies: Vec<f32>,
+    spectrums: MultiChannel<Spectrum>,
+}
+
+impl SpectrumOutput {
+    pub fn new(format: SpectrumFormat) -> Self {
+        Self {
+            format,
+            gains: Vec::new(),
+            frequencies: Vec::new(),
+            spectrums: MultiChannel::new(0, Spectrum::new()),
+        }
+    }
+
+    pub fn get_format(&self) -> SpectrumFormat {
+        self.format
+    }
+
+    pub fn set_format(&mut self, format: SpectrumFormat) {
+        if format != self.format {
+            self.format = format;
+            self.gains.clear();
+        }
+    }
+
+    /// `frequencies` are the centers of the spectrum bins. Linear, unweighted spectrums are
+    /// passed through without a copy.
+    pub fn convert<'a>(
+        &'a mut self,
+        spectrums: &'a MultiChannel<Spectrum>,
+        frequencies: &[f32],
+    ) -> &'a MultiChannel<Spectrum> {
+        if self.format == SpectrumFormat::default() {
+            return spectrums;
+        }
+
+        if self.gains.len() != frequencies.len() || self.frequencies != frequencies {
+            self.gains = self.format.weighting.generate(frequencies);
+            self.frequencies = frequencies.to_vec();
+        }
+
+        let outputs = self.spectrums.inner_mut();
+        outputs.resize_with(spectrums.len(), Spectrum::new);
+        for (output, spectrum) in outputs.iter_mut().zip(spectrums) {
+            output.copy_from(spectrum);
+            output
+                .into_iter()
+                .zip(self.gains.iter())
+                .for_each(|(magnitude, gain)| {
+                    *magnitude = self.format.scale.apply(*magnitude * gain);
+                });
+        }
+        &self.spectrums
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gain_db(weighting: FrequencyWeighting, frequency: f32) -> f32 {
+        20.0 * weighting.get_gain(frequency).log10()
+    }
+
+    #[test]
+    fn test_weighting_curves() {
+        // IEC 61672-1 table values, rounded and for nominal frequencies
+        for (frequency, a, c) in [
+            (31.5, -39.4, -3.0),
+            (100.0, -19.1, -0.3),
+            (1000.0, 0.0, 0.0),
+            (4000.0, 1.0, -0.8),
+            (10000.0, -2.5, -4.4),
+        ] {
+            assert!((gain_db(FrequencyWeighting::A, frequency) - a).abs() < 0.15);
+            assert!((gain_db(FrequencyWeighting::C, frequency) - c).abs() < 0.15);
+            assert_eq!(FrequencyWeighting::Z.get_gain(frequency), 1.0);
+        }
+        assert_eq!(FrequencyWeighting::A.get_gain(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_decibels() {
+        let scale = MagnitudeScale::Decibels { floor: -80.0 };
+        assert_eq!(scale.apply(1.0), 0.0);
+        assert!((scale.apply(0.5) + 6.0206).abs() < 1e-3);
+        assert_eq!(scale.apply(1e-6), -80.0);
+        assert_eq!(scale.apply(0.0), -80.0);
+        assert_eq!(scale.get_range(), Some((-80.0, 0.0)));
+        assert_eq!(MagnitudeScale::Linear.apply(0.5), 0.5);
+    }
+
+    #[test]
+    fn test_spectrum_output() {
+        let spectrums: MultiChannel<Spectrum> =
+            vec![Spectrum::from(vec![1.0; 4]), Spectrum::from(vec![0.1; 4])].into();
+
+        let frequencies = [0.0, 500.0, 1000.0, 1500.0];
+        let mut output = SpectrumOutput::new(SpectrumFormat::default());
+        assert!(std::ptr::eq(
+            output.convert(&spectrums, &frequencies),
+            &spectrums
+        ));
+
+        output.set_format(SpectrumFormat::new(
+            FrequencyWeighting::A,
+            MagnitudeScale::Decibels { floor: -100.0 },
+        ));
+        let converted = output.convert(&spectrums, &frequencies);
+        assert_eq!(converted.len(), 2);
+        assert_eq!(converted.get_channel(0)[0], -100.0);
+        assert!((converted.get_channel(0)[2]).abs() < 1e-4);
+        assert!((converted.get_channel(1)[2] + 20.0).abs() < 1e-4);
+        assert!(converted.get_channel(0)[1] < 0.0);
+
+        // gains follow the frequencies
+        let converted = output.convert(&spectrums, &[27.5, 55.0, 110.0, 220.0]);
+        let expected = gain_db(FrequencyWeighting::A, 220.0);
+        assert!((converted.get_channel(0)[3] - expected).abs() < 1e-4);
+    }
+}
@@ -1,36 +1,136 @@
 use std::{
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use log::{info, trace};
 
-use crate::audio::{AudioBuffer, AudioStreamConsumer, StreamParameters};
+use crate::audio::{
+    input_conditioning::{ConditioningSettings, ConditioningStatus, InputConditioning},
+    statistics::{BufferStatistics, DurationStatistics},
+    AudioBuffer, AudioStreamConsumer, StreamParameters, Timestamp,
+};
 
 use super::{
-    AnalyzerParameters, FftAnalyzer, Magnitude, MelFilterBank, MultiChannel, Spectrogram, Spectrum,
-    TimeSeries,
+    AnalyzerParameters, ChannelRouter, ChannelRouting, ConstantQBank, FftAnalyzer, Magnitude,
+    MelFilterBank, MultiChannel, OctaveBandAnalyzer, Spectrogram, Spectrum, SpectrumFormat,
+    SpectrumOutput, SpectrumScaling, TimeSeries, WindowFunction,
 };
 
 pub trait StreamAnalyzerReceiver: Send {
     fn receive(&mut self, spectrums: &MultiChannel<Spectrum>);
+
+    /// Constant-Q spectrums of the same frame, received after the linear ones.
+    fn receive_constant_q(&mut self, _spectrums: &MultiChannel<Spectrum>) {}
+
+    /// Mel spectrums of the same frame, received after the constant-Q ones.
+    fn receive_mel(&mut self, _spectrums: &MultiChannel<Spectrum>) {}
+
+    /// Fractional-octave band levels after the frame, `frequencies` are the mid-band ones.
+    fn receive_octave_bands(&mut self, _levels: &MultiChannel<Spectrum>, _frequencies: &[f32]) {}
+
+    /// Weighting and units of the received spectrums, asked for before each frame.
+    fn get_spectrum_format(&self) -> SpectrumFormat {
+        SpectrumFormat::default()
+    }
+}
+
+/// Registered receiver with the spectrums converted to the format it asks for.
+struct ReceiverOutput {
+    receiver: Arc<Mutex<dyn StreamAnalyzerReceiver>>,
+    spectrums: SpectrumOutput,
+    constant_q: SpectrumOutput,
+    mel: SpectrumOutput,
+    octave_bands: SpectrumOutput,
+}
+
+/// Health of the analysis, processing of a frame has to fit into the refresh time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AnalyzerStatistics {
+    /// Time spent on one frame, all channels, the mel bank and receivers included.
+    pub processing: DurationStatistics,
+    pub budget: Duration,
+    pub buffer: BufferStatistics,
+}
+
+impl AnalyzerStatistics {
+    /// Average processing time relative to the refresh time.
+    pub fn get_load(&self) -> f32 {
+        if self.budget.is_zero() {
+            return 0.0;
+        }
+        self.processing.average.as_secs_f32() / self.budget.as_secs_f32()
+    }
 }
 
 pub struct StreamAnalyzer {
     audio_buffer: Arc<Mutex<AudioBuffer>>,
+    stream_parameters: Arc<StreamParameters>,
+    channel_router: ChannelRouter,
+    conditioning_settings: ConditioningSettings,
     analyzer_parameters: Arc<AnalyzerParameters>,
     spectrum_analyzer: FftAnalyzer,
     spectrogram: Spectrogram,
+    /// Output of the latest frame, reused by the next one.
+    spectrums: MultiChannel<Spectrum>,
+    bin_frequencies: Vec<f32>,
     mel_filter_bank: MelFilterBank,
     mel_spectrums: MultiChannel<Spectrum>,
-    receivers: Vec<Arc<Mutex<dyn StreamAnalyzerReceiver>>>,
+    mel_spectrogram: Spectrogram,
+    constant_q_bank: ConstantQBank,
+    constant_q_spectrums: MultiChannel<Spectrum>,
+    constant_q_spectrogram: Spectrogram,
+    /// Format of the spectrograms and latest spectrums shown by the UI.
+    display: SpectrumOutput,
+    constant_q_display: SpectrumOutput,
+    mel_display: SpectrumOutput,
+    /// Filter banks of the routed channels, fed with the new samples of every frame.
+    octave_band_analyzers: Vec<OctaveBandAnalyzer>,
+    octave_band_frequencies: Vec<f32>,
+    octave_band_levels: MultiChannel<Spectrum>,
+    receivers: Vec<ReceiverOutput>,
+    processing: DurationStatistics,
     is_alive: bool,
 }
 
 pub trait AudioAnalyzysProvider {
     fn get_analyzer_parameters(&self) -> Arc<AnalyzerParameters>;
     fn get_latest_spectrum(&self) -> MultiChannel<Spectrum>;
+    /// Capture time of the newest sample of the latest spectrum.
+    fn get_latest_timestamp(&self) -> Timestamp;
     fn get_spectrogram_for_channel(&self, channel: usize) -> (TimeSeries<Magnitude>, (u32, u32));
+    fn get_latest_constant_q_spectrum(&self) -> MultiChannel<Spectrum>;
+    fn get_constant_q_spectrogram_for_channel(
+        &self,
+        channel: usize,
+    ) -> (TimeSeries<Magnitude>, (u32, u32));
+    /// Center frequencies of the constant-Q bands, in Hz.
+    fn get_constant_q_frequencies(&self) -> Vec<f32>;
+    fn get_latest_mel_spectrum(&self) -> MultiChannel<Spectrum>;
+    fn get_mel_spectrogram_for_channel(
+        &self,
+        channel: usize,
+    ) -> (TimeSeries<Magnitude>, (u32, u32));
+    /// Center frequencies of the mel filters, in Hz.
+    fn get_mel_frequencies(&self) -> Vec<f32>;
+    /// Fractional-octave band levels of the latest frame, unweighted and linear.
+    fn get_latest_octave_bands(&self) -> MultiChannel<Spectrum>;
+    /// Mid-band frequencies of the octave bands, in Hz.
+    fn get_octave_band_frequencies(&self) -> Vec<f32>;
+    /// Names of the analyzed channels, their count may differ from the stream.
+    fn get_channel_names(&self) -> Vec<String>;
+    fn get_input_channels(&self) -> usize;
+    fn get_channel_routing(&self) -> ChannelRouting;
+    fn set_channel_routing(&mut self, routing: ChannelRouting);
+    fn get_conditioning_settings(&self) -> ConditioningSettings;
+    fn set_conditioning_settings(&mut self, settings: ConditioningSettings);
+    fn get_conditioning_status(&self) -> ConditioningStatus;
+    fn set_spectrum_scaling(&mut self, window: WindowFunction, scaling: SpectrumScaling);
+    fn get_display_format(&self) -> SpectrumFormat;
+    fn set_display_format(&mut self, format: SpectrumFormat);
+    fn reconfigure(&mut self, parameters: AnalyzerParameters) -> Result<(), String>;
+    fn get_statistics(&self) -> AnalyzerStatistics;
+    fn reset_statistics(&mut self);
 }
 
 impl AudioStreamConsumer for StreamAnalyzer {
@@ -38,38 +138,96 @@ impl AudioStreamConsumer for StreamAnalyzer {
         let total_sample_count = self.analyzer_parameters.spectrum_width;
         let new_samples = self.analyzer_parameters.refresh_time_in_samples;
 
+        self.audio_buffer.lock().unwrap().fetch();
         while self.audio_buffer.lock().unwrap().get_new_samples_count() >= new_samples {
-            if let Ok(new_multichannel_samples) = self
+            if let Ok((new_multichannel_samples, timestamp)) = self
                 .audio_buffer
                 .lock()
                 .unwrap()
                 .read_new_samples(new_samples, total_sample_count)
             {
+                let start = Instant::now();
                 trace!(
                     "Reading {} samples for all channels, with new samples: {}",
                     total_sample_count,
                     new_samples
                 );
-                let mut spectrums: Vec<Spectrum> = vec![];
-
-                new_multichannel_samples
+                let routed = self.channel_router.route(new_multichannel_samples);
+                let spectrums = self.spectrums.inner_mut();
+                spectrums.resize_with(routed.len(), Spectrum::new);
+                for (channel, ((samples, spectrum), octave_bands)) in routed
                     .into_iter()
+                    .zip(spectrums.iter_mut())
+                    .zip(self.octave_band_analyzers.iter_mut())
                     .enumerate()
-                    .for_each(|(channel, samples)| {
-                        trace!("Processing samples for channel: {}", channel);
-                        spectrums.push(self.spectrum_analyzer.analyze(&samples));
-                    });
+                {
+                    trace!("Processing samples for channel: {}", channel);
+                    self.spectrum_analyzer.analyze_into(&samples, spectrum);
+                    spectrum.set_timestamp(timestamp);
+                    // filters continue from the previous frame, only new samples are fed
+                    let samples = samples.inner();
+                    octave_bands.process(&samples[samples.len() - new_samples..]);
+                }
+                self.octave_band_levels = self
+                    .octave_band_analyzers
+                    .iter()
+                    .map(|octave_bands| octave_bands.get_levels().with_timestamp(timestamp))
+                    .collect::<Vec<Spectrum>>()
+                    .into();
 
-                self.spectrogram.push_spectrums(spectrums.clone().into());
-                self.mel_spectrums = spectrums
+                self.spectrogram
+                    .push_spectrums(self.display.convert(&self.spectrums, &self.bin_frequencies));
+                self.mel_spectrums = self
+                    .spectrums
+                    .channels
                     .iter()
                     .map(|spectrum| self.mel_filter_bank.apply(spectrum))
                     .collect::<Vec<Spectrum>>()
                     .into();
+                let mel_frequencies = self.mel_filter_bank.get_frequencies();
+                self.mel_spectrogram.push_spectrums(
+                    self.mel_display
+                        .convert(&self.mel_spectrums, mel_frequencies),
+                );
+                self.constant_q_spectrums = self
+                    .spectrums
+                    .channels
+                    .iter()
+                    .map(|spectrum| self.constant_q_bank.apply(spectrum))
+                    .collect::<Vec<Spectrum>>()
+                    .into();
+                let constant_q_frequencies = self.constant_q_bank.get_frequencies();
+                self.constant_q_spectrogram.push_spectrums(
+                    self.constant_q_display
+                        .convert(&self.constant_q_spectrums, constant_q_frequencies),
+                );
 
-                self.receivers.iter().for_each(|receiver| {
-                    receiver.lock().unwrap().receive(&spectrums.clone().into());
-                });
+                for output in self.receivers.iter_mut() {
+                    let mut receiver = output.receiver.lock().unwrap();
+                    let format = receiver.get_spectrum_format();
+                    output.spectrums.set_format(format);
+                    output.constant_q.set_format(format);
+                    output.mel.set_format(format);
+                    output.octave_bands.set_format(format);
+                    receiver.receive(
+                        output
+                            .spectrums
+                            .convert(&self.spectrums, &self.bin_frequencies),
+                    );
+                    receiver.receive_constant_q(
+                        output
+                            .constant_q
+                            .convert(&self.constant_q_spectrums, constant_q_frequencies),
+                    );
+                    receiver.receive_mel(output.mel.convert(&self.mel_spectrums, mel_frequencies));
+                    receiver.receive_octave_bands(
+                        output
+                            .octave_bands
+                            .convert(&self.octave_band_levels, &self.octave_band_frequencies),
+                        &self.octave_band_frequencies,
+                    );
+                }
+                self.processing.push(start.elapsed());
             }
         }
     }
@@ -81,6 +239,10 @@ impl AudioStreamConsumer for StreamAnalyzer {
     fn get_name(&self) -> String {
         String::from("Stream spectrum analyzer")
     }
+
+    fn on_stream_parameters_changed(&mut self, parameters: Arc<StreamParameters>) {
+        self.set_stream_parameters(parameters);
+    }
 }
 
 impl AudioAnalyzysProvider for StreamAnalyzer {
@@ -92,14 +254,117 @@ impl AudioAnalyzysProvider for StreamAnalyzer {
         self.spectrogram.get_latest_spectrum()
     }
 
+    fn get_latest_timestamp(&self) -> Timestamp {
+        self.spectrogram.get_latest_timestamp()
+    }
+
     fn get_spectrogram_for_channel(&self, channel: usize) -> (TimeSeries<Magnitude>, (u32, u32)) {
         self.spectrogram.get_spectrogram_for_channel(channel)
     }
+
+    fn get_latest_constant_q_spectrum(&self) -> MultiChannel<Spectrum> {
+        self.constant_q_spectrogram.get_latest_spectrum()
+    }
+
+    fn get_constant_q_spectrogram_for_channel(
+        &self,
+        channel: usize,
+    ) -> (TimeSeries<Magnitude>, (u32, u32)) {
+        self.constant_q_spectrogram
+            .get_spectrogram_for_channel(channel)
+    }
+
+    fn get_constant_q_frequencies(&self) -> Vec<f32> {
+        self.constant_q_bank.get_frequencies().to_vec()
+    }
+
+    fn get_latest_mel_spectrum(&self) -> MultiChannel<Spectrum> {
+        self.mel_spectrogram.get_latest_spectrum()
+    }
+
+    fn get_mel_spectrogram_for_channel(
+        &self,
+        channel: usize,
+    ) -> (TimeSeries<Magnitude>, (u32, u32)) {
+        self.mel_spectrogram.get_spectrogram_for_channel(channel)
+    }
+
+    fn get_mel_frequencies(&self) -> Vec<f32> {
+        self.mel_filter_bank.get_frequencies().to_vec()
+    }
+
+    fn get_latest_octave_bands(&self) -> MultiChannel<Spectrum> {
+        self.octave_band_levels.clone()
+    }
+
+    fn get_octave_band_frequencies(&self) -> Vec<f32> {
+        self.octave_band_frequencies.clone()
+    }
+
+    fn get_channel_names(&self) -> Vec<String> {
+        self.channel_router.get_channel_names()
+    }
+
+    fn get_input_channels(&self) -> usize {
+        self.stream_parameters.channels as usize
+    }
+
+    fn get_channel_routing(&self) -> ChannelRouting {
+        self.channel_router.get_routing().clone()
+    }
+
+    fn set_channel_routing(&mut self, routing: ChannelRouting) {
+        self.set_channel_routing(routing);
+    }
+
+    fn get_conditioning_settings(&self) -> ConditioningSettings {
+        self.conditioning_settings.clone()
+    }
+
+    fn set_conditioning_settings(&mut self, settings: ConditioningSettings) {
+        self.set_conditioning_settings(settings);
+    }
+
+    fn get_conditioning_status(&self) -> ConditioningStatus {
+        self.audio_buffer
+            .lock()
+            .unwrap()
+            .get_conditioning()
+            .map(|conditioning| conditioning.get_status())
+            .unwrap_or_default()
+    }
+
+    fn set_spectrum_scaling(&mut self, window: WindowFunction, scaling: SpectrumScaling) {
+        self.set_spectrum_scaling(window, scaling);
+    }
+
+    fn get_display_format(&self) -> SpectrumFormat {
+        self.display.get_format()
+    }
+
+    fn set_display_format(&mut self, format: SpectrumFormat) {
+        self.set_display_format(format);
+    }
+
+    fn reconfigure(&mut self, parameters: AnalyzerParameters) -> Result<(), String> {
+        self.reconfigure(parameters)
+    }
+
+    fn get_statistics(&self) -> AnalyzerStatistics {
+        AnalyzerStatistics {
+            processing: self.processing,
+            budget: self.analyzer_parameters.refresh_time,
+            buffer: self.audio_buffer.lock().unwrap().get_statistics(),
+        }
+    }
+
+    fn reset_statistics(&mut self) {
+        self.processing = DurationStatistics::default();
+        self.audio_buffer.lock().unwrap().reset_statistics();
+    }
 }
 
 impl StreamAnalyzer {
-    const NUM_OF_MEL_FILTERS: usize = 40;
-
     pub fn new(
         refresh_time: Duration,
         buffer_duration: Duration,
@@ -108,41 +373,76 @@ impl StreamAnalyzer {
     ) -> StreamAnalyzer {
         info!("Creating new StreamAnalyzer with: {stream_parameters}");
 
-        let refresh_time_in_samples =
-            (stream_parameters.sample_rate as f32 * refresh_time.as_secs_f32()) as usize;
-
-        let number_of_spectrums_in_history =
-            (buffer_duration.as_secs_f32() / refresh_time.as_secs_f32()) as usize;
-
-        let parameters = Arc::new(AnalyzerParameters {
+        let parameters = Arc::new(AnalyzerParameters::new(
             spectrum_width,
-            refresh_time_in_samples,
-            length_of_history: number_of_spectrums_in_history,
             refresh_time,
-            spectrogram_duration: buffer_duration,
-            sample_rate: stream_parameters.sample_rate,
-        });
+            buffer_duration,
+            stream_parameters.sample_rate,
+        ));
 
         let mel_filter_bank = MelFilterBank::new(
-            Self::NUM_OF_MEL_FILTERS,
-            parameters.spectrum_width,
+            &parameters.mel,
+            parameters.get_fft_size(),
             parameters.sample_rate as f32,
         );
 
+        let channel_router = ChannelRouter::new(
+            ChannelRouting::default(),
+            stream_parameters.channels as usize,
+        );
+
+        let conditioning_settings = ConditioningSettings::default();
+        let mut audio_buffer = AudioBuffer::new(stream_parameters.clone(), buffer_duration);
+        audio_buffer.set_conditioning(Some(InputConditioning::new(
+            conditioning_settings.clone(),
+            &stream_parameters,
+        )));
+
+        let spectrum_analyzer = FftAnalyzer::new(
+            spectrum_width,
+            parameters.get_fft_size(),
+            stream_parameters.sample_rate as usize,
+            parameters.window,
+            parameters.scaling,
+        );
+        let constant_q_bank = Self::build_constant_q_bank(&parameters, &spectrum_analyzer);
+        let octave_bands = OctaveBandAnalyzer::new(
+            &parameters.octave_bands,
+            stream_parameters.sample_rate as f32,
+        );
+
         StreamAnalyzer {
-            audio_buffer: Arc::new(Mutex::new(AudioBuffer::new(
-                stream_parameters.clone(),
-                buffer_duration,
-            ))),
+            audio_buffer: Arc::new(Mutex::new(audio_buffer)),
+            stream_parameters: stream_parameters.clone(),
+            conditioning_settings,
             analyzer_parameters: parameters.clone(),
-            spectrum_analyzer: FftAnalyzer::new(
-                spectrum_width,
-                stream_parameters.sample_rate as usize,
+            spectrum_analyzer,
+            spectrogram: Spectrogram::new(parameters.clone(), channel_router.get_output_channels()),
+            spectrums: MultiChannel::new(0, Spectrum::new()),
+            bin_frequencies: parameters.get_bin_frequencies(),
+            mel_spectrums: MultiChannel::new(channel_router.get_output_channels(), Spectrum::new()),
+            mel_spectrogram: Spectrogram::with_width(
+                parameters.clone(),
+                channel_router.get_output_channels(),
+                mel_filter_bank.get_frequencies().len(),
             ),
-            spectrogram: Spectrogram::new(parameters, stream_parameters.clone()),
             mel_filter_bank,
-            mel_spectrums: MultiChannel::new(stream_parameters.channels as usize, Spectrum::new()),
+            constant_q_spectrums: MultiChannel::new(0, Spectrum::new()),
+            constant_q_spectrogram: Spectrogram::with_width(
+                parameters,
+                channel_router.get_output_channels(),
+                constant_q_bank.get_frequencies().len(),
+            ),
+            constant_q_bank,
+            display: SpectrumOutput::new(SpectrumFormat::default()),
+            constant_q_display: SpectrumOutput::new(SpectrumFormat::default()),
+            mel_display: SpectrumOutput::new(SpectrumFormat::default()),
+            octave_band_analyzers: vec![octave_bands.clone(); channel_router.get_output_channels()],
+            octave_band_frequencies: octave_bands.get_frequencies().to_vec(),
+            octave_band_levels: MultiChannel::new(0, Spectrum::new()),
+            channel_router,
             receivers: vec![],
+            processing: DurationStatistics::default(),
             is_alive: true,
         }
     }
@@ -151,13 +451,181 @@ impl StreamAnalyzer {
         &mut self,
         stream_analyzer_receiver: Arc<Mutex<dyn StreamAnalyzerReceiver>>,
     ) {
-        self.receivers.push(stream_analyzer_receiver);
+        self.receivers.push(ReceiverOutput {
+            receiver: stream_analyzer_receiver,
+            spectrums: SpectrumOutput::new(SpectrumFormat::default()),
+            constant_q: SpectrumOutput::new(SpectrumFormat::default()),
+            mel: SpectrumOutput::new(SpectrumFormat::default()),
+            octave_bands: SpectrumOutput::new(SpectrumFormat::default()),
+        });
     }
 
     pub fn get_analyzer_parameters(&self) -> Arc<AnalyzerParameters> {
         self.analyzer_parameters.clone()
     }
 
+    /// Rebuilds buffers and history for a new stream. Registered receivers, channel routing,
+    /// input conditioning, spectrum scaling, zero padding, constant-Q range, mel and octave
+    /// bands and display format are preserved.
+    pub fn set_stream_parameters(&mut self, stream_parameters: Arc<StreamParameters>) {
+        let mut analyzer = Self::new(
+            self.analyzer_parameters.refresh_time,
+            self.analyzer_parameters.spectrogram_duration,
+            self.analyzer_parameters.spectrum_width,
+            stream_parameters,
+        );
+        analyzer.receivers = std::mem::take(&mut self.receivers);
+        analyzer.is_alive = self.is_alive;
+        analyzer.set_display_format(self.display.get_format());
+        analyzer.set_channel_routing(self.channel_router.get_routing().clone());
+        analyzer.set_conditioning_settings(self.conditioning_settings.clone());
+        analyzer.rebuild(
+            self.analyzer_parameters
+                .with_sample_rate(analyzer.stream_parameters.sample_rate),
+        );
+        *self = analyzer;
+    }
+
+    /// Changes analyzed channels, the history is cleared when their count changes.
+    /// The audio buffer is kept, so the stream stays connected.
+    pub fn set_channel_routing(&mut self, routing: ChannelRouting) {
+        info!("Channel routing: {routing}");
+        let channel_router = ChannelRouter::new(routing, self.stream_parameters.channels as usize);
+        let channels = channel_router.get_output_channels();
+        let changed = channels != self.channel_router.get_output_channels();
+        self.channel_router = channel_router;
+        if changed {
+            self.reset_spectrograms();
+            self.mel_spectrums = MultiChannel::new(channels, Spectrum::new());
+            self.reset_octave_bands();
+        }
+    }
+
+    /// Conditioning runs on samples entering the audio buffer, so filters see the stream
+    /// continuously and not the overlapping analysis windows. Its state is reset.
+    pub fn set_conditioning_settings(&mut self, settings: ConditioningSettings) {
+        info!("Input conditioning: {settings:?}");
+        self.audio_buffer
+            .lock()
+            .unwrap()
+            .set_conditioning(Some(InputConditioning::new(
+                settings.clone(),
+                &self.stream_parameters,
+            )));
+        self.conditioning_settings = settings;
+    }
+
+    /// Changes the window and units of the spectrums. The history is cleared, as it would
+    /// mix them.
+    pub fn set_spectrum_scaling(&mut self, window: WindowFunction, scaling: SpectrumScaling) {
+        info!("Spectrum window: {window}, scaling: {scaling}");
+        self.rebuild(AnalyzerParameters {
+            window,
+            scaling,
+            ..(*self.analyzer_parameters).clone()
+        });
+    }
+
+    /// Changes weighting and units of the spectrogram and latest spectrums. The history is
+    /// cleared, as it would mix them.
+    pub fn set_display_format(&mut self, format: SpectrumFormat) {
+        info!(
+            "Display format: {} weighting, {}",
+            format.weighting, format.scale
+        );
+        self.display.set_format(format);
+        self.constant_q_display.set_format(format);
+        self.mel_display.set_format(format);
+        self.reset_spectrograms();
+    }
+
+    /// Changes spectrum width, refresh time and history length while the stream is running.
+    /// Sample counts are derived again for the sample rate of the stream. The audio buffer
+    /// is resized in place, so the stream stays connected; the history is cleared.
+    pub fn reconfigure(&mut self, parameters: AnalyzerParameters) -> Result<(), String> {
+        let parameters = parameters.with_sample_rate(self.stream_parameters.sample_rate);
+        parameters.validate()?;
+        info!(
+            "Analyzer parameters: width {}, FFT size {}, refresh {} samples, history {:?}",
+            parameters.spectrum_width,
+            parameters.get_fft_size(),
+            parameters.refresh_time_in_samples,
+            parameters.spectrogram_duration
+        );
+
+        self.audio_buffer
+            .lock()
+            .unwrap()
+            .set_buffer_duration(parameters.spectrogram_duration);
+        self.rebuild(parameters);
+        Ok(())
+    }
+
+    /// New FFT plan, window, mel, constant-Q and octave banks and history for the parameters.
+    fn rebuild(&mut self, parameters: AnalyzerParameters) {
+        let parameters = Arc::new(parameters);
+        self.spectrum_analyzer = FftAnalyzer::new(
+            parameters.spectrum_width,
+            parameters.get_fft_size(),
+            parameters.sample_rate as usize,
+            parameters.window,
+            parameters.scaling,
+        );
+        self.bin_frequencies = parameters.get_bin_frequencies();
+        self.mel_filter_bank = MelFilterBank::new(
+            &parameters.mel,
+            parameters.get_fft_size(),
+            parameters.sample_rate as f32,
+        );
+        self.mel_spectrums =
+            MultiChannel::new(self.channel_router.get_output_channels(), Spectrum::new());
+        self.constant_q_bank = Self::build_constant_q_bank(&parameters, &self.spectrum_analyzer);
+        self.analyzer_parameters = parameters;
+        self.reset_spectrograms();
+        self.reset_octave_bands();
+    }
+
+    fn build_constant_q_bank(
+        parameters: &AnalyzerParameters,
+        spectrum_analyzer: &FftAnalyzer,
+    ) -> ConstantQBank {
+        let fft_size = parameters.get_fft_size();
+        ConstantQBank::new(
+            &parameters.constant_q,
+            fft_size,
+            parameters.sample_rate as f32,
+            spectrum_analyzer.get_nenbw() * fft_size as f32 / parameters.spectrum_width as f32,
+            parameters.scaling,
+        )
+    }
+
+    /// Empty histories for the current parameters and channels.
+    fn reset_spectrograms(&mut self) {
+        let channels = self.channel_router.get_output_channels();
+        self.spectrogram = Spectrogram::new(self.analyzer_parameters.clone(), channels);
+        self.constant_q_spectrogram = Spectrogram::with_width(
+            self.analyzer_parameters.clone(),
+            channels,
+            self.constant_q_bank.get_frequencies().len(),
+        );
+        self.mel_spectrogram = Spectrogram::with_width(
+            self.analyzer_parameters.clone(),
+            channels,
+            self.mel_filter_bank.get_frequencies().len(),
+        );
+    }
+
+    /// Octave band filters with cleared state, one per analyzed channel.
+    fn reset_octave_bands(&mut self) {
+        let octave_bands = OctaveBandAnalyzer::new(
+            &self.analyzer_parameters.octave_bands,
+            self.analyzer_parameters.sample_rate as f32,
+        );
+        self.octave_band_frequencies = octave_bands.get_frequencies().to_vec();
+        self.octave_band_analyzers = vec![octave_bands; self.channel_router.get_output_channels()];
+        self.octave_band_levels = MultiChannel::new(0, Spectrum::new());
+    }
+
     pub fn kill(&mut self) {
         self.is_alive = false;
     }
@@ -166,3 +634,568 @@ impl StreamAnalyzer {
         self.is_alive
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio::{
+            input_conditioning::{db_to_gain, gain_to_db, AgcSettings},
+            signal_generator::{Signal, SignalGenerator},
+        },
+        audio_analyzer::{
+            ConstantQParameters, FrequencyWeighting, MagnitudeScale, MelParameters, MelScale,
+            OctaveBandParameters, OctaveFraction, TimeWeighting, ZeroPadding,
+        },
+    };
+
+    #[test]
+    fn test_channel_routing() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 2,
+        };
+        let mut analyzer = StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            4800,
+            Arc::new(parameters.clone()),
+        );
+        assert_eq!(analyzer.get_latest_spectrum().len(), 2);
+
+        analyzer.set_channel_routing(ChannelRouting::MidSide);
+        assert_eq!(analyzer.get_latest_spectrum().len(), 2);
+        assert_eq!(analyzer.get_channel_names(), vec!["Mid", "Side"]);
+
+        // same sine on both channels has no side component
+        let mut sine = SignalGenerator::new(Signal::Sine { frequency: 1000.0 }, 0.5, parameters);
+        analyzer
+            .get_audio_buffer()
+            .lock()
+            .unwrap()
+            .store(sine.generate(48000).into());
+        analyzer.process_new_samples();
+
+        let spectrums = analyzer.get_latest_spectrum();
+        assert!((spectrums.get_channel(0)[100] - 0.5).abs() < 0.01);
+        assert!(spectrums
+            .get_channel(1)
+            .as_slice()
+            .iter()
+            .all(|v| *v < 1e-6));
+
+        analyzer.set_channel_routing(ChannelRouting::MonoDownmix);
+        assert_eq!(analyzer.get_latest_spectrum().len(), 1);
+        analyzer.set_stream_parameters(Arc::new(StreamParameters {
+            sample_rate: 48000,
+            channels: 6,
+        }));
+        assert_eq!(analyzer.get_channel_routing(), ChannelRouting::MonoDownmix);
+        assert_eq!(analyzer.get_latest_spectrum().len(), 1);
+    }
+
+    #[test]
+    fn test_spectrum_timestamps() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 2,
+        };
+        let mut analyzer = StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            4800,
+            Arc::new(parameters.clone()),
+        );
+        let receiver = Arc::new(Mutex::new(TimestampReceiver(Vec::new())));
+        analyzer.register_receiver(receiver.clone());
+
+        // two blocks of 100 ms captured at 1 s and 1.1 s
+        let mut input = analyzer.connect();
+        let mut sine = SignalGenerator::new(Signal::Sine { frequency: 1000.0 }, 0.5, parameters);
+        for block in 0..2 {
+            let timestamp = Timestamp::from_duration(Duration::from_millis(1000 + block * 100));
+            assert!(input.push(&sine.generate(4800), timestamp));
+        }
+        analyzer.process_new_samples();
+
+        // every spectrum is stamped with its newest sample, 20 ms apart
+        let timestamps = receiver.lock().unwrap().0.clone();
+        assert_eq!(timestamps.len(), 10);
+        for (index, timestamp) in timestamps.iter().enumerate() {
+            let expected = 1.0 + (index as f64 + 1.0) * 0.02 - 1.0 / 48000.0;
+            let actual = timestamp.as_duration().as_secs_f64();
+            assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+        }
+        assert_eq!(analyzer.get_latest_timestamp(), *timestamps.last().unwrap());
+        let spectrums = analyzer.get_latest_spectrum();
+        assert_eq!(
+            spectrums.get_channel(1).get_timestamp(),
+            analyzer.get_latest_timestamp()
+        );
+    }
+
+    #[test]
+    fn test_reconfigure() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 1,
+        };
+        let mut analyzer = StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            4800,
+            Arc::new(parameters.clone()),
+        );
+        let mut input = analyzer.connect();
+        let mut sine = SignalGenerator::new(Signal::Sine { frequency: 1000.0 }, 0.5, parameters);
+        let mut send_second = |analyzer: &mut StreamAnalyzer| {
+            for _ in 0..10 {
+                assert!(input.push(&sine.generate(4800), Timestamp::now()));
+            }
+            analyzer.process_new_samples();
+        };
+        send_second(&mut analyzer);
+        assert_eq!(analyzer.get_latest_spectrum().get_channel(0).len(), 2400);
+
+        let invalid = AnalyzerParameters::new(
+            96000,
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            48000,
+        );
+        assert!(analyzer.reconfigure(invalid).is_err());
+        assert_eq!(analyzer.get_analyzer_parameters().spectrum_width, 4800);
+
+        let reconfigured = AnalyzerParameters::new(
+            9600,
+            Duration::from_millis(20),
+            Duration::from_secs(2),
+            48000,
+        )
+        .with_overlap(0.75);
+        analyzer.reconfigure(reconfigured.clone()).unwrap();
+        assert_eq!(*analyzer.get_analyzer_parameters(), reconfigured);
+        assert_eq!(
+            analyzer.get_audio_buffer().lock().unwrap().get_capacity(),
+            96000
+        );
+
+        // the stream keeps sending into the same input, bins are 5 Hz wide
+        send_second(&mut analyzer);
+        let spectrum = analyzer.get_latest_spectrum().get_channel(0).clone();
+        assert_eq!(spectrum.len(), 4800);
+        assert!((spectrum[200] - 0.5).abs() < 0.01, "{}", spectrum[200]);
+        let (spectrogram, (width, length)) = analyzer.get_spectrogram_for_channel(0);
+        assert_eq!((width, length), (4800, 40));
+        assert_eq!(spectrogram.get_total_len(), 4800 * 40);
+
+        // zero padded to 16384 points, the history follows the number of bins
+        analyzer
+            .reconfigure(AnalyzerParameters {
+                zero_padding: ZeroPadding::NextPowerOfTwo,
+                ..reconfigured
+            })
+            .unwrap();
+        send_second(&mut analyzer);
+        let spectrum = analyzer.get_latest_spectrum().get_channel(0).clone();
+        assert_eq!(spectrum.len(), 8192);
+        let bin = (1000.0 / analyzer.get_analyzer_parameters().get_bin_width()).round() as usize;
+        assert!((spectrum[bin] - 0.5).abs() < 0.02, "{}", spectrum[bin]);
+        assert_eq!(analyzer.get_spectrogram_for_channel(0).1, (8192, 40));
+    }
+
+    #[test]
+    fn test_statistics() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 1,
+        };
+        let mut analyzer = StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            4800,
+            Arc::new(parameters.clone()),
+        );
+        let mut sine = SignalGenerator::new(Signal::Sine { frequency: 1000.0 }, 0.5, parameters);
+
+        // 1.5 s stored at once, the oldest 0.5 s never reach the analyzer
+        let buffer = analyzer.get_audio_buffer();
+        buffer.lock().unwrap().store(sine.generate(48000).into());
+        buffer.lock().unwrap().store(sine.generate(24000).into());
+        analyzer.process_new_samples();
+
+        let statistics = analyzer.get_statistics();
+        assert_eq!(statistics.budget, Duration::from_millis(20));
+        assert!(statistics.processing.count > 0);
+        assert!(statistics.processing.max >= statistics.processing.last);
+        assert_eq!(statistics.buffer.overruns, 2);
+        assert!(!statistics.buffer.is_healthy());
+
+        // a full ring buffer drops new blocks on the producer side
+        let mut input = analyzer.connect();
+        let free = input.get_free_len();
+        assert!(input.push(&sine.generate(free), Timestamp::now()));
+        assert!(!input.push(&sine.generate(480), Timestamp::now()));
+        assert_eq!(analyzer.get_statistics().buffer.dropped_samples, 480);
+
+        analyzer.reset_statistics();
+        let statistics = analyzer.get_statistics();
+        assert_eq!(statistics.processing, DurationStatistics::default());
+        assert_eq!(statistics.buffer, BufferStatistics::default());
+    }
+
+    #[test]
+    fn test_constant_q_spectrums() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 1,
+        };
+        let mut analyzer = StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            4800,
+            Arc::new(parameters.clone()),
+        );
+        let constant_q = ConstantQParameters {
+            bins_per_octave: 24,
+            min_frequency: 110.0,
+            max_frequency: 7040.0,
+        };
+        analyzer
+            .reconfigure(AnalyzerParameters {
+                constant_q,
+                ..(*analyzer.get_analyzer_parameters()).clone()
+            })
+            .unwrap();
+        analyzer.set_stream_parameters(Arc::new(parameters.clone()));
+        assert_eq!(analyzer.get_analyzer_parameters().constant_q, constant_q);
+        assert_eq!(analyzer.get_constant_q_frequencies().len(), 145);
+
+        // A5, three octaves of quarter tones above the start
+        let mut sine = SignalGenerator::new(Signal::Sine { frequency: 880.0 }, 0.5, parameters);
+        analyzer
+            .get_audio_buffer()
+            .lock()
+            .unwrap()
+            .store(sine.generate(48000).into());
+        analyzer.process_new_samples();
+
+        let bands = analyzer
+            .get_latest_constant_q_spectrum()
+            .get_channel(0)
+            .clone();
+        let loudest = (0..bands.len())
+            .max_by(|a, b| bands[*a].total_cmp(&bands[*b]))
+            .unwrap();
+        assert_eq!(loudest, 72);
+        assert!((bands[72] - 0.5).abs() < 0.05, "{}", bands[72]);
+        assert_eq!(
+            analyzer.get_constant_q_spectrogram_for_channel(0).1,
+            (145, 50)
+        );
+        assert!(analyzer
+            .reconfigure(AnalyzerParameters {
+                constant_q: ConstantQParameters {
+                    bins_per_octave: 0,
+                    ..constant_q
+                },
+                ..(*analyzer.get_analyzer_parameters()).clone()
+            })
+            .is_err());
+    }
+
+    #[derive(Default)]
+    struct OctaveBandReceiver {
+        levels: Vec<Spectrum>,
+        frequencies: Vec<f32>,
+    }
+
+    impl StreamAnalyzerReceiver for OctaveBandReceiver {
+        fn receive(&mut self, _spectrums: &MultiChannel<Spectrum>) {}
+
+        fn receive_octave_bands(&mut self, levels: &MultiChannel<Spectrum>, frequencies: &[f32]) {
+            self.levels = levels.channels.clone();
+            self.frequencies = frequencies.to_vec();
+        }
+
+        fn get_spectrum_format(&self) -> SpectrumFormat {
+            SpectrumFormat::new(
+                FrequencyWeighting::Z,
+                MagnitudeScale::Decibels {
+                    floor: MagnitudeScale::DEFAULT_FLOOR,
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn test_octave_bands() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 1,
+        };
+        let mut analyzer = StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            4800,
+            Arc::new(parameters.clone()),
+        );
+        let receiver = Arc::new(Mutex::new(OctaveBandReceiver::default()));
+        analyzer.register_receiver(receiver.clone());
+        assert_eq!(analyzer.get_octave_band_frequencies().len(), 31);
+
+        let mut sine = SignalGenerator::new(Signal::Sine { frequency: 1000.0 }, 0.5, parameters);
+        let mut input = analyzer.connect();
+        for _ in 0..10 {
+            assert!(input.push(&sine.generate(4800), Timestamp::now()));
+        }
+        analyzer.process_new_samples();
+
+        // the filters see the stream once, so the level settles as for a continuous tone
+        let levels = analyzer.get_latest_octave_bands();
+        assert_eq!(levels.len(), 1);
+        assert!((levels.get_channel(0)[17] - 0.5).abs() < 0.01);
+        let received = receiver.lock().unwrap();
+        assert_eq!(received.frequencies.len(), 31);
+        assert!(
+            (received.levels[0][17] + 6.02).abs() < 0.2,
+            "{}",
+            received.levels[0][17]
+        );
+        assert!(received.levels[0][10] < -60.0);
+        drop(received);
+
+        analyzer
+            .reconfigure(AnalyzerParameters {
+                octave_bands: OctaveBandParameters {
+                    fraction: OctaveFraction::Full,
+                    time_weighting: TimeWeighting::Slow,
+                },
+                ..(*analyzer.get_analyzer_parameters()).clone()
+            })
+            .unwrap();
+        assert_eq!(analyzer.get_octave_band_frequencies().len(), 10);
+        assert_eq!(analyzer.get_latest_octave_bands().len(), 0);
+    }
+
+    struct MelReceiver(Vec<f32>);
+
+    impl StreamAnalyzerReceiver for MelReceiver {
+        fn receive(&mut self, _spectrums: &MultiChannel<Spectrum>) {}
+
+        fn receive_mel(&mut self, spectrums: &MultiChannel<Spectrum>) {
+            self.0 = spectrums.get_channel(0).as_slice().to_vec();
+        }
+    }
+
+    #[test]
+    fn test_mel_spectrums() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 1,
+        };
+        let mut analyzer = StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            4800,
+            Arc::new(parameters.clone()),
+        );
+        let receiver = Arc::new(Mutex::new(MelReceiver(Vec::new())));
+        analyzer.register_receiver(receiver.clone());
+        let mel = MelParameters {
+            filters: 24,
+            min_frequency: 50.0,
+            max_frequency: 8000.0,
+            scale: MelScale::Slaney,
+            normalize: true,
+        };
+        analyzer
+            .reconfigure(AnalyzerParameters {
+                mel,
+                ..(*analyzer.get_analyzer_parameters()).clone()
+            })
+            .unwrap();
+        let frequencies = analyzer.get_mel_frequencies();
+        assert_eq!(frequencies.len(), 24);
+
+        let mut sine = SignalGenerator::new(Signal::Sine { frequency: 1000.0 }, 0.5, parameters);
+        analyzer
+            .get_audio_buffer()
+            .lock()
+            .unwrap()
+            .store(sine.generate(48000).into());
+        analyzer.process_new_samples();
+
+        let bands = analyzer.get_latest_mel_spectrum().get_channel(0).clone();
+        assert_eq!(bands.len(), 24);
+        assert_eq!(receiver.lock().unwrap().0, bands.as_slice());
+        let loudest = (0..bands.len())
+            .max_by(|a, b| bands[*a].total_cmp(&bands[*b]))
+            .unwrap();
+        let nearest = (0..frequencies.len())
+            .min_by(|a, b| {
+                (frequencies[*a] - 1000.0)
+                    .abs()
+                    .total_cmp(&(frequencies[*b] - 1000.0).abs())
+            })
+            .unwrap();
+        assert_eq!(loudest, nearest);
+        assert_eq!(analyzer.get_mel_spectrogram_for_channel(0).1, (24, 50));
+
+        // the range is cut at Nyquist of a slower stream
+        analyzer.set_stream_parameters(Arc::new(StreamParameters {
+            sample_rate: 8000,
+            channels: 1,
+        }));
+        assert_eq!(analyzer.get_analyzer_parameters().mel, mel);
+        assert!(analyzer
+            .get_mel_frequencies()
+            .into_iter()
+            .all(|frequency| frequency < 4000.0));
+    }
+
+    struct FormatReceiver(SpectrumFormat, Vec<f32>);
+
+    impl StreamAnalyzerReceiver for FormatReceiver {
+        fn receive(&mut self, spectrums: &MultiChannel<Spectrum>) {
+            self.1 = spectrums.get_channel(0).as_slice().to_vec();
+        }
+
+        fn get_spectrum_format(&self) -> SpectrumFormat {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_spectrum_formats() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 1,
+        };
+        let mut analyzer = StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            4800,
+            Arc::new(parameters.clone()),
+        );
+        let decibels = MagnitudeScale::Decibels { floor: -120.0 };
+        let receiver = Arc::new(Mutex::new(FormatReceiver(
+            SpectrumFormat::new(FrequencyWeighting::C, decibels),
+            Vec::new(),
+        )));
+        analyzer.register_receiver(receiver.clone());
+        analyzer.set_display_format(SpectrumFormat::new(FrequencyWeighting::A, decibels));
+        analyzer.set_stream_parameters(Arc::new(parameters.clone()));
+        assert_eq!(
+            analyzer.get_display_format().weighting,
+            FrequencyWeighting::A
+        );
+
+        // -6 dBFS at 1 kHz, where both weightings are 0 dB
+        let mut sine = SignalGenerator::new(Signal::Sine { frequency: 1000.0 }, 0.5, parameters);
+        analyzer
+            .get_audio_buffer()
+            .lock()
+            .unwrap()
+            .store(sine.generate(48000).into());
+        analyzer.process_new_samples();
+
+        let displayed = analyzer.get_latest_spectrum().get_channel(0).clone();
+        assert!((displayed[100] + 6.02).abs() < 0.01, "{}", displayed[100]);
+        assert_eq!(displayed[0], -120.0);
+        let received = receiver.lock().unwrap().1.clone();
+        assert!((received[100] + 6.02).abs() < 0.01, "{}", received[100]);
+    }
+
+    struct TimestampReceiver(Vec<Timestamp>);
+
+    impl StreamAnalyzerReceiver for TimestampReceiver {
+        fn receive(&mut self, spectrums: &MultiChannel<Spectrum>) {
+            self.0.push(spectrums.get_channel(0).get_timestamp());
+        }
+    }
+
+    #[test]
+    fn test_conditioning_normalizes_level() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 1,
+        };
+        let mut levels = Vec::new();
+        for amplitude in [0.05, 0.8] {
+            let mut analyzer = StreamAnalyzer::new(
+                Duration::from_millis(20),
+                Duration::from_secs(1),
+                4800,
+                Arc::new(parameters.clone()),
+            );
+            let default = ConditioningSettings::default();
+            let settings = ConditioningSettings {
+                dc_blocker_enabled: true,
+                agc: AgcSettings {
+                    enabled: true,
+                    ..default.agc
+                },
+                ..default
+            };
+            analyzer.set_conditioning_settings(settings.clone());
+            analyzer.set_stream_parameters(Arc::new(parameters.clone()));
+            assert_eq!(analyzer.get_conditioning_settings(), settings);
+
+            let mut sine = SignalGenerator::new(
+                Signal::Sine { frequency: 1000.0 },
+                amplitude,
+                parameters.clone(),
+            );
+            for _ in 0..10 {
+                analyzer
+                    .get_audio_buffer()
+                    .lock()
+                    .unwrap()
+                    .store(sine.generate(48000).into());
+                analyzer.process_new_samples();
+            }
+            levels.push(analyzer.get_latest_spectrum().get_channel(0)[100]);
+            let expected_gain = gain_to_db(db_to_gain(settings.agc.target_level_db) / amplitude);
+            let gain = analyzer.get_conditioning_status().agc_gain_db;
+            assert!(
+                (gain - expected_gain).abs() < 1.0,
+                "{gain} != {expected_gain}"
+            );
+        }
+        assert!((levels[0] / levels[1] - 1.0).abs() < 0.05, "{levels:?}");
+    }
+
+    #[test]
+    fn test_spectrum_scaling() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 1,
+        };
+        let mut analyzer = StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            4800,
+            Arc::new(parameters.clone()),
+        );
+        analyzer.set_spectrum_scaling(WindowFunction::Hann, SpectrumScaling::PowerSpectralDensity);
+        analyzer.set_stream_parameters(Arc::new(parameters.clone()));
+        let analyzer_parameters = analyzer.get_analyzer_parameters();
+        assert_eq!(analyzer_parameters.window, WindowFunction::Hann);
+        assert_eq!(
+            analyzer_parameters.scaling,
+            SpectrumScaling::PowerSpectralDensity
+        );
+
+        // sine power spread over the 1.5 bins (15 Hz) noise bandwidth of Hann
+        let mut sine = SignalGenerator::new(Signal::Sine { frequency: 1000.0 }, 0.5, parameters);
+        analyzer
+            .get_audio_buffer()
+            .lock()
+            .unwrap()
+            .store(sine.generate(48000).into());
+        analyzer.process_new_samples();
+        let density = analyzer.get_latest_spectrum().get_channel(0)[100];
+        assert!((density * density - 0.125 / 15.0).abs() < 1e-4, "{density}");
+    }
+}
@@ -19,8 +19,8 @@ where
         }
     }
 
-    pub fn push(&mut self, data: Vec<T>) {
-        self.data.extend(data);
+    pub fn push(&mut self, data: &[T]) {
+        self.data.extend_from_slice(data);
 
         if self.data.len() >= self.length * self.width {
             self.data.drain(0..self.data.len() - self.total_size);
@@ -5,13 +5,20 @@ use std::{
 
 use log::{debug, info};
 
+use crate::audio::Timestamp;
 use crate::audio_analyzer::{
-    AnalyzerParameters, ManyChannelsSpectrums, MultiChannel, StreamAnalyzerReceiver,
+    AnalyzerParameters, MultiChannel, Spectrum, SpectrumFormat, StreamAnalyzerReceiver,
 };
 
-// add send trait
-
-pub struct StreamAnnotation {}
+/// Changes of the bass ranges of one channel, `None` when the range didn't change.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamAnnotation {
+    pub channel: usize,
+    pub sub_bas: Option<BasState>,
+    pub bas: Option<BasState>,
+    /// Capture time of the newest sample of the annotated spectrum.
+    pub timestamp: Timestamp,
+}
 
 pub trait StreamAnnotationReceiver: Send {
     fn receive(&mut self, annotations: &StreamAnnotation);
@@ -23,7 +30,9 @@ pub struct StreamAnalyzerAnnotator {
     duration: Duration,
     filters: MultiChannel<Vec<SpectrumFilter>>,
     receivers: Vec<Arc<Mutex<dyn StreamAnnotationReceiver>>>,
-    bass_annotator: BasAnnotator,
+    bass_annotators: MultiChannel<BasAnnotator>,
+    /// Weighting and units the frequency ranges are measured in.
+    format: SpectrumFormat,
 }
 
 /*
@@ -36,7 +45,7 @@ Treble: 4 kHz to 6 kHz
 Upper Treble: 6 to 20 kHz
 */
 #[derive(PartialEq, Debug, Clone, Copy)]
-enum BasState {
+pub enum BasState {
     BasUp,
     BasDown,
 }
@@ -48,6 +57,7 @@ enum BasEvent {
     },
 }
 
+#[derive(Clone)]
 struct BasAnnotator {
     bas_state: BasState,
     sub_bass_state: BasState,
@@ -80,6 +90,12 @@ impl BasAnnotator {
             BasState::BasDown
         };
 
+        // changes are taken before the states are updated
+        let event = BasEvent::NewState {
+            sub_bas: (new_sub_bas_state != self.sub_bass_state).then_some(new_sub_bas_state),
+            bas: (new_bas_state != self.bas_state).then_some(new_bas_state),
+        };
+
         if new_sub_bas_state != self.sub_bass_state {
             self.sub_bass_state = new_sub_bas_state;
             info!("Sub bass state changed to: {:#?}", new_sub_bas_state)
@@ -90,18 +106,7 @@ impl BasAnnotator {
             info!("Bass state changed to: {:#?}", new_bas_state)
         }
 
-        BasEvent::NewState {
-            sub_bas: if new_sub_bas_state != self.sub_bass_state {
-                Some(new_sub_bas_state)
-            } else {
-                None
-            },
-            bas: if new_bas_state != self.bas_state {
-                Some(new_bas_state)
-            } else {
-                None
-            },
-        }
+        event
     }
 }
 
@@ -125,7 +130,7 @@ impl StreamAnalyzerAnnotator {
     const UPPER_TREBLE: usize = 6;
 
     pub fn new(parameters: Arc<AnalyzerParameters>, duration: Duration, channels: usize) -> Self {
-        let bin_width = parameters.sample_rate as f32 / parameters.spectrum_width as f32;
+        let bin_width = parameters.get_bin_width();
 
         let ranges_indices: Vec<Vec<usize>> = Self::FREQUENCY_RANGES
             .iter()
@@ -161,40 +166,70 @@ impl StreamAnalyzerAnnotator {
             duration,
             filters,
             receivers: vec![],
-            bass_annotator: BasAnnotator::new(),
+            bass_annotators: MultiChannel::new(channels, BasAnnotator::new()),
+            format: SpectrumFormat::default(),
         }
     }
 
-    fn check_for_annotation(&mut self) {
-        self.filters
-            .channels
-            .iter()
-            .enumerate()
-            .for_each(|(channel, filters)| {
-                self.bass_annotator.update(filters);
-            });
+    pub fn with_spectrum_format(mut self, format: SpectrumFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn register_receiver(&mut self, receiver: Arc<Mutex<dyn StreamAnnotationReceiver>>) {
+        self.receivers.push(receiver);
+    }
+
+    fn check_for_annotation(&mut self, timestamp: Timestamp) {
+        for (channel, filters) in self.filters.channels.iter().enumerate() {
+            let BasEvent::NewState { sub_bas, bas } = self
+                .bass_annotators
+                .get_channel_mut(channel)
+                .update(filters);
+            if sub_bas.is_none() && bas.is_none() {
+                continue;
+            }
+
+            let annotation = StreamAnnotation {
+                channel,
+                sub_bas,
+                bas,
+                timestamp,
+            };
+            for receiver in &self.receivers {
+                receiver.lock().unwrap().receive(&annotation);
+            }
+        }
     }
 
-    pub fn push_spectrum(&mut self, spectrums: &ManyChannelsSpectrums) {
+    pub fn push_spectrum(&mut self, spectrums: &MultiChannel<Spectrum>) {
         spectrums
+            .channels
             .iter()
             .enumerate()
             .for_each(|(channel, spectrum)| {
                 self.filters.channels[channel]
                     .iter_mut()
                     .for_each(|filter| {
-                        filter.filter(spectrum);
+                        filter.filter(spectrum.as_slice());
                     });
             });
 
-        self.check_for_annotation();
+        // channels are spectrums of the same frame
+        if let Some(spectrum) = spectrums.channels.first() {
+            self.check_for_annotation(spectrum.get_timestamp());
+        }
     }
 }
 
 impl StreamAnalyzerReceiver for StreamAnalyzerAnnotator {
-    fn receive(&mut self, spectrums: &ManyChannelsSpectrums) {
+    fn receive(&mut self, spectrums: &MultiChannel<Spectrum>) {
         self.push_spectrum(spectrums);
     }
+
+    fn get_spectrum_format(&self) -> SpectrumFormat {
+        self.format
+    }
 }
 
 #[derive(Clone)]
@@ -286,3 +321,105 @@ impl SpectrumFilter {
         *self.history.peek().last().unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        audio::{
+            signal_generator::{Signal, SignalGenerator},
+            AudioStreamConsumer, StreamParameters,
+        },
+        audio_analyzer::StreamAnalyzer,
+    };
+
+    use super::*;
+
+    struct AnnotationCollector(Vec<StreamAnnotation>);
+
+    impl StreamAnnotationReceiver for AnnotationCollector {
+        fn receive(&mut self, annotation: &StreamAnnotation) {
+            self.0.push(annotation.clone());
+        }
+    }
+
+    /// Analyzes 1 s of silence, 2 s of a 100 Hz tone and 2 s of silence again, in 100 ms
+    /// blocks captured from 0 s. The tone is played only on the left channel.
+    fn annotate(channels: u16) -> Vec<StreamAnnotation> {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 1,
+        };
+        let mut analyzer = StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            4800,
+            Arc::new(StreamParameters {
+                channels,
+                ..parameters.clone()
+            }),
+        );
+        let annotator = Arc::new(Mutex::new(StreamAnalyzerAnnotator::new(
+            analyzer.get_analyzer_parameters(),
+            Duration::from_secs(1),
+            channels as usize,
+        )));
+        let collector = Arc::new(Mutex::new(AnnotationCollector(Vec::new())));
+        annotator
+            .lock()
+            .unwrap()
+            .register_receiver(collector.clone());
+        analyzer.register_receiver(annotator);
+
+        let mut input = analyzer.connect();
+        let mut tone = SignalGenerator::new(Signal::Sine { frequency: 100.0 }, 0.5, parameters);
+        for block in 0..50u64 {
+            let left = if (10..30).contains(&block) {
+                tone.generate(4800)
+            } else {
+                vec![0.0; 4800]
+            };
+            let samples: Vec<f32> = left
+                .into_iter()
+                .flat_map(|sample| {
+                    std::iter::once(sample).chain(std::iter::repeat_n(0.0, channels as usize - 1))
+                })
+                .collect();
+            let timestamp = Timestamp::from_duration(Duration::from_millis(block * 100));
+            assert!(input.push(&samples, timestamp));
+            analyzer.process_new_samples();
+        }
+
+        let annotations = std::mem::take(&mut collector.lock().unwrap().0);
+        annotations
+    }
+
+    #[test]
+    fn test_bass_annotations() {
+        let annotations = annotate(1);
+        let bas: Vec<(BasState, f64)> = annotations
+            .iter()
+            .filter_map(|annotation| {
+                let time = annotation.timestamp.as_duration().as_secs_f64();
+                annotation.bas.map(|state| (state, time))
+            })
+            .collect();
+
+        // nothing is annotated in silence, the tone raises the bass and it falls after it
+        assert_eq!(bas.len(), 2, "{:?}", bas);
+        assert_eq!(bas[0].0, BasState::BasUp);
+        assert!((1.0..1.05).contains(&bas[0].1), "{}", bas[0].1);
+        // smoothed level has to fall below its average first
+        assert_eq!(bas[1].0, BasState::BasDown);
+        assert!((3.0..3.5).contains(&bas[1].1), "{}", bas[1].1);
+        assert!(annotations.iter().all(|annotation| annotation.channel == 0));
+    }
+
+    #[test]
+    fn test_channels_are_annotated_separately() {
+        let annotations = annotate(2);
+        assert!(annotations
+            .iter()
+            .any(|annotation| annotation.bas == Some(BasState::BasUp)));
+        assert!(annotations.iter().all(|annotation| annotation.channel == 0));
+    }
+}
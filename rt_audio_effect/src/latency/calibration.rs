@@ -0,0 +1,326 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use headlight_if::{FlashAckMessage, FlashMessage, Message};
+use log::{info, warn};
+use serializer::ByteMessagePort;
+
+use crate::audio::Timestamp;
+
+use super::{ClickDetection, ClickSource};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CalibrationConfig {
+    pub click_source: ClickSource,
+    pub period: Duration,
+    /// Flashes a headlight emulated by the app instead of a real one.
+    pub simulated_device: bool,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            click_source: ClickSource::Generator,
+            period: Duration::from_secs(1),
+            simulated_device: false,
+        }
+    }
+}
+
+/// End-to-end latency of one click, from its emission until the headlight was lit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LatencyReport {
+    /// From the emission to the capture of the newest sample of the spectrum revealing it,
+    /// includes the output and input paths and filling of the analysis window.
+    pub capture: Duration,
+    /// From the capture to the detection, buffering and processing of the analyzer.
+    pub analysis: Duration,
+    /// From the detection until the device received the flash, one way time is half of
+    /// the round trip without the time spent in the device.
+    pub network: Duration,
+    /// From the reception of the flash until the light was set, measured by the device.
+    pub device: Duration,
+}
+
+impl LatencyReport {
+    /// `sent` and `acknowledged` are host times of the flash and its acknowledgement.
+    pub fn new(
+        detection: &ClickDetection,
+        sent: Timestamp,
+        acknowledged: Timestamp,
+        ack: &FlashAckMessage,
+    ) -> Self {
+        let device = Duration::from_micros(ack.lit_us.saturating_sub(ack.received_us));
+        let round_trip = acknowledged.duration_since(sent).saturating_sub(device);
+        Self {
+            capture: detection.captured.duration_since(detection.emitted),
+            analysis: detection.detected.duration_since(detection.captured),
+            network: sent.duration_since(detection.detected) + round_trip / 2,
+            device,
+        }
+    }
+
+    pub fn get_total(&self) -> Duration {
+        self.capture + self.analysis + self.network + self.device
+    }
+}
+
+impl Display for LatencyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let milliseconds = |duration: Duration| duration.as_secs_f32() * 1000.0;
+        write!(
+            f,
+            "{:.1} ms (capture {:.1} ms, analysis {:.1} ms, network {:.1} ms, device {:.1} ms)",
+            milliseconds(self.get_total()),
+            milliseconds(self.capture),
+            milliseconds(self.analysis),
+            milliseconds(self.network),
+            milliseconds(self.device)
+        )
+    }
+}
+
+/// Latest reports of the calibration, shared with the UI.
+#[derive(Default)]
+pub struct LatencyResults {
+    reports: VecDeque<LatencyReport>,
+    failures: usize,
+}
+
+impl LatencyResults {
+    const MAX_REPORTS: usize = 50;
+
+    pub fn push(&mut self, report: LatencyReport) {
+        if self.reports.len() == Self::MAX_REPORTS {
+            self.reports.pop_front();
+        }
+        self.reports.push_back(report);
+    }
+
+    /// Flashes which weren't acknowledged.
+    pub fn add_failure(&mut self) {
+        self.failures += 1;
+    }
+
+    pub fn get_failures(&self) -> usize {
+        self.failures
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.reports.len()
+    }
+
+    pub fn get_latest(&self) -> Option<&LatencyReport> {
+        self.reports.back()
+    }
+
+    /// Average of every stage over the kept reports.
+    pub fn get_average(&self) -> Option<LatencyReport> {
+        let count = self.reports.len() as u32;
+        if count == 0 {
+            return None;
+        }
+        let sum = |stage: fn(&LatencyReport) -> Duration| {
+            self.reports.iter().map(stage).sum::<Duration>() / count
+        };
+        Some(LatencyReport {
+            capture: sum(|report| report.capture),
+            analysis: sum(|report| report.analysis),
+            network: sum(|report| report.network),
+            device: sum(|report| report.device),
+        })
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Headlight flashed by the calibration.
+pub trait FlashTarget: Send {
+    /// Sends the flash and waits for its acknowledgement.
+    fn flash(&mut self, flash: FlashMessage, timeout: Duration) -> Result<FlashAckMessage, String>;
+}
+
+impl FlashTarget for ByteMessagePort<Message> {
+    /// Other messages received in the meantime are dropped.
+    fn flash(&mut self, flash: FlashMessage, timeout: Duration) -> Result<FlashAckMessage, String> {
+        let id = flash.id;
+        self.send(Message::Flash(flash))?;
+
+        let deadline = Instant::now() + timeout;
+        let result = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break Err(format!("Flash {id} not acknowledged"));
+            }
+            self.set_read_timeout(Some(remaining))?;
+            match self.recv() {
+                Ok(Message::FlashAck(ack)) if ack.id == id => break Ok(ack),
+                Ok(_) => continue,
+                Err(err) => break Err(err),
+            }
+        };
+        self.set_read_timeout(None)?;
+        result
+    }
+}
+
+/// Flashes the headlight on every detected click and collects latency reports, until dropped.
+pub struct LatencyCalibration {
+    alive: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LatencyCalibration {
+    const ACK_TIMEOUT: Duration = Duration::from_millis(500);
+    const POLL_PERIOD: Duration = Duration::from_millis(100);
+
+    pub fn start(
+        detections: Receiver<ClickDetection>,
+        mut target: Box<dyn FlashTarget>,
+        results: Arc<Mutex<LatencyResults>>,
+    ) -> Self {
+        info!("Starting latency calibration");
+        let alive = Arc::new(AtomicBool::new(true));
+        let thread_alive = alive.clone();
+        let thread = thread::spawn(move || {
+            let mut id = 0;
+            while thread_alive.load(Ordering::Relaxed) {
+                let detection = match detections.recv_timeout(Self::POLL_PERIOD) {
+                    Ok(detection) => detection,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+
+                id += 1;
+                let flash = FlashMessage {
+                    id,
+                    r: 255,
+                    g: 255,
+                    b: 255,
+                };
+                let sent = Timestamp::now();
+                match target.flash(flash, Self::ACK_TIMEOUT) {
+                    Ok(ack) => {
+                        let report = LatencyReport::new(&detection, sent, Timestamp::now(), &ack);
+                        info!("Latency: {report}");
+                        results.lock().unwrap().push(report);
+                    }
+                    Err(err) => {
+                        warn!("Flash failed: {err}");
+                        results.lock().unwrap().add_failure();
+                    }
+                }
+            }
+        });
+
+        Self {
+            alive,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for LatencyCalibration {
+    fn drop(&mut self) {
+        info!("Stopping latency calibration");
+        self.alive.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, sync::mpsc};
+
+    use super::*;
+    use crate::latency::SimulatedHeadlight;
+
+    fn at(milliseconds: u64) -> Timestamp {
+        Timestamp::from_duration(Duration::from_millis(milliseconds))
+    }
+
+    #[test]
+    fn test_report_stages() {
+        let detection = ClickDetection {
+            emitted: at(1000),
+            captured: at(1020),
+            detected: at(1025),
+        };
+        // 10 ms round trip, 4 ms of it in the device, on a clock with a different origin
+        let ack = FlashAckMessage {
+            id: 1,
+            received_us: 5_000_000,
+            lit_us: 5_004_000,
+        };
+        let report = LatencyReport::new(&detection, at(1026), at(1036), &ack);
+        assert_eq!(report.capture, Duration::from_millis(20));
+        assert_eq!(report.analysis, Duration::from_millis(5));
+        assert_eq!(report.network, Duration::from_millis(4));
+        assert_eq!(report.device, Duration::from_millis(4));
+        assert_eq!(report.get_total(), Duration::from_millis(33));
+
+        let mut results = LatencyResults::default();
+        assert_eq!(results.get_average(), None);
+        results.push(report);
+        results.push(LatencyReport {
+            capture: Duration::from_millis(40),
+            ..report
+        });
+        let average = results.get_average().unwrap();
+        assert_eq!(average.capture, Duration::from_millis(30));
+        assert_eq!(average.device, Duration::from_millis(4));
+    }
+
+    #[test]
+    fn test_simulated_headlight() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let link_delay = Duration::from_millis(5);
+        let processing = Duration::from_millis(10);
+        let _device =
+            SimulatedHeadlight::connect(listener.local_addr().unwrap(), link_delay, processing)
+                .unwrap();
+
+        // the device introduces itself like the firmware
+        let (stream, _) = listener.accept().unwrap();
+        let mut port = ByteMessagePort::<Message>::new(stream);
+        assert!(matches!(port.recv().unwrap(), Message::Echo(_)));
+
+        let (detections_sender, detections) = mpsc::channel();
+        let results = Arc::new(Mutex::new(LatencyResults::default()));
+        let calibration = LatencyCalibration::start(detections, Box::new(port), results.clone());
+        for _ in 0..3 {
+            let now = Timestamp::now();
+            detections_sender
+                .send(ClickDetection {
+                    emitted: now,
+                    captured: now,
+                    detected: now,
+                })
+                .unwrap();
+            thread::sleep(Duration::from_millis(100));
+        }
+        drop(calibration);
+
+        let results = results.lock().unwrap();
+        assert_eq!(results.get_count(), 3);
+        assert_eq!(results.get_failures(), 0);
+        let report = results.get_latest().unwrap();
+        assert!(report.device >= processing, "{report}");
+        assert!(report.device < processing * 2, "{report}");
+        assert!(report.network >= link_delay, "{report}");
+        assert!(report.network < link_delay * 4, "{report}");
+    }
+}
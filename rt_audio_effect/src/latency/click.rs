@@ -0,0 +1,230 @@
+use std::{f32::consts::PI, sync::mpsc::Sender, time::Duration};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, OutputCallbackInfo, SampleFormat, SizedSample, Stream, StreamConfig,
+};
+use log::{error, info};
+
+use crate::audio::{file_decoder::AudioFileDecoder, Sample, StreamParameters, Timestamp};
+
+/// Where the click used for the latency measurement comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClickSource {
+    /// Generated in place of the analyzed source, the capture stage is only the analysis window.
+    Generator,
+    /// Played by the default output device and captured by the analyzed source, e.g. loopback
+    /// or a microphone.
+    OutputDevice,
+}
+
+impl std::fmt::Display for ClickSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClickSource::Generator => write!(f, "Generator"),
+            ClickSource::OutputDevice => write!(f, "Output device"),
+        }
+    }
+}
+
+/// Short tone burst, loud in every spectrum containing it, but band limited, so it survives
+/// resampling of the output and capture paths.
+pub struct Click;
+
+impl Click {
+    const FREQUENCY: f32 = 3000.0;
+    const DURATION: Duration = Duration::from_millis(5);
+    const AMPLITUDE: Sample = 0.8;
+
+    /// Sample at `frame` counted from the start of the click, zero after its end.
+    pub fn sample(frame: u64, sample_rate: u32) -> Sample {
+        let length = Self::DURATION.as_secs_f32() * sample_rate as f32;
+        let frame = frame as f32;
+        if frame >= length {
+            return 0.0;
+        }
+        let envelope = 0.5 - 0.5 * (2.0 * PI * frame / length).cos();
+        let phase = 2.0 * PI * Self::FREQUENCY * frame / sample_rate as f32;
+        Self::AMPLITUDE * envelope * phase.sin()
+    }
+
+    fn period_in_frames(period: Duration, sample_rate: u32) -> u64 {
+        (period.as_secs_f64() * sample_rate as f64).round().max(1.0) as u64
+    }
+}
+
+/// Silence with a click every `period`, played by `FileStream` in place of the source.
+/// Emission time of every click is sent to `emitted`.
+pub struct ClickGenerator {
+    parameters: StreamParameters,
+    period: u64,
+    position: u64,
+    emitted: Sender<Timestamp>,
+}
+
+impl ClickGenerator {
+    pub fn new(period: Duration, parameters: StreamParameters, emitted: Sender<Timestamp>) -> Self {
+        Self {
+            period: Click::period_in_frames(period, parameters.sample_rate),
+            parameters,
+            position: 0,
+            emitted,
+        }
+    }
+}
+
+impl AudioFileDecoder for ClickGenerator {
+    fn get_parameters(&self) -> StreamParameters {
+        self.parameters.clone()
+    }
+
+    fn get_length(&self) -> Option<u64> {
+        None
+    }
+
+    /// `FileStream` stamps the samples right after they are read, so the emission time of
+    /// a click is now plus its offset in the chunk.
+    fn read(&mut self, frames: usize) -> Result<Vec<Sample>, String> {
+        let now = Timestamp::now();
+        let channels = self.parameters.channels as usize;
+        let sample_rate = self.parameters.sample_rate;
+        let mut samples = Vec::with_capacity(frames * channels);
+        for frame in 0..frames as u64 {
+            let position = self.position + frame;
+            let click_frame = position % self.period;
+            if click_frame == 0 {
+                // nobody listens when the calibration was stopped
+                let _ = self
+                    .emitted
+                    .send(now + Duration::from_secs_f64(frame as f64 / sample_rate as f64));
+            }
+            let value = Click::sample(click_frame, sample_rate);
+            samples.extend(std::iter::repeat_n(value, channels));
+        }
+        self.position += frames as u64;
+        Ok(samples)
+    }
+
+    fn seek(&mut self, frame: u64) -> Result<(), String> {
+        self.position = frame;
+        Ok(())
+    }
+}
+
+/// Plays a click every `period` on the default output device, stops when dropped.
+pub struct ClickPlayer {
+    _stream: Stream,
+}
+
+impl ClickPlayer {
+    pub fn new(period: Duration, emitted: Sender<Timestamp>) -> Result<ClickPlayer, String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or(String::from("No output device"))?;
+        let config = device
+            .default_output_config()
+            .map_err(|err| err.to_string())?;
+        info!(
+            "Playing clicks on {} with: {} ch, {} Hz, {}",
+            device.name().unwrap_or_default(),
+            config.channels(),
+            config.sample_rate().0,
+            config.sample_format()
+        );
+
+        let stream_config = config.config();
+        let stream = match config.sample_format() {
+            SampleFormat::I16 => {
+                Self::build_stream::<i16>(&device, &stream_config, period, emitted)
+            }
+            SampleFormat::I32 => {
+                Self::build_stream::<i32>(&device, &stream_config, period, emitted)
+            }
+            SampleFormat::U16 => {
+                Self::build_stream::<u16>(&device, &stream_config, period, emitted)
+            }
+            SampleFormat::F32 => {
+                Self::build_stream::<f32>(&device, &stream_config, period, emitted)
+            }
+            sample_format => Err(format!("Unsupported format: {sample_format}")),
+        }?;
+        stream.play().map_err(|err| err.to_string())?;
+
+        Ok(ClickPlayer { _stream: stream })
+    }
+
+    fn build_stream<T>(
+        device: &cpal::Device,
+        config: &StreamConfig,
+        period: Duration,
+        emitted: Sender<Timestamp>,
+    ) -> Result<Stream, String>
+    where
+        T: SizedSample + FromSample<Sample>,
+    {
+        let channels = config.channels as usize;
+        let sample_rate = config.sample_rate.0;
+        let period = Click::period_in_frames(period, sample_rate);
+        let mut position = 0;
+        device
+            .build_output_stream(
+                config,
+                move |data: &mut [T], info: &OutputCallbackInfo| {
+                    // samples are played after the delay reported by the host
+                    let timestamp = info.timestamp();
+                    let delay = timestamp
+                        .playback
+                        .duration_since(&timestamp.callback)
+                        .unwrap_or_default();
+                    let start = Timestamp::now() + delay;
+
+                    for (frame, samples) in data.chunks_mut(channels).enumerate() {
+                        let click_frame = position % period;
+                        if click_frame == 0 {
+                            let offset = frame as f64 / sample_rate as f64;
+                            let _ = emitted.send(start + Duration::from_secs_f64(offset));
+                        }
+                        let value = T::from_sample(Click::sample(click_frame, sample_rate));
+                        samples.fill(value);
+                        position += 1;
+                    }
+                },
+                |err| error!("A error occured on output stream: {err:?}"),
+                None,
+            )
+            .map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test_click_generator() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 2,
+        };
+        let (sender, receiver) = mpsc::channel();
+        let mut generator = ClickGenerator::new(Duration::from_millis(100), parameters, sender);
+
+        let start = Timestamp::now();
+        let samples = generator.read(9600).unwrap();
+        assert_eq!(samples.len(), 19200);
+
+        // clicks at 0 and 100 ms, silent in between
+        let emitted: Vec<Timestamp> = receiver.try_iter().collect();
+        assert_eq!(emitted.len(), 2);
+        let interval = emitted[1].duration_since(emitted[0]);
+        assert_eq!(interval, Duration::from_millis(100));
+        assert!(emitted[0].duration_since(start) < Duration::from_millis(100));
+
+        let click = &samples[..480];
+        assert!(click.iter().any(|sample| sample.abs() > 0.5));
+        assert!(samples[480..9600].iter().all(|sample| *sample == 0.0));
+        assert_eq!(samples[9600..10080], *click);
+    }
+}
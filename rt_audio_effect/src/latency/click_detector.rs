@@ -0,0 +1,205 @@
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::Duration,
+};
+
+use log::{info, warn};
+
+use crate::{
+    audio::Timestamp,
+    audio_analyzer::{MultiChannel, Spectrum, StreamAnalyzerReceiver},
+};
+
+/// Click found in the analyzed spectrums.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClickDetection {
+    pub emitted: Timestamp,
+    /// Capture time of the newest sample of the first spectrum containing the click.
+    pub captured: Timestamp,
+    pub detected: Timestamp,
+}
+
+/// Looks for emitted clicks in the spectrums of the first analyzed channel. A click is
+/// detected when the energy of a spectrum exceeds the energy of spectrums without clicks.
+pub struct ClickDetector {
+    emitted: Option<Receiver<Timestamp>>,
+    detections: Option<Sender<ClickDetection>>,
+    pending: Option<Timestamp>,
+    floor: f32,
+}
+
+impl ClickDetector {
+    /// Energy ratio between a spectrum with the click and the floor, 10 dB.
+    const THRESHOLD: f32 = 10.0;
+    /// Absolute minimum of the floor, so any click is detected in digital silence.
+    const MIN_FLOOR: f32 = 1e-9;
+    const FLOOR_SMOOTHING: f32 = 0.05;
+    /// Clicks which weren't found, e.g. too quiet, are given up after this time.
+    const TIMEOUT: Duration = Duration::from_millis(500);
+
+    pub fn new() -> Self {
+        Self {
+            emitted: None,
+            detections: None,
+            pending: None,
+            floor: Self::MIN_FLOOR,
+        }
+    }
+
+    /// Channels for emission times of clicks and detections, replacing the previous ones.
+    pub fn connect(&mut self) -> (Sender<Timestamp>, Receiver<ClickDetection>) {
+        let (emitted_sender, emitted) = mpsc::channel();
+        let (detections, detections_receiver) = mpsc::channel();
+        self.emitted = Some(emitted);
+        self.detections = Some(detections);
+        self.pending = None;
+        (emitted_sender, detections_receiver)
+    }
+
+    pub fn disconnect(&mut self) {
+        self.emitted = None;
+        self.detections = None;
+        self.pending = None;
+    }
+
+    fn detect(&mut self, spectrum: &Spectrum) -> Option<ClickDetection> {
+        if let Some(emitted) = &self.emitted {
+            // a click is measured at a time, the next one is taken when it is done
+            for timestamp in emitted.try_iter() {
+                self.pending.get_or_insert(timestamp);
+            }
+        }
+
+        let energy: f32 = spectrum.as_slice().iter().map(|value| value * value).sum();
+        let captured = spectrum.get_timestamp();
+        match self.pending {
+            // the window contains samples captured after the click was emitted
+            Some(emitted) if captured >= emitted => {
+                if energy > self.floor * Self::THRESHOLD {
+                    self.pending = None;
+                    return Some(ClickDetection {
+                        emitted,
+                        captured,
+                        detected: Timestamp::now(),
+                    });
+                }
+                if captured.duration_since(emitted) > Self::TIMEOUT {
+                    warn!("Click emitted at {emitted} not detected");
+                    self.pending = None;
+                }
+            }
+            _ => {
+                self.floor = (self.floor * (1.0 - Self::FLOOR_SMOOTHING)
+                    + energy * Self::FLOOR_SMOOTHING)
+                    .max(Self::MIN_FLOOR);
+            }
+        }
+        None
+    }
+}
+
+impl Default for ClickDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamAnalyzerReceiver for ClickDetector {
+    fn receive(&mut self, spectrums: &MultiChannel<Spectrum>) {
+        if spectrums.len() == 0 {
+            return;
+        }
+        let Some(detection) = self.detect(spectrums.get_channel(0)) else {
+            return;
+        };
+        info!(
+            "Click emitted at {} detected after {} ms",
+            detection.emitted,
+            detection
+                .detected
+                .duration_since(detection.emitted)
+                .as_millis()
+        );
+        if let Some(detections) = &self.detections {
+            let _ = detections.send(detection);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        audio::{
+            file_stream::{FileStream, PlaybackMode},
+            AudioStreamConsumer, StreamParameters,
+        },
+        audio_analyzer::StreamAnalyzer,
+        latency::ClickGenerator,
+    };
+
+    #[test]
+    fn test_detects_generated_clicks() {
+        let parameters = StreamParameters {
+            sample_rate: 48000,
+            channels: 1,
+        };
+        let analyzer = Arc::new(Mutex::new(StreamAnalyzer::new(
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            4800,
+            Arc::new(parameters.clone()),
+        )));
+        let detector = Arc::new(Mutex::new(ClickDetector::new()));
+        analyzer.lock().unwrap().register_receiver(detector.clone());
+        let (emitted, detections) = detector.lock().unwrap().connect();
+
+        let generator = ClickGenerator::new(Duration::from_millis(250), parameters, emitted);
+        let mut stream = FileStream::from_decoder(Box::new(generator), PlaybackMode::RealTime);
+        crate::audio::AudioInput::add_stream_consumer(&mut stream, analyzer.clone());
+        for _ in 0..100 {
+            stream.pump(480).unwrap();
+            analyzer.lock().unwrap().process_new_samples();
+        }
+
+        // 1 s with a click every 250 ms, each found in the first window containing it
+        let detections: Vec<ClickDetection> = detections.try_iter().collect();
+        assert_eq!(detections.len(), 4);
+        for detection in detections {
+            let capture = detection.captured.duration_since(detection.emitted);
+            assert!(detection.captured >= detection.emitted);
+            // within one refresh time, the samples are stamped when pumped
+            assert!(capture < Duration::from_millis(30), "{capture:?}");
+        }
+    }
+
+    #[test]
+    fn test_ignores_spectrums_before_click() {
+        let mut detector = ClickDetector::new();
+        let (emitted, _detections) = detector.connect();
+
+        let quiet: Spectrum = vec![1e-3; 100].into();
+        let loud: Spectrum = vec![1.0; 100].into();
+        for index in 0..10 {
+            let timestamp = Timestamp::from_duration(Duration::from_millis(index * 20));
+            assert_eq!(
+                detector.detect(&quiet.clone().with_timestamp(timestamp)),
+                None
+            );
+        }
+
+        // loud spectrum captured before the emission isn't the click
+        emitted
+            .send(Timestamp::from_duration(Duration::from_millis(300)))
+            .unwrap();
+        let early = Timestamp::from_duration(Duration::from_millis(290));
+        assert_eq!(detector.detect(&loud.clone().with_timestamp(early)), None);
+
+        let late = Timestamp::from_duration(Duration::from_millis(310));
+        let detection = detector.detect(&loud.clone().with_timestamp(late)).unwrap();
+        assert_eq!(detection.captured, late);
+        assert_eq!(detector.detect(&loud.with_timestamp(late)), None);
+    }
+}
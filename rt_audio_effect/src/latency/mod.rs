@@ -0,0 +1,9 @@
+pub mod calibration;
+pub mod click;
+pub mod click_detector;
+pub mod simulated_headlight;
+
+pub use calibration::*;
+pub use click::*;
+pub use click_detector::*;
+pub use simulated_headlight::*;
@@ -0,0 +1,78 @@
+use std::{
+    net::{Shutdown, SocketAddr, TcpStream},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use headlight_if::{EchoMessage, FlashAckMessage, Message};
+use log::{debug, info};
+use serializer::ByteMessagePort;
+
+/// Headlight emulated on the host, so the latency can be measured without hardware.
+/// Connects to the service like the firmware and acknowledges flashes after `link_delay`
+/// in each direction and `processing` in the device.
+pub struct SimulatedHeadlight {
+    stream: TcpStream,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SimulatedHeadlight {
+    pub const DEFAULT_LINK_DELAY: Duration = Duration::from_millis(2);
+    pub const DEFAULT_PROCESSING: Duration = Duration::from_millis(1);
+
+    pub fn connect(
+        address: SocketAddr,
+        link_delay: Duration,
+        processing: Duration,
+    ) -> Result<SimulatedHeadlight, String> {
+        let stream = TcpStream::connect(address)
+            .map_err(|err| format!("Failed to connect to {address}: {err}"))?;
+        info!("Simulated headlight connected to {address}");
+
+        let mut port =
+            ByteMessagePort::<Message>::new(stream.try_clone().map_err(|err| err.to_string())?);
+        // the service waits for the first message of a new client
+        port.send(Message::Echo(EchoMessage {
+            message: String::from("Simulated headlight"),
+        }))?;
+
+        let thread = thread::spawn(move || {
+            // origin of the device clock, unrelated to the clock of the host
+            let boot = Instant::now();
+            // ends when the connection is shut down
+            while let Ok(message) = port.recv() {
+                let Message::Flash(flash) = message else {
+                    debug!("Simulated headlight ignores: {message:?}");
+                    continue;
+                };
+                thread::sleep(link_delay);
+                let received_us = boot.elapsed().as_micros() as u64;
+                thread::sleep(processing);
+                let ack = FlashAckMessage {
+                    id: flash.id,
+                    received_us,
+                    lit_us: boot.elapsed().as_micros() as u64,
+                };
+                thread::sleep(link_delay);
+                if port.send(Message::FlashAck(ack)).is_err() {
+                    break;
+                }
+            }
+            info!("Simulated headlight disconnected");
+        });
+
+        Ok(SimulatedHeadlight {
+            stream,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for SimulatedHeadlight {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -1,12 +1,18 @@
 mod audio;
 mod audio_analyzer;
+// nothing consumes bass annotations in the app yet, only its tests run it
+#[cfg(test)]
+mod audio_annotator;
+mod latency;
 mod logger;
 mod ui;
 
 use egui_glfw::AppWindow;
+use headlight_if::ServiceProperties;
 use log::{error, info};
 use service::AudioHeadlightService;
 use std::{
+    net::SocketAddr,
     sync::{Arc, Mutex},
     thread,
     time::Duration,
@@ -14,16 +20,31 @@ use std::{
 use ui::central_panel::HeatMapImage;
 
 use crate::{
-    audio::{audio_stream::AudioStream, AudioManager, AudioStreamConsumer},
+    audio::{
+        audio_stream::AudioStream,
+        file_stream::{FileStream, FileStreamConfig, PlaybackMode},
+        pipe_stream::{PipeStream, PipeStreamConfig},
+        stream_recorder::StreamRecorder,
+        stream_supervisor::StreamSupervisor,
+        AudioInput, AudioManager, AudioStreamConsumer,
+    },
     audio_analyzer::StreamAnalyzer,
+    latency::{
+        CalibrationConfig, ClickDetector, ClickGenerator, ClickPlayer, ClickSource,
+        LatencyCalibration, LatencyResults, SimulatedHeadlight,
+    },
     service::ServiceRegister,
-    ui::ui_controller::UiController,
+    ui::{
+        audio_source_selection::AudioSourceRequest, calibration_selection::CalibrationRequest,
+        ui_controller::UiController,
+    },
 };
 
 mod service;
 
 const SCREEN_WIDTH: u32 = 1920;
 const SCREEN_HEIGHT: u32 = 1080;
+const REPLAY_DURATION: Duration = Duration::from_secs(30);
 extern crate serializer;
 
 fn main() {
@@ -35,10 +56,30 @@ fn main() {
         eprintln!("log::set_logger failed: {err:#?}");
     }
 
+    let input_config = FileStreamConfig::from_args(std::env::args().skip(1)).and_then(
+        |(file_config, args)| match (file_config, PipeStreamConfig::from_args(args)?) {
+            (Some(_), Some(_)) => Err(String::from("Only one of a file and a pipe can be given")),
+            (Some(file_config), None) => Ok(ExternalInput::File(file_config)),
+            (None, Some(pipe_config)) => Ok(ExternalInput::Pipe(pipe_config)),
+            (None, None) => Ok(ExternalInput::None),
+        },
+    );
+    let input_config = match input_config {
+        Ok(input_config) => input_config,
+        Err(err) => {
+            eprintln!(
+                "{err}\nUsage: rt_audio_efect [{} | {}]",
+                FileStreamConfig::USAGE,
+                PipeStreamConfig::USAGE
+            );
+            std::process::exit(2);
+        }
+    };
+
     let mut service_register = ServiceRegister::new();
-    let service = service_register.add_service("RtAudioEffect");
+    let service = service_register.add_service("RtAudioEffect", ServiceProperties::from_env());
 
-    let mut context = AppContext::new(service);
+    let mut context = AppContext::new(service, input_config);
 
     if context.run() {
         info!("RtAudioEffect exit successfully");
@@ -47,55 +88,116 @@ fn main() {
     }
 }
 
+/// Parts of a running latency calibration.
+struct ActiveCalibration {
+    calibration: LatencyCalibration,
+    click_player: Option<ClickPlayer>,
+    simulated_device: Option<SimulatedHeadlight>,
+    /// External source replaced by the click generator, restored at the end.
+    replaced_source: Option<Option<Box<dyn AudioInput>>>,
+}
+
+/// Source given on the command line instead of the device.
+enum ExternalInput {
+    None,
+    File(FileStreamConfig),
+    Pipe(PipeStreamConfig),
+}
+
 struct AppContext {
-    audio_stream: Arc<Mutex<AudioStream>>,
     analyzer: Arc<Mutex<StreamAnalyzer>>,
+    recorder: Arc<Mutex<StreamRecorder>>,
+    supervisor: StreamSupervisor,
     app_window: AppWindow,
     ui_controller: UiController,
     service: Arc<Mutex<AudioHeadlightService>>,
+    click_detector: Arc<Mutex<ClickDetector>>,
+    latency_results: Arc<Mutex<LatencyResults>>,
+    calibration: Option<ActiveCalibration>,
 }
 
 impl AppContext {
-    fn new(service: Arc<Mutex<AudioHeadlightService>>) -> AppContext {
-        let audio_stream = Arc::new(Mutex::new(
-            AudioStream::new(AudioManager::get_default_loopback().unwrap()).unwrap(),
-        ));
+    fn new(service: Arc<Mutex<AudioHeadlightService>>, input_config: ExternalInput) -> AppContext {
+        let external_source: Option<Box<dyn AudioInput>> = match input_config {
+            ExternalInput::None => None,
+            ExternalInput::File(file_config) => match file_config.open() {
+                Ok(file_stream) => Some(Box::new(file_stream)),
+                Err(err) => {
+                    error!("Failed to open {}: {err}", file_config.path.display());
+                    None
+                }
+            },
+            ExternalInput::Pipe(pipe_config) => Some(Box::new(PipeStream::new(pipe_config))),
+        };
+
+        // cpal streams can't be sent between threads, so the supervisor runs in the UI loop
+        let mut supervisor = match external_source {
+            // the device is opened only if the user switches to it
+            Some(external_source) => StreamSupervisor::with_external_source(external_source, true),
+            None => StreamSupervisor::new(
+                Arc::new(Mutex::new(
+                    AudioStream::new(&AudioManager::get_default_loopback().unwrap()).unwrap(),
+                )),
+                true,
+            ),
+        };
 
         let analyzer = Arc::new(Mutex::new(StreamAnalyzer::new(
             Duration::from_secs_f32(0.02),
             Duration::from_secs_f32(1.0),
             4800,
-            audio_stream.lock().unwrap().get_parameters(),
+            supervisor.get_source_parameters(),
         )));
 
-        audio_stream
+        // idle until a calibration connects to it
+        let click_detector = Arc::new(Mutex::new(ClickDetector::new()));
+        analyzer
             .lock()
             .unwrap()
-            .add_stream_consumer(analyzer.clone());
+            .register_receiver(click_detector.clone());
+        let latency_results = Arc::new(Mutex::new(LatencyResults::default()));
+
+        let recorder = Arc::new(Mutex::new(StreamRecorder::new(
+            supervisor.get_source_parameters(),
+            StreamRecorder::directory_from_env(),
+            REPLAY_DURATION,
+        )));
+
+        supervisor.add_stream_consumer(analyzer.clone());
+        supervisor.add_stream_consumer(recorder.clone());
 
         let app_window = AppWindow::new_default(SCREEN_WIDTH, SCREEN_HEIGHT);
 
         let ui_controller = UiController::new(
             analyzer.clone(),
-            audio_stream.clone(),
+            supervisor.get_audio_stream(),
+            supervisor.get_source_parameters(),
+            recorder.clone(),
+            latency_results.clone(),
             HeatMapImage::new(app_window.get_egui_context()),
         );
         ui_controller.set_text_styles(&app_window.egui_context, 15.0);
 
         AppContext {
-            audio_stream,
             analyzer,
+            recorder,
+            supervisor,
             app_window,
             ui_controller,
             service,
+            click_detector,
+            latency_results,
+            calibration: None,
         }
     }
 
     fn run(&mut self) -> bool {
         let analyzer_clone = self.analyzer.clone();
+        let recorder_clone = self.recorder.clone();
         let analyzer_thread = thread::spawn(move || {
             while analyzer_clone.lock().unwrap().is_alive() {
                 analyzer_clone.lock().unwrap().process_new_samples();
+                recorder_clone.lock().unwrap().process_new_samples();
             }
         });
 
@@ -105,9 +207,13 @@ impl AppContext {
         // let msg: Message = connection.recv_message().into();
         // println!("Received: {:#?}", msg);
 
-        self.audio_stream.lock().unwrap().start();
+        // not opened yet with an external source
+        if let Some(audio_stream) = self.supervisor.get_audio_stream() {
+            audio_stream.lock().unwrap().start();
+        }
 
         let mut last_time = std::time::Instant::now();
+        let mut last_supervisor_poll = std::time::Instant::now();
         let mut filtered_elapsed_time = std::time::Duration::from_secs_f32(0.0);
 
         while !self.app_window.window.should_close() {
@@ -123,18 +229,147 @@ impl AppContext {
             let egui_context = self.app_window.get_egui_context();
 
             self.ui_controller.update_data(elapsed_time);
+            // requests and the supervisor replace the source or open the device
+            self.ui_controller.set_source(
+                self.supervisor.get_audio_stream(),
+                self.supervisor.get_source_parameters(),
+            );
 
             egui::CentralPanel::default().show(&egui_context, |ui| {
                 ui.add(self.ui_controller.get_central_panel(fps));
             });
 
             self.app_window.end_frame();
+
+            if let Some(request) = self.ui_controller.take_audio_source_request() {
+                self.handle_audio_source_request(request);
+            }
+
+            if let Some(request) = self.ui_controller.take_calibration_request() {
+                match request {
+                    CalibrationRequest::Start(config) => {
+                        if let Err(err) = self.start_calibration(config) {
+                            error!("Failed to start latency calibration: {err}");
+                            self.ui_controller.set_calibration_running(false);
+                        }
+                    }
+                    CalibrationRequest::Stop => self.stop_calibration(),
+                }
+            }
+
+            if last_supervisor_poll.elapsed() >= StreamSupervisor::POLL_PERIOD {
+                last_supervisor_poll = std::time::Instant::now();
+                self.supervisor.poll();
+            }
         }
 
-        self.audio_stream.lock().unwrap().stop();
+        self.stop_calibration();
+        if let Some(audio_stream) = self.supervisor.get_audio_stream() {
+            audio_stream.lock().unwrap().stop();
+        }
         self.analyzer.lock().unwrap().kill();
         analyzer_thread.join().unwrap();
+        if let Err(err) = self.recorder.lock().unwrap().stop_recording() {
+            error!("{err}");
+        }
 
         true
     }
+
+    fn handle_audio_source_request(&mut self, request: AudioSourceRequest) {
+        let supervisor = &mut self.supervisor;
+        match request {
+            AudioSourceRequest::Device(source) => {
+                supervisor.set_follow_default(false);
+                if let Err(err) = supervisor.switch_source(&source) {
+                    error!("Failed to open {source}: {err}");
+                }
+            }
+            AudioSourceRequest::FollowDefault(follow_default) => {
+                supervisor.set_follow_default(follow_default);
+            }
+            AudioSourceRequest::TestSignal(signal) => {
+                supervisor.set_test_signal(signal);
+            }
+            AudioSourceRequest::Network(config) => {
+                if let Err(err) = supervisor.set_network_source(config) {
+                    error!("Failed to open network source: {err}");
+                }
+            }
+        }
+    }
+
+    fn start_calibration(&mut self, config: CalibrationConfig) -> Result<(), String> {
+        self.stop_calibration();
+        if self.supervisor.get_test_signal().is_some() {
+            return Err(String::from("the test signal would hide clicks"));
+        }
+        info!("Latency calibration: {config:?}");
+
+        // a real headlight connected to the service is flashed otherwise
+        let simulated_device = match config.simulated_device {
+            true => {
+                let port = self.service.lock().unwrap().get_port();
+                Some(SimulatedHeadlight::connect(
+                    SocketAddr::from(([127, 0, 0, 1], port)),
+                    SimulatedHeadlight::DEFAULT_LINK_DELAY,
+                    SimulatedHeadlight::DEFAULT_PROCESSING,
+                )?)
+            }
+            false => None,
+        };
+
+        let (emitted, detections) = self.click_detector.lock().unwrap().connect();
+        let mut click_player = None;
+        let mut replaced_source = None;
+        match config.click_source {
+            ClickSource::Generator => {
+                let parameters = (*self.supervisor.get_source_parameters()).clone();
+                let generator = ClickGenerator::new(config.period, parameters, emitted);
+                let click_stream =
+                    FileStream::from_decoder(Box::new(generator), PlaybackMode::RealTime);
+                replaced_source = Some(
+                    self.supervisor
+                        .replace_external_source(Some(Box::new(click_stream)))?,
+                );
+            }
+            ClickSource::OutputDevice => {
+                click_player = Some(ClickPlayer::new(config.period, emitted)?);
+            }
+        }
+
+        self.latency_results.lock().unwrap().clear();
+        self.calibration = Some(ActiveCalibration {
+            calibration: LatencyCalibration::start(
+                detections,
+                Box::new(self.service.clone()),
+                self.latency_results.clone(),
+            ),
+            click_player,
+            simulated_device,
+            replaced_source,
+        });
+        Ok(())
+    }
+
+    fn stop_calibration(&mut self) {
+        let Some(active) = self.calibration.take() else {
+            return;
+        };
+        let ActiveCalibration {
+            calibration,
+            click_player,
+            simulated_device,
+            replaced_source,
+        } = active;
+        drop(calibration);
+        drop(click_player);
+        drop(simulated_device);
+        self.click_detector.lock().unwrap().disconnect();
+        if let Some(source) = replaced_source {
+            if let Err(err) = self.supervisor.set_external_source(source) {
+                error!("Failed to restore the audio source: {err}");
+            }
+        }
+    }
 }
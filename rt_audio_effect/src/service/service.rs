@@ -2,15 +2,19 @@ use std::{
     net::TcpListener,
     sync::{Arc, Mutex},
     thread::{sleep, JoinHandle},
-    time::{Duration, Instant},
+    time::Duration,
 };
 
-use log::{error, info};
+use log::{error, info, warn};
 use mdns_sd::ServiceInfo;
 
 use super::ServiceClient;
+use crate::latency::FlashTarget;
 
-use headlight_if::{Message, SetColorMessage, SetServo};
+use headlight_if::{
+    Easing, FlashAckMessage, FlashMessage, KeepaliveMessage, Message, ServoKeyframe, ServoPath,
+    SetColorMessage, KEEPALIVE_PERIOD,
+};
 
 struct ServiceSharedCtx {
     is_alive: bool,
@@ -24,7 +28,7 @@ pub struct AudioHeadlightService {
     shared_ctx: Arc<Mutex<ServiceSharedCtx>>,
     listner_thread: Option<JoinHandle<()>>,
     rainbow_thread: Option<JoinHandle<()>>,
-    servo_thread: Option<JoinHandle<()>>,
+    keepalive_thread: Option<JoinHandle<()>>,
 }
 
 impl AudioHeadlightService {
@@ -44,7 +48,7 @@ impl AudioHeadlightService {
             })),
             listner_thread: None,
             rainbow_thread: None,
-            servo_thread: None,
+            keepalive_thread: None,
         }
     }
 
@@ -80,6 +84,10 @@ impl AudioHeadlightService {
                             error!("Failed to receive message: {}", err);
                         }
                     }
+                    // device repeats the swing, so it's sent once
+                    if let Err(err) = last_client.send_message(Message::ServoPath(Self::swing())) {
+                        error!("Failed to send servo path to {}: {}", addr, err);
+                    }
                 }
                 Err(_) => {
                     sleep(Duration::from_millis(100));
@@ -88,42 +96,62 @@ impl AudioHeadlightService {
         }
     }
 
+    /// Clients which can't be sent to are disconnected.
+    fn send_to_all<F>(shared_ctx: &Mutex<ServiceSharedCtx>, message: F)
+    where
+        F: Fn() -> Message,
+    {
+        shared_ctx.lock().unwrap().clients.retain_mut(|client| {
+            match client.send_message(message()) {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!("Disconnecting {}: {}", client.addr, err);
+                    false
+                }
+            }
+        });
+    }
+
     fn rainbow_thread(shared_ctx: Arc<Mutex<ServiceSharedCtx>>) {
         let mut hue = 0;
         while shared_ctx.lock().unwrap().is_alive {
             let rgb = Rgb::from_hsv(hue, 100, 100);
-            for client in shared_ctx.lock().unwrap().clients.iter_mut() {
-                client
-                    .send_message(Message::SetColor(SetColorMessage {
-                        r: rgb.r,
-                        g: rgb.g,
-                        b: rgb.b,
-                    }))
-                    .unwrap();
-            }
+            Self::send_to_all(&shared_ctx, || {
+                Message::SetColor(SetColorMessage {
+                    r: rgb.r,
+                    g: rgb.g,
+                    b: rgb.b,
+                })
+            });
             hue = (hue + 1) % 360;
             sleep(Duration::from_millis(30));
         }
     }
-    fn servo_thread(shared_ctx: Arc<Mutex<ServiceSharedCtx>>) {
-        let dt = 0.01;
-        let mut time = 0.0;
-        let mut timer = Instant::now();
+    /// Swings servo from side to side, device interpolates motion between keyframes.
+    fn swing() -> ServoPath {
+        let half_period_ms = 500;
+        ServoPath {
+            id: 0,
+            keyframes: vec![
+                ServoKeyframe {
+                    position: 1.0,
+                    duration_ms: half_period_ms,
+                    easing: Easing::EaseInOut,
+                },
+                ServoKeyframe {
+                    position: -1.0,
+                    duration_ms: half_period_ms,
+                    easing: Easing::EaseInOut,
+                },
+            ],
+            looping: true,
+        }
+    }
+    /// Devices time out a host which stops sending, other messages may pause for long.
+    fn keepalive_thread(shared_ctx: Arc<Mutex<ServiceSharedCtx>>) {
         while shared_ctx.lock().unwrap().is_alive {
-            // generate sin wave
-            let elapsed = timer.elapsed();
-            timer = Instant::now();
-            time += elapsed.as_secs_f32();
-            let pos = ((2.0 * std::f32::consts::PI) * (time / 1.0)).sin();
-            for client in shared_ctx.lock().unwrap().clients.iter_mut() {
-                client
-                    .send_message(Message::SetServo(SetServo {
-                        id: 0,
-                        position: pos,
-                    }))
-                    .unwrap();
-            }
-            sleep(Duration::from_secs_f32(dt));
+            Self::send_to_all(&shared_ctx, || Message::Keepalive(KeepaliveMessage {}));
+            sleep(KEEPALIVE_PERIOD);
         }
     }
     pub fn start(&mut self) {
@@ -141,20 +169,42 @@ impl AudioHeadlightService {
             }));
         }
 
-        if let None = self.servo_thread {
+        if let None = self.keepalive_thread {
             let shared_ctx = self.shared_ctx.clone();
-            self.servo_thread = Some(std::thread::spawn(move || {
-                Self::servo_thread(shared_ctx);
+            self.keepalive_thread = Some(std::thread::spawn(move || {
+                Self::keepalive_thread(shared_ctx);
             }));
         }
     }
 
+    /// Port of the listener, devices find it through mDNS.
+    pub fn get_port(&self) -> u16 {
+        self.service_info.get_port()
+    }
+
+    /// Flashes the most recently connected headlight and waits for its acknowledgement.
+    /// Other messages to clients are held back in the meantime.
+    pub fn flash(&self, flash: FlashMessage, timeout: Duration) -> Result<FlashAckMessage, String> {
+        let mut shared_ctx = self.shared_ctx.lock().unwrap();
+        let client = shared_ctx
+            .clients
+            .last_mut()
+            .ok_or(String::from("No headlight connected"))?;
+        client.port.flash(flash, timeout)
+    }
+
     pub fn stop(&mut self) {
         self.shared_ctx.lock().unwrap().is_alive = false;
         self.listner_thread.take().map(|t| t.join().unwrap());
     }
 }
 
+impl FlashTarget for Arc<Mutex<AudioHeadlightService>> {
+    fn flash(&mut self, flash: FlashMessage, timeout: Duration) -> Result<FlashAckMessage, String> {
+        self.lock().unwrap().flash(flash, timeout)
+    }
+}
+
 struct Rgb {
     r: u8,
     g: u8,
@@ -3,6 +3,7 @@ use std::{
     sync::{Arc, Mutex},
 };
 
+use headlight_if::ServiceProperties;
 use log::info;
 use mdns_sd::{ServiceDaemon, ServiceInfo};
 
@@ -23,18 +24,24 @@ impl ServiceRegister {
         }
     }
 
-    pub fn add_service(&mut self, name: &str) -> Arc<Mutex<AudioHeadlightService>> {
+    pub fn add_service(
+        &mut self,
+        name: &str,
+        properties: ServiceProperties,
+    ) -> Arc<Mutex<AudioHeadlightService>> {
         let listner = TcpListener::bind("0.0.0.0:0").expect("Failed to bind to random port");
         let port = listner.local_addr().expect("").port();
         info!("{} is listening on {}:{}", name, "localhost", port);
+        info!("{} properties: {:?}", name, properties);
 
+        // instance and host names have to be unique, when there are multiple hosts in the network
         let service_info = ServiceInfo::new(
             &format!("_{}._tcp.local.", name),
-            name,
-            &format!("{}.local.", name),
+            &format!("{} {}", name, properties.instance_id),
+            &format!("{}-{}.local.", name, properties.host_label()),
             "0.0.0.0",
             port,
-            None,
+            properties.to_txt(),
         )
         .unwrap()
         .enable_addr_auto();
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use crate::audio_analyzer::AnalyzerParameters;
+
+/// Text of the analyzer parameter inputs, kept between frames while being edited.
+#[derive(Default)]
+pub struct AnalyzerParametersEditor {
+    pub spectrum_width: String,
+    pub overlap: String,
+    pub history: String,
+    /// Why the last edit was rejected by the analyzer.
+    pub error: Option<String>,
+    shown: Option<AnalyzerParameters>,
+}
+
+impl AnalyzerParametersEditor {
+    /// Resets the texts when the parameters were changed, e.g. by a new stream.
+    pub fn update(&mut self, parameters: &AnalyzerParameters) {
+        if self.shown.as_ref() == Some(parameters) {
+            return;
+        }
+        self.spectrum_width = parameters.spectrum_width.to_string();
+        self.overlap = format!("{:.1}", parameters.get_overlap() * 100.0);
+        self.history = format!("{:.1}", parameters.spectrogram_duration.as_secs_f32());
+        self.shown = Some(parameters.clone());
+    }
+
+    /// Result of applying an edit, rejected values are replaced by the current ones.
+    pub fn set_result(&mut self, result: Result<(), String>) {
+        if result.is_err() {
+            self.shown = None;
+        }
+        self.error = result.err();
+    }
+
+    /// Parameters with a new spectrum width, the overlap is kept.
+    pub fn with_spectrum_width(
+        parameters: &AnalyzerParameters,
+        width: usize,
+    ) -> AnalyzerParameters {
+        AnalyzerParameters {
+            spectrum_width: width,
+            ..parameters.clone()
+        }
+        .with_overlap(parameters.get_overlap())
+    }
+
+    /// Parameters with a new overlap, in percent, at most 99 %.
+    pub fn with_overlap(parameters: &AnalyzerParameters, percent: f32) -> AnalyzerParameters {
+        let overlap = match percent.is_finite() {
+            true => (percent / 100.0).clamp(0.0, 0.99),
+            false => 0.0,
+        };
+        parameters.with_overlap(overlap)
+    }
+
+    /// Parameters with a new history length, in seconds.
+    pub fn with_history(parameters: &AnalyzerParameters, seconds: f32) -> AnalyzerParameters {
+        AnalyzerParameters {
+            // rejected by the validation of the analyzer
+            spectrogram_duration: Duration::try_from_secs_f32(seconds).unwrap_or_default(),
+            ..parameters.clone()
+        }
+    }
+}
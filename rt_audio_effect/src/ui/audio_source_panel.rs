@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use egui::{CollapsingHeader, ComboBox, Ui};
+use egui_addons::inputs::clamped_number_input;
+
+use crate::audio::{network_stream::NetworkStreamConfig, rtp::PayloadFormat, AudioSource};
+
+use super::audio_source_selection::AudioSourceSelection;
+
+/// Device, test signal and network source selection, keeps the texts of the network inputs.
+#[derive(Default)]
+pub struct AudioSourcePanel {
+    sample_rate: String,
+    channels: String,
+    latency: String,
+    shown: Option<NetworkStreamConfig>,
+}
+
+impl AudioSourcePanel {
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        selection: &mut AudioSourceSelection,
+        current_source: Option<&AudioSource>,
+    ) {
+        CollapsingHeader::new("Audio source")
+            .default_open(true)
+            .show(ui, |ui| {
+                Self::draw_devices(ui, selection, current_source);
+                Self::draw_test_signals(ui, selection);
+                self.draw_network(ui, selection);
+            });
+    }
+
+    fn draw_devices(
+        ui: &mut Ui,
+        selection: &mut AudioSourceSelection,
+        current_source: Option<&AudioSource>,
+    ) {
+        let mut requested = None;
+        ComboBox::from_id_source("audio_source")
+            .selected_text(
+                current_source.map_or(String::from("None"), |source| source.device_name.clone()),
+            )
+            .width(ui.available_width())
+            .show_ui(ui, |ui| {
+                for device in selection.get_devices() {
+                    let mut text = device.source.to_string();
+                    if device.is_default {
+                        text += " [default]";
+                    }
+                    let configs = device
+                        .supported_configs
+                        .iter()
+                        .map(|config| {
+                            format!(
+                                "{} ch, {}-{} Hz, {}",
+                                config.channels(),
+                                config.min_sample_rate().0,
+                                config.max_sample_rate().0,
+                                config.sample_format()
+                            )
+                        })
+                        .collect::<Vec<String>>()
+                        .join("\n");
+
+                    if ui
+                        .selectable_label(current_source == Some(&device.source), text)
+                        .on_hover_text(configs)
+                        .clicked()
+                    {
+                        requested = Some(device.source.clone());
+                    }
+                }
+            });
+        let mut follow_default = selection.is_follow_default();
+        if ui
+            .checkbox(&mut follow_default, "Follow default device")
+            .changed()
+        {
+            selection.request_follow_default(follow_default);
+        }
+        if ui.button("Refresh devices").clicked() {
+            selection.refresh();
+        }
+        if let Some(source) = requested {
+            selection.request_device(source);
+        }
+    }
+
+    fn draw_test_signals(ui: &mut Ui, selection: &mut AudioSourceSelection) {
+        let mut requested = None;
+        let current_signal = selection.get_test_signal().cloned();
+        ComboBox::from_label("Test signal")
+            .selected_text(
+                current_signal
+                    .as_ref()
+                    .map_or(String::from("Off"), |signal| signal.to_string()),
+            )
+            .show_ui(ui, |ui| {
+                if ui
+                    .selectable_label(current_signal.is_none(), "Off")
+                    .clicked()
+                {
+                    requested = Some(None);
+                }
+                for signal in selection.get_test_signals() {
+                    if ui
+                        .selectable_label(
+                            current_signal.as_ref() == Some(signal),
+                            signal.to_string(),
+                        )
+                        .clicked()
+                    {
+                        requested = Some(Some(signal.clone()));
+                    }
+                }
+            });
+        if let Some(signal) = requested {
+            selection.request_test_signal(signal);
+        }
+    }
+
+    fn draw_network(&mut self, ui: &mut Ui, selection: &mut AudioSourceSelection) {
+        let mut network_enabled = selection.is_network_enabled();
+        if ui.checkbox(&mut network_enabled, "Network (RTP)").changed() {
+            selection.request_network(network_enabled);
+        }
+        ui.add_enabled_ui(!network_enabled, |ui| {
+            let config = selection.get_network_config_mut();
+            if self.shown.as_ref() != Some(config) {
+                self.sample_rate = config.parameters.sample_rate.to_string();
+                self.channels = config.parameters.channels.to_string();
+                self.latency = config.latency.as_millis().to_string();
+                self.shown = Some(config.clone());
+            }
+
+            let mut address = config.address.to_string();
+            if ui.text_edit_singleline(&mut address).changed() {
+                if let Ok(address) = address.parse() {
+                    config.address = address;
+                }
+            }
+            ComboBox::from_label("Format")
+                .selected_text(config.format.to_string())
+                .show_ui(ui, |ui| {
+                    for format in [PayloadFormat::L16, PayloadFormat::L24] {
+                        ui.selectable_value(&mut config.format, format, format.to_string());
+                    }
+                });
+            ui.horizontal(|ui| {
+                if let Some(sample_rate) = clamped_number_input(
+                    ui,
+                    "Sample rate [Hz]:",
+                    &mut self.sample_rate,
+                    8000..=192000,
+                ) {
+                    config.parameters.sample_rate = sample_rate;
+                }
+            });
+            ui.horizontal(|ui| {
+                if let Some(channels) =
+                    clamped_number_input(ui, "Channels:", &mut self.channels, 1..=64)
+                {
+                    config.parameters.channels = channels;
+                }
+            });
+            ui.horizontal(|ui| {
+                if let Some(latency) =
+                    clamped_number_input(ui, "Jitter buffer [ms]:", &mut self.latency, 1..=1000)
+                {
+                    config.latency = Duration::from_millis(latency);
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use crate::audio::{
+    network_stream::NetworkStreamConfig, signal_generator::Signal, AudioDeviceInfo, AudioManager,
+    AudioSource,
+};
+
+pub enum AudioSourceRequest {
+    /// Switch to the device and stop following the default one.
+    Device(AudioSource),
+    FollowDefault(bool),
+    /// Analyze generated signal instead of the device, `None` switches back.
+    TestSignal(Option<Signal>),
+    /// Receive audio over the network instead of the device, `None` switches back.
+    Network(Option<NetworkStreamConfig>),
+}
+
+/// Devices offered in the UI and the source chosen by the user, applied by the main loop.
+pub struct AudioSourceSelection {
+    devices: Vec<AudioDeviceInfo>,
+    test_signals: Vec<Signal>,
+    follow_default: bool,
+    test_signal: Option<Signal>,
+    network_config: NetworkStreamConfig,
+    network_enabled: bool,
+    requested: Option<AudioSourceRequest>,
+}
+
+impl AudioSourceSelection {
+    pub fn new() -> Self {
+        Self {
+            devices: AudioManager::get_all_devices(),
+            test_signals: Self::default_test_signals(),
+            follow_default: true,
+            test_signal: None,
+            network_config: NetworkStreamConfig::default(),
+            network_enabled: false,
+            requested: None,
+        }
+    }
+
+    fn default_test_signals() -> Vec<Signal> {
+        vec![
+            Signal::Sine { frequency: 1000.0 },
+            Signal::MultiTone {
+                frequencies: vec![100.0, 1000.0, 10000.0],
+            },
+            Signal::Sweep {
+                start_frequency: 20.0,
+                end_frequency: 20000.0,
+                duration: Duration::from_secs(10),
+            },
+            Signal::WhiteNoise,
+            Signal::PinkNoise,
+            Signal::ImpulseTrain {
+                period: Duration::from_millis(100),
+            },
+            Signal::Kick {
+                beats_per_minute: 120.0,
+            },
+        ]
+    }
+
+    pub fn refresh(&mut self) {
+        self.devices = AudioManager::get_all_devices();
+    }
+
+    pub fn get_devices(&self) -> &[AudioDeviceInfo] {
+        &self.devices
+    }
+
+    pub fn is_follow_default(&self) -> bool {
+        self.follow_default
+    }
+
+    pub fn request_device(&mut self, source: AudioSource) {
+        self.follow_default = false;
+        self.requested = Some(AudioSourceRequest::Device(source));
+    }
+
+    pub fn request_follow_default(&mut self, follow_default: bool) {
+        self.follow_default = follow_default;
+        self.requested = Some(AudioSourceRequest::FollowDefault(follow_default));
+    }
+
+    pub fn get_test_signals(&self) -> &[Signal] {
+        &self.test_signals
+    }
+
+    pub fn get_test_signal(&self) -> Option<&Signal> {
+        self.test_signal.as_ref()
+    }
+
+    pub fn request_test_signal(&mut self, signal: Option<Signal>) {
+        self.test_signal = signal.clone();
+        self.requested = Some(AudioSourceRequest::TestSignal(signal));
+    }
+
+    /// Edited in the UI, applied when the network source is enabled.
+    pub fn get_network_config_mut(&mut self) -> &mut NetworkStreamConfig {
+        &mut self.network_config
+    }
+
+    pub fn is_network_enabled(&self) -> bool {
+        self.network_enabled
+    }
+
+    pub fn request_network(&mut self, enabled: bool) {
+        self.network_enabled = enabled;
+        let config = enabled.then(|| self.network_config.clone());
+        self.requested = Some(AudioSourceRequest::Network(config));
+    }
+
+    pub fn take_requested(&mut self) -> Option<AudioSourceRequest> {
+        self.requested.take()
+    }
+}
+
+impl Default for AudioSourceSelection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
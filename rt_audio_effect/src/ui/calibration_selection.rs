@@ -0,0 +1,42 @@
+use crate::latency::CalibrationConfig;
+
+pub enum CalibrationRequest {
+    Start(CalibrationConfig),
+    Stop,
+}
+
+/// Latency calibration configured in the UI, started and stopped by the main loop.
+#[derive(Default)]
+pub struct CalibrationSelection {
+    config: CalibrationConfig,
+    running: bool,
+    requested: Option<CalibrationRequest>,
+}
+
+impl CalibrationSelection {
+    /// Edited in the UI, applied when the calibration is started.
+    pub fn get_config_mut(&mut self) -> &mut CalibrationConfig {
+        &mut self.config
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Reported by the main loop, e.g. when the calibration failed to start.
+    pub fn set_running(&mut self, running: bool) {
+        self.running = running;
+    }
+
+    pub fn request(&mut self, running: bool) {
+        self.running = running;
+        self.requested = Some(match running {
+            true => CalibrationRequest::Start(self.config.clone()),
+            false => CalibrationRequest::Stop,
+        });
+    }
+
+    pub fn take_requested(&mut self) -> Option<CalibrationRequest> {
+        self.requested.take()
+    }
+}
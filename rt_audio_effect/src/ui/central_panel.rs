@@ -1,26 +1,54 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
 
 use egui::{
-    load::SizedTexture, vec2, Align, CollapsingHeader, Color32, ColorImage, Context, ImageData,
-    Layout, Sense, TextureOptions, Ui, Vec2, Widget,
+    load::SizedTexture, vec2, Align, CollapsingHeader, Color32, ColorImage, ComboBox, Context,
+    ImageData, Layout, Sense, TextureOptions, Ui, Vec2, Widget,
 };
-use egui_addons::layouts::add_columns;
+use egui_addons::{inputs::number_input, layouts::add_columns};
+use log::error;
 
-use crate::{audio::audio_stream::AudioStream, audio_analyzer::AudioAnalyzysProvider};
+use crate::{
+    audio::{
+        audio_stream::AudioStream, stream_recorder::StreamRecorder, AudioInput, StreamParameters,
+    },
+    audio_analyzer::{AnalyzerParameters, AudioAnalyzysProvider, ZeroPadding},
+    latency::LatencyResults,
+};
 
+use super::analyzer_parameters_editor::AnalyzerParametersEditor;
+use super::audio_source_panel::AudioSourcePanel;
+use super::audio_source_selection::AudioSourceSelection;
+use super::calibration_selection::CalibrationSelection;
+use super::channel_routing_panel::channel_routing_panel;
+use super::constant_q_panel::ConstantQPanel;
+use super::display_format_panel::DisplayFormatPanel;
+use super::health_panel::health_panel;
+use super::input_conditioning_panel::InputConditioningPanel;
+use super::latency_calibration_panel::LatencyCalibrationPanel;
+use super::mel_panel::MelPanel;
+use super::octave_bands_panel::octave_bands_panel;
 use super::plot::spectrum::{
     spectrogram_renderer::SpectrogramRenderer,
     spectrogram_renderer_widget::SpectrogramRendererWidget, spectrum_renderer::SpectrumRenderer,
     spectrum_renderer_widget::SprectrumRendererWidget,
 };
+use super::window_panel::WindowPanel;
 
 pub struct CentralPanel {
     audio_analyzer: Arc<Mutex<dyn AudioAnalyzysProvider>>,
-    audio_stream: Arc<Mutex<AudioStream>>,
-    spectrum_left: SprectrumRendererWidget,
-    spectrum_right: SprectrumRendererWidget,
-    spectrogram_left: SpectrogramRendererWidget,
-    spectrogram_right: SpectrogramRendererWidget,
+    audio_stream: Option<Arc<Mutex<AudioStream>>>,
+    stream_parameters: Arc<StreamParameters>,
+    recorder: Arc<Mutex<StreamRecorder>>,
+    audio_source_selection: Arc<Mutex<AudioSourceSelection>>,
+    calibration_selection: Arc<Mutex<CalibrationSelection>>,
+    settings_panels: Arc<Mutex<SettingsPanels>>,
+    frequency_axis: Arc<Mutex<FrequencyAxis>>,
+    latency_results: Arc<Mutex<LatencyResults>>,
+    spectrums: Vec<SprectrumRendererWidget>,
+    spectrograms: Vec<SpectrogramRendererWidget>,
     heat_map: HeatMapImage,
     _auto_range: bool,
     fps: f32,
@@ -29,11 +57,16 @@ pub struct CentralPanel {
 impl CentralPanel {
     pub fn build(
         audio_analyzer: Arc<Mutex<dyn AudioAnalyzysProvider>>,
-        audio_stream: Arc<Mutex<AudioStream>>,
-        spectrum_renderer_left: Arc<Mutex<SpectrumRenderer>>,
-        spectrum_renderer_right: Arc<Mutex<SpectrumRenderer>>,
-        spectrogram_renderer_left: Arc<Mutex<SpectrogramRenderer>>,
-        spectrogram_renderer_right: Arc<Mutex<SpectrogramRenderer>>,
+        audio_stream: Option<Arc<Mutex<AudioStream>>>,
+        stream_parameters: Arc<StreamParameters>,
+        recorder: Arc<Mutex<StreamRecorder>>,
+        audio_source_selection: Arc<Mutex<AudioSourceSelection>>,
+        calibration_selection: Arc<Mutex<CalibrationSelection>>,
+        settings_panels: Arc<Mutex<SettingsPanels>>,
+        frequency_axis: Arc<Mutex<FrequencyAxis>>,
+        latency_results: Arc<Mutex<LatencyResults>>,
+        spectrum_renderers: Vec<Arc<Mutex<SpectrumRenderer>>>,
+        spectrogram_renderers: Vec<Arc<Mutex<SpectrogramRenderer>>>,
         heat_map: HeatMapImage,
         auto_range: bool,
         fps: f32,
@@ -41,34 +74,95 @@ impl CentralPanel {
         Self {
             audio_analyzer,
             audio_stream,
-            spectrum_left: SprectrumRendererWidget {
-                renderer: spectrum_renderer_left,
-            },
-            spectrum_right: SprectrumRendererWidget {
-                renderer: spectrum_renderer_right,
-            },
-            spectrogram_left: SpectrogramRendererWidget {
-                renderer: spectrogram_renderer_left,
-            },
-            spectrogram_right: SpectrogramRendererWidget {
-                renderer: spectrogram_renderer_right,
-            },
+            stream_parameters,
+            recorder,
+            audio_source_selection,
+            calibration_selection,
+            settings_panels,
+            frequency_axis,
+            latency_results,
+            spectrums: spectrum_renderers
+                .into_iter()
+                .map(|renderer| SprectrumRendererWidget { renderer })
+                .collect(),
+            spectrograms: spectrogram_renderers
+                .into_iter()
+                .map(|renderer| SpectrogramRendererWidget { renderer })
+                .collect(),
             heat_map,
             _auto_range: auto_range,
             fps,
         }
     }
+
+    /// Takes only the analyzer, so the drawing closures don't borrow the whole panel.
+    fn reconfigure(
+        audio_analyzer: &Mutex<dyn AudioAnalyzysProvider>,
+        editor: &mut AnalyzerParametersEditor,
+        parameters: AnalyzerParameters,
+    ) {
+        let result = audio_analyzer.lock().unwrap().reconfigure(parameters);
+        if let Err(error) = &result {
+            error!("Analyzer parameters rejected: {error}");
+        }
+        editor.set_result(result);
+    }
 }
 
 impl Widget for CentralPanel {
     fn ui(mut self, ui: &mut egui::Ui) -> egui::Response {
-        let stream_parameters = self.audio_stream.lock().unwrap().get_parameters();
-        let analyzer_parameters = self
-            .audio_analyzer
-            .lock()
-            .unwrap()
-            .get_analyzer_parameters();
+        let stream_parameters = self.stream_parameters.clone();
+        // the device isn't opened while an external source is given at start
+        let (current_source, callback_statistics) = match &self.audio_stream {
+            Some(audio_stream) => {
+                let audio_stream = audio_stream.lock().unwrap();
+                (
+                    Some(audio_stream.get_source().clone()),
+                    Some(audio_stream.get_statistics()),
+                )
+            }
+            None => (None, None),
+        };
+        let (analyzer_parameters, latest_timestamp, display_format) = {
+            let analyzer = self.audio_analyzer.lock().unwrap();
+            (
+                analyzer.get_analyzer_parameters(),
+                analyzer.get_latest_timestamp(),
+                analyzer.get_display_format(),
+            )
+        };
+        let (channel_names, input_channels, channel_routing) = {
+            let analyzer = self.audio_analyzer.lock().unwrap();
+            (
+                analyzer.get_channel_names(),
+                analyzer.get_input_channels(),
+                analyzer.get_channel_routing(),
+            )
+        };
+        let (octave_bands, octave_band_frequencies) = {
+            let analyzer = self.audio_analyzer.lock().unwrap();
+            (
+                analyzer.get_latest_octave_bands(),
+                analyzer.get_octave_band_frequencies(),
+            )
+        };
+        let analyzer_statistics = self.audio_analyzer.lock().unwrap().get_statistics();
+        let (conditioning_settings, conditioning_status) = {
+            let analyzer = self.audio_analyzer.lock().unwrap();
+            (
+                analyzer.get_conditioning_settings(),
+                analyzer.get_conditioning_status(),
+            )
+        };
 
+        let draw_audio_source = |ui: &mut Ui| {
+            let mut selection = self.audio_source_selection.lock().unwrap();
+            self.settings_panels.lock().unwrap().audio_source.ui(
+                ui,
+                &mut selection,
+                current_source.as_ref(),
+            );
+        };
         let draw_stream_parameters = |ui: &mut Ui| {
             CollapsingHeader::new("Stream parameters")
                 .default_open(true)
@@ -89,7 +183,10 @@ impl Widget for CentralPanel {
                 .show(ui, |ui| {
                     ui.horizontal(|ui| {
                         ui.label("Refresh time:");
-                        ui.label(analyzer_parameters.refresh_time.as_millis().to_string() + " ms");
+                        ui.label(format!(
+                            "{:.1} ms",
+                            analyzer_parameters.refresh_time.as_secs_f32() * 1000.0
+                        ));
                     });
                     ui.horizontal(|ui| {
                         ui.label("Spectrogram durations:");
@@ -101,46 +198,283 @@ impl Widget for CentralPanel {
                                 + " s",
                         );
                     });
+                    ui.horizontal(|ui| {
+                        ui.label("Spectrum age:");
+                        ui.label(format!("{} ms", latest_timestamp.elapsed().as_millis()))
+                            .on_hover_text("Time since capture of the newest analyzed sample");
+                    });
                 });
         };
+        let draw_channel_routing = |ui: &mut Ui| {
+            if let Some(routing) = channel_routing_panel(ui, &channel_routing, input_channels) {
+                self.audio_analyzer
+                    .lock()
+                    .unwrap()
+                    .set_channel_routing(routing);
+            }
+        };
+        let draw_input_conditioning = |ui: &mut Ui| {
+            let settings = self.settings_panels.lock().unwrap().input_conditioning.ui(
+                ui,
+                &conditioning_settings,
+                &conditioning_status,
+            );
+            if let Some(settings) = settings {
+                self.audio_analyzer
+                    .lock()
+                    .unwrap()
+                    .set_conditioning_settings(settings);
+            }
+        };
         let draw_fft_parameters = |ui: &mut Ui| {
             CollapsingHeader::new("FFT parameters")
                 .default_open(true)
                 .show(ui, |ui| {
+                    let mut panels = self.settings_panels.lock().unwrap();
+                    let panels = &mut *panels;
+                    let editor = &mut panels.analyzer_parameters;
+                    editor.update(&analyzer_parameters);
+                    let mut requested = None;
                     ui.horizontal(|ui| {
-                        ui.label("Spectrum width:");
-                        ui.label(analyzer_parameters.spectrum_width.to_string());
+                        if let Some(width) =
+                            number_input::<usize>(ui, "Spectrum width:", &mut editor.spectrum_width)
+                        {
+                            requested = Some(AnalyzerParametersEditor::with_spectrum_width(
+                                &analyzer_parameters,
+                                width,
+                            ));
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        if let Some(percent) =
+                            number_input::<f32>(ui, "Overlap [%]:", &mut editor.overlap)
+                        {
+                            requested = Some(AnalyzerParametersEditor::with_overlap(
+                                &analyzer_parameters,
+                                percent,
+                            ));
+                        }
                     });
                     ui.horizontal(|ui| {
                         ui.label("Refresh time in samples:");
                         ui.label(analyzer_parameters.refresh_time_in_samples.to_string());
                     });
+                    ui.horizontal(|ui| {
+                        if let Some(seconds) =
+                            number_input::<f32>(ui, "History [s]:", &mut editor.history)
+                        {
+                            requested = Some(AnalyzerParametersEditor::with_history(
+                                &analyzer_parameters,
+                                seconds,
+                            ));
+                        }
+                    });
+                    let next_power_of_two = analyzer_parameters.spectrum_width.next_power_of_two();
+                    ComboBox::from_label("Zero padding")
+                        .selected_text(analyzer_parameters.zero_padding.to_string())
+                        .show_ui(ui, |ui| {
+                            for padding in [
+                                ZeroPadding::None,
+                                ZeroPadding::NextPowerOfTwo,
+                                ZeroPadding::Size(2 * next_power_of_two),
+                                ZeroPadding::Size(4 * next_power_of_two),
+                            ] {
+                                if ui
+                                    .selectable_label(
+                                        padding == analyzer_parameters.zero_padding,
+                                        padding.to_string(),
+                                    )
+                                    .clicked()
+                                {
+                                    requested = Some(AnalyzerParameters {
+                                        zero_padding: padding,
+                                        ..(*analyzer_parameters).clone()
+                                    });
+                                }
+                            }
+                        });
+                    ui.horizontal(|ui| {
+                        ui.label("FFT size:");
+                        ui.label(format!(
+                            "{} ({:.2} Hz bins)",
+                            analyzer_parameters.get_fft_size(),
+                            analyzer_parameters.get_bin_width()
+                        ));
+                    });
+                    if let Some(parameters) = requested {
+                        Self::reconfigure(&self.audio_analyzer, editor, parameters);
+                    }
+                    if let Some(error) = &editor.error {
+                        ui.colored_label(ui.visuals().error_fg_color, error);
+                    }
+
+                    if let Some((window, scaling)) = panels.window.ui(
+                        ui,
+                        analyzer_parameters.window,
+                        analyzer_parameters.scaling,
+                    ) {
+                        self.audio_analyzer
+                            .lock()
+                            .unwrap()
+                            .set_spectrum_scaling(window, scaling);
+                    }
+                    if let Some(format) = panels.display_format.ui(ui, display_format) {
+                        self.audio_analyzer
+                            .lock()
+                            .unwrap()
+                            .set_display_format(format);
+                    }
+
+                    let mut frequency_axis = self.frequency_axis.lock().unwrap();
+                    ComboBox::from_label("Frequency axis")
+                        .selected_text(frequency_axis.to_string())
+                        .show_ui(ui, |ui| {
+                            for axis in FrequencyAxis::ALL {
+                                ui.selectable_value(&mut *frequency_axis, axis, axis.to_string());
+                            }
+                        });
+                    let sample_rate = stream_parameters.sample_rate;
+                    let requested = match *frequency_axis {
+                        FrequencyAxis::Linear => None,
+                        FrequencyAxis::ConstantQ => panels
+                            .constant_q
+                            .ui(ui, analyzer_parameters.constant_q, sample_rate)
+                            .map(|constant_q| AnalyzerParameters {
+                                constant_q,
+                                ..(*analyzer_parameters).clone()
+                            }),
+                        FrequencyAxis::Mel => panels
+                            .mel
+                            .ui(ui, analyzer_parameters.mel, sample_rate)
+                            .map(|mel| AnalyzerParameters {
+                                mel,
+                                ..(*analyzer_parameters).clone()
+                            }),
+                    };
+                    if let Some(parameters) = requested {
+                        Self::reconfigure(&self.audio_analyzer, editor, parameters);
+                    }
                 });
         };
+        let draw_octave_bands = |ui: &mut Ui| {
+            let requested = octave_bands_panel(
+                ui,
+                analyzer_parameters.octave_bands,
+                &channel_names,
+                &octave_bands,
+                &octave_band_frequencies,
+                display_format,
+            );
+            if let Some(octave_bands) = requested {
+                let mut panels = self.settings_panels.lock().unwrap();
+                Self::reconfigure(
+                    &self.audio_analyzer,
+                    &mut panels.analyzer_parameters,
+                    AnalyzerParameters {
+                        octave_bands,
+                        ..(*analyzer_parameters).clone()
+                    },
+                );
+            }
+        };
+        let draw_health = |ui: &mut Ui| {
+            if health_panel(ui, callback_statistics.as_ref(), &analyzer_statistics) {
+                if let Some(audio_stream) = &self.audio_stream {
+                    audio_stream.lock().unwrap().reset_statistics();
+                }
+                self.audio_analyzer.lock().unwrap().reset_statistics();
+            }
+        };
+        let draw_latency_calibration = |ui: &mut Ui| {
+            let mut selection = self.calibration_selection.lock().unwrap();
+            let mut results = self.latency_results.lock().unwrap();
+            self.settings_panels.lock().unwrap().latency_calibration.ui(
+                ui,
+                &mut selection,
+                &mut results,
+            );
+        };
         let draw_stream_controls = |ui: &mut Ui| {
+            let Some(audio_stream) = &self.audio_stream else {
+                return;
+            };
             ui.strong("Stream control:");
             ui.columns(2, |uis| {
                 if uis[0].button("Start").clicked() {
-                    self.audio_stream.lock().unwrap().start();
+                    audio_stream.lock().unwrap().start();
                 }
                 if uis[1].button("Stop").clicked() {
-                    self.audio_stream.lock().unwrap().stop();
+                    audio_stream.lock().unwrap().stop();
+                }
+            })
+        };
+        let draw_recording_controls = |ui: &mut Ui| {
+            let mut recorder = self.recorder.lock().unwrap();
+            let device_name = current_source
+                .as_ref()
+                .map_or(String::from("external input"), ToString::to_string);
+            ui.strong("Recording:");
+            ui.columns(2, |uis| {
+                let result = match recorder.get_recording_duration() {
+                    Some(duration) => {
+                        let text = format!("Stop ({:.0} s)", duration.as_secs_f32());
+                        if uis[0].button(text).clicked() {
+                            recorder.stop_recording().map(|_| ())
+                        } else {
+                            Ok(())
+                        }
+                    }
+                    None if uis[0].button("Record").clicked() => {
+                        recorder.start_recording(&device_name).map(|_| ())
+                    }
+                    None => Ok(()),
+                };
+                if let Err(err) = result {
+                    error!("{err}");
+                }
+
+                let text = format!("Save last {} s", recorder.get_pre_roll().as_secs());
+                if uis[1].button(text).clicked() {
+                    if let Err(err) = recorder.save_replay(&device_name) {
+                        error!("{err}");
+                    }
                 }
             })
         };
         let draw_parameters_and_control_panel = |ui: &mut Ui| {
+            draw_audio_source(ui);
+            ui.separator();
+
             draw_stream_parameters(ui);
             ui.separator();
 
             draw_analyzer_parameters(ui);
             ui.separator();
 
+            draw_channel_routing(ui);
+            ui.separator();
+
+            draw_input_conditioning(ui);
+            ui.separator();
+
             draw_fft_parameters(ui);
             ui.separator();
 
+            draw_octave_bands(ui);
+            ui.separator();
+
+            draw_health(ui);
+            ui.separator();
+
+            draw_latency_calibration(ui);
+            ui.separator();
+
             draw_stream_controls(ui);
             ui.separator();
 
+            draw_recording_controls(ui);
+            ui.separator();
+
             CollapsingHeader::new("Magnitude colors")
                 .default_open(true)
                 .show(ui, |ui| {
@@ -173,12 +507,16 @@ impl Widget for CentralPanel {
                 draw_parameters_and_control_panel,
             );
 
-            add_columns(ui, 2, |ui| {
-                ui[0].add_sized(ui[0].available_size() / vec2(1.0, 2.0), self.spectrum_left);
-                ui[0].add(self.spectrogram_left);
-
-                ui[1].add_sized(ui[1].available_size() / vec2(1.0, 2.0), self.spectrum_right);
-                ui[1].add(self.spectrogram_right);
+            let channels = self.spectrums.len().min(self.spectrograms.len());
+            add_columns(ui, channels.max(1) as i32, |ui| {
+                let widgets = self.spectrums.into_iter().zip(self.spectrograms);
+                for ((ui, name), (spectrum, spectrogram)) in
+                    ui.iter_mut().zip(&channel_names).zip(widgets)
+                {
+                    ui.label(name);
+                    ui.add_sized(ui.available_size() / vec2(1.0, 2.0), spectrum);
+                    ui.add(spectrogram);
+                }
             });
             response
         })
@@ -186,6 +524,46 @@ impl Widget for CentralPanel {
     }
 }
 
+/// Panels of the settings, kept between frames for the texts of their inputs.
+#[derive(Default)]
+pub struct SettingsPanels {
+    pub analyzer_parameters: AnalyzerParametersEditor,
+    pub audio_source: AudioSourcePanel,
+    pub input_conditioning: InputConditioningPanel,
+    pub window: WindowPanel,
+    pub display_format: DisplayFormatPanel,
+    pub constant_q: ConstantQPanel,
+    pub mel: MelPanel,
+    pub latency_calibration: LatencyCalibrationPanel,
+}
+
+/// Bins of the spectrum and spectrogram views.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FrequencyAxis {
+    #[default]
+    Linear,
+    ConstantQ,
+    Mel,
+}
+
+impl FrequencyAxis {
+    pub const ALL: [FrequencyAxis; 3] = [
+        FrequencyAxis::Linear,
+        FrequencyAxis::ConstantQ,
+        FrequencyAxis::Mel,
+    ];
+}
+
+impl Display for FrequencyAxis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrequencyAxis::Linear => write!(f, "Linear"),
+            FrequencyAxis::ConstantQ => write!(f, "Constant-Q"),
+            FrequencyAxis::Mel => write!(f, "Mel"),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct HeatMapImage {
     texture_horizontal: egui::TextureHandle,
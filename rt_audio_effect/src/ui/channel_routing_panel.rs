@@ -0,0 +1,30 @@
+use egui::{ComboBox, Ui};
+
+use crate::audio_analyzer::ChannelRouting;
+
+/// Selection of the analyzed channels, returns the routing chosen by the user.
+pub fn channel_routing_panel(
+    ui: &mut Ui,
+    current: &ChannelRouting,
+    input_channels: usize,
+) -> Option<ChannelRouting> {
+    let mut routings = vec![
+        ChannelRouting::AllChannels,
+        ChannelRouting::MonoDownmix,
+        ChannelRouting::MidSide,
+    ];
+    routings.extend((0..input_channels).map(|channel| ChannelRouting::select(&[channel])));
+
+    let mut requested = None;
+    ComboBox::from_label("Channels")
+        .selected_text(current.to_string())
+        .show_ui(ui, |ui| {
+            for routing in routings {
+                let text = routing.to_string();
+                if ui.selectable_label(&routing == current, text).clicked() {
+                    requested = Some(routing);
+                }
+            }
+        });
+    requested
+}
@@ -0,0 +1,68 @@
+use egui::Ui;
+use egui_addons::inputs::clamped_number_input;
+
+use crate::audio_analyzer::ConstantQParameters;
+
+/// Range and resolution of the constant-Q spectrum, the texts are kept between frames.
+#[derive(Default)]
+pub struct ConstantQPanel {
+    bins_per_octave: String,
+    min_frequency: String,
+    max_frequency: String,
+    shown: Option<ConstantQParameters>,
+}
+
+impl ConstantQPanel {
+    /// Returns the parameters edited by the user, the range is limited to the Nyquist frequency.
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        current: ConstantQParameters,
+        sample_rate: u32,
+    ) -> Option<ConstantQParameters> {
+        if self.shown != Some(current) {
+            self.bins_per_octave = current.bins_per_octave.to_string();
+            self.min_frequency = format!("{:.1}", current.min_frequency);
+            self.max_frequency = format!("{:.1}", current.max_frequency);
+            self.shown = Some(current);
+        }
+
+        let mut parameters = current;
+        ui.horizontal(|ui| {
+            if let Some(bins) = clamped_number_input(
+                ui,
+                "Bins per octave:",
+                &mut self.bins_per_octave,
+                1..=ConstantQParameters::MAX_BINS_PER_OCTAVE,
+            ) {
+                parameters.bins_per_octave = bins;
+            }
+        });
+        ui.horizontal(|ui| {
+            if let Some(frequency) = clamped_number_input(
+                ui,
+                "Min frequency [Hz]:",
+                &mut self.min_frequency,
+                10.0..=current.max_frequency / 2.0,
+            ) {
+                parameters.min_frequency = frequency;
+            }
+        });
+        ui.horizontal(|ui| {
+            if let Some(frequency) = clamped_number_input(
+                ui,
+                "Max frequency [Hz]:",
+                &mut self.max_frequency,
+                current.min_frequency * 2.0..=sample_rate as f32 / 2.0,
+            ) {
+                parameters.max_frequency = frequency;
+            }
+        });
+        if parameters == current {
+            return None;
+        }
+        // shown as applied by the analyzer, which may reject them
+        self.shown = None;
+        Some(parameters)
+    }
+}
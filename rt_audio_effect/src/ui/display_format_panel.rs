@@ -0,0 +1,52 @@
+use egui::{ComboBox, Ui};
+use egui_addons::inputs::clamped_number_input;
+
+use crate::audio_analyzer::{FrequencyWeighting, MagnitudeScale, SpectrumFormat};
+
+/// Weighting and units of the displayed spectrums, keeps the text of the decibel floor.
+#[derive(Default)]
+pub struct DisplayFormatPanel {
+    floor: String,
+    shown: Option<SpectrumFormat>,
+}
+
+impl DisplayFormatPanel {
+    /// Returns the format edited by the user.
+    pub fn ui(&mut self, ui: &mut Ui, current: SpectrumFormat) -> Option<SpectrumFormat> {
+        if self.shown != Some(current) {
+            if let MagnitudeScale::Decibels { floor } = current.scale {
+                self.floor = format!("{floor:.1}");
+            }
+            self.shown = Some(current);
+        }
+
+        let mut format = current;
+        ComboBox::from_label("Weighting")
+            .selected_text(format.weighting.to_string())
+            .show_ui(ui, |ui| {
+                for weighting in FrequencyWeighting::ALL {
+                    ui.selectable_value(&mut format.weighting, weighting, weighting.to_string());
+                }
+            });
+        let mut decibels = matches!(format.scale, MagnitudeScale::Decibels { .. });
+        if ui.checkbox(&mut decibels, "dBFS").changed() {
+            format.scale = if decibels {
+                MagnitudeScale::Decibels {
+                    floor: MagnitudeScale::DEFAULT_FLOOR,
+                }
+            } else {
+                MagnitudeScale::Linear
+            };
+        }
+        if let MagnitudeScale::Decibels { floor } = &mut format.scale {
+            ui.horizontal(|ui| {
+                if let Some(value) =
+                    clamped_number_input(ui, "Floor [dB]:", &mut self.floor, -200.0..=-20.0)
+                {
+                    *floor = value;
+                }
+            });
+        }
+        (format != current).then_some(format)
+    }
+}
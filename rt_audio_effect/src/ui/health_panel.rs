@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use egui::{CollapsingHeader, Ui};
+
+use crate::{audio::statistics::CallbackStatistics, audio_analyzer::AnalyzerStatistics};
+
+fn milliseconds(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+/// Statistics of the audio callback and the analyzer, returns whether a reset was requested.
+pub fn health_panel(
+    ui: &mut Ui,
+    callback: Option<&CallbackStatistics>,
+    analyzer: &AnalyzerStatistics,
+) -> bool {
+    CollapsingHeader::new("Health")
+        .default_open(false)
+        .show(ui, |ui| {
+            if let Some(callback) = callback {
+                ui.horizontal(|ui| {
+                    ui.label("Callback period:");
+                    ui.label(format!(
+                        "{:.1} ms (max {:.1} ms)",
+                        milliseconds(callback.period.average),
+                        milliseconds(callback.period.max)
+                    ));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Callback jitter:");
+                    ui.label(format!(
+                        "{:.2} ms (max {:.2} ms)",
+                        milliseconds(callback.jitter.average),
+                        milliseconds(callback.jitter.max)
+                    ))
+                    .on_hover_text("Deviation of the period from the duration of its frames");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Dropped callbacks:");
+                    ui.label(callback.dropped_callbacks.to_string());
+                });
+            }
+
+            let buffer = analyzer.buffer;
+            let color = if buffer.is_healthy() {
+                ui.visuals().text_color()
+            } else {
+                ui.visuals().warn_fg_color
+            };
+            ui.horizontal(|ui| {
+                ui.label("Overruns:");
+                ui.colored_label(
+                    color,
+                    format!(
+                        "{} ({} samples skipped, {} dropped)",
+                        buffer.overruns, buffer.skipped_samples, buffer.dropped_samples
+                    ),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Underruns:");
+                ui.label(buffer.underruns.to_string());
+            });
+
+            let load = analyzer.get_load();
+            let color = if analyzer.processing.max > analyzer.budget {
+                ui.visuals().error_fg_color
+            } else if load > 0.5 {
+                ui.visuals().warn_fg_color
+            } else {
+                ui.visuals().text_color()
+            };
+            ui.horizontal(|ui| {
+                ui.label("Processing:");
+                ui.colored_label(
+                    color,
+                    format!(
+                        "{:.2} ms (max {:.2} ms), {:.0} % of {} ms",
+                        milliseconds(analyzer.processing.average),
+                        milliseconds(analyzer.processing.max),
+                        load * 100.0,
+                        analyzer.budget.as_millis()
+                    ),
+                )
+                .on_hover_text("Analysis of one frame has to fit into the refresh time");
+            });
+            ui.button("Reset").clicked()
+        })
+        .body_returned
+        .unwrap_or(false)
+}
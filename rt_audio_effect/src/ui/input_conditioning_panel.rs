@@ -0,0 +1,115 @@
+use egui::{CollapsingHeader, Ui};
+use egui_addons::inputs::clamped_number_input;
+
+use crate::audio::input_conditioning::{
+    gain_to_db, ConditioningSettings, ConditioningStatus, FilterSettings,
+};
+
+/// Texts of the filter inputs.
+#[derive(Default)]
+struct FilterInputs {
+    frequency: String,
+    q: String,
+}
+
+impl FilterInputs {
+    fn update(&mut self, filter: &FilterSettings) {
+        self.frequency = format!("{:.1}", filter.frequency);
+        self.q = format!("{:.2}", filter.q);
+    }
+
+    fn ui(&mut self, ui: &mut Ui, name: &str, filter: &mut FilterSettings) {
+        ui.checkbox(&mut filter.enabled, name);
+        ui.horizontal(|ui| {
+            if let Some(frequency) =
+                clamped_number_input(ui, "Frequency [Hz]:", &mut self.frequency, 10.0..=20000.0)
+            {
+                filter.frequency = frequency;
+            }
+        });
+        ui.horizontal(|ui| {
+            if let Some(q) = clamped_number_input(ui, "Q:", &mut self.q, 0.1..=10.0) {
+                filter.q = q;
+            }
+        });
+    }
+}
+
+/// Input conditioning settings, the texts of the inputs are kept between frames.
+#[derive(Default)]
+pub struct InputConditioningPanel {
+    gain: String,
+    high_pass: FilterInputs,
+    low_pass: FilterInputs,
+    agc_target_level: String,
+    shown: Option<ConditioningSettings>,
+}
+
+impl InputConditioningPanel {
+    /// Returns the settings edited by the user.
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        current: &ConditioningSettings,
+        status: &ConditioningStatus,
+    ) -> Option<ConditioningSettings> {
+        self.update(current);
+        let mut settings = current.clone();
+        CollapsingHeader::new("Input conditioning")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut settings.gain_enabled, "Gain");
+                ui.horizontal(|ui| {
+                    if let Some(gain) =
+                        clamped_number_input(ui, "Gain [dB]:", &mut self.gain, -40.0..=40.0)
+                    {
+                        settings.gain_db = gain;
+                    }
+                });
+                ui.checkbox(&mut settings.dc_blocker_enabled, "DC blocker");
+                self.high_pass.ui(ui, "High-pass", &mut settings.high_pass);
+                self.low_pass.ui(ui, "Low-pass", &mut settings.low_pass);
+                ui.checkbox(&mut settings.agc.enabled, "AGC");
+                ui.horizontal(|ui| {
+                    if let Some(level) = clamped_number_input(
+                        ui,
+                        "Target [dBFS]:",
+                        &mut self.agc_target_level,
+                        -40.0..=0.0,
+                    ) {
+                        settings.agc.target_level_db = level;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Peak in/out:");
+                    ui.label(format!(
+                        "{:.1} / {:.1} dBFS",
+                        gain_to_db(status.input_peak),
+                        gain_to_db(status.output_peak)
+                    ));
+                });
+                if settings.agc.enabled {
+                    ui.horizontal(|ui| {
+                        ui.label("AGC gain:");
+                        ui.label(format!("{:.1} dB", status.agc_gain_db));
+                    });
+                }
+                if ui.button("Reset").clicked() {
+                    settings = ConditioningSettings::default();
+                }
+            });
+        (settings != *current).then_some(settings)
+    }
+
+    /// Resets the texts when the settings were changed.
+    fn update(&mut self, settings: &ConditioningSettings) {
+        if self.shown.as_ref() == Some(settings) {
+            return;
+        }
+        self.gain = format!("{:.1}", settings.gain_db);
+        self.high_pass.update(&settings.high_pass);
+        self.low_pass.update(&settings.low_pass);
+        self.agc_target_level = format!("{:.1}", settings.agc.target_level_db);
+        self.shown = Some(settings.clone());
+    }
+}
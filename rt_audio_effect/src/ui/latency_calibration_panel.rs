@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use egui::{CollapsingHeader, ComboBox, Grid, Ui};
+use egui_addons::inputs::clamped_number_input;
+
+use crate::latency::{CalibrationConfig, ClickSource, LatencyReport, LatencyResults};
+
+use super::calibration_selection::CalibrationSelection;
+
+/// Calibration setup and measured latencies, keeps the text of the click period.
+#[derive(Default)]
+pub struct LatencyCalibrationPanel {
+    period: String,
+    shown: Option<CalibrationConfig>,
+}
+
+impl LatencyCalibrationPanel {
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        selection: &mut CalibrationSelection,
+        results: &mut LatencyResults,
+    ) {
+        CollapsingHeader::new("Latency calibration")
+            .default_open(false)
+            .show(ui, |ui| {
+                let running = selection.is_running();
+                ui.add_enabled_ui(!running, |ui| {
+                    self.draw_config(ui, selection.get_config_mut())
+                });
+                let text = if running { "Stop" } else { "Start" };
+                if ui.button(text).clicked() {
+                    selection.request(!running);
+                }
+                Self::draw_results(ui, results);
+            });
+    }
+
+    fn draw_config(&mut self, ui: &mut Ui, config: &mut CalibrationConfig) {
+        if self.shown.as_ref() != Some(config) {
+            self.period = config.period.as_millis().to_string();
+            self.shown = Some(config.clone());
+        }
+
+        ComboBox::from_label("Click")
+            .selected_text(config.click_source.to_string())
+            .show_ui(ui, |ui| {
+                for source in [ClickSource::Generator, ClickSource::OutputDevice] {
+                    ui.selectable_value(&mut config.click_source, source, source.to_string());
+                }
+            });
+        ui.horizontal(|ui| {
+            if let Some(period) =
+                clamped_number_input(ui, "Every [ms]:", &mut self.period, 200..=5000)
+            {
+                config.period = Duration::from_millis(period);
+            }
+        });
+        ui.checkbox(&mut config.simulated_device, "Simulated headlight");
+    }
+
+    fn draw_results(ui: &mut Ui, results: &mut LatencyResults) {
+        let (Some(latest), Some(average)) = (results.get_latest().copied(), results.get_average())
+        else {
+            ui.label("No measurements");
+            return;
+        };
+        type Stage = fn(&LatencyReport) -> Duration;
+        let stages: [(&str, Stage); 5] = [
+            ("Capture", |report| report.capture),
+            ("Analysis", |report| report.analysis),
+            ("Network", |report| report.network),
+            ("Device", |report| report.device),
+            ("Total", |report| report.get_total()),
+        ];
+        Grid::new("latency")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("");
+                ui.label("Latest");
+                ui.label(format!("Average of {}", results.get_count()));
+                ui.end_row();
+                for (name, stage) in stages {
+                    ui.label(name);
+                    ui.label(format!("{:.1} ms", stage(&latest).as_secs_f32() * 1e3));
+                    ui.label(format!("{:.1} ms", stage(&average).as_secs_f32() * 1e3));
+                    ui.end_row();
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.label(format!("Not acknowledged: {}", results.get_failures()));
+            if ui.button("Clear").clicked() {
+                results.clear();
+            }
+        });
+    }
+}
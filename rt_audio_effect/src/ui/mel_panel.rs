@@ -0,0 +1,77 @@
+use egui::{ComboBox, Ui};
+use egui_addons::inputs::clamped_number_input;
+
+use crate::audio_analyzer::{MelParameters, MelScale};
+
+/// Filter bank of the mel spectrum, the texts are kept between frames.
+#[derive(Default)]
+pub struct MelPanel {
+    filters: String,
+    min_frequency: String,
+    max_frequency: String,
+    shown: Option<MelParameters>,
+}
+
+impl MelPanel {
+    /// Returns the parameters edited by the user, the range is limited to the Nyquist frequency.
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        current: MelParameters,
+        sample_rate: u32,
+    ) -> Option<MelParameters> {
+        if self.shown != Some(current) {
+            self.filters = current.filters.to_string();
+            self.min_frequency = format!("{:.1}", current.min_frequency);
+            self.max_frequency = format!("{:.1}", current.max_frequency);
+            self.shown = Some(current);
+        }
+
+        let mut parameters = current;
+        ui.horizontal(|ui| {
+            if let Some(filters) = clamped_number_input(
+                ui,
+                "Filters:",
+                &mut self.filters,
+                1..=MelParameters::MAX_FILTERS,
+            ) {
+                parameters.filters = filters;
+            }
+        });
+        ComboBox::from_label("Mel scale")
+            .selected_text(parameters.scale.to_string())
+            .show_ui(ui, |ui| {
+                for scale in MelScale::ALL {
+                    ui.selectable_value(&mut parameters.scale, scale, scale.to_string());
+                }
+            });
+        ui.horizontal(|ui| {
+            if let Some(frequency) = clamped_number_input(
+                ui,
+                "Min frequency [Hz]:",
+                &mut self.min_frequency,
+                0.0..=current.max_frequency / 2.0,
+            ) {
+                parameters.min_frequency = frequency;
+            }
+        });
+        ui.horizontal(|ui| {
+            if let Some(frequency) = clamped_number_input(
+                ui,
+                "Max frequency [Hz]:",
+                &mut self.max_frequency,
+                (current.min_frequency * 2.0).max(100.0)..=sample_rate as f32 / 2.0,
+            ) {
+                parameters.max_frequency = frequency;
+            }
+        });
+        ui.checkbox(&mut parameters.normalize, "Area normalization")
+            .on_hover_text("Filters average the bins they cover instead of summing them");
+        if parameters == current {
+            return None;
+        }
+        // shown as applied by the analyzer, which may reject them
+        self.shown = None;
+        Some(parameters)
+    }
+}
@@ -1,3 +1,16 @@
+pub mod analyzer_parameters_editor;
+pub mod audio_source_panel;
+pub mod audio_source_selection;
+pub mod calibration_selection;
 pub mod central_panel;
+pub mod channel_routing_panel;
+pub mod constant_q_panel;
+pub mod display_format_panel;
+pub mod health_panel;
+pub mod input_conditioning_panel;
+pub mod latency_calibration_panel;
+pub mod mel_panel;
+pub mod octave_bands_panel;
 pub mod plot;
 pub mod ui_controller;
+pub mod window_panel;
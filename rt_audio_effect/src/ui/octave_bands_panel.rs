@@ -0,0 +1,99 @@
+use egui::{pos2, vec2, CollapsingHeader, ComboBox, Rect, Sense, Ui};
+
+use crate::audio_analyzer::{
+    MagnitudeScale, MultiChannel, OctaveBandParameters, OctaveFraction, Spectrum, SpectrumFormat,
+    TimeWeighting,
+};
+
+/// Band settings and levels per channel, returns the parameters edited by the user.
+pub fn octave_bands_panel(
+    ui: &mut Ui,
+    current: OctaveBandParameters,
+    channel_names: &[String],
+    levels: &MultiChannel<Spectrum>,
+    frequencies: &[f32],
+    format: SpectrumFormat,
+) -> Option<OctaveBandParameters> {
+    let mut parameters = current;
+    CollapsingHeader::new("Octave bands")
+        .default_open(false)
+        .show(ui, |ui| {
+            ComboBox::from_label("Bandwidth")
+                .selected_text(parameters.fraction.to_string())
+                .show_ui(ui, |ui| {
+                    for fraction in OctaveFraction::ALL {
+                        ui.selectable_value(
+                            &mut parameters.fraction,
+                            fraction,
+                            fraction.to_string(),
+                        );
+                    }
+                });
+            ComboBox::from_label("Time weighting")
+                .selected_text(parameters.time_weighting.to_string())
+                .show_ui(ui, |ui| {
+                    for time_weighting in TimeWeighting::ALL {
+                        ui.selectable_value(
+                            &mut parameters.time_weighting,
+                            time_weighting,
+                            time_weighting.to_string(),
+                        );
+                    }
+                });
+
+            for (name, levels) in channel_names.iter().zip(levels) {
+                ui.label(name);
+                draw_band_levels(ui, levels, frequencies, format);
+            }
+        });
+    (parameters != current).then_some(parameters)
+}
+
+/// Bar chart of band levels in the display format. Decibels span the fixed range, linear
+/// levels are scaled to the loudest band.
+fn draw_band_levels(ui: &mut Ui, levels: &Spectrum, frequencies: &[f32], format: SpectrumFormat) {
+    const HEIGHT: f32 = 80.0;
+    let levels: Vec<f32> = levels
+        .as_slice()
+        .iter()
+        .zip(frequencies)
+        .map(|(level, &frequency)| {
+            format
+                .scale
+                .apply(level * format.weighting.get_gain(frequency))
+        })
+        .collect();
+    let (min, max) = format
+        .scale
+        .get_range()
+        .unwrap_or((0.0, levels.iter().copied().fold(f32::EPSILON, f32::max)));
+
+    let (response, painter) =
+        ui.allocate_painter(vec2(ui.available_width(), HEIGHT), Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+    let width = rect.width() / levels.len().max(1) as f32;
+    for (band, level) in levels.iter().enumerate() {
+        let height = ((level - min) / (max - min)).clamp(0.0, 1.0) * rect.height();
+        let left = rect.left() + band as f32 * width;
+        painter.rect_filled(
+            Rect::from_min_max(
+                pos2(left + 0.5, rect.bottom() - height),
+                pos2(left + width - 0.5, rect.bottom()),
+            ),
+            0.0,
+            ui.visuals().selection.bg_fill,
+        );
+    }
+
+    if let Some(position) = response.hover_pos() {
+        let band = ((position.x - rect.left()) / width) as usize;
+        if let (Some(level), Some(frequency)) = (levels.get(band), frequencies.get(band)) {
+            let unit = match format.scale {
+                MagnitudeScale::Linear => "",
+                MagnitudeScale::Decibels { .. } => " dB",
+            };
+            response.on_hover_text(format!("{frequency:.0} Hz: {level:.1}{unit}"));
+        }
+    }
+}
@@ -149,6 +149,8 @@ impl SpectrumRendererShader {
 pub struct SpectrumRenderer {
     program: SpectrumRendererShader,
     spectrums: Option<(Spectrum, Spectrum, Spectrum)>,
+    /// Fixed range of decibel magnitudes, peaks fall to its bottom.
+    magnitude_range: Option<Vec2>,
     // uniforms
     render_config: RenderConfig,
     draw_config: DrawConfig,
@@ -166,6 +168,7 @@ impl SpectrumRenderer {
         Self {
             program: SpectrumRendererShader::new(bar_segments),
             spectrums: None,
+            magnitude_range: None,
             render_config: RenderConfig::new(),
             draw_config: DrawConfig::new(),
         }
@@ -176,7 +179,24 @@ impl SpectrumRenderer {
         end + (start - end) * (-time_step * decay).exp()
     }
 
+    pub fn set_magnitude_range(&mut self, range: Option<(f32, f32)>) {
+        let range = range.map(|(min, max)| Vec2::new(min, max));
+        if range != self.magnitude_range {
+            self.magnitude_range = range;
+            self.spectrums = None;
+        }
+    }
+
     pub fn set_spectrum(&mut self, spectrum: &Spectrum, time_step: Duration) {
+        // the spectrum width can be changed at runtime
+        if self
+            .spectrums
+            .as_ref()
+            .is_some_and(|(last, _, _)| last.len() != spectrum.len())
+        {
+            self.spectrums = None;
+        }
+        let floor = self.magnitude_range.map_or(0.0, |range| range.x);
         if let Some((last, mean, peek)) = &mut self.spectrums {
             *last = spectrum.clone();
             mean.into_iter()
@@ -193,7 +213,7 @@ impl SpectrumRenderer {
                         *current = *new;
                     } else {
                         // *current = *current * 0.9;
-                        *current = Self::exp_decay(*current, 0.0, 0.1, time_step.as_secs_f32());
+                        *current = Self::exp_decay(*current, floor, 0.1, time_step.as_secs_f32());
                     }
                 });
         } else {
@@ -202,6 +222,15 @@ impl SpectrumRenderer {
         let (last, mean, peek) = self.spectrums.as_ref().unwrap();
     }
 
+    /// Magnitudes are drawn within `range`, or between their own extremes when it is `None`.
+    fn min_max(range: Option<Vec2>, magnitudes: &[f32]) -> Vec2 {
+        range.unwrap_or_else(|| {
+            let min = magnitudes.iter().cloned().reduce(f32::min).unwrap();
+            let max = magnitudes.iter().cloned().reduce(f32::max).unwrap();
+            Vec2::new(min, max)
+        })
+    }
+
     pub fn set_render_size(&mut self, size: (u32, u32)) {
         self.render_config.client_size = Vector2::new(size.0 as f32, size.1 as f32);
         let view_matrix =
@@ -217,6 +246,7 @@ impl SpectrumRenderer {
         }
 
         let (last, mean, peek) = self.spectrums.as_ref().unwrap();
+        let range = self.magnitude_range;
 
         self.program.set_magnitdes(mean.as_slice());
         self.program.set_draw_config(&self.draw_config);
@@ -224,9 +254,8 @@ impl SpectrumRenderer {
         // render mean_spectrum
         self.spectrum_mean.bind(Self::MAGNITUDES_BINDING_POINT);
         self.spectrum_mean.store_array(mean.as_slice());
-        let min = mean.into_iter().cloned().reduce(f32::min).unwrap();
-        let max = mean.into_iter().cloned().reduce(f32::max).unwrap() * 0.9;
-        self.min_max_values.buffer_subdata(&Vec2::new(min, max), 0);
+        self.min_max_values
+            .buffer_subdata(&Self::min_max(range, mean.as_slice()), 0);
         self.min_max_values
             .buffer_subdata(&(mean.len() as u32), size_of::<Vec2>() as isize);
         self.min_max_values
@@ -246,9 +275,8 @@ impl SpectrumRenderer {
         // render peek_slow_falling spectrum
         self.spectrum_peek.bind(Self::MAGNITUDES_BINDING_POINT);
         self.spectrum_peek.store_array(peek.as_slice());
-        let min = peek.into_iter().cloned().reduce(f32::min).unwrap();
-        let max = peek.into_iter().cloned().reduce(f32::max).unwrap() * 0.9;
-        self.min_max_values.buffer_subdata(&Vec2::new(min, max), 0);
+        self.min_max_values
+            .buffer_subdata(&Self::min_max(range, peek.as_slice()), 0);
         self.min_max_values
             .buffer_subdata(&(peek.len() as u32), size_of::<Vec2>() as isize);
         self.min_max_values
@@ -267,9 +295,8 @@ impl SpectrumRenderer {
         // render current spectrum
         self.spectrum_current.bind(Self::MAGNITUDES_BINDING_POINT);
         self.spectrum_current.store_array(last.as_slice());
-        let min = last.into_iter().cloned().reduce(f32::min).unwrap();
-        let max = last.into_iter().cloned().reduce(f32::max).unwrap();
-        self.min_max_values.buffer_subdata(&Vec2::new(min, max), 0);
+        self.min_max_values
+            .buffer_subdata(&Self::min_max(range, last.as_slice()), 0);
         self.min_max_values
             .buffer_subdata(&(last.len() as u32), size_of::<Vec2>() as isize);
         self.min_max_values
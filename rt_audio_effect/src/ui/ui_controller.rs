@@ -5,10 +5,16 @@ use std::{
 
 use egui::{FontData, FontDefinitions, FontFamily, FontId, TextStyle, TextureId};
 
-use crate::{audio::audio_stream::AudioStream, audio_analyzer::AudioAnalyzysProvider};
+use crate::{
+    audio::{audio_stream::AudioStream, stream_recorder::StreamRecorder, StreamParameters},
+    audio_analyzer::AudioAnalyzysProvider,
+    latency::LatencyResults,
+};
 
 use super::{
-    central_panel::{CentralPanel, HeatMapImage},
+    audio_source_selection::{AudioSourceRequest, AudioSourceSelection},
+    calibration_selection::{CalibrationRequest, CalibrationSelection},
+    central_panel::{CentralPanel, FrequencyAxis, HeatMapImage, SettingsPanels},
     plot::spectrum::{
         spectrogram_renderer::SpectrogramRenderer, spectrum_renderer::SpectrumRenderer,
     },
@@ -16,11 +22,19 @@ use super::{
 
 pub struct UiController {
     audio_analyzer: Arc<Mutex<dyn AudioAnalyzysProvider>>,
-    audio_stream: Arc<Mutex<AudioStream>>,
-    spectrum_renderer_left: Arc<Mutex<SpectrumRenderer>>,
-    spectrum_renderer_right: Arc<Mutex<SpectrumRenderer>>,
-    spectrogram_renderer_left: Arc<Mutex<SpectrogramRenderer>>,
-    spectrogram_renderer_right: Arc<Mutex<SpectrogramRenderer>>,
+    /// `None` until the device is opened, when started with an external source.
+    audio_stream: Option<Arc<Mutex<AudioStream>>>,
+    /// Parameters of the source feeding the analyzer.
+    stream_parameters: Arc<StreamParameters>,
+    recorder: Arc<Mutex<StreamRecorder>>,
+    audio_source_selection: Arc<Mutex<AudioSourceSelection>>,
+    calibration_selection: Arc<Mutex<CalibrationSelection>>,
+    settings_panels: Arc<Mutex<SettingsPanels>>,
+    frequency_axis: Arc<Mutex<FrequencyAxis>>,
+    latency_results: Arc<Mutex<LatencyResults>>,
+    /// One renderer per analyzed channel.
+    spectrum_renderers: Vec<Arc<Mutex<SpectrumRenderer>>>,
+    spectrogram_renderers: Vec<Arc<Mutex<SpectrogramRenderer>>>,
     heat_map: HeatMapImage,
     auto_range: bool,
 }
@@ -28,62 +42,107 @@ pub struct UiController {
 impl UiController {
     pub fn new(
         audio_analyzer: Arc<Mutex<dyn AudioAnalyzysProvider>>,
-        audio_stream: Arc<Mutex<AudioStream>>,
+        audio_stream: Option<Arc<Mutex<AudioStream>>>,
+        stream_parameters: Arc<StreamParameters>,
+        recorder: Arc<Mutex<StreamRecorder>>,
+        latency_results: Arc<Mutex<LatencyResults>>,
         heat_map: HeatMapImage,
     ) -> Self {
         Self {
             audio_analyzer,
             audio_stream,
-            spectrum_renderer_left: Arc::new(Mutex::new(SpectrumRenderer::new(8))),
-            spectrum_renderer_right: Arc::new(Mutex::new(SpectrumRenderer::new(8))),
-            spectrogram_renderer_left: Arc::new(Mutex::new(SpectrogramRenderer::new())),
-            spectrogram_renderer_right: Arc::new(Mutex::new(SpectrogramRenderer::new())),
+            stream_parameters,
+            recorder,
+            audio_source_selection: Arc::new(Mutex::new(AudioSourceSelection::new())),
+            calibration_selection: Arc::new(Mutex::new(CalibrationSelection::default())),
+            settings_panels: Arc::new(Mutex::new(SettingsPanels::default())),
+            frequency_axis: Arc::new(Mutex::new(FrequencyAxis::default())),
+            latency_results,
+            spectrum_renderers: Vec::new(),
+            spectrogram_renderers: Vec::new(),
             heat_map,
             auto_range: true,
         }
     }
 
-    pub fn update_data(&self, time_step: Duration) {
-        self.spectrum_renderer_left.lock().unwrap().set_spectrum(
-            self.audio_analyzer
-                .lock()
-                .unwrap()
-                .get_latest_spectrum()
-                .get_channel(0),
-            time_step,
-        );
-        self.spectrum_renderer_right.lock().unwrap().set_spectrum(
-            &self
-                .audio_analyzer
-                .lock()
-                .unwrap()
-                .get_latest_spectrum()
-                .get_channel(1),
-            time_step,
-        );
+    pub fn update_data(&mut self, time_step: Duration) {
+        let frequency_axis = *self.frequency_axis.lock().unwrap();
+        let (spectrums, display_format) = {
+            let analyzer = self.audio_analyzer.lock().unwrap();
+            let spectrums = match frequency_axis {
+                FrequencyAxis::Linear => analyzer.get_latest_spectrum(),
+                FrequencyAxis::ConstantQ => analyzer.get_latest_constant_q_spectrum(),
+                FrequencyAxis::Mel => analyzer.get_latest_mel_spectrum(),
+            };
+            (spectrums, analyzer.get_display_format())
+        };
 
-        self.spectrogram_renderer_left.lock().unwrap().buffer_data(
-            self.audio_analyzer
-                .lock()
-                .unwrap()
-                .get_spectrogram_for_channel(0),
-        );
-        self.spectrogram_renderer_right.lock().unwrap().buffer_data(
-            self.audio_analyzer
+        // routing or stream may change the number of analyzed channels
+        self.spectrum_renderers.resize_with(spectrums.len(), || {
+            Arc::new(Mutex::new(SpectrumRenderer::new(8)))
+        });
+        self.spectrogram_renderers.resize_with(spectrums.len(), || {
+            Arc::new(Mutex::new(SpectrogramRenderer::new()))
+        });
+
+        for (channel, spectrum) in spectrums.into_iter().enumerate() {
+            let mut spectrum_renderer = self.spectrum_renderers[channel].lock().unwrap();
+            spectrum_renderer.set_magnitude_range(display_format.scale.get_range());
+            spectrum_renderer.set_spectrum(&spectrum, time_step);
+            let spectrogram = {
+                let analyzer = self.audio_analyzer.lock().unwrap();
+                match frequency_axis {
+                    FrequencyAxis::Linear => analyzer.get_spectrogram_for_channel(channel),
+                    FrequencyAxis::ConstantQ => {
+                        analyzer.get_constant_q_spectrogram_for_channel(channel)
+                    }
+                    FrequencyAxis::Mel => analyzer.get_mel_spectrogram_for_channel(channel),
+                }
+            };
+            self.spectrogram_renderers[channel]
                 .lock()
                 .unwrap()
-                .get_spectrogram_for_channel(1),
-        );
+                .buffer_data(spectrogram);
+        }
+    }
+
+    pub fn set_source(
+        &mut self,
+        audio_stream: Option<Arc<Mutex<AudioStream>>>,
+        stream_parameters: Arc<StreamParameters>,
+    ) {
+        self.audio_stream = audio_stream;
+        self.stream_parameters = stream_parameters;
+    }
+
+    pub fn take_audio_source_request(&self) -> Option<AudioSourceRequest> {
+        self.audio_source_selection.lock().unwrap().take_requested()
+    }
+
+    pub fn take_calibration_request(&self) -> Option<CalibrationRequest> {
+        self.calibration_selection.lock().unwrap().take_requested()
+    }
+
+    pub fn set_calibration_running(&self, running: bool) {
+        self.calibration_selection
+            .lock()
+            .unwrap()
+            .set_running(running);
     }
 
     pub fn get_central_panel(&self, fps: f32) -> CentralPanel {
         CentralPanel::build(
             self.audio_analyzer.clone(),
             self.audio_stream.clone(),
-            self.spectrum_renderer_left.clone(),
-            self.spectrum_renderer_right.clone(),
-            self.spectrogram_renderer_left.clone(),
-            self.spectrogram_renderer_right.clone(),
+            self.stream_parameters.clone(),
+            self.recorder.clone(),
+            self.audio_source_selection.clone(),
+            self.calibration_selection.clone(),
+            self.settings_panels.clone(),
+            self.frequency_axis.clone(),
+            self.latency_results.clone(),
+            self.spectrum_renderers.clone(),
+            self.spectrogram_renderers.clone(),
             self.heat_map.clone(),
             self.auto_range,
             fps,
@@ -0,0 +1,63 @@
+use egui::{ComboBox, Ui};
+use egui_addons::inputs::clamped_number_input;
+
+use crate::audio_analyzer::{SpectrumScaling, WindowFunction};
+
+/// Window function and scaling of the spectrum, keeps the text of the Kaiser beta.
+#[derive(Default)]
+pub struct WindowPanel {
+    beta: String,
+    shown: Option<WindowFunction>,
+}
+
+impl WindowPanel {
+    /// Returns the window and scaling edited by the user.
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        current_window: WindowFunction,
+        current_scaling: SpectrumScaling,
+    ) -> Option<(WindowFunction, SpectrumScaling)> {
+        if self.shown != Some(current_window) {
+            if let WindowFunction::Kaiser { beta } = current_window {
+                self.beta = format!("{beta:.1}");
+            }
+            self.shown = Some(current_window);
+        }
+
+        let (mut window, mut scaling) = (current_window, current_scaling);
+        ComboBox::from_label("Window")
+            .selected_text(window.to_string())
+            .show_ui(ui, |ui| {
+                for function in WindowFunction::ALL {
+                    let selected =
+                        std::mem::discriminant(&function) == std::mem::discriminant(&window);
+                    if ui
+                        .selectable_label(selected, function.to_string())
+                        .clicked()
+                        && !selected
+                    {
+                        window = function;
+                    }
+                }
+            });
+        if let WindowFunction::Kaiser { beta } = &mut window {
+            ui.horizontal(|ui| {
+                if let Some(value) = clamped_number_input(ui, "β:", &mut self.beta, 0.0..=20.0) {
+                    *beta = value;
+                }
+            });
+        }
+        ComboBox::from_label("Scaling")
+            .selected_text(scaling.to_string())
+            .show_ui(ui, |ui| {
+                for option in [
+                    SpectrumScaling::Amplitude,
+                    SpectrumScaling::PowerSpectralDensity,
+                ] {
+                    ui.selectable_value(&mut scaling, option, option.to_string());
+                }
+            });
+        (window != current_window || scaling != current_scaling).then_some((window, scaling))
+    }
+}
@@ -4,6 +4,7 @@ pub use serializer_macro::ByteMessage;
 use std::{
     io::{Read, Write},
     net::TcpStream,
+    time::Duration,
 };
 
 pub trait ByteMessage {
@@ -19,7 +20,11 @@ pub struct ByteMessagePort<T> {
 }
 
 impl<T: ByteMessage + Default> ByteMessagePort<T> {
+    /// Messages are small commands, so Nagle's algorithm is disabled to send them right away.
     pub fn new(stream: TcpStream) -> Self {
+        if let Err(err) = stream.set_nodelay(true) {
+            log::warn!("Failed to disable Nagle's algorithm: {:?}", err);
+        }
         Self {
             stream,
             _phantom: std::marker::PhantomData,
@@ -29,15 +34,33 @@ impl<T: ByteMessage + Default> ByteMessagePort<T> {
     pub fn send(&mut self, message: impl ByteMessage) -> Result<(), String> {
         let bytes: Vec<u8> = message.to_bytes();
 
-        let len_buffer = (bytes.len() as u32).to_ne_bytes();
-        self.stream
-            .write_all(&len_buffer)
-            .map_err(|err| format!("Failed to send message length: {:?}", err))?;
+        // single write, so the length and the message go out in one segment
+        let mut buffer = Vec::with_capacity(4 + bytes.len());
+        buffer.extend_from_slice(&(bytes.len() as u32).to_ne_bytes());
+        buffer.extend_from_slice(&bytes);
         self.stream
-            .write_all(&bytes)
+            .write_all(&buffer)
             .map_err(|err| format!("Failed to send message: {:?}", err))
     }
 
+    /// Second handle of the same connection, e.g. to wait for a reply on another thread.
+    pub fn try_clone(&self) -> Result<Self, String> {
+        let stream = self
+            .stream
+            .try_clone()
+            .map_err(|err| format!("Failed to clone stream: {:?}", err))?;
+        Ok(Self {
+            stream,
+            _phantom: std::marker::PhantomData,
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), String> {
+        self.stream
+            .set_read_timeout(timeout)
+            .map_err(|err| format!("Failed to set read timeout: {:?}", err))
+    }
+
     pub fn recv(&mut self) -> Result<T, String> {
         let mut len_buffer: [u8; 4] = [0; 4];
         self.stream
@@ -84,6 +107,75 @@ impl ByteMessage for String {
     }
 }
 
+impl<T: ByteMessage + Default> ByteMessage for Vec<T> {
+    // The first 4 bytes are the number of elements
+    // Each element is prefixed with its own 4 bytes length, because nested
+    // structs and enums expect to consume all of the bytes they are given
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = (self.len() as u32).to_ne_bytes().to_vec();
+        for element in self.iter() {
+            let element_bytes = element.to_bytes();
+            bytes.extend_from_slice(&(element_bytes.len() as u32).to_ne_bytes());
+            bytes.extend_from_slice(&element_bytes);
+        }
+        bytes
+    }
+
+    fn from_bytes(&mut self, bytes: Vec<u8>) -> Result<u32, String> {
+        // sizes come from the peer, on 32 bit targets their sum can overflow
+        let get = |offset: usize, size: usize| -> Result<&[u8], String> {
+            let end = offset
+                .checked_add(size)
+                .ok_or(format!("Invalid size of bytes: {}", size))?;
+            bytes.get(offset..end).ok_or(format!(
+                "Invalid length of bytes. Expected at least {}, got: {}",
+                end,
+                bytes.len(),
+            ))
+        };
+        let read_u32 = |offset: usize| -> Result<u32, String> {
+            let slice = get(offset, 4)?;
+            Ok(u32::from_ne_bytes(
+                slice
+                    .try_into()
+                    .map_err(|e| format!("Error parsing bytes: {}", e))?,
+            ))
+        };
+
+        let count = read_u32(0)?;
+        let mut offset = 4;
+        self.clear();
+        for _ in 0..count {
+            let size = read_u32(offset)? as usize;
+            offset += 4;
+            let element_bytes = get(offset, size)?;
+
+            let mut element = T::default();
+            element.from_bytes(element_bytes.to_vec())?;
+            self.push(element);
+            offset += size;
+        }
+        Ok(offset as u32)
+    }
+}
+
+impl ByteMessage for bool {
+    fn to_bytes(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+
+    fn from_bytes(&mut self, bytes: Vec<u8>) -> Result<u32, String> {
+        match bytes.first() {
+            Some(0) => *self = false,
+            Some(1) => *self = true,
+            Some(value) => return Err(format!("Invalid bool value: {}", value)),
+            None => return Err(String::from("Invalid length of bytes. Expected at least 1")),
+        }
+        Ok(1)
+    }
+}
+
 macro_rules! impl_byte_message_for_trivial {
     ($($t:ty),*) => {
         $(
@@ -93,10 +185,14 @@ macro_rules! impl_byte_message_for_trivial {
                 }
 
                 fn from_bytes(&mut self, bytes: Vec<u8>) -> Result<u32, String> {
-                    if bytes.len() != std::mem::size_of::<Self>() {
-
-                    }
                     let size = std::mem::size_of::<Self>();
+                    if bytes.len() < size {
+                        return Err(format!(
+                            "Invalid length of bytes. Expected at least {}, got: {}",
+                            size,
+                            bytes.len(),
+                        ));
+                    }
                     *self = <$t>::from_ne_bytes(bytes[0..size].try_into().map_err(|e| format!("Error parsing bytes: {}", e))?);
                     Ok(size as u32)
                 }
@@ -205,3 +205,99 @@ mod test_enum_named {
         assert_eq!(packet, expected_test_data);
     }
 }
+
+#[cfg(test)]
+mod test_vec {
+    use crate::ByteMessage;
+
+    #[derive(Default, ByteMessage, Debug, PartialEq, Clone)]
+    enum TestE {
+        #[default]
+        A,
+        B(u8),
+    }
+
+    #[derive(Default, ByteMessage, Debug, PartialEq, Clone)]
+    struct TestSInner {
+        a: u32,
+        e: TestE,
+    }
+
+    #[derive(Default, ByteMessage, Debug, PartialEq)]
+    struct TestS {
+        a: Vec<TestSInner>,
+        b: String,
+        c: bool,
+        d: Vec<u16>,
+    }
+
+    #[test]
+    fn test_trivial() {
+        let test_data: Vec<u32> = vec![1, 2, 3];
+
+        let bytes = test_data.to_bytes();
+
+        // [count: u32, 3 * (len: u32, value: u32)]
+        // 4 + 3 * (4 + 4) = 28
+        assert_eq!(bytes.len(), 28);
+
+        let mut packet: Vec<u32> = Vec::default();
+        packet.from_bytes(bytes).unwrap();
+
+        assert_eq!(packet, test_data);
+    }
+
+    #[test]
+    fn test_empty() {
+        let test_data: Vec<String> = vec![];
+
+        let bytes = test_data.to_bytes();
+        assert_eq!(bytes.len(), 4);
+
+        let mut packet: Vec<String> = vec!["not empty".to_string()];
+        packet.from_bytes(bytes).unwrap();
+
+        assert_eq!(packet, test_data);
+    }
+
+    #[test]
+    fn test_nested() {
+        let test_data = TestS {
+            a: vec![
+                TestSInner { a: 1, e: TestE::A },
+                TestSInner {
+                    a: 2,
+                    e: TestE::B(42),
+                },
+            ],
+            b: "hello".to_string(),
+            c: true,
+            d: vec![7, 8],
+        };
+
+        let bytes = test_data.to_bytes();
+        let mut packet: TestS = TestS::default();
+        packet.from_bytes(bytes).unwrap();
+
+        assert_eq!(packet, test_data);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut bytes = vec![1u32, 2].to_bytes();
+        bytes.truncate(bytes.len() - 1);
+
+        let mut packet: Vec<u32> = Vec::default();
+        assert!(packet.from_bytes(bytes).is_err());
+    }
+
+    #[test]
+    fn test_oversized_element() {
+        let mut bytes = 1u32.to_ne_bytes().to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_ne_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+
+        let mut packet: Vec<u64> = Vec::default();
+        assert!(packet.from_bytes(bytes).is_err());
+    }
+}